@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX events_search_vector_idx;
+ALTER TABLE events DROP COLUMN search_vector;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE events ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(eventName, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(eventCity, '') || ' ' || coalesce(eventPlace, '')), 'B') ||
+    setweight(to_tsvector('english', coalesce(eventDescription, '')), 'C')
+) STORED;
+
+CREATE INDEX events_search_vector_idx ON events USING GIN (search_vector);
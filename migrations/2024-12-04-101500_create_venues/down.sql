@@ -0,0 +1,31 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER venues_search_vector_refresh ON venues;
+DROP FUNCTION venues_search_vector_refresh();
+DROP TRIGGER events_search_vector_refresh ON events;
+DROP FUNCTION events_search_vector_refresh();
+
+ALTER TABLE events DROP COLUMN search_vector;
+ALTER TABLE events
+    ADD COLUMN eventCountry TEXT,
+    ADD COLUMN eventCity TEXT,
+    ADD COLUMN eventPlace TEXT;
+
+UPDATE events SET eventCountry = venues.country, eventCity = venues.city, eventPlace = venues.name
+FROM venues
+WHERE venues.id = events.venue_id;
+
+ALTER TABLE events
+    ALTER COLUMN eventCountry SET NOT NULL,
+    ALTER COLUMN eventCity SET NOT NULL,
+    ALTER COLUMN eventPlace SET NOT NULL,
+    DROP COLUMN venue_id;
+ALTER TABLE events ADD CONSTRAINT events_eventdate_check CHECK (eventDate >= CURRENT_DATE) NOT VALID;
+
+ALTER TABLE events ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(eventName, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(eventCity, '') || ' ' || coalesce(eventPlace, '')), 'B') ||
+    setweight(to_tsvector('english', coalesce(eventDescription, '')), 'C')
+) STORED;
+CREATE INDEX events_search_vector_idx ON events USING GIN (search_vector);
+
+DROP TABLE venues;
@@ -0,0 +1,75 @@
+-- Your SQL goes here
+CREATE TABLE venues (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    address TEXT NOT NULL,
+    city TEXT NOT NULL,
+    country TEXT NOT NULL,
+    latitude DOUBLE PRECISION CHECK (latitude BETWEEN -90 AND 90),
+    longitude DOUBLE PRECISION CHECK (longitude BETWEEN -180 AND 180),
+    capacity INTEGER CHECK (capacity > 0),
+    timezone TEXT NOT NULL DEFAULT 'UTC',
+    CHECK ((latitude IS NULL) = (longitude IS NULL))
+);
+
+-- Organizers used to type the same place in ten different ways.
+CREATE UNIQUE INDEX venues_name_city_country_key ON venues (lower(name), lower(city), lower(country));
+CREATE INDEX venues_latitude_longitude_idx ON venues (latitude, longitude);
+
+-- Existing free-text places become venues without coordinates; organizers
+-- can fill those in later.
+INSERT INTO venues (name, address, city, country)
+SELECT DISTINCT ON (lower(eventPlace), lower(eventCity), lower(eventCountry))
+       eventPlace, eventPlace, eventCity, eventCountry
+FROM events;
+
+-- The CHECK is re-evaluated on every UPDATE, which makes rows of past events
+-- impossible to touch (including the backfill below).
+ALTER TABLE events DROP CONSTRAINT events_eventdate_check;
+ALTER TABLE events ADD COLUMN venue_id INTEGER REFERENCES venues(id);
+
+UPDATE events SET venue_id = venues.id
+FROM venues
+WHERE lower(venues.name) = lower(events.eventPlace)
+  AND lower(venues.city) = lower(events.eventCity)
+  AND lower(venues.country) = lower(events.eventCountry);
+
+ALTER TABLE events ALTER COLUMN venue_id SET NOT NULL;
+CREATE INDEX events_venue_id_idx ON events (venue_id);
+
+-- A generated column cannot look into venues, so the search vector is now
+-- maintained by triggers on both tables.
+ALTER TABLE events DROP COLUMN search_vector;
+ALTER TABLE events
+    DROP COLUMN eventCountry,
+    DROP COLUMN eventCity,
+    DROP COLUMN eventPlace;
+ALTER TABLE events ADD COLUMN search_vector TSVECTOR;
+
+CREATE OR REPLACE FUNCTION events_search_vector_refresh() RETURNS trigger AS $$
+BEGIN
+    SELECT setweight(to_tsvector('english', coalesce(NEW.eventName, '')), 'A') ||
+           setweight(to_tsvector('english', coalesce(venues.city, '') || ' ' || coalesce(venues.name, '')), 'B') ||
+           setweight(to_tsvector('english', coalesce(NEW.eventDescription, '')), 'C')
+    INTO NEW.search_vector
+    FROM venues
+    WHERE venues.id = NEW.venue_id;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER events_search_vector_refresh BEFORE INSERT OR UPDATE ON events
+    FOR EACH ROW EXECUTE PROCEDURE events_search_vector_refresh();
+
+CREATE OR REPLACE FUNCTION venues_search_vector_refresh() RETURNS trigger AS $$
+BEGIN
+    UPDATE events SET venue_id = venue_id WHERE venue_id = NEW.id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER venues_search_vector_refresh AFTER UPDATE OF name, city ON venues
+    FOR EACH ROW EXECUTE PROCEDURE venues_search_vector_refresh();
+
+UPDATE events SET venue_id = venue_id;
+CREATE INDEX events_search_vector_idx ON events USING GIN (search_vector);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+-- Events created before this migration were already live.
+ALTER TABLE events ADD COLUMN status VARCHAR(32) NOT NULL DEFAULT 'published'
+    CHECK (status IN ('draft', 'published', 'cancelled', 'postponed', 'completed'));
+ALTER TABLE events ALTER COLUMN status SET DEFAULT 'draft';
+
+CREATE INDEX events_status_idx ON events (status);
@@ -0,0 +1,15 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE events
+    ADD COLUMN eventDate DATE,
+    ADD COLUMN eventDateTime TIMESTAMP;
+
+UPDATE events
+SET eventDateTime = starts_at AT TIME ZONE timezone,
+    eventDate = (starts_at AT TIME ZONE timezone)::date;
+
+ALTER TABLE events
+    ALTER COLUMN eventDate SET NOT NULL,
+    ALTER COLUMN eventDateTime SET NOT NULL,
+    DROP COLUMN timezone,
+    DROP COLUMN starts_at,
+    DROP COLUMN ends_at;
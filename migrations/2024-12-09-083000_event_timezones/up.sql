@@ -0,0 +1,25 @@
+-- Your SQL goes here
+ALTER TABLE events
+    ADD COLUMN timezone TEXT,
+    ADD COLUMN starts_at TIMESTAMPTZ,
+    ADD COLUMN ends_at TIMESTAMPTZ;
+
+-- Naive datetimes were entered as wall-clock time at the venue.
+UPDATE events
+SET timezone = venues.timezone,
+    starts_at = events.eventDateTime AT TIME ZONE venues.timezone
+FROM venues
+WHERE venues.id = events.venue_id;
+
+-- No end time was ever recorded, assume a three hour event.
+UPDATE events SET ends_at = starts_at + INTERVAL '3 hours';
+
+ALTER TABLE events
+    ALTER COLUMN timezone SET NOT NULL,
+    ALTER COLUMN starts_at SET NOT NULL,
+    ALTER COLUMN ends_at SET NOT NULL,
+    ADD CONSTRAINT events_ends_after_starts CHECK (ends_at > starts_at),
+    DROP COLUMN eventDate,
+    DROP COLUMN eventDateTime;
+
+CREATE INDEX events_starts_at_idx ON events (starts_at);
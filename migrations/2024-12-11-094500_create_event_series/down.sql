@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE events
+    DROP COLUMN series_id,
+    DROP COLUMN recurrence_id,
+    DROP COLUMN capacity,
+    DROP COLUMN tickets_sold;
+DROP TABLE event_series;
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+CREATE TABLE event_series (
+    id SERIAL PRIMARY KEY,
+    userid INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    eventname TEXT NOT NULL,
+    eventdescription TEXT NOT NULL,
+    eventtype VARCHAR(255) NOT NULL,
+    eventimage TEXT NOT NULL,
+    eventticketprice INTEGER NOT NULL,
+    venue_id INTEGER NOT NULL REFERENCES venues(id),
+    timezone TEXT NOT NULL,
+    -- RFC 5545 RRULE, e.g. FREQ=WEEKLY;BYDAY=FR,SA;COUNT=10
+    rrule TEXT NOT NULL,
+    -- Wall-clock start of the first occurrence in `timezone`.
+    dtstart TIMESTAMP NOT NULL,
+    duration_minutes INTEGER NOT NULL CHECK (duration_minutes > 0),
+    capacity INTEGER CHECK (capacity > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Occurrences are materialized as regular events so each one can be
+-- overridden, cancelled and sold on its own.
+ALTER TABLE events
+    ADD COLUMN series_id INTEGER REFERENCES event_series(id) ON DELETE CASCADE,
+    ADD COLUMN recurrence_id TIMESTAMPTZ,
+    ADD COLUMN capacity INTEGER CHECK (capacity > 0),
+    ADD COLUMN tickets_sold INTEGER NOT NULL DEFAULT 0,
+    ADD CONSTRAINT events_tickets_sold_within_capacity
+        CHECK (tickets_sold >= 0 AND (capacity IS NULL OR tickets_sold <= capacity)),
+    ADD CONSTRAINT events_series_recurrence_id_key UNIQUE (series_id, recurrence_id);
@@ -0,0 +1,34 @@
+-- Your SQL goes here
+CREATE TABLE orders (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    event_id INTEGER NOT NULL REFERENCES events(id),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    unit_price INTEGER NOT NULL,
+    total_amount INTEGER NOT NULL,
+    status VARCHAR(32) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'paid', 'expired', 'cancelled', 'refunded')),
+    -- Seats are held for a pending order until this time.
+    expires_at TIMESTAMPTZ NOT NULL,
+    payment_reference TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    paid_at TIMESTAMPTZ
+);
+
+CREATE INDEX orders_user_id_idx ON orders (user_id);
+CREATE INDEX orders_event_id_idx ON orders (event_id);
+CREATE INDEX orders_pending_expires_at_idx ON orders (expires_at) WHERE status = 'pending';
+
+CREATE TABLE tickets (
+    id SERIAL PRIMARY KEY,
+    order_id INTEGER NOT NULL REFERENCES orders(id),
+    event_id INTEGER NOT NULL REFERENCES events(id),
+    owner_id INTEGER NOT NULL REFERENCES users(id),
+    -- Opaque value encoded in the ticket's QR code.
+    qr_token TEXT NOT NULL UNIQUE,
+    status VARCHAR(32) NOT NULL DEFAULT 'valid' CHECK (status IN ('valid', 'void')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX tickets_owner_id_idx ON tickets (owner_id);
+CREATE INDEX tickets_event_id_idx ON tickets (event_id);
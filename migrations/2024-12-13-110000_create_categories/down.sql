@@ -0,0 +1,32 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE events ADD COLUMN eventType VARCHAR(255);
+ALTER TABLE event_series ADD COLUMN eventtype VARCHAR(255);
+
+-- Only top-level categories map back onto the old enum values.
+UPDATE events
+SET eventType = coalesce((
+    SELECT initcap(categories.slug)
+    FROM event_categories
+    JOIN categories ON categories.id = event_categories.category_id
+    WHERE event_categories.event_id = events.id
+      AND categories.slug IN ('music', 'games', 'performing', 'movies', 'tour')
+    ORDER BY categories.position
+    LIMIT 1
+), 'Music');
+
+UPDATE event_series
+SET eventtype = coalesce((
+    SELECT initcap(categories.slug)
+    FROM categories
+    WHERE categories.id = ANY(event_series.category_ids)
+      AND categories.slug IN ('music', 'games', 'performing', 'movies', 'tour')
+    ORDER BY categories.position
+    LIMIT 1
+), 'Music');
+
+ALTER TABLE events ALTER COLUMN eventType SET NOT NULL;
+ALTER TABLE event_series ALTER COLUMN eventtype SET NOT NULL, DROP COLUMN category_ids;
+
+DROP TABLE event_categories;
+DROP TABLE categories;
+ALTER TABLE users DROP COLUMN is_admin;
@@ -0,0 +1,42 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE categories (
+    id SERIAL PRIMARY KEY,
+    parent_id INTEGER REFERENCES categories(id),
+    slug TEXT NOT NULL UNIQUE CHECK (slug ~ '^[a-z0-9]+(-[a-z0-9]+)*$'),
+    name TEXT NOT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    CHECK (parent_id <> id)
+);
+
+CREATE INDEX categories_parent_id_idx ON categories (parent_id);
+
+-- The values of the old hard-coded EventType enum.
+INSERT INTO categories (slug, name, position) VALUES
+    ('music', 'Music', 1),
+    ('games', 'Games', 2),
+    ('performing', 'Performing', 3),
+    ('movies', 'Movies', 4),
+    ('tour', 'Tour', 5);
+
+CREATE TABLE event_categories (
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    category_id INTEGER NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    PRIMARY KEY (event_id, category_id)
+);
+
+CREATE INDEX event_categories_category_id_idx ON event_categories (category_id);
+
+INSERT INTO event_categories (event_id, category_id)
+SELECT events.id, categories.id
+FROM events
+JOIN categories ON categories.slug = lower(events.eventType);
+
+-- Series only keep the categories their occurrences are created with.
+ALTER TABLE event_series ADD COLUMN category_ids INTEGER[] NOT NULL DEFAULT '{}';
+UPDATE event_series
+SET category_ids = ARRAY(SELECT id FROM categories WHERE categories.slug = lower(event_series.eventtype));
+
+ALTER TABLE events DROP COLUMN eventType;
+ALTER TABLE event_series DROP COLUMN eventtype;
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE tags (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(32) NOT NULL UNIQUE CHECK (name = lower(btrim(name)) AND name <> ''),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Prefix lookups for autocompletion (`name LIKE 'out%'`).
+CREATE INDEX tags_name_pattern_idx ON tags (name text_pattern_ops);
+
+CREATE TABLE event_tags (
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (event_id, tag_id)
+);
+
+CREATE INDEX event_tags_tag_id_idx ON event_tags (tag_id);
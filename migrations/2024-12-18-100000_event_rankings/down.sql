@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE event_rankings;
+DROP TABLE event_views;
+ALTER TABLE likes DROP COLUMN created_at;
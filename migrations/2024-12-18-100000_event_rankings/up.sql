@@ -0,0 +1,24 @@
+-- Your SQL goes here
+-- Likes only count towards trending while they are recent.
+ALTER TABLE likes ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE TABLE event_views (
+    id BIGSERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    user_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    viewed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX event_views_viewed_at_event_id_idx ON event_views (viewed_at, event_id);
+
+-- Rebuilt periodically from likes, paid orders and views.
+CREATE TABLE event_rankings (
+    event_id INTEGER PRIMARY KEY REFERENCES events(id) ON DELETE CASCADE,
+    score DOUBLE PRECISION NOT NULL,
+    likes_count BIGINT NOT NULL,
+    tickets_sold BIGINT NOT NULL,
+    recent_views BIGINT NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX event_rankings_score_idx ON event_rankings (score DESC);
@@ -0,0 +1,21 @@
+-- This file should undo anything in `up.sql`
+-- Only the most recent like of each user survives the old schema.
+DELETE FROM likes
+WHERE (user_id, event_id) NOT IN (
+    SELECT DISTINCT ON (user_id) user_id, event_id
+    FROM likes
+    ORDER BY user_id, created_at DESC
+);
+
+DROP INDEX likes_event_id_idx;
+ALTER TABLE likes
+    DROP CONSTRAINT likes_pkey,
+    DROP CONSTRAINT likes_user_id_fkey,
+    DROP CONSTRAINT likes_event_id_fkey,
+    ADD CONSTRAINT likes_user_id_fkey FOREIGN KEY (user_id) REFERENCES users(id),
+    ADD CONSTRAINT likes_event_id_fkey FOREIGN KEY (event_id) REFERENCES events(id),
+    ADD CONSTRAINT likes_pkey PRIMARY KEY (user_id);
+
+CREATE SEQUENCE likes_user_id_seq OWNED BY likes.user_id;
+SELECT setval('likes_user_id_seq', coalesce(max(user_id), 0) + 1, false) FROM likes;
+ALTER TABLE likes ALTER COLUMN user_id SET DEFAULT nextval('likes_user_id_seq');
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- user_id was a SERIAL primary key, so a user could only ever like one event.
+ALTER TABLE likes DROP CONSTRAINT likes_pkey;
+ALTER TABLE likes ALTER COLUMN user_id DROP DEFAULT;
+DROP SEQUENCE IF EXISTS likes_user_id_seq;
+
+ALTER TABLE likes
+    DROP CONSTRAINT likes_user_id_fkey,
+    DROP CONSTRAINT likes_event_id_fkey,
+    ADD CONSTRAINT likes_user_id_fkey FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    ADD CONSTRAINT likes_event_id_fkey FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE,
+    ADD CONSTRAINT likes_pkey PRIMARY KEY (user_id, event_id);
+
+CREATE INDEX likes_event_id_idx ON likes (event_id);
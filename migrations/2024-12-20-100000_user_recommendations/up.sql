@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- Per-user cache rebuilt by the recommendations job.
+CREATE TABLE user_recommendations (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    score DOUBLE PRECISION NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, event_id)
+);
+
+CREATE INDEX user_recommendations_user_id_score_idx ON user_recommendations (user_id, score DESC);
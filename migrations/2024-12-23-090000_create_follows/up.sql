@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- A user follows either an organizer (another user) or a venue.
+CREATE TABLE follows (
+    id SERIAL PRIMARY KEY,
+    follower_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    followed_user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    venue_id INTEGER REFERENCES venues(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CONSTRAINT follows_one_target CHECK ((followed_user_id IS NULL) <> (venue_id IS NULL)),
+    CONSTRAINT follows_not_self CHECK (followed_user_id <> follower_id)
+);
+
+CREATE UNIQUE INDEX follows_follower_user_key ON follows (follower_id, followed_user_id)
+    WHERE followed_user_id IS NOT NULL;
+CREATE UNIQUE INDEX follows_follower_venue_key ON follows (follower_id, venue_id)
+    WHERE venue_id IS NOT NULL;
+CREATE INDEX follows_followed_user_id_idx ON follows (followed_user_id);
+CREATE INDEX follows_venue_id_idx ON follows (venue_id);
@@ -0,0 +1,27 @@
+-- Your SQL goes here
+-- Things worth telling people about, written when they happen and fanned
+-- out into `feed_items` by the feed worker.
+CREATE TABLE feed_activities (
+    id BIGSERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    kind VARCHAR(32) NOT NULL
+        CHECK (kind IN ('event_published', 'price_drop', 'tickets_released')),
+    old_price INTEGER,
+    new_price INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    fanned_out_at TIMESTAMPTZ
+);
+
+CREATE INDEX feed_activities_pending_idx ON feed_activities (id) WHERE fanned_out_at IS NULL;
+
+CREATE TABLE feed_items (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    activity_id BIGINT NOT NULL REFERENCES feed_activities(id) ON DELETE CASCADE,
+    reason VARCHAR(32) NOT NULL
+        CHECK (reason IN ('followed_organizer', 'followed_venue', 'liked_event', 'liked_category')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (user_id, activity_id)
+);
+
+CREATE INDEX feed_items_user_id_id_idx ON feed_items (user_id, id DESC);
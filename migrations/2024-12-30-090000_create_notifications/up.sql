@@ -0,0 +1,44 @@
+-- Your SQL goes here
+CREATE TABLE notifications (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(32) NOT NULL
+        CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder')),
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    event_id INTEGER REFERENCES events(id) ON DELETE SET NULL,
+    order_id INTEGER REFERENCES orders(id) ON DELETE SET NULL,
+    read_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX notifications_user_id_id_idx ON notifications (user_id, id DESC);
+CREATE INDEX notifications_unread_idx ON notifications (user_id) WHERE read_at IS NULL;
+
+-- Missing rows mean the defaults: in-app and email on, push off.
+CREATE TABLE notification_preferences (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind VARCHAR(32) NOT NULL
+        CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder')),
+    in_app BOOLEAN NOT NULL DEFAULT true,
+    email BOOLEAN NOT NULL DEFAULT true,
+    push BOOLEAN NOT NULL DEFAULT false,
+    PRIMARY KEY (user_id, kind)
+);
+
+-- Email and push messages waiting for their sender.
+CREATE TABLE notification_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    channel VARCHAR(16) NOT NULL CHECK (channel IN ('email', 'push')),
+    kind VARCHAR(32) NOT NULL,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    sent_at TIMESTAMPTZ
+);
+
+CREATE INDEX notification_deliveries_pending_idx ON notification_deliveries (id) WHERE status = 'pending';
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX scheduled_jobs_dead_idx;
+DROP INDEX scheduled_jobs_lease_idx;
+ALTER TABLE scheduled_jobs DROP CONSTRAINT scheduled_jobs_status_check;
+UPDATE scheduled_jobs SET status = 'failed' WHERE status = 'dead';
+UPDATE scheduled_jobs SET status = 'pending', locked_until = NULL WHERE status = 'running';
+ALTER TABLE scheduled_jobs
+    DROP COLUMN locked_until,
+    DROP COLUMN max_attempts,
+    ADD CONSTRAINT scheduled_jobs_status_check CHECK (status IN ('pending', 'done', 'failed'));
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Turn scheduled_jobs into a general queue. A worker leases a job by
+-- setting it to running with a locked_until deadline; if the worker dies
+-- the lease runs out and another worker picks the job up again. Jobs that
+-- keep failing end up as dead until an admin retries them.
+ALTER TABLE scheduled_jobs DROP CONSTRAINT scheduled_jobs_status_check;
+UPDATE scheduled_jobs SET status = 'dead' WHERE status = 'failed';
+ALTER TABLE scheduled_jobs
+    ADD CONSTRAINT scheduled_jobs_status_check CHECK (status IN ('pending', 'running', 'done', 'dead')),
+    ADD COLUMN max_attempts INTEGER NOT NULL DEFAULT 5 CHECK (max_attempts > 0),
+    ADD COLUMN locked_until TIMESTAMPTZ;
+
+CREATE INDEX scheduled_jobs_lease_idx ON scheduled_jobs (locked_until) WHERE status = 'running';
+CREATE INDEX scheduled_jobs_dead_idx ON scheduled_jobs (id) WHERE status = 'dead';
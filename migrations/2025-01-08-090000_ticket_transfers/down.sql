@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE ticket_owners;
+DROP TABLE ticket_transfers;
+ALTER TABLE events DROP COLUMN transfer_cutoff_hours, DROP COLUMN transfers_enabled;
+ALTER TABLE users DROP COLUMN wallet_address;
@@ -0,0 +1,42 @@
+-- Your SQL goes here
+ALTER TABLE users
+    ADD COLUMN wallet_address VARCHAR(42) UNIQUE CHECK (wallet_address ~ '^0x[0-9a-f]{40}$');
+
+-- Organizer rules: transfers can be turned off, or closed a number of
+-- hours before the event starts (0 = until the start).
+ALTER TABLE events
+    ADD COLUMN transfers_enabled BOOLEAN NOT NULL DEFAULT true,
+    ADD COLUMN transfer_cutoff_hours INTEGER NOT NULL DEFAULT 0 CHECK (transfer_cutoff_hours >= 0);
+
+CREATE TABLE ticket_transfers (
+    id SERIAL PRIMARY KEY,
+    ticket_id INTEGER NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    from_user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    to_user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'accepted', 'declined', 'cancelled')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    responded_at TIMESTAMPTZ,
+    CONSTRAINT ticket_transfers_not_self CHECK (from_user_id <> to_user_id)
+);
+
+-- A ticket can only be offered to one person at a time.
+CREATE UNIQUE INDEX ticket_transfers_one_pending ON ticket_transfers (ticket_id) WHERE status = 'pending';
+CREATE INDEX ticket_transfers_to_user_idx ON ticket_transfers (to_user_id) WHERE status = 'pending';
+CREATE INDEX ticket_transfers_from_user_idx ON ticket_transfers (from_user_id) WHERE status = 'pending';
+
+-- Everyone who has held a ticket, oldest first. The current owner is the
+-- row without released_at.
+CREATE TABLE ticket_owners (
+    id SERIAL PRIMARY KEY,
+    ticket_id INTEGER NOT NULL REFERENCES tickets (id) ON DELETE CASCADE,
+    owner_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    acquired_via VARCHAR(16) NOT NULL CHECK (acquired_via IN ('purchase', 'transfer')),
+    transfer_id INTEGER REFERENCES ticket_transfers (id) ON DELETE SET NULL,
+    acquired_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    released_at TIMESTAMPTZ
+);
+
+CREATE INDEX ticket_owners_ticket_id_idx ON ticket_owners (ticket_id, acquired_at);
+
+INSERT INTO ticket_owners (ticket_id, owner_id, acquired_via, acquired_at)
+SELECT id, owner_id, 'purchase', created_at FROM tickets;
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+UPDATE ticket_owners SET acquired_via = 'transfer' WHERE acquired_via = 'resale';
+ALTER TABLE ticket_owners DROP CONSTRAINT ticket_owners_acquired_via_check;
+ALTER TABLE ticket_owners ADD CONSTRAINT ticket_owners_acquired_via_check
+    CHECK (acquired_via IN ('purchase', 'transfer'));
+DROP TABLE ticket_listings;
+ALTER TABLE events
+    DROP COLUMN resale_royalty_percent,
+    DROP COLUMN resale_max_markup_percent,
+    DROP COLUMN resale_enabled;
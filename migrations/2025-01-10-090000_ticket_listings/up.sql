@@ -0,0 +1,38 @@
+-- Your SQL goes here
+-- Organizer resale rules. By default tickets can be resold at face value
+-- at most, with no royalty.
+ALTER TABLE events
+    ADD COLUMN resale_enabled BOOLEAN NOT NULL DEFAULT true,
+    ADD COLUMN resale_max_markup_percent INTEGER NOT NULL DEFAULT 0 CHECK (resale_max_markup_percent >= 0),
+    ADD COLUMN resale_royalty_percent INTEGER NOT NULL DEFAULT 0
+        CHECK (resale_royalty_percent BETWEEN 0 AND 100);
+
+CREATE TABLE ticket_listings (
+    id SERIAL PRIMARY KEY,
+    ticket_id INTEGER NOT NULL REFERENCES tickets(id) ON DELETE CASCADE,
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    seller_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    price INTEGER NOT NULL CHECK (price >= 0),
+    status VARCHAR(16) NOT NULL DEFAULT 'active'
+        CHECK (status IN ('active', 'reserved', 'sold', 'cancelled')),
+    -- A buyer holds a reserved listing until this time while paying.
+    buyer_id INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    reserved_until TIMESTAMPTZ,
+    payment_reference TEXT,
+    -- Split of the price at settlement.
+    royalty_amount INTEGER,
+    seller_amount INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    sold_at TIMESTAMPTZ
+);
+
+-- A ticket is on sale at most once at a time.
+CREATE UNIQUE INDEX ticket_listings_one_open ON ticket_listings (ticket_id)
+    WHERE status IN ('active', 'reserved');
+CREATE INDEX ticket_listings_event_price_idx ON ticket_listings (event_id, price) WHERE status = 'active';
+CREATE INDEX ticket_listings_seller_id_idx ON ticket_listings (seller_id);
+CREATE INDEX ticket_listings_reserved_until_idx ON ticket_listings (reserved_until) WHERE status = 'reserved';
+
+ALTER TABLE ticket_owners DROP CONSTRAINT ticket_owners_acquired_via_check;
+ALTER TABLE ticket_owners ADD CONSTRAINT ticket_owners_acquired_via_check
+    CHECK (acquired_via IN ('purchase', 'transfer', 'resale'));
@@ -0,0 +1,14 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM notification_preferences WHERE kind = 'refund_updated';
+DELETE FROM notifications WHERE kind = 'refund_updated';
+ALTER TABLE notification_preferences DROP CONSTRAINT notification_preferences_kind_check;
+ALTER TABLE notification_preferences ADD CONSTRAINT notification_preferences_kind_check
+    CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder'));
+ALTER TABLE notifications DROP CONSTRAINT notifications_kind_check;
+ALTER TABLE notifications ADD CONSTRAINT notifications_kind_check
+    CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder'));
+DROP TABLE refunds;
+ALTER TABLE events
+    DROP COLUMN refund_cutoff_hours,
+    DROP COLUMN refund_partial_percent,
+    DROP COLUMN refund_full_until;
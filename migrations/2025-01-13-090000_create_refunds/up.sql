@@ -0,0 +1,42 @@
+-- Your SQL goes here
+-- Refund policy: a full refund until refund_full_until, then
+-- refund_partial_percent of the price, and nothing within
+-- refund_cutoff_hours of the start. Cancelled events are always refunded
+-- in full.
+ALTER TABLE events
+    ADD COLUMN refund_full_until TIMESTAMPTZ,
+    ADD COLUMN refund_partial_percent INTEGER NOT NULL DEFAULT 0
+        CHECK (refund_partial_percent BETWEEN 0 AND 100),
+    ADD COLUMN refund_cutoff_hours INTEGER NOT NULL DEFAULT 24 CHECK (refund_cutoff_hours >= 0);
+
+CREATE TABLE refunds (
+    id SERIAL PRIMARY KEY,
+    ticket_id INTEGER NOT NULL REFERENCES tickets(id),
+    order_id INTEGER NOT NULL REFERENCES orders(id),
+    event_id INTEGER NOT NULL REFERENCES events(id),
+    -- The ticket holder, who receives the money.
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    amount INTEGER NOT NULL CHECK (amount >= 0),
+    reason VARCHAR(16) NOT NULL CHECK (reason IN ('requested', 'event_cancelled')),
+    status VARCHAR(16) NOT NULL DEFAULT 'requested'
+        CHECK (status IN ('requested', 'approved', 'rejected', 'paid')),
+    -- How the money goes back: on-chain to the paying wallet or through a
+    -- fiat payment provider.
+    method VARCHAR(16) NOT NULL CHECK (method IN ('onchain', 'fiat')),
+    payout_reference TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    decided_at TIMESTAMPTZ,
+    paid_at TIMESTAMPTZ
+);
+
+-- A ticket is refunded at most once; rejected requests can be retried.
+CREATE UNIQUE INDEX refunds_one_per_ticket ON refunds (ticket_id) WHERE status <> 'rejected';
+CREATE INDEX refunds_event_id_idx ON refunds (event_id, status);
+CREATE INDEX refunds_user_id_idx ON refunds (user_id);
+
+ALTER TABLE notifications DROP CONSTRAINT notifications_kind_check;
+ALTER TABLE notifications ADD CONSTRAINT notifications_kind_check
+    CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder', 'refund_updated'));
+ALTER TABLE notification_preferences DROP CONSTRAINT notification_preferences_kind_check;
+ALTER TABLE notification_preferences ADD CONSTRAINT notification_preferences_kind_check
+    CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder', 'refund_updated'));
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM notification_preferences WHERE kind = 'waitlist_offer';
+DELETE FROM notifications WHERE kind = 'waitlist_offer';
+ALTER TABLE notification_preferences DROP CONSTRAINT notification_preferences_kind_check;
+ALTER TABLE notification_preferences ADD CONSTRAINT notification_preferences_kind_check
+    CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder', 'refund_updated'));
+ALTER TABLE notifications DROP CONSTRAINT notifications_kind_check;
+ALTER TABLE notifications ADD CONSTRAINT notifications_kind_check
+    CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder', 'refund_updated'));
+DROP TABLE waitlist_entries;
@@ -0,0 +1,29 @@
+-- Your SQL goes here
+-- People waiting for a sold-out event, served first come first served.
+-- An offer is a pending order held for the person at the front of the
+-- line; if it isn't paid in time the next person gets the seats.
+CREATE TABLE waitlist_entries (
+    id SERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    status VARCHAR(16) NOT NULL DEFAULT 'waiting'
+        CHECK (status IN ('waiting', 'offered', 'purchased', 'expired', 'left')),
+    order_id INTEGER REFERENCES orders(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    offered_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX waitlist_entries_one_open ON waitlist_entries (event_id, user_id)
+    WHERE status IN ('waiting', 'offered');
+CREATE INDEX waitlist_entries_line_idx ON waitlist_entries (event_id, id) WHERE status = 'waiting';
+CREATE INDEX waitlist_entries_order_id_idx ON waitlist_entries (order_id);
+
+ALTER TABLE notifications DROP CONSTRAINT notifications_kind_check;
+ALTER TABLE notifications ADD CONSTRAINT notifications_kind_check
+    CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder', 'refund_updated',
+                    'waitlist_offer'));
+ALTER TABLE notification_preferences DROP CONSTRAINT notification_preferences_kind_check;
+ALTER TABLE notification_preferences ADD CONSTRAINT notification_preferences_kind_check
+    CHECK (kind IN ('order_paid', 'event_cancelled', 'ticket_transferred', 'event_reminder', 'refund_updated',
+                    'waitlist_offer'));
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX orders_promo_code_id_idx;
+ALTER TABLE orders
+    DROP CONSTRAINT orders_discount_check,
+    DROP COLUMN early_bird_rule_id,
+    DROP COLUMN promo_code_id,
+    DROP COLUMN discount_amount,
+    DROP COLUMN subtotal_amount;
+DROP TABLE early_bird_rules;
+DROP TABLE promo_codes;
@@ -0,0 +1,55 @@
+-- Your SQL goes here
+-- Discount codes entered at checkout. A code belongs to an organizer and
+-- applies to all of their events, or to one event when event_id is set.
+-- Events have a single ticket price, so an event-scoped code is also
+-- scoped to that price tier.
+CREATE TABLE promo_codes (
+    id SERIAL PRIMARY KEY,
+    organizer_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    event_id INTEGER REFERENCES events(id) ON DELETE CASCADE,
+    code VARCHAR(32) NOT NULL CHECK (code ~ '^[A-Z0-9_-]{3,32}$'),
+    discount_kind VARCHAR(16) NOT NULL CHECK (discount_kind IN ('percent', 'fixed')),
+    -- Percent off the order, or a fixed amount off each ticket.
+    discount_value INTEGER NOT NULL CHECK (discount_value > 0),
+    max_uses INTEGER CHECK (max_uses > 0),
+    max_uses_per_user INTEGER CHECK (max_uses_per_user > 0),
+    valid_from TIMESTAMPTZ,
+    valid_until TIMESTAMPTZ,
+    active BOOLEAN NOT NULL DEFAULT true,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (discount_kind <> 'percent' OR discount_value <= 100),
+    CHECK (valid_until IS NULL OR valid_from IS NULL OR valid_from < valid_until)
+);
+
+CREATE UNIQUE INDEX promo_codes_organizer_code ON promo_codes (organizer_id, code);
+CREATE INDEX promo_codes_event_id_idx ON promo_codes (event_id);
+
+-- Automatic price drops for buying early: before a deadline, for the first
+-- N tickets sold, or both. The best matching rule applies to every order.
+CREATE TABLE early_bird_rules (
+    id SERIAL PRIMARY KEY,
+    event_id INTEGER NOT NULL REFERENCES events(id) ON DELETE CASCADE,
+    discount_kind VARCHAR(16) NOT NULL CHECK (discount_kind IN ('percent', 'fixed')),
+    discount_value INTEGER NOT NULL CHECK (discount_value > 0),
+    ends_at TIMESTAMPTZ,
+    max_tickets INTEGER CHECK (max_tickets > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (discount_kind <> 'percent' OR discount_value <= 100),
+    CHECK (ends_at IS NOT NULL OR max_tickets IS NOT NULL)
+);
+
+CREATE INDEX early_bird_rules_event_id_idx ON early_bird_rules (event_id);
+
+-- Orders keep what the tickets cost at face value and what was taken off;
+-- total_amount stays the amount charged.
+ALTER TABLE orders
+    ADD COLUMN subtotal_amount INTEGER,
+    ADD COLUMN discount_amount INTEGER NOT NULL DEFAULT 0 CHECK (discount_amount >= 0),
+    ADD COLUMN promo_code_id INTEGER REFERENCES promo_codes(id) ON DELETE SET NULL,
+    ADD COLUMN early_bird_rule_id INTEGER REFERENCES early_bird_rules(id) ON DELETE SET NULL;
+UPDATE orders SET subtotal_amount = total_amount;
+ALTER TABLE orders ALTER COLUMN subtotal_amount SET NOT NULL;
+ALTER TABLE orders ADD CONSTRAINT orders_discount_check
+    CHECK (total_amount = subtotal_amount - discount_amount);
+
+CREATE INDEX orders_promo_code_id_idx ON orders (promo_code_id, user_id);
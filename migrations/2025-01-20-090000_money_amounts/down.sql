@@ -0,0 +1,56 @@
+-- This file should undo anything in `up.sql`
+-- Amounts go back to whole units; anything below a whole unit is lost.
+ALTER TABLE early_bird_rules ADD COLUMN discount_value INTEGER;
+UPDATE early_bird_rules SET discount_value = COALESCE(percent_off, (amount_off / 100)::integer);
+ALTER TABLE early_bird_rules
+    DROP CONSTRAINT early_bird_rules_discount_check,
+    DROP COLUMN amount_off,
+    DROP COLUMN percent_off,
+    ALTER COLUMN discount_value SET NOT NULL,
+    ADD CHECK (discount_value > 0),
+    ADD CHECK (discount_kind <> 'percent' OR discount_value <= 100);
+
+ALTER TABLE promo_codes ADD COLUMN discount_value INTEGER;
+UPDATE promo_codes SET discount_value = COALESCE(percent_off, (amount_off / 100)::integer);
+ALTER TABLE promo_codes
+    DROP CONSTRAINT promo_codes_discount_check,
+    DROP COLUMN currency,
+    DROP COLUMN amount_off,
+    DROP COLUMN percent_off,
+    ALTER COLUMN discount_value SET NOT NULL,
+    ADD CHECK (discount_value > 0),
+    ADD CHECK (discount_kind <> 'percent' OR discount_value <= 100);
+
+ALTER TABLE ticket_listings
+    DROP COLUMN currency,
+    ALTER COLUMN price TYPE INTEGER USING (price / 100)::integer,
+    ALTER COLUMN royalty_amount TYPE INTEGER USING (royalty_amount / 100)::integer,
+    ALTER COLUMN seller_amount TYPE INTEGER USING (seller_amount / 100)::integer;
+
+ALTER TABLE refunds
+    DROP COLUMN currency,
+    ALTER COLUMN amount TYPE INTEGER USING (amount / 100)::integer;
+
+ALTER TABLE orders
+    DROP CONSTRAINT orders_discount_check,
+    DROP COLUMN currency,
+    ALTER COLUMN unit_price TYPE INTEGER USING (unit_price / 100)::integer,
+    ALTER COLUMN subtotal_amount TYPE INTEGER USING (subtotal_amount / 100)::integer,
+    ALTER COLUMN discount_amount TYPE INTEGER USING (discount_amount / 100)::integer,
+    ALTER COLUMN total_amount TYPE INTEGER USING (total_amount / 100)::integer;
+ALTER TABLE orders ADD CONSTRAINT orders_discount_check
+    CHECK (total_amount = subtotal_amount - discount_amount) NOT VALID;
+
+ALTER TABLE feed_activities
+    ALTER COLUMN old_price TYPE INTEGER USING (old_price / 100)::integer,
+    ALTER COLUMN new_price TYPE INTEGER USING (new_price / 100)::integer;
+
+ALTER TABLE event_series
+    DROP CONSTRAINT event_series_eventticketprice_check,
+    DROP COLUMN currency,
+    ALTER COLUMN eventticketprice TYPE INTEGER USING (eventticketprice / 100)::integer;
+
+ALTER TABLE events
+    DROP CONSTRAINT events_eventticketprice_check,
+    DROP COLUMN currency,
+    ALTER COLUMN eventticketprice TYPE INTEGER USING (eventticketprice / 100)::integer;
@@ -0,0 +1,75 @@
+-- Your SQL goes here
+-- Money is stored as whole minor units of a currency in NUMERIC(38, 0),
+-- wide enough for 18-decimal tokens. Existing prices were whole US dollars
+-- and become cents.
+ALTER TABLE events
+    ADD COLUMN currency VARCHAR(8) NOT NULL DEFAULT 'USD'
+        CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    ALTER COLUMN eventticketprice TYPE NUMERIC(38, 0) USING eventticketprice::numeric * 100,
+    ADD CONSTRAINT events_eventticketprice_check CHECK (eventticketprice >= 0);
+ALTER TABLE events ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE event_series
+    ADD COLUMN currency VARCHAR(8) NOT NULL DEFAULT 'USD'
+        CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    ALTER COLUMN eventticketprice TYPE NUMERIC(38, 0) USING eventticketprice::numeric * 100,
+    ADD CONSTRAINT event_series_eventticketprice_check CHECK (eventticketprice >= 0);
+ALTER TABLE event_series ALTER COLUMN currency DROP DEFAULT;
+
+-- In the currency of the activity's event.
+ALTER TABLE feed_activities
+    ALTER COLUMN old_price TYPE NUMERIC(38, 0) USING old_price::numeric * 100,
+    ALTER COLUMN new_price TYPE NUMERIC(38, 0) USING new_price::numeric * 100;
+
+ALTER TABLE orders
+    ADD COLUMN currency VARCHAR(8) NOT NULL DEFAULT 'USD'
+        CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    ALTER COLUMN unit_price TYPE NUMERIC(38, 0) USING unit_price::numeric * 100,
+    ALTER COLUMN subtotal_amount TYPE NUMERIC(38, 0) USING subtotal_amount::numeric * 100,
+    ALTER COLUMN discount_amount TYPE NUMERIC(38, 0) USING discount_amount::numeric * 100,
+    ALTER COLUMN total_amount TYPE NUMERIC(38, 0) USING total_amount::numeric * 100;
+ALTER TABLE orders ALTER COLUMN currency DROP DEFAULT;
+
+-- Refunds are in the currency of their order, resales in the currency of
+-- their event.
+ALTER TABLE refunds
+    ADD COLUMN currency VARCHAR(8) NOT NULL DEFAULT 'USD'
+        CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    ALTER COLUMN amount TYPE NUMERIC(38, 0) USING amount::numeric * 100;
+ALTER TABLE refunds ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE ticket_listings
+    ADD COLUMN currency VARCHAR(8) NOT NULL DEFAULT 'USD'
+        CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    ALTER COLUMN price TYPE NUMERIC(38, 0) USING price::numeric * 100,
+    ALTER COLUMN royalty_amount TYPE NUMERIC(38, 0) USING royalty_amount::numeric * 100,
+    ALTER COLUMN seller_amount TYPE NUMERIC(38, 0) USING seller_amount::numeric * 100;
+ALTER TABLE ticket_listings ALTER COLUMN currency DROP DEFAULT;
+
+-- Discounts are a percentage or an amount of money, kept in separate
+-- columns. A fixed promo code only applies to events priced in its
+-- currency; a fixed early-bird rule is in its event's currency.
+ALTER TABLE promo_codes
+    ADD COLUMN percent_off INTEGER,
+    ADD COLUMN amount_off NUMERIC(38, 0),
+    ADD COLUMN currency VARCHAR(8) CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH'));
+UPDATE promo_codes SET percent_off = discount_value WHERE discount_kind = 'percent';
+UPDATE promo_codes SET amount_off = discount_value::numeric * 100, currency = 'USD' WHERE discount_kind = 'fixed';
+ALTER TABLE promo_codes
+    DROP COLUMN discount_value,
+    ADD CONSTRAINT promo_codes_discount_check CHECK (
+        (discount_kind = 'percent' AND percent_off BETWEEN 1 AND 100 AND amount_off IS NULL AND currency IS NULL)
+        OR (discount_kind = 'fixed' AND amount_off > 0 AND currency IS NOT NULL AND percent_off IS NULL)
+    );
+
+ALTER TABLE early_bird_rules
+    ADD COLUMN percent_off INTEGER,
+    ADD COLUMN amount_off NUMERIC(38, 0);
+UPDATE early_bird_rules SET percent_off = discount_value WHERE discount_kind = 'percent';
+UPDATE early_bird_rules SET amount_off = discount_value::numeric * 100 WHERE discount_kind = 'fixed';
+ALTER TABLE early_bird_rules
+    DROP COLUMN discount_value,
+    ADD CONSTRAINT early_bird_rules_discount_check CHECK (
+        (discount_kind = 'percent' AND percent_off BETWEEN 1 AND 100 AND amount_off IS NULL)
+        OR (discount_kind = 'fixed' AND amount_off > 0 AND percent_off IS NULL)
+    );
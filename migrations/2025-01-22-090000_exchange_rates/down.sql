@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE orders
+    DROP COLUMN pay_amount,
+    DROP COLUMN pay_currency;
+
+ALTER TABLE users DROP COLUMN preferred_currency;
+
+DROP TABLE exchange_rates;
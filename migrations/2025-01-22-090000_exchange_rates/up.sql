@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Latest rate of each currency against the US dollar: how many units of the
+-- currency one dollar buys. Refreshed from the configured provider; the
+-- dollar itself is always 1 and isn't stored.
+CREATE TABLE exchange_rates (
+    currency VARCHAR(8) PRIMARY KEY CHECK (currency IN ('TZS', 'USDC', 'ETH')),
+    per_usd NUMERIC(38, 18) NOT NULL CHECK (per_usd > 0),
+    source VARCHAR(32) NOT NULL,
+    fetched_at TIMESTAMPTZ NOT NULL
+);
+
+-- Currency prices are shown in when the user doesn't ask for one.
+ALTER TABLE users
+    ADD COLUMN preferred_currency VARCHAR(8)
+        CHECK (preferred_currency IN ('TZS', 'USD', 'USDC', 'ETH'));
+
+-- Amount due when paying in another currency than the event's, converted
+-- at the rate locked when the seats were held.
+ALTER TABLE orders
+    ADD COLUMN pay_currency VARCHAR(8)
+        CHECK (pay_currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    ADD COLUMN pay_amount NUMERIC(38, 0) CHECK (pay_amount >= 0),
+    ADD CONSTRAINT orders_pay_check CHECK ((pay_currency IS NULL) = (pay_amount IS NULL));
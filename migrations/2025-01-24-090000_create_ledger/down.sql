@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE ledger_entries;
+DROP TABLE ledger_transactions;
+DROP TABLE payouts;
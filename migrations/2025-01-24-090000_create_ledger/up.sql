@@ -0,0 +1,85 @@
+-- Your SQL goes here
+-- Money owed to organizers, paid out to a bank account or a wallet.
+CREATE TABLE payouts (
+    id SERIAL PRIMARY KEY,
+    organizer_id INTEGER NOT NULL REFERENCES users(id) ON DELETE RESTRICT,
+    amount NUMERIC(38, 0) NOT NULL CHECK (amount > 0),
+    currency VARCHAR(8) NOT NULL CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    method VARCHAR(16) NOT NULL CHECK (method IN ('bank', 'wallet')),
+    -- Bank account number or wallet address.
+    destination TEXT NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'paid', 'failed')),
+    -- Bank transfer reference or transaction hash.
+    reference TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    settled_at TIMESTAMPTZ
+);
+
+CREATE INDEX payouts_organizer_id_idx ON payouts (organizer_id, created_at);
+CREATE INDEX payouts_pending_idx ON payouts (created_at) WHERE status = 'pending';
+
+-- Double-entry bookkeeping. Every transaction has entries in one currency
+-- that sum to zero; a positive amount credits the account.
+CREATE TABLE ledger_transactions (
+    id SERIAL PRIMARY KEY,
+    kind VARCHAR(16) NOT NULL
+        CHECK (kind IN ('sale', 'refund', 'payout', 'payout_settled', 'payout_failed')),
+    currency VARCHAR(8) NOT NULL CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    order_id INTEGER REFERENCES orders(id) ON DELETE RESTRICT,
+    refund_id INTEGER REFERENCES refunds(id) ON DELETE RESTRICT,
+    payout_id INTEGER REFERENCES payouts(id) ON DELETE RESTRICT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK (kind <> 'sale' OR order_id IS NOT NULL),
+    CHECK (kind <> 'refund' OR refund_id IS NOT NULL),
+    CHECK (kind NOT IN ('payout', 'payout_settled', 'payout_failed') OR payout_id IS NOT NULL)
+);
+
+-- Each order, refund and payout step is booked once.
+CREATE UNIQUE INDEX ledger_transactions_once ON ledger_transactions
+    (kind, COALESCE(order_id, 0), COALESCE(refund_id, 0), COALESCE(payout_id, 0));
+
+CREATE TABLE ledger_entries (
+    id SERIAL PRIMARY KEY,
+    transaction_id INTEGER NOT NULL REFERENCES ledger_transactions(id) ON DELETE RESTRICT,
+    account VARCHAR(16) NOT NULL
+        CHECK (account IN ('buyers', 'platform_fees', 'organizer', 'payouts_pending', 'paid_out')),
+    -- Owner of organizer accounts; platform accounts have none.
+    organizer_id INTEGER REFERENCES users(id) ON DELETE RESTRICT,
+    amount NUMERIC(38, 0) NOT NULL CHECK (amount <> 0),
+    currency VARCHAR(8) NOT NULL CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    CHECK ((organizer_id IS NULL) = (account IN ('buyers', 'platform_fees')))
+);
+
+CREATE INDEX ledger_entries_transaction_id_idx ON ledger_entries (transaction_id);
+CREATE INDEX ledger_entries_organizer_idx ON ledger_entries (organizer_id, account, currency, id);
+
+-- Book what was sold and refunded so far, without a platform fee.
+INSERT INTO ledger_transactions (kind, currency, order_id, created_at)
+SELECT 'sale', currency, id, paid_at
+FROM orders
+WHERE paid_at IS NOT NULL AND total_amount > 0;
+
+INSERT INTO ledger_entries (transaction_id, account, organizer_id, amount, currency, created_at)
+SELECT t.id, 'buyers', NULL, -o.total_amount, o.currency, t.created_at
+FROM ledger_transactions t JOIN orders o ON o.id = t.order_id
+WHERE t.kind = 'sale'
+UNION ALL
+SELECT t.id, 'organizer', e.userid, o.total_amount, o.currency, t.created_at
+FROM ledger_transactions t JOIN orders o ON o.id = t.order_id JOIN events e ON e.id = o.event_id
+WHERE t.kind = 'sale';
+
+INSERT INTO ledger_transactions (kind, currency, refund_id, created_at)
+SELECT 'refund', currency, id, COALESCE(decided_at, created_at)
+FROM refunds
+WHERE status IN ('approved', 'paid') AND amount > 0;
+
+INSERT INTO ledger_entries (transaction_id, account, organizer_id, amount, currency, created_at)
+SELECT t.id, 'organizer', e.userid, -r.amount, r.currency, t.created_at
+FROM ledger_transactions t JOIN refunds r ON r.id = t.refund_id JOIN events e ON e.id = r.event_id
+WHERE t.kind = 'refund'
+UNION ALL
+SELECT t.id, 'buyers', NULL, r.amount, r.currency, t.created_at
+FROM ledger_transactions t JOIN refunds r ON r.id = t.refund_id
+WHERE t.kind = 'refund';
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE orders DROP COLUMN payment_method;
+
+DROP TABLE payments;
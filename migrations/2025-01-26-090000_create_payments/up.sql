@@ -0,0 +1,33 @@
+-- Your SQL goes here
+-- Fiat payments of orders through a card / mobile money provider. The
+-- provider confirms the outcome with a signed webhook.
+CREATE TABLE payments (
+    id SERIAL PRIMARY KEY,
+    order_id INTEGER NOT NULL REFERENCES orders(id) ON DELETE RESTRICT,
+    provider VARCHAR(16) NOT NULL,
+    -- The provider's id for the payment, known once it was created there.
+    provider_reference TEXT,
+    method VARCHAR(16) NOT NULL CHECK (method IN ('card', 'mobile_money')),
+    amount NUMERIC(38, 0) NOT NULL CHECK (amount >= 0),
+    currency VARCHAR(8) NOT NULL CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    -- 'unmatched': money arrived for an order that could no longer be paid
+    -- and has to be returned.
+    status VARCHAR(16) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'succeeded', 'failed', 'unmatched')),
+    -- Where to send the buyer to enter card details or approve the charge.
+    checkout_url TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX payments_provider_reference ON payments (provider, provider_reference);
+CREATE INDEX payments_order_id_idx ON payments (order_id);
+-- One payment in flight per order.
+CREATE UNIQUE INDEX payments_one_pending ON payments (order_id) WHERE status = 'pending';
+
+-- How a paid order was paid; NULL for orders paid before this was tracked,
+-- which were all on-chain.
+ALTER TABLE orders
+    ADD COLUMN payment_method VARCHAR(16)
+        CHECK (payment_method IN ('onchain', 'card', 'mobile_money'));
+UPDATE orders SET payment_method = 'onchain' WHERE paid_at IS NOT NULL;
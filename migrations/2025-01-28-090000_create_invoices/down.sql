@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE notification_deliveries DROP COLUMN order_id;
+DROP TABLE invoices;
+DROP TABLE invoice_counters;
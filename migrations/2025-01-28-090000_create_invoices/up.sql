@@ -0,0 +1,53 @@
+-- Your SQL goes here
+-- Last invoice number given out by each organizer; numbers have no gaps.
+CREATE TABLE invoice_counters (
+    organizer_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    last_number INTEGER NOT NULL CHECK (last_number > 0)
+);
+
+-- The invoice of a paid order, issued by the event's organizer. Names,
+-- tax and fee are copied at payment time so the document never changes.
+CREATE TABLE invoices (
+    id SERIAL PRIMARY KEY,
+    order_id INTEGER NOT NULL UNIQUE REFERENCES orders(id) ON DELETE RESTRICT,
+    organizer_id INTEGER NOT NULL REFERENCES users(id) ON DELETE RESTRICT,
+    number INTEGER NOT NULL CHECK (number > 0),
+    seller_name TEXT NOT NULL,
+    seller_email TEXT NOT NULL,
+    buyer_name TEXT NOT NULL,
+    buyer_email TEXT NOT NULL,
+    -- Tax included in the order total, in hundredths of a percent.
+    tax_rate_bps INTEGER NOT NULL CHECK (tax_rate_bps BETWEEN 0 AND 10000),
+    tax_amount NUMERIC(38, 0) NOT NULL CHECK (tax_amount >= 0),
+    -- Platform fee kept from the total.
+    fee_amount NUMERIC(38, 0) NOT NULL CHECK (fee_amount >= 0),
+    currency VARCHAR(8) NOT NULL CHECK (currency IN ('TZS', 'USD', 'USDC', 'ETH')),
+    issued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (organizer_id, number)
+);
+
+-- Lets the email worker attach the receipt of the order it reports on.
+ALTER TABLE notification_deliveries
+    ADD COLUMN order_id INTEGER REFERENCES orders(id) ON DELETE SET NULL;
+
+-- Orders paid so far get numbers in the order they were paid, with the fee
+-- the ledger booked for them and no tax.
+INSERT INTO invoices (order_id, organizer_id, number, seller_name, seller_email,
+                      buyer_name, buyer_email, tax_rate_bps, tax_amount, fee_amount,
+                      currency, issued_at)
+SELECT o.id, e.userid,
+       row_number() OVER (PARTITION BY e.userid ORDER BY o.paid_at, o.id),
+       seller.username, seller.email, buyer.username, buyer.email, 0, 0,
+       COALESCE((SELECT sum(le.amount) FROM ledger_entries le
+                 JOIN ledger_transactions lt ON lt.id = le.transaction_id
+                 WHERE lt.kind = 'sale' AND lt.order_id = o.id
+                   AND le.account = 'platform_fees'), 0),
+       o.currency, o.paid_at
+FROM orders o
+JOIN events e ON e.id = o.event_id
+JOIN users seller ON seller.id = e.userid
+JOIN users buyer ON buyer.id = o.user_id
+WHERE o.paid_at IS NOT NULL;
+
+INSERT INTO invoice_counters (organizer_id, last_number)
+SELECT organizer_id, max(number) FROM invoices GROUP BY organizer_id;
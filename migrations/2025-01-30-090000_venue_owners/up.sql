@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- Who may edit a venue. Venues created before this have no owner and are
+-- left to admins.
+ALTER TABLE venues ADD COLUMN created_by INTEGER REFERENCES users(id) ON DELETE SET NULL;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- The organizer an order was sold for, fixed at checkout. Its sale, refunds
+-- and invoice are booked to them even if the event changes hands later.
+ALTER TABLE orders ADD COLUMN organizer_id INTEGER REFERENCES users(id);
+
+UPDATE orders
+SET organizer_id = COALESCE(
+    (SELECT invoices.organizer_id FROM invoices WHERE invoices.order_id = orders.id),
+    (SELECT events.userid FROM events WHERE events.id = orders.event_id)
+);
+
+ALTER TABLE orders ALTER COLUMN organizer_id SET NOT NULL;
@@ -0,0 +1,20 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM ledger_entries
+WHERE transaction_id IN (SELECT id FROM ledger_transactions WHERE kind = 'resale');
+DELETE FROM ledger_transactions WHERE kind = 'resale';
+
+DROP INDEX ledger_transactions_once;
+CREATE UNIQUE INDEX ledger_transactions_once ON ledger_transactions
+    (kind, COALESCE(order_id, 0), COALESCE(refund_id, 0), COALESCE(payout_id, 0));
+
+ALTER TABLE ledger_entries
+    DROP CONSTRAINT ledger_entries_account_check,
+    ADD CONSTRAINT ledger_entries_account_check
+        CHECK (account IN ('buyers', 'platform_fees', 'organizer', 'payouts_pending', 'paid_out'));
+
+ALTER TABLE ledger_transactions
+    DROP CONSTRAINT ledger_transactions_resale_check,
+    DROP CONSTRAINT ledger_transactions_kind_check,
+    ADD CONSTRAINT ledger_transactions_kind_check
+        CHECK (kind IN ('sale', 'refund', 'payout', 'payout_settled', 'payout_failed')),
+    DROP COLUMN listing_id;
@@ -0,0 +1,39 @@
+-- Your SQL goes here
+-- Resales are booked too: the buyer's money goes to the seller, less the
+-- organizer's royalty and the platform fee.
+ALTER TABLE ledger_transactions
+    ADD COLUMN listing_id INTEGER REFERENCES ticket_listings(id) ON DELETE RESTRICT,
+    DROP CONSTRAINT ledger_transactions_kind_check,
+    ADD CONSTRAINT ledger_transactions_kind_check
+        CHECK (kind IN ('sale', 'refund', 'payout', 'payout_settled', 'payout_failed', 'resale')),
+    ADD CONSTRAINT ledger_transactions_resale_check
+        CHECK (kind <> 'resale' OR listing_id IS NOT NULL);
+
+-- What resale sellers are owed, per seller.
+ALTER TABLE ledger_entries
+    DROP CONSTRAINT ledger_entries_account_check,
+    ADD CONSTRAINT ledger_entries_account_check
+        CHECK (account IN ('buyers', 'platform_fees', 'organizer', 'payouts_pending', 'paid_out', 'seller'));
+
+DROP INDEX ledger_transactions_once;
+CREATE UNIQUE INDEX ledger_transactions_once ON ledger_transactions
+    (kind, COALESCE(order_id, 0), COALESCE(refund_id, 0), COALESCE(payout_id, 0), COALESCE(listing_id, 0));
+
+-- Book what was resold so far, without a platform fee.
+INSERT INTO ledger_transactions (kind, currency, listing_id, created_at)
+SELECT 'resale', currency, id, sold_at
+FROM ticket_listings
+WHERE status = 'sold' AND price > 0;
+
+INSERT INTO ledger_entries (transaction_id, account, organizer_id, amount, currency, created_at)
+SELECT t.id, 'buyers', NULL, -l.price, l.currency, t.created_at
+FROM ledger_transactions t JOIN ticket_listings l ON l.id = t.listing_id
+WHERE t.kind = 'resale'
+UNION ALL
+SELECT t.id, 'seller', l.seller_id, l.price - COALESCE(l.royalty_amount, 0), l.currency, t.created_at
+FROM ledger_transactions t JOIN ticket_listings l ON l.id = t.listing_id
+WHERE t.kind = 'resale' AND l.price - COALESCE(l.royalty_amount, 0) <> 0
+UNION ALL
+SELECT t.id, 'organizer', e.userid, l.royalty_amount, l.currency, t.created_at
+FROM ledger_transactions t JOIN ticket_listings l ON l.id = t.listing_id JOIN events e ON e.id = l.event_id
+WHERE t.kind = 'resale' AND COALESCE(l.royalty_amount, 0) <> 0;
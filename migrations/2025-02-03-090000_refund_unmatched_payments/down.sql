@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX payments_unmatched_idx;
+UPDATE payments SET status = 'unmatched' WHERE status = 'refunded';
+ALTER TABLE payments
+    DROP CONSTRAINT payments_status_check,
+    ADD CONSTRAINT payments_status_check
+        CHECK (status IN ('pending', 'succeeded', 'failed', 'unmatched'));
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- 'refunded': an unmatched payment whose money went back to the buyer
+-- through the provider.
+ALTER TABLE payments
+    DROP CONSTRAINT payments_status_check,
+    ADD CONSTRAINT payments_status_check
+        CHECK (status IN ('pending', 'succeeded', 'failed', 'unmatched', 'refunded'));
+
+CREATE INDEX payments_unmatched_idx ON payments (id) WHERE status = 'unmatched';
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- Every on-chain transaction accepted as payment, so that one transfer pays
+-- for one order or one resale only.
+CREATE TABLE chain_payments (
+    tx_hash TEXT PRIMARY KEY,
+    order_id INT REFERENCES orders (id) ON DELETE CASCADE,
+    listing_id INT REFERENCES ticket_listings (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (num_nonnulls(order_id, listing_id) = 1)
+);
+
+INSERT INTO chain_payments (tx_hash, order_id, created_at)
+SELECT lower(payment_reference), id, COALESCE(paid_at, created_at)
+FROM orders
+WHERE payment_reference IS NOT NULL
+  AND COALESCE(payment_method, 'onchain') = 'onchain'
+ON CONFLICT DO NOTHING;
+
+INSERT INTO chain_payments (tx_hash, listing_id, created_at)
+SELECT lower(payment_reference), id, COALESCE(sold_at, created_at)
+FROM ticket_listings
+WHERE payment_reference IS NOT NULL
+ON CONFLICT DO NOTHING;
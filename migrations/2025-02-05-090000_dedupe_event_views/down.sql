@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX event_views_once;
+ALTER TABLE event_views
+    DROP COLUMN bucket,
+    DROP COLUMN viewer_key;
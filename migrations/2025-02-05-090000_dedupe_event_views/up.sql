@@ -0,0 +1,23 @@
+-- Your SQL goes here
+-- A view counts once per viewer, event and hour. Viewers are users, or
+-- anonymous visitors told apart by a keyed hash of their IP address.
+ALTER TABLE event_views
+    ADD COLUMN viewer_key TEXT,
+    ADD COLUMN bucket TIMESTAMPTZ;
+
+UPDATE event_views
+SET viewer_key = CASE WHEN user_id IS NULL THEN 'view:' || id ELSE 'user:' || user_id END,
+    bucket = date_trunc('hour', viewed_at);
+
+DELETE FROM event_views duplicate
+USING event_views kept
+WHERE duplicate.event_id = kept.event_id
+  AND duplicate.viewer_key = kept.viewer_key
+  AND duplicate.bucket = kept.bucket
+  AND duplicate.id > kept.id;
+
+ALTER TABLE event_views
+    ALTER COLUMN viewer_key SET NOT NULL,
+    ALTER COLUMN bucket SET NOT NULL;
+
+CREATE UNIQUE INDEX event_views_once ON event_views (event_id, viewer_key, bucket);
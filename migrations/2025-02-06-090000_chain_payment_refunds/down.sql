@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX chain_payments_unmatched_idx;
+ALTER TABLE chain_payments
+    DROP COLUMN user_id,
+    DROP COLUMN sender,
+    DROP COLUMN amount,
+    DROP COLUMN currency,
+    DROP COLUMN status,
+    DROP COLUMN refund_reference,
+    DROP COLUMN refunded_at;
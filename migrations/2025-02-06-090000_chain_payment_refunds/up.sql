@@ -0,0 +1,37 @@
+-- Your SQL goes here
+-- A transaction is claimed before it pays for anything. 'pending' while the
+-- order or listing is being paid, 'settled' once it is, 'unmatched' when the
+-- money arrived but couldn't buy anything any more and is owed back to the
+-- sender, 'refunded' once it has been sent back.
+ALTER TABLE chain_payments
+    ADD COLUMN user_id INTEGER REFERENCES users(id),
+    ADD COLUMN sender VARCHAR(42),
+    ADD COLUMN amount NUMERIC,
+    ADD COLUMN currency VARCHAR(8),
+    ADD COLUMN status VARCHAR(16) NOT NULL DEFAULT 'settled'
+        CHECK (status IN ('pending', 'settled', 'unmatched', 'refunded')),
+    ADD COLUMN refund_reference TEXT,
+    ADD COLUMN refunded_at TIMESTAMPTZ;
+
+UPDATE chain_payments
+SET user_id = orders.user_id,
+    amount = COALESCE(orders.pay_amount, orders.total_amount),
+    currency = COALESCE(orders.pay_currency, orders.currency)
+FROM orders
+WHERE orders.id = chain_payments.order_id;
+
+UPDATE chain_payments
+SET user_id = ticket_listings.buyer_id,
+    amount = ticket_listings.price,
+    currency = ticket_listings.currency
+FROM ticket_listings
+WHERE ticket_listings.id = chain_payments.listing_id;
+
+ALTER TABLE chain_payments
+    ALTER COLUMN user_id SET NOT NULL,
+    ALTER COLUMN amount SET NOT NULL,
+    ALTER COLUMN currency SET NOT NULL,
+    ALTER COLUMN status DROP DEFAULT;
+
+CREATE INDEX chain_payments_unmatched_idx ON chain_payments (created_at)
+    WHERE status = 'unmatched';
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX payments_unmatched_idx;
+CREATE INDEX payments_unmatched_idx ON payments (id) WHERE status = 'unmatched';
+UPDATE payments SET status = 'unmatched' WHERE status = 'refunding';
+ALTER TABLE payments
+    DROP CONSTRAINT payments_status_check,
+    ADD CONSTRAINT payments_status_check
+        CHECK (status IN ('pending', 'succeeded', 'failed', 'unmatched', 'refunded'));
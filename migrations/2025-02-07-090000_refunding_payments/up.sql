@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- 'refunding': the provider has been asked to refund an unmatched payment
+-- and hasn't answered yet.
+ALTER TABLE payments
+    DROP CONSTRAINT payments_status_check,
+    ADD CONSTRAINT payments_status_check
+        CHECK (status IN ('pending', 'succeeded', 'failed', 'unmatched', 'refunding', 'refunded'));
+
+DROP INDEX payments_unmatched_idx;
+CREATE INDEX payments_unmatched_idx ON payments (id) WHERE status IN ('unmatched', 'refunding');
@@ -0,0 +1,16 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE ledger_entries
+    DROP CONSTRAINT ledger_entries_account_check,
+    ADD CONSTRAINT ledger_entries_account_check
+        CHECK (account IN ('buyers', 'platform_fees', 'organizer', 'payouts_pending', 'paid_out', 'seller'));
+
+UPDATE ledger_entries e
+SET account = 'seller'
+FROM ledger_transactions t
+JOIN ticket_listings l ON l.id = t.listing_id
+JOIN events ev ON ev.id = l.event_id
+WHERE e.transaction_id = t.id
+  AND t.kind = 'resale'
+  AND e.account = 'organizer'
+  AND e.organizer_id = l.seller_id
+  AND e.organizer_id <> ev.userid;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- Resale sellers are paid out from the same balance as organizers, so what
+-- they were owed so far moves there.
+UPDATE ledger_entries SET account = 'organizer' WHERE account = 'seller';
+
+ALTER TABLE ledger_entries
+    DROP CONSTRAINT ledger_entries_account_check,
+    ADD CONSTRAINT ledger_entries_account_check
+        CHECK (account IN ('buyers', 'platform_fees', 'organizer', 'payouts_pending', 'paid_out'));
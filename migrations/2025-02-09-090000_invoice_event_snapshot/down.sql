@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE invoices
+    DROP COLUMN event_name,
+    DROP COLUMN event_starts_at,
+    DROP COLUMN event_timezone,
+    DROP COLUMN venue_name,
+    DROP COLUMN venue_address,
+    DROP COLUMN venue_city,
+    DROP COLUMN venue_country;
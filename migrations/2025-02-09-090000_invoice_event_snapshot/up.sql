@@ -0,0 +1,33 @@
+-- Your SQL goes here
+-- The event and venue as they were when the invoice was issued, so later
+-- edits don't change the receipt.
+ALTER TABLE invoices
+    ADD COLUMN event_name TEXT,
+    ADD COLUMN event_starts_at TIMESTAMPTZ,
+    ADD COLUMN event_timezone TEXT,
+    ADD COLUMN venue_name TEXT,
+    ADD COLUMN venue_address TEXT,
+    ADD COLUMN venue_city TEXT,
+    ADD COLUMN venue_country TEXT;
+
+UPDATE invoices
+SET event_name = e.eventname,
+    event_starts_at = e.starts_at,
+    event_timezone = e.timezone,
+    venue_name = v.name,
+    venue_address = v.address,
+    venue_city = v.city,
+    venue_country = v.country
+FROM orders o
+JOIN events e ON e.id = o.event_id
+JOIN venues v ON v.id = e.venue_id
+WHERE o.id = invoices.order_id;
+
+ALTER TABLE invoices
+    ALTER COLUMN event_name SET NOT NULL,
+    ALTER COLUMN event_starts_at SET NOT NULL,
+    ALTER COLUMN event_timezone SET NOT NULL,
+    ALTER COLUMN venue_name SET NOT NULL,
+    ALTER COLUMN venue_address SET NOT NULL,
+    ALTER COLUMN venue_city SET NOT NULL,
+    ALTER COLUMN venue_country SET NOT NULL;
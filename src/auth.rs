@@ -1,4 +1,5 @@
 use crate::config::AppState;
+use crate::database::{self, Db};
 use jwt::{DecodingKey, EncodingKey};
 use rocket::http::Status;
 use rocket::outcome::Outcome;
@@ -43,6 +44,33 @@ impl<'r> FromRequest<'r> for Auth {
     }
 }
 
+/// An authenticated user with the `is_admin` flag set.
+pub struct Admin(pub Auth);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Admin, Self::Error> {
+        let auth = match req.guard::<Auth>().await {
+            Outcome::Success(auth) => auth,
+            Outcome::Error(error) => return Outcome::Error(error),
+            Outcome::Forward(status) => return Outcome::Forward(status),
+        };
+        let db = match req.guard::<Db>().await {
+            Outcome::Success(db) => db,
+            _ => return Outcome::Error((Status::ServiceUnavailable, ())),
+        };
+
+        let id = auth.id;
+        if db.run(move |conn| database::users::is_admin(conn, id)).await {
+            Outcome::Success(Admin(auth))
+        } else {
+            Outcome::Error((Status::Forbidden, ()))
+        }
+    }
+}
+
 fn extract_auth_from_request(request: &Request, secret: &[u8]) -> Option<Auth> {
     request
         .headers()
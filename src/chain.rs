@@ -0,0 +1,317 @@
+//! Checks on-chain payments against an Ethereum JSON-RPC node. Buyers send
+//! ETH or USDC from the wallet on their profile to the platform wallet and
+//! hand in the transaction hash. The payment counts once the transaction
+//! succeeded, came from the buyer's wallet, pays the platform wallet exactly
+//! the amount due and is buried deep enough not to be reorganized away.
+//!
+//! `ETH_RPC_URL` and `PAYMENT_WALLET_ADDRESS` turn it on, `USDC_CONTRACT_ADDRESS`
+//! enables USDC and `ETH_MIN_CONFIRMATIONS` overrides the default depth.
+//! Without them on-chain payments are refused.
+
+use crate::money::{Currency, Money};
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+
+/// Blocks on top of the transaction's, itself included, when
+/// `ETH_MIN_CONFIRMATIONS` isn't set.
+const DEFAULT_MIN_CONFIRMATIONS: u64 = 12;
+
+/// keccak256("Transfer(address,address,uint256)"), the first topic of every
+/// ERC-20 transfer log.
+const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+
+#[derive(Debug)]
+pub enum VerifyError {
+    /// The node doesn't know the transaction.
+    NotFound,
+    /// Still pending, or not yet buried under enough blocks.
+    Unconfirmed,
+    /// Reverted, or doesn't pay the wallet the amount due.
+    Mismatch,
+    /// Paid from a wallet other than the buyer's.
+    WrongSender,
+    /// The amount due is in a currency that isn't paid on chain.
+    UnsupportedCurrency,
+    /// The node couldn't be asked.
+    Unavailable(String),
+}
+
+/// A transaction hash as stored: `0x` and 64 lowercase hex digits. `None`
+/// for anything else.
+pub fn normalize_tx_hash(hash: &str) -> Option<String> {
+    let hash = hash.trim().to_lowercase();
+    let digits = hash.strip_prefix("0x")?;
+    if digits.len() != 64 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(hash)
+}
+
+pub struct ChainVerifier {
+    url: String,
+    /// Lowercase, `0x` prefixed.
+    wallet: String,
+    usdc_contract: Option<String>,
+    min_confirmations: u64,
+    client: reqwest::blocking::Client,
+}
+
+impl ChainVerifier {
+    pub fn new(
+        url: String,
+        wallet: &str,
+        usdc_contract: Option<&str>,
+        min_confirmations: u64,
+    ) -> Result<ChainVerifier, String> {
+        let wallet = normalize_address(wallet)
+            .ok_or_else(|| format!("invalid wallet address {:?}", wallet))?;
+        let usdc_contract = match usdc_contract {
+            Some(contract) => Some(
+                normalize_address(contract)
+                    .ok_or_else(|| format!("invalid USDC contract address {:?}", contract))?,
+            ),
+            None => None,
+        };
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok(ChainVerifier {
+            url,
+            wallet,
+            usdc_contract,
+            min_confirmations: min_confirmations.max(1),
+            client,
+        })
+    }
+
+    /// Check that `tx_hash` pays `expected` from `sender` to the wallet and is
+    /// confirmed.
+    pub fn verify(&self, tx_hash: &str, sender: &str, expected: Money) -> Result<(), VerifyError> {
+        let sender = normalize_address(sender).ok_or(VerifyError::WrongSender)?;
+        let expected_units =
+            u128::try_from(expected.amount.minor_units()).map_err(|_| VerifyError::Mismatch)?;
+
+        let transaction = self.call("eth_getTransactionByHash", json!([tx_hash]))?;
+        if transaction.is_null() {
+            return Err(VerifyError::NotFound);
+        }
+        let receipt = self.call("eth_getTransactionReceipt", json!([tx_hash]))?;
+        if receipt.is_null() {
+            return Err(VerifyError::Unconfirmed);
+        }
+        if receipt.get("status").and_then(Value::as_str) != Some("0x1") {
+            return Err(VerifyError::Mismatch);
+        }
+
+        let paid = match expected.currency {
+            Currency::Eth => {
+                if address_field(&transaction, "from").as_deref() != Some(sender.as_str()) {
+                    return Err(VerifyError::WrongSender);
+                }
+                address_field(&transaction, "to").as_deref() == Some(self.wallet.as_str())
+                    && quantity_field(&transaction, "value") == Some(expected_units)
+            }
+            Currency::Usdc => {
+                let contract = self
+                    .usdc_contract
+                    .as_deref()
+                    .ok_or(VerifyError::UnsupportedCurrency)?;
+                let logs = receipt.get("logs").and_then(Value::as_array);
+                let transfers = logs
+                    .into_iter()
+                    .flatten()
+                    .filter(|log| self.is_transfer_to_wallet(log, contract, expected_units));
+                let mut senders = transfers.map(transfer_sender).peekable();
+                if senders.peek().is_none() {
+                    return Err(VerifyError::Mismatch);
+                }
+                if !senders.any(|from| from.as_deref() == Some(sender.as_str())) {
+                    return Err(VerifyError::WrongSender);
+                }
+                true
+            }
+            Currency::Tzs | Currency::Usd => return Err(VerifyError::UnsupportedCurrency),
+        };
+        if !paid {
+            return Err(VerifyError::Mismatch);
+        }
+
+        let mined_in = quantity_field(&receipt, "blockNumber").ok_or(VerifyError::Unconfirmed)?;
+        let head = quantity(&self.call("eth_blockNumber", json!([]))?).ok_or_else(|| {
+            VerifyError::Unavailable("eth_blockNumber returned no number".to_string())
+        })?;
+        if head.saturating_sub(mined_in) + 1 < self.min_confirmations as u128 {
+            return Err(VerifyError::Unconfirmed);
+        }
+        Ok(())
+    }
+
+    /// An ERC-20 `Transfer` log of `contract` moving `units` to the wallet.
+    fn is_transfer_to_wallet(&self, log: &Value, contract: &str, units: u128) -> bool {
+        let topics = match log.get("topics").and_then(Value::as_array) {
+            Some(topics) if topics.len() == 3 => topics,
+            _ => return false,
+        };
+        address_field(log, "address").as_deref() == Some(contract)
+            && topics[0].as_str().map(str::to_lowercase).as_deref() == Some(TRANSFER_TOPIC)
+            && topics[2].as_str().and_then(topic_address).as_deref() == Some(self.wallet.as_str())
+            && quantity_field(log, "data") == Some(units)
+    }
+
+    /// The `result` of a JSON-RPC call.
+    fn call(&self, method: &str, params: Value) -> Result<Value, VerifyError> {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|err| VerifyError::Unavailable(err.to_string()))?;
+        let response: Value = serde_json::from_str(&response)
+            .map_err(|err| VerifyError::Unavailable(err.to_string()))?;
+        if let Some(error) = response.get("error") {
+            return Err(VerifyError::Unavailable(format!("{}: {}", method, error)));
+        }
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+}
+
+/// The address an ERC-20 `Transfer` log moves tokens from.
+fn transfer_sender(log: &Value) -> Option<String> {
+    log.get("topics")?.get(1)?.as_str().and_then(topic_address)
+}
+
+/// `0x` and 40 hex digits, lowercased.
+fn normalize_address(address: &str) -> Option<String> {
+    let address = address.trim().to_lowercase();
+    let digits = address.strip_prefix("0x")?;
+    if digits.len() != 40 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(address)
+}
+
+fn address_field(object: &Value, field: &str) -> Option<String> {
+    object
+        .get(field)
+        .and_then(Value::as_str)
+        .and_then(normalize_address)
+}
+
+/// The address in a 32-byte log topic, which is left padded with zeros.
+fn topic_address(topic: &str) -> Option<String> {
+    let digits = topic.strip_prefix("0x")?;
+    if digits.len() != 64 || !digits[..24].bytes().all(|byte| byte == b'0') {
+        return None;
+    }
+    normalize_address(&format!("0x{}", &digits[24..]))
+}
+
+/// A hex quantity or 32-byte word as a number, `None` if it doesn't fit.
+fn quantity(value: &Value) -> Option<u128> {
+    let digits = value.as_str()?.strip_prefix("0x")?.trim_start_matches('0');
+    if digits.is_empty() {
+        return Some(0);
+    }
+    if digits.len() > 32 {
+        return None;
+    }
+    u128::from_str_radix(digits, 16).ok()
+}
+
+fn quantity_field(object: &Value, field: &str) -> Option<u128> {
+    object.get(field).and_then(quantity)
+}
+
+/// The configured verifier, if on-chain payments are turned on.
+pub fn verifier() -> Result<Option<ChainVerifier>, String> {
+    let (url, wallet) = match (env::var("ETH_RPC_URL"), env::var("PAYMENT_WALLET_ADDRESS")) {
+        (Ok(url), Ok(wallet)) => (url, wallet),
+        _ => return Ok(None),
+    };
+    let usdc_contract = env::var("USDC_CONTRACT_ADDRESS").ok();
+    let min_confirmations = match env::var("ETH_MIN_CONFIRMATIONS") {
+        Ok(count) => count
+            .parse::<u64>()
+            .map_err(|_| format!("invalid ETH_MIN_CONFIRMATIONS {:?}", count))?,
+        Err(_) => DEFAULT_MIN_CONFIRMATIONS,
+    };
+    ChainVerifier::new(url, &wallet, usdc_contract.as_deref(), min_confirmations).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLET: &str = "0x1111111111111111111111111111111111111111";
+
+    #[test]
+    fn normalizes_transaction_hashes() {
+        let hash = format!("0x{}", "AB".repeat(32));
+        assert_eq!(
+            normalize_tx_hash(&format!(" {} ", hash)),
+            Some(hash.to_lowercase())
+        );
+        assert_eq!(normalize_tx_hash(&"ab".repeat(32)), None);
+        assert_eq!(normalize_tx_hash("0x1234"), None);
+        assert_eq!(normalize_tx_hash(&format!("0x{}", "zz".repeat(32))), None);
+    }
+
+    #[test]
+    fn reads_quantities() {
+        assert_eq!(quantity(&json!("0x0")), Some(0));
+        assert_eq!(
+            quantity(&json!("0x2386f26fc10000")),
+            Some(10_000_000_000_000_000)
+        );
+        assert_eq!(
+            quantity(&json!(format!("0x{:0>64}", "f4240"))),
+            Some(1_000_000)
+        );
+        assert_eq!(quantity(&json!(format!("0x1{}", "0".repeat(32)))), None);
+        assert_eq!(quantity(&json!(12)), None);
+    }
+
+    #[test]
+    fn reads_addresses_from_topics() {
+        let topic = format!("0x{}{}", "0".repeat(24), &WALLET[2..]);
+        assert_eq!(topic_address(&topic).as_deref(), Some(WALLET));
+        let dirty = format!("0x{}{}", "1".repeat(24), &WALLET[2..]);
+        assert_eq!(topic_address(&dirty), None);
+    }
+
+    #[test]
+    fn matches_usdc_transfers_to_the_wallet() {
+        let contract = "0x2222222222222222222222222222222222222222";
+        let verifier =
+            ChainVerifier::new("http://localhost".to_string(), WALLET, Some(contract), 12).unwrap();
+        let log = |to: &str, units: u64| {
+            json!({
+                "address": contract,
+                "topics": [
+                    TRANSFER_TOPIC,
+                    format!("0x{}{}", "0".repeat(24), "3".repeat(40)),
+                    format!("0x{}{}", "0".repeat(24), &to[2..]),
+                ],
+                "data": format!("0x{:064x}", units),
+            })
+        };
+
+        assert!(verifier.is_transfer_to_wallet(&log(WALLET, 5_000_000), contract, 5_000_000));
+        assert!(!verifier.is_transfer_to_wallet(&log(WALLET, 4_999_999), contract, 5_000_000));
+        assert!(!verifier.is_transfer_to_wallet(
+            &log("0x3333333333333333333333333333333333333333", 5_000_000),
+            contract,
+            5_000_000
+        ));
+        assert_eq!(
+            transfer_sender(&log(WALLET, 5_000_000)),
+            Some(format!("0x{}", "3".repeat(40)))
+        );
+    }
+}
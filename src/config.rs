@@ -12,6 +12,10 @@ pub const DATE_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S%.3fZ";
 
 pub const TOKEN_PREFIX: &'static str = "Token ";
 
+/// Platform fee on ticket sales when `PLATFORM_FEE_BPS` isn't set, in
+/// hundredths of a percent.
+const DEFAULT_PLATFORM_FEE_BPS: i32 = 500;
+
 pub struct AppState {
     pub secret: Vec<u8>,
 }
@@ -35,6 +39,25 @@ impl AppState {
     }
 }
 
+/// Share of each sale the platform keeps, in hundredths of a percent.
+pub fn platform_fee_bps() -> i32 {
+    env::var("PLATFORM_FEE_BPS")
+        .ok()
+        .and_then(|bps| bps.parse::<i32>().ok())
+        .filter(|bps| (0..=10_000).contains(bps))
+        .unwrap_or(DEFAULT_PLATFORM_FEE_BPS)
+}
+
+/// Tax included in ticket prices, in hundredths of a percent. Shown on
+/// receipts; prices are not changed by it.
+pub fn tax_rate_bps() -> i32 {
+    env::var("TAX_RATE_BPS")
+        .ok()
+        .and_then(|bps| bps.parse::<i32>().ok())
+        .filter(|bps| (0..=10_000).contains(bps))
+        .unwrap_or(0)
+}
+
 /// Create rocket config from environment variables
 pub fn from_env() -> Figment {
     let port = env::var("PORT")
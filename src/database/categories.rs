@@ -0,0 +1,246 @@
+use crate::database::events::EventStatus;
+use crate::models::categories::{Category, CategoryNode};
+use crate::schema::{categories, event_categories, events};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Insertable)]
+#[diesel(table_name = categories)]
+pub struct NewCategory<'a> {
+    pub parent_id: Option<i32>,
+    pub slug: &'a str,
+    pub name: &'a str,
+    pub position: i32,
+}
+
+pub enum CategoryError {
+    DuplicatedSlug,
+    InvalidSlug,
+    NonExistParent,
+    CyclicParent,
+    InUse,
+    NotFound,
+    Other,
+}
+
+impl From<Error> for CategoryError {
+    fn from(err: Error) -> CategoryError {
+        match &err {
+            Error::NotFound => return CategoryError::NotFound,
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                if let Some("categories_slug_key") = info.constraint_name() {
+                    return CategoryError::DuplicatedSlug;
+                }
+            }
+            Error::DatabaseError(DatabaseErrorKind::CheckViolation, info) => {
+                if let Some("categories_slug_check") = info.constraint_name() {
+                    return CategoryError::InvalidSlug;
+                }
+            }
+            Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                if let Some("categories_parent_id_fkey") = info.constraint_name() {
+                    return CategoryError::NonExistParent;
+                }
+            }
+            _ => {}
+        }
+        CategoryError::Other
+    }
+}
+
+pub fn create(conn: &mut PgConnection, new_category: &NewCategory) -> Result<Category, CategoryError> {
+    diesel::insert_into(categories::table)
+        .values(new_category)
+        .returning(Category::as_returning())
+        .get_result(conn)
+        .map_err(Into::into)
+}
+
+/// Tells a `null` field apart from a missing one: `Some(None)` clears the
+/// column, `None` leaves it alone.
+fn nullable<'de, D>(deserializer: D) -> Result<Option<Option<i32>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<i32>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, AsChangeset, Default, Clone)]
+#[diesel(table_name = categories)]
+pub struct UpdateCategoryData {
+    /// `null` moves the category to the top level.
+    #[serde(default, deserialize_with = "nullable")]
+    pub parent_id: Option<Option<i32>>,
+    slug: Option<String>,
+    name: Option<String>,
+    position: Option<i32>,
+}
+
+pub fn update(
+    conn: &mut PgConnection,
+    id: i32,
+    data: &UpdateCategoryData,
+) -> Result<Category, CategoryError> {
+    conn.transaction(|conn| {
+        if let Some(Some(parent_id)) = data.parent_id {
+            let all = categories::table
+                .select(Category::as_select())
+                .load::<Category>(conn)?;
+            if descendants(&all, id).contains(&parent_id) {
+                return Err(CategoryError::CyclicParent);
+            }
+        }
+
+        let category = diesel::update(categories::table.find(id))
+            .set(data)
+            .returning(Category::as_returning())
+            .get_result(conn)?;
+        Ok(category)
+    })
+}
+
+/// Categories that still have events or subcategories can't be deleted.
+pub fn delete(conn: &mut PgConnection, id: i32) -> Result<usize, CategoryError> {
+    let linked = event_categories::table
+        .filter(event_categories::category_id.eq(id))
+        .count()
+        .get_result::<i64>(conn)?;
+    if linked > 0 {
+        return Err(CategoryError::InUse);
+    }
+
+    let deleted = diesel::delete(categories::table.find(id))
+        .execute(conn)
+        .map_err(|err| match err {
+            Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => CategoryError::InUse,
+            err => err.into(),
+        })?;
+    if deleted == 0 {
+        return Err(CategoryError::NotFound);
+    }
+    Ok(deleted)
+}
+
+/// `id` itself plus every category below it.
+fn descendants(all: &[Category], id: i32) -> Vec<i32> {
+    let mut found = vec![id];
+    let mut index = 0;
+    while index < found.len() {
+        let parent = found[index];
+        found.extend(
+            all.iter()
+                .filter(|category| category.parent_id == Some(parent))
+                .map(|category| category.id),
+        );
+        index += 1;
+    }
+    found
+}
+
+/// Ids of the category with this slug and all of its subcategories, so that
+/// filtering by "music" also matches events filed under "music/jazz".
+pub fn descendant_ids(conn: &mut PgConnection, slug: &str) -> QueryResult<Vec<i32>> {
+    let all = categories::table
+        .select(Category::as_select())
+        .load::<Category>(conn)?;
+
+    Ok(match all.iter().find(|category| category.slug == slug) {
+        Some(category) => descendants(&all, category.id),
+        None => Vec::new(),
+    })
+}
+
+/// Full category tree with public event counts rolled up into parents. An
+/// event filed under a category and one of its subcategories counts once.
+pub fn get_tree(conn: &mut PgConnection) -> QueryResult<Vec<CategoryNode>> {
+    let all = categories::table
+        .order((categories::position.asc(), categories::name.asc()))
+        .select(Category::as_select())
+        .load::<Category>(conn)?;
+
+    let mut events_by_category: HashMap<i32, HashSet<i32>> = HashMap::new();
+    for (category_id, event_id) in event_categories::table
+        .inner_join(events::table)
+        .filter(events::status.ne(EventStatus::Draft))
+        .select((event_categories::category_id, event_categories::event_id))
+        .load::<(i32, i32)>(conn)?
+    {
+        events_by_category.entry(category_id).or_default().insert(event_id);
+    }
+
+    /// Nodes below `parent`, and the events filed anywhere under them.
+    fn build(
+        all: &[Category],
+        events_by_category: &HashMap<i32, HashSet<i32>>,
+        parent: Option<i32>,
+    ) -> (Vec<CategoryNode>, HashSet<i32>) {
+        let mut level_events = HashSet::new();
+        let nodes = all
+            .iter()
+            .filter(|category| category.parent_id == parent)
+            .map(|category| {
+                let (children, mut subtree_events) =
+                    build(all, events_by_category, Some(category.id));
+                if let Some(own) = events_by_category.get(&category.id) {
+                    subtree_events.extend(own);
+                }
+                level_events.extend(&subtree_events);
+                CategoryNode {
+                    category: category.clone(),
+                    event_count: subtree_events.len() as i64,
+                    children,
+                }
+            })
+            .collect();
+        (nodes, level_events)
+    }
+
+    Ok(build(&all, &events_by_category, None).0)
+}
+
+/// Replace the categories of an event.
+pub fn set_event_categories(
+    conn: &mut PgConnection,
+    event_id: i32,
+    category_ids: &[i32],
+) -> QueryResult<()> {
+    diesel::delete(event_categories::table.filter(event_categories::event_id.eq(event_id)))
+        .execute(conn)?;
+
+    let rows = category_ids
+        .iter()
+        .map(|category_id| {
+            (
+                event_categories::event_id.eq(event_id),
+                event_categories::category_id.eq(*category_id),
+            )
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(event_categories::table)
+        .values(&rows)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Categories of each of the given events, keyed by event id.
+pub fn for_events(
+    conn: &mut PgConnection,
+    event_ids: &[i32],
+) -> QueryResult<HashMap<i32, Vec<Category>>> {
+    let rows = event_categories::table
+        .inner_join(categories::table)
+        .filter(event_categories::event_id.eq_any(event_ids))
+        .order((categories::position.asc(), categories::name.asc()))
+        .select((event_categories::event_id, Category::as_select()))
+        .load::<(i32, Category)>(conn)?;
+
+    let mut by_event: HashMap<i32, Vec<Category>> = HashMap::new();
+    for (event_id, category) in rows {
+        by_event.entry(event_id).or_default().push(category);
+    }
+    Ok(by_event)
+}
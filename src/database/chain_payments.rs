@@ -0,0 +1,198 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::models::chain_payments::ChainPayment;
+use crate::money::{Amount, Currency};
+use crate::schema::chain_payments;
+use chrono::Utc;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use rocket::form::FromFormField;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainPaymentStatus {
+    /// Claimed, and the order or listing is being paid with it.
+    Pending,
+    Settled,
+    /// The money arrived but the order or listing couldn't be paid any more;
+    /// it is owed back to the sender.
+    Unmatched,
+    /// The money of an unmatched payment went back to the sender.
+    Refunded,
+}
+
+impl ChainPaymentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChainPaymentStatus::Pending => "pending",
+            ChainPaymentStatus::Settled => "settled",
+            ChainPaymentStatus::Unmatched => "unmatched",
+            ChainPaymentStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl FromStr for ChainPaymentStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "pending" => Ok(ChainPaymentStatus::Pending),
+            "settled" => Ok(ChainPaymentStatus::Settled),
+            "unmatched" => Ok(ChainPaymentStatus::Unmatched),
+            "refunded" => Ok(ChainPaymentStatus::Refunded),
+            _ => Err(()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for ChainPaymentStatus {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation(
+                "invalid chain payment status",
+            ))
+        })
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for ChainPaymentStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for ChainPaymentStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = chain_payments)]
+pub struct NewChainPayment<'a> {
+    pub tx_hash: &'a str,
+    pub order_id: Option<i32>,
+    pub listing_id: Option<i32>,
+    pub user_id: i32,
+    pub sender: &'a str,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub status: ChainPaymentStatus,
+}
+
+pub enum ChainPaymentError {
+    NotFound,
+    InvalidStatus,
+    Other,
+}
+
+impl From<Error> for ChainPaymentError {
+    fn from(err: Error) -> ChainPaymentError {
+        match err {
+            Error::NotFound => ChainPaymentError::NotFound,
+            _ => ChainPaymentError::Other,
+        }
+    }
+}
+
+/// Claim a transaction for one order or listing, committed before anything
+/// is paid with it so that the money is accounted for whatever happens next.
+/// `false` if it is claimed already, unless by a pending claim for the same
+/// order or listing: an earlier attempt that didn't get to finish.
+pub fn claim(conn: &mut PgConnection, payment: &NewChainPayment) -> QueryResult<bool> {
+    let inserted = diesel::insert_into(chain_payments::table)
+        .values(payment)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    if inserted == 1 {
+        return Ok(true);
+    }
+    let claimed = chain_payments::table
+        .find(payment.tx_hash)
+        .select(ChainPayment::as_select())
+        .get_result::<ChainPayment>(conn)?;
+    Ok(claimed.status == ChainPaymentStatus::Pending
+        && claimed.order_id == payment.order_id
+        && claimed.listing_id == payment.listing_id)
+}
+
+/// Lock a pending claim while its order or listing is being paid. `None` if
+/// it isn't pending any more.
+pub fn lock_pending(conn: &mut PgConnection, tx_hash: &str) -> QueryResult<Option<ChainPayment>> {
+    chain_payments::table
+        .find(tx_hash)
+        .filter(chain_payments::status.eq(ChainPaymentStatus::Pending))
+        .select(ChainPayment::as_select())
+        .for_update()
+        .get_result(conn)
+        .optional()
+}
+
+/// Settle a pending claim, or leave its money owed back as unmatched.
+pub fn complete(
+    conn: &mut PgConnection,
+    tx_hash: &str,
+    status: ChainPaymentStatus,
+) -> QueryResult<ChainPayment> {
+    diesel::update(chain_payments::table.find(tx_hash))
+        .filter(chain_payments::status.eq(ChainPaymentStatus::Pending))
+        .set(chain_payments::status.eq(status))
+        .returning(ChainPayment::as_returning())
+        .get_result(conn)
+}
+
+pub fn list(
+    conn: &mut PgConnection,
+    status: Option<ChainPaymentStatus>,
+) -> QueryResult<Vec<ChainPayment>> {
+    let mut query = chain_payments::table.into_boxed();
+    if let Some(status) = status {
+        query = query.filter(chain_payments::status.eq(status));
+    }
+    query
+        .order(chain_payments::created_at.asc())
+        .select(ChainPayment::as_select())
+        .load(conn)
+}
+
+/// Record that the money of an unmatched payment has been sent back to its
+/// sender in the transaction `refund_reference`.
+pub fn mark_refunded(
+    conn: &mut PgConnection,
+    tx_hash: &str,
+    refund_reference: &str,
+) -> Result<ChainPayment, ChainPaymentError> {
+    conn.transaction(|conn| {
+        let payment = chain_payments::table
+            .find(tx_hash)
+            .select(ChainPayment::as_select())
+            .for_update()
+            .get_result::<ChainPayment>(conn)?;
+        if payment.status != ChainPaymentStatus::Unmatched {
+            return Err(ChainPaymentError::InvalidStatus);
+        }
+
+        Ok(diesel::update(chain_payments::table.find(tx_hash))
+            .set((
+                chain_payments::status.eq(ChainPaymentStatus::Refunded),
+                chain_payments::refund_reference.eq(refund_reference),
+                chain_payments::refunded_at.eq(Utc::now()),
+            ))
+            .returning(ChainPayment::as_returning())
+            .get_result(conn)?)
+    })
+}
@@ -1,123 +1,173 @@
-use crate::models::events::{Event, EventFiltering};
-use crate::schema::events;
-use chrono::{NaiveDate, NaiveDateTime};
+use crate::models::events::{
+    Event, EventDetails, EventFacets, EventFiltering, EventSearchResult, EventView, FacetCount,
+    NearbyEvent, PriceBand,
+};
+use crate::database::feed::{self, FeedKind};
+use crate::database::notifications::{self, NotificationKind};
+use crate::database::rates::RateTable;
+use crate::database::{categories, refunds, tags};
+use crate::models::categories::Category;
+use crate::models::series::EventSeries;
+use crate::models::tags::Tag;
+use crate::models::venues::Venue;
+use crate::money::{Amount, Currency, Money};
+use crate::schema::{event_categories, event_tags, events, venues};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use diesel::deserialize::{self, FromSql, FromSqlRow};
-use diesel::dsl::exists;
-use diesel::dsl::select;
+use diesel::dsl::{count_star, exists, InnerJoin, IntoBoxed};
 use diesel::expression::AsExpression;
-use diesel::pg::PgConnection;
-use diesel::result::Error;
+use diesel::pg::{Pg, PgConnection};
+use diesel::result::{DatabaseErrorKind, Error};
 use diesel::serialize::{IsNull, Output, ToSql};
-use diesel::sql_types::Text;
+use diesel::dsl::sql;
+use diesel::sql_types::{BigInt, Bool, Date, Text};
 use diesel::{prelude::*, serialize};
 use rocket::form::FromFormField;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::Write;
 use std::str::FromStr;
 
+/// Lifecycle of an event. New events start as drafts and only become
+/// visible to the public once published.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
 #[diesel(sql_type = Text)]
-#[serde(rename_all = "PascalCase")]
-pub enum EventType {
-    Music,
-    Games,
-    Performing,
-    Movies,
-    Tour,
+#[serde(rename_all = "lowercase")]
+pub enum EventStatus {
+    Draft,
+    Published,
+    Cancelled,
+    Postponed,
+    Completed,
 }
 
-impl FromStr for EventType {
+impl EventStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventStatus::Draft => "draft",
+            EventStatus::Published => "published",
+            EventStatus::Cancelled => "cancelled",
+            EventStatus::Postponed => "postponed",
+            EventStatus::Completed => "completed",
+        }
+    }
+
+    /// draft -> published -> cancelled/postponed/completed, and a postponed
+    /// event can be published again once rescheduled or cancelled for good.
+    pub fn can_transition_to(self, next: EventStatus) -> bool {
+        use EventStatus::*;
+        matches!(
+            (self, next),
+            (Draft, Published)
+                | (Published, Cancelled)
+                | (Published, Postponed)
+                | (Published, Completed)
+                | (Postponed, Published)
+                | (Postponed, Cancelled)
+        )
+    }
+}
+
+impl FromStr for EventStatus {
     type Err = ();
 
     fn from_str(input: &str) -> Result<Self, Self::Err> {
         match input.to_lowercase().as_str() {
-            "music" => Ok(EventType::Music),
-            "games" => Ok(EventType::Games),
-            "performing" => Ok(EventType::Performing),
-            "movies" => Ok(EventType::Movies),
-            "tour" => Ok(EventType::Tour),
+            "draft" => Ok(EventStatus::Draft),
+            "published" => Ok(EventStatus::Published),
+            "cancelled" => Ok(EventStatus::Cancelled),
+            "postponed" => Ok(EventStatus::Postponed),
+            "completed" => Ok(EventStatus::Completed),
             _ => Err(()),
         }
     }
 }
 
 #[rocket::async_trait]
-impl<'v> FromFormField<'v> for EventType {
+impl<'v> FromFormField<'v> for EventStatus {
     fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
         field.value.parse::<Self>().map_err(|_| {
-            rocket::form::Errors::from(rocket::form::Error::validation("invalid event type"))
+            rocket::form::Errors::from(rocket::form::Error::validation("invalid event status"))
         })
     }
 }
 
-impl ToSql<Text, diesel::pg::Pg> for EventType {
+impl ToSql<Text, diesel::pg::Pg> for EventStatus {
     fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
-        let value = match self {
-            EventType::Music => "Music",
-            EventType::Games => "Games",
-            EventType::Performing => "Performing",
-            EventType::Movies => "Movies",
-            EventType::Tour => "Tour",
-        };
-        out.write_all(value.as_bytes())?;
+        out.write_all(self.as_str().as_bytes())?;
         Ok(IsNull::No)
     }
 }
 
-impl FromSql<Text, diesel::pg::Pg> for EventType {
+impl FromSql<Text, diesel::pg::Pg> for EventStatus {
     fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
-        match std::str::from_utf8(bytes.as_bytes())? {
-            "Music" => Ok(EventType::Music),
-            "Games" => Ok(EventType::Games),
-            "Performing" => Ok(EventType::Performing),
-            "Movies" => Ok(EventType::Movies),
-            "Tour" => Ok(EventType::Tour),
-            _ => Err("Unrecognized enum variant".into()),
-        }
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
     }
 }
 
 #[derive(Insertable)]
-#[table_name = "events"]
+#[diesel(table_name = events)]
 pub struct NewEvent<'a> {
     pub userid: i32,
     pub eventname: &'a str,
     pub eventdescription: &'a str,
-    pub eventdate: NaiveDate,
-    pub eventdatetime: NaiveDateTime,
-    pub eventtype: &'a EventType,
-    pub eventcountry: &'a str,
-    pub eventcity: &'a str,
-    pub eventplace: &'a str,
     pub eventimage: &'a str,
-    pub eventticketprice: i32,
+    pub eventticketprice: Amount,
+    pub currency: Currency,
+    pub venue_id: i32,
+    pub timezone: &'a str,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub capacity: Option<i32>,
+    pub series_id: Option<i32>,
+    pub recurrence_id: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Debug)]
 pub struct EventsLogged {
-    pub id: i32,
-    pub userid: i32,
-    pub eventname: String,
-    pub eventdescription: String,
-    pub eventdate: NaiveDate,
-    pub eventdatetime: NaiveDateTime,
-    pub eventtype: EventType,
-    pub eventcountry: String,
-    pub eventcity: String,
-    pub eventplace: String,
-    pub eventimage: String,
-    pub eventticketprice: i32,
+    #[serde(flatten)]
+    pub event: Event,
+    pub venue: Venue,
+    pub categories: Vec<Category>,
+    pub tags: Vec<Tag>,
+    pub likes_count: i64,
     pub eventliked: bool,
+    /// Ticket price in the currency the viewer asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_price: Option<Money>,
 }
 
 pub enum EventCreationError {
     NonExistUsername,
+    NonExistVenue,
+    NonExistCategory,
+    Other,
+}
+
+impl From<Error> for EventCreationError {
+    fn from(err: Error) -> EventCreationError {
+        if let Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) = &err {
+            match info.constraint_name() {
+                Some("events_userid_fkey") => return EventCreationError::NonExistUsername,
+                Some("events_venue_id_fkey") => return EventCreationError::NonExistVenue,
+                Some("event_categories_category_id_fkey") => {
+                    return EventCreationError::NonExistCategory
+                }
+                _ => {}
+            }
+        }
+        EventCreationError::Other
+    }
 }
 
 #[derive(Serialize)]
 pub enum EventResult {
     UserLogged(Vec<EventsLogged>),
-    UnLoggedUser(Vec<Event>),
+    UnLoggedUser(Vec<EventDetails>),
+    Series(Vec<EventSeries>),
 }
 
 pub fn create(
@@ -125,145 +175,413 @@ pub fn create(
     userid: i32,
     eventname: &str,
     eventdescription: &str,
-    eventdate: NaiveDate,
-    eventdatetime: NaiveDateTime,
-    eventtype: EventType,
-    eventcountry: &str,
-    eventcity: &str,
-    eventplace: &str,
+    category_ids: &[i32],
+    tag_names: &[String],
+    venue_id: i32,
     eventimage: &str,
-    eventticket_price: i32,
-) -> Result<Event, diesel::result::Error> {
+    eventticket_price: Amount,
+    currency: Currency,
+    timezone: &str,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    capacity: Option<i32>,
+) -> Result<Event, EventCreationError> {
     let new_event = &NewEvent {
         userid,
         eventname,
         eventdescription,
-        eventdate,
-        eventdatetime,
-        eventtype: &eventtype,
-        eventcountry,
-        eventcity,
-        eventplace,
         eventimage,
         eventticketprice: eventticket_price,
+        currency,
+        venue_id,
+        timezone,
+        starts_at,
+        ends_at,
+        capacity,
+        series_id: None,
+        recurrence_id: None,
     };
 
-    diesel::insert_into(events::table)
-        .values(new_event)
-        .get_result::<Event>(conn)
-        .map_err(Into::into)
+    conn.transaction(|conn| {
+        let event = diesel::insert_into(events::table)
+            .values(new_event)
+            .returning(Event::as_returning())
+            .get_result::<Event>(conn)?;
+        categories::set_event_categories(conn, event.id, category_ids)?;
+        tags::set_event_tags(conn, event.id, tag_names)?;
+        Ok(event)
+    })
 }
 
+/// Drafts are only returned to their organizer (`viewer`). Facets are
+/// counted over every matching event, `limit` and `offset` only apply to the
+/// list.
 pub fn get_events(
     conn: &mut PgConnection,
     filters: Option<EventFiltering>,
-) -> Result<Vec<EventResult>, diesel::result::Error> {
-    use crate::schema::events::dsl::*;
-    use crate::schema::likes::dsl::*;
+    viewer: Option<i32>,
+) -> Result<(Vec<EventResult>, EventFacets), diesel::result::Error> {
+    let display_in = match filters.as_ref().and_then(|f| f.currency) {
+        Some(wanted) => Some(wanted),
+        None => viewer
+            .map(|user_id| crate::database::users::preferred_currency(conn, user_id))
+            .transpose()?
+            .flatten(),
+    };
+    // Also needed to band prices in other currencies.
+    let rates = RateTable::load(conn)?;
+    let display_price = |event: &Event| -> Option<Money> {
+        rates.display(event.eventticketprice, event.currency, display_in?)
+    };
 
     if let Some(f) = filters {
-        let mut query = events.into_boxed();
-        if let Some(ref eventname_filter) = f.eventname {
-            query = query.filter(eventname.eq(eventname_filter));
-        }
-        if let Some(id_filter) = f.id {
-            query = query.filter(id.eq(id_filter));
-        }
-        if let Some(userid_filter) = f.userid {
-            query = query.filter(userid.eq(userid_filter));
+        if f.view == Some(EventView::Series) {
+            let window_start = f.from.as_deref().and_then(parse_window_bound);
+            let window_end = f.to.as_deref().and_then(parse_window_bound);
+            let series = crate::database::series::get_series(conn, window_start, window_end, viewer)?;
+            return Ok((vec![EventResult::Series(series)], EventFacets::default()));
         }
+
+        let category_ids = match f.category {
+            Some(ref category_filter) => Some(categories::descendant_ids(conn, category_filter)?),
+            None => None,
+        };
+        let matching = || filtered_events(&f, viewer, category_ids.as_deref(), &rates);
+
+        let mut query = matching().order(events::starts_at.asc());
         if let Some(limit_filter) = f.limit {
-            query = query.limit(limit_filter);
-        }
-        if let Some(ref eventcountry_filter) = f.eventcountry {
-            query = query.filter(eventcountry.eq(eventcountry_filter));
-        }
-        if let Some(ref eventcity_filter) = f.eventcity {
-            query = query.filter(eventcity.eq(eventcity_filter));
+            query = query.limit(limit_filter.max(0));
         }
-        if let Some(ref eventplace_filter) = f.eventplace {
-            query = query.filter(eventplace.eq(eventplace_filter));
+        if let Some(offset_filter) = f.offset {
+            query = query.offset(offset_filter.max(0));
         }
-        if let Some(ref eventdate_filter) = f.eventdate {
-            if let Ok(parsed_date) = NaiveDate::parse_from_str(eventdate_filter, "%Y-%m-%d") {
-                query = query.filter(eventdate.eq(parsed_date));
-            } else {
-                eprintln!("Invalid date format for eventdate_filter");
-            }
+        let result = query
+            .select((Event::as_select(), Venue::as_select()))
+            .load::<(Event, Venue)>(conn)?;
+        let facets = facet_counts(conn, matching, &rates)?;
+
+        let page_ids = result.iter().map(|(event, _)| event.id).collect::<Vec<_>>();
+        let mut categories_by_event = categories::for_events(conn, &page_ids)?;
+        let mut tags_by_event = tags::for_events(conn, &page_ids)?;
+        let likes_by_event = crate::database::likes::counts_for_events(conn, &page_ids)?;
+        let liked = crate::database::likes::liked_by(conn, viewer.or(f.logged_user), &page_ids)?;
+
+        let events_logged: Vec<EventsLogged> = result
+            .into_iter()
+            .map(|(event, venue)| EventsLogged {
+                categories: categories_by_event.remove(&event.id).unwrap_or_default(),
+                tags: tags_by_event.remove(&event.id).unwrap_or_default(),
+                likes_count: likes_by_event.get(&event.id).copied().unwrap_or(0),
+                eventliked: liked.contains(&event.id),
+                display_price: display_price(&event),
+                event,
+                venue,
+            })
+            .collect();
+
+        Ok((vec![EventResult::UserLogged(events_logged)], facets))
+    } else {
+        let f = EventFiltering::default();
+        let matching = || filtered_events(&f, viewer, None, &rates);
+
+        let result = matching()
+            .order(events::starts_at.asc())
+            .select((Event::as_select(), Venue::as_select()))
+            .load::<(Event, Venue)>(conn)?;
+        let facets = facet_counts(conn, matching, &rates)?;
+
+        let event_ids = result.iter().map(|(event, _)| event.id).collect::<Vec<_>>();
+        let mut categories_by_event = categories::for_events(conn, &event_ids)?;
+        let mut tags_by_event = tags::for_events(conn, &event_ids)?;
+        let likes_by_event = crate::database::likes::counts_for_events(conn, &event_ids)?;
+
+        let result = result
+            .into_iter()
+            .map(|(event, venue)| EventDetails {
+                categories: categories_by_event.remove(&event.id).unwrap_or_default(),
+                tags: tags_by_event.remove(&event.id).unwrap_or_default(),
+                likes_count: likes_by_event.get(&event.id).copied().unwrap_or(0),
+                display_price: display_price(&event),
+                event,
+                venue,
+            })
+            .collect();
+
+        Ok((vec![EventResult::UnLoggedUser(result)], facets))
+    }
+}
+
+type EventsWithVenues<'a> = IntoBoxed<'a, InnerJoin<events::table, venues::table>, Pg>;
+
+/// Events `viewer` may see, joined with their venues and narrowed by `f`.
+/// `category_ids` are the filtered category and its descendants. Built anew
+/// for the page and for each facet count so they all cover the same events.
+fn filtered_events<'a>(
+    f: &'a EventFiltering,
+    viewer: Option<i32>,
+    category_ids: Option<&'a [i32]>,
+    rates: &RateTable,
+) -> EventsWithVenues<'a> {
+    use crate::schema::events::dsl::*;
+
+    let mut query = events
+        .inner_join(venues::table)
+        .filter(status.ne(EventStatus::Draft).or(userid.nullable().eq(viewer)))
+        .into_boxed();
+    if let Some(window_start) = f.from.as_deref().and_then(parse_window_bound) {
+        query = query.filter(ends_at.gt(window_start));
+    }
+    if let Some(window_end) = f.to.as_deref().and_then(parse_window_bound) {
+        query = query.filter(starts_at.lt(window_end));
+    }
+    if let Some(series_id_filter) = f.series_id {
+        query = query.filter(series_id.eq(series_id_filter));
+    }
+    if let Some(ref eventname_filter) = f.eventname {
+        query = query.filter(eventname.eq(eventname_filter));
+    }
+    if let Some(id_filter) = f.id {
+        query = query.filter(id.eq(id_filter));
+    }
+    if let Some(userid_filter) = f.userid {
+        query = query.filter(userid.eq(userid_filter));
+    }
+    if let Some(ref eventcountry_filter) = f.eventcountry {
+        query = query.filter(venues::country.eq(eventcountry_filter));
+    }
+    if let Some(ref eventcity_filter) = f.eventcity {
+        query = query.filter(venues::city.eq(eventcity_filter));
+    }
+    if let Some(ref eventplace_filter) = f.eventplace {
+        query = query.filter(venues::name.eq(eventplace_filter));
+    }
+    if let Some(venue_id_filter) = f.venue_id {
+        query = query.filter(venue_id.eq(venue_id_filter));
+    }
+    if let Some(ref eventdate_filter) = f.eventdate {
+        if let Ok(parsed_date) = NaiveDate::parse_from_str(eventdate_filter, "%Y-%m-%d") {
+            // Match on the calendar day where the event takes place.
+            query = query.filter(
+                sql::<Bool>("(events.starts_at AT TIME ZONE events.timezone)::date = ")
+                    .bind::<Date, _>(parsed_date),
+            );
+        } else {
+            eprintln!("Invalid date format for eventdate_filter");
         }
-        if let Some(ref eventtype_filter) = f.eventtype {
-            query = query.filter(eventtype.eq(eventtype_filter));
+    }
+    if let Some(category_ids) = category_ids {
+        query = query.filter(exists(
+            event_categories::table
+                .filter(event_categories::event_id.eq(id))
+                .filter(event_categories::category_id.eq_any(category_ids)),
+        ));
+    }
+    if let Some(ref tag_filter) = f.tag {
+        let tag_filter = tags::normalize(tag_filter).unwrap_or_default();
+        query = query.filter(exists(
+            event_tags::table
+                .inner_join(crate::schema::tags::table)
+                .filter(event_tags::event_id.eq(id))
+                .filter(crate::schema::tags::name.eq(tag_filter)),
+        ));
+    }
+    if let Some(price_band_filter) = f.price_band {
+        query = query.filter(sql::<Bool>(&price_band_filter.sql_condition(rates)));
+    }
+    if let Some(status_filter) = f.status {
+        query = query.filter(status.eq(status_filter));
+    }
+    query
+}
+
+/// Facet counts over every event `matching` returns, one GROUP BY each.
+fn facet_counts<'a>(
+    conn: &mut PgConnection,
+    matching: impl Fn() -> EventsWithVenues<'a>,
+    rates: &RateTable,
+) -> QueryResult<EventFacets> {
+    use crate::schema::{categories as categories_table, tags as tags_table};
+
+    let by_category = event_categories::table
+        .inner_join(categories_table::table)
+        .filter(event_categories::event_id.eq_any(matching().select(events::id)))
+        .group_by((categories_table::slug, categories_table::name))
+        .select((categories_table::slug, categories_table::name, count_star()))
+        .load::<(String, String, i64)>(conn)?;
+    let by_tag = event_tags::table
+        .inner_join(tags_table::table)
+        .filter(event_tags::event_id.eq_any(matching().select(events::id)))
+        .group_by(tags_table::name)
+        .select((tags_table::name, tags_table::name, count_star()))
+        .load::<(String, String, i64)>(conn)?;
+    let by_city = venues::table
+        .inner_join(events::table)
+        .filter(events::id.eq_any(matching().select(events::id)))
+        .group_by(venues::city)
+        .select((venues::city, venues::city, count_star()))
+        .load::<(String, String, i64)>(conn)?;
+    // Bands depend on exchange rates, so prices are counted in SQL and
+    // banded here.
+    let by_price = events::table
+        .filter(events::id.eq_any(matching().select(events::id)))
+        .group_by((events::currency, events::eventticketprice))
+        .select((events::currency, events::eventticketprice, count_star()))
+        .load::<(Currency, Amount, i64)>(conn)?;
+
+    let mut by_price_band: HashMap<PriceBand, i64> = HashMap::new();
+    for (currency, price, count) in by_price {
+        // Prices in a currency without a rate can't be banded.
+        if let Some(band) = PriceBand::of(price, currency, rates) {
+            *by_price_band.entry(band).or_default() += count;
         }
+    }
 
-        let result = query.load::<Event>(conn)?;
+    // Most common values first, ties in alphabetical order.
+    fn ranked(counts: Vec<(String, String, i64)>) -> Vec<FacetCount> {
+        let mut facets = counts
+            .into_iter()
+            .map(|(value, label, count)| FacetCount { value, label, count })
+            .collect::<Vec<_>>();
+        facets.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.label.cmp(&b.label)));
+        facets
+    }
 
-        let events_logged: Vec<EventsLogged> = result
+    Ok(EventFacets {
+        categories: ranked(by_category),
+        cities: ranked(by_city),
+        tags: ranked(by_tag),
+        // Bands keep their natural order.
+        price_bands: PriceBand::ALL
             .into_iter()
-            .map(|event| {
-                let is_liked_query = select(exists(
-                    likes
-                        .filter(user_id.eq(event.userid))
-                        .filter(event_id.eq(event.id)),
-                ));
-
-                let is_event_liked = is_liked_query.get_result(conn)?;
-
-                Ok(EventsLogged {
-                    id: event.id,
-                    userid: event.userid,
-                    eventname: event.eventname,
-                    eventdescription: event.eventdescription,
-                    eventdate: event.eventdate,
-                    eventdatetime: event.eventdatetime,
-                    eventcountry: event.eventcountry,
-                    eventcity: event.eventcity,
-                    eventplace: event.eventplace,
-                    eventimage: event.eventimage,
-                    eventtype: event.eventtype,
-                    eventticketprice: event.eventticketprice,
-                    eventliked: is_event_liked,
+            .filter_map(|band| {
+                by_price_band.get(&band).map(|count| FacetCount {
+                    value: band.as_str().to_string(),
+                    label: band.label().to_string(),
+                    count: *count,
                 })
             })
-            .collect::<Result<Vec<EventsLogged>, diesel::result::Error>>()?;
+            .collect(),
+    })
+}
+
+/// Accepts an RFC 3339 timestamp or a plain date, read as midnight UTC.
+pub fn parse_window_bound(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+            Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+        })
+}
 
-        // let mut events_logged: Vec<EventsLogged> = Vec::new();
+/// Events at venues within `km` of (`lat`, `lng`), nearest first.
+///
+/// A bounding box on the indexed venue coordinates narrows the candidates
+/// before the exact haversine distance is computed.
+pub fn nearby(
+    conn: &mut PgConnection,
+    lat: f64,
+    lng: f64,
+    km: f64,
+    limit: Option<i64>,
+) -> Result<Vec<NearbyEvent>, diesel::result::Error> {
+    use crate::schema::venues;
+
+    let lat_delta = km / KM_PER_DEGREE;
+    let mut query = events::table
+        .inner_join(venues::table)
+        .filter(events::status.ne(EventStatus::Draft))
+        .filter(venues::latitude.between(lat - lat_delta, lat + lat_delta))
+        .into_boxed();
+
+    // Near the poles or across the antimeridian the longitude window wraps,
+    // so only the latitude band is used to prefilter there.
+    let lng_delta = km / (KM_PER_DEGREE * lat.to_radians().cos().abs().max(1e-6));
+    if lng - lng_delta >= -180.0 && lng + lng_delta <= 180.0 {
+        query = query.filter(venues::longitude.between(lng - lng_delta, lng + lng_delta));
+    }
 
+    let candidates = query
+        .select((Event::as_select(), Venue::as_select()))
+        .load::<(Event, Venue)>(conn)?;
+
+    let mut result: Vec<NearbyEvent> = candidates
+        .into_iter()
+        .filter_map(|(event, venue)| {
+            let distance_km = haversine_km(lat, lng, venue.latitude?, venue.longitude?);
+            (distance_km <= km).then(|| NearbyEvent {
+                event,
+                venue,
+                categories: Vec::new(),
+                tags: Vec::new(),
+                distance_km,
+            })
+        })
+        .collect();
 
-        // for event in result.into_iter() {
-        //     let is_liked_query = select(exists(
-        //         likes
-        //             .filter(user_id.eq(event.userid))
-        //             .filter(event_id.eq(event.id)),
-        //     ));
+    result.sort_by(|a, b| a.distance_km.total_cmp(&b.distance_km));
+    if let Some(limit) = limit {
+        result.truncate(limit.max(0) as usize);
+    }
 
+    let event_ids = result.iter().map(|nearby| nearby.event.id).collect::<Vec<_>>();
+    let mut categories_by_event = categories::for_events(conn, &event_ids)?;
+    let mut tags_by_event = tags::for_events(conn, &event_ids)?;
+    for nearby in result.iter_mut() {
+        nearby.categories = categories_by_event.remove(&nearby.event.id).unwrap_or_default();
+        nearby.tags = tags_by_event.remove(&nearby.event.id).unwrap_or_default();
+    }
 
-        //     let is_event_liked = is_liked_query.get_result(conn)?;
+    Ok(result)
+}
 
-        //     events_logged.push(EventsLogged {
-        //         userid: event.userid,
-        //         eventname: event.eventname,
-        //         eventdescription: event.eventdescription,
-        //         eventdate: event.eventdate,
-        //         eventdatetime: event.eventdatetime,
-        //         eventcountry: event.eventcountry,
-        //         eventcity: event.eventcity,
-        //         eventplace: event.eventplace,
-        //         eventimage: event.eventimage,
-        //         eventtype: event.eventtype,
-        //         eventticketprice: event.eventticketprice,
-        //         eventliked: is_event_liked,
-        //     });
-        // }
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE: f64 = 111.32;
 
-        // println!("Logged events {:#?}", events_logged);
+fn haversine_km(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lng = (lng2 - lng1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
 
-        // Ok(events_logged)
-        Ok(vec![EventResult::UserLogged(events_logged)])
-    } else {
-        return Ok(vec![EventResult::UnLoggedUser(
-            events.load::<Event>(conn)?,
-        )]);
-    }
+/// Default and maximum number of rows returned by `search`.
+pub const SEARCH_DEFAULT_LIMIT: i64 = 20;
+pub const SEARCH_MAX_LIMIT: i64 = 100;
+
+/// Full-text search over event name, venue and description.
+///
+/// `terms` is free text as typed by the user ("jazz dar es salaam") and is
+/// parsed with `websearch_to_tsquery`, so quoting and `-exclusions` work too.
+/// The snippet is HTML: the text is escaped before matches are wrapped in
+/// `<mark>`, so markup typed into a description is shown, not rendered.
+pub fn search(
+    conn: &mut PgConnection,
+    terms: &str,
+    limit: i64,
+) -> Result<Vec<EventSearchResult>, diesel::result::Error> {
+    diesel::sql_query(
+        "SELECT events.*, \
+                ts_rank_cd(events.search_vector, query) AS rank, \
+                ts_headline('english', \
+                    replace(replace(replace( \
+                        concat_ws(' - ', events.eventdescription, venues.name, venues.city), \
+                        '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), \
+                    query, \
+                    'StartSel=<mark>, StopSel=</mark>, MaxFragments=2, MaxWords=30, MinWords=10' \
+                ) AS snippet \
+         FROM events \
+         JOIN venues ON venues.id = events.venue_id, \
+         websearch_to_tsquery('english', $1) query \
+         WHERE events.search_vector @@ query AND events.status <> 'draft' \
+         ORDER BY rank DESC, events.starts_at ASC \
+         LIMIT $2",
+    )
+    .bind::<Text, _>(terms)
+    .bind::<BigInt, _>(limit.clamp(1, SEARCH_MAX_LIMIT))
+    .load::<EventSearchResult>(conn)
 }
 
 pub mod date_format {
@@ -291,34 +609,258 @@ pub mod date_format {
     }
 }
 
+/// Resolve a wall-clock time in `tz` to an instant. Times that are skipped or
+/// repeated by a DST change are rejected rather than guessed.
+pub fn local_to_utc(tz: &Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&local)
+        .single()
+        .map(|datetime| datetime.with_timezone(&Utc))
+}
+
+/// When an event takes place, in wall-clock time at the venue. Fields left
+/// out keep their current wall-clock value, so moving an event to another
+/// timezone keeps its local times and moves the instants.
+#[derive(Default, Clone, Copy)]
+pub struct ScheduleChange {
+    pub timezone: Option<Tz>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+}
+
+impl ScheduleChange {
+    fn is_empty(&self) -> bool {
+        self.timezone.is_none() && self.starts_at.is_none() && self.ends_at.is_none()
+    }
+}
+
 #[derive(Deserialize, AsChangeset, Default, Clone)]
-#[table_name = "events"]
+#[diesel(table_name = events)]
 pub struct UpdateEventData {
-    userid: Option<i32>,
     eventname: Option<String>,
     eventdescription: Option<String>,
-    eventdate: NaiveDate,
-    // #[serde(deserialize_with = "date_format")]
-    eventdatetime: NaiveDateTime,
-    eventtype: Option<EventType>,
-    eventcountry: Option<String>,
-    eventcity: Option<String>,
-    eventplace: Option<String>,
+    /// Set from the `ScheduleChange` of the update.
+    #[serde(skip_deserializing)]
+    timezone: Option<String>,
+    #[serde(skip_deserializing)]
+    starts_at: Option<DateTime<Utc>>,
+    #[serde(skip_deserializing)]
+    ends_at: Option<DateTime<Utc>>,
+    venue_id: Option<i32>,
     eventimage: Option<String>,
+    pub eventticketprice: Option<Amount>,
+    capacity: Option<i32>,
+    transfers_enabled: Option<bool>,
+    pub transfer_cutoff_hours: Option<i32>,
+    resale_enabled: Option<bool>,
+    pub resale_max_markup_percent: Option<i32>,
+    pub resale_royalty_percent: Option<i32>,
+    refund_full_until: Option<DateTime<Utc>>,
+    pub refund_partial_percent: Option<i32>,
+    pub refund_cutoff_hours: Option<i32>,
 }
 
-pub fn update(conn: &mut PgConnection, id: i32, data: &UpdateEventData) -> Option<Event> {
-    let new_event_data = &UpdateEventData { ..data.clone() };
-    diesel::update(events::table.find(id))
-        .set(new_event_data)
-        .get_result(conn)
-        .ok()
+pub enum EventUpdateError {
+    NotFound,
+    NotOwner,
+    /// The new start is skipped or repeated by a DST change.
+    InvalidStart,
+    InvalidEnd,
+    EndsBeforeStart,
+    NonExistVenue,
+    NonExistCategory,
+    Other,
+}
+
+impl From<Error> for EventUpdateError {
+    fn from(err: Error) -> EventUpdateError {
+        match &err {
+            Error::NotFound => EventUpdateError::NotFound,
+            Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                match info.constraint_name() {
+                    Some("events_venue_id_fkey") => EventUpdateError::NonExistVenue,
+                    Some("event_categories_category_id_fkey") => EventUpdateError::NonExistCategory,
+                    _ => EventUpdateError::Other,
+                }
+            }
+            _ => EventUpdateError::Other,
+        }
+    }
+}
+
+/// Only the organizer who created the event may edit it. Categories and
+/// tags are replaced when given, in the same transaction. Moving the event
+/// to another venue takes that venue's timezone unless one is given, keeping
+/// the wall-clock times. Price drops and capacity increases of published events are announced in the followers'
+/// feeds.
+pub fn update(
+    conn: &mut PgConnection,
+    id: i32,
+    organizer_id: i32,
+    data: &UpdateEventData,
+    mut schedule: ScheduleChange,
+    category_ids: Option<&[i32]>,
+    tag_names: Option<&[String]>,
+) -> Result<Event, EventUpdateError> {
+    conn.transaction(|conn| {
+        let before = events::table
+            .find(id)
+            .select(Event::as_select())
+            .for_update()
+            .get_result::<Event>(conn)?;
+        if before.userid != organizer_id {
+            return Err(EventUpdateError::NotOwner);
+        }
+
+        let new_venue_id = data
+            .venue_id
+            .filter(|venue_id| *venue_id != before.venue_id);
+        if let (Some(venue_id), None) = (new_venue_id, schedule.timezone) {
+            let venue =
+                crate::database::venues::find(conn, venue_id).map_err(|error| match error {
+                    Error::NotFound => EventUpdateError::NonExistVenue,
+                    error => error.into(),
+                })?;
+            let tz = venue.timezone.parse().map_err(|_| {
+                Error::DeserializationError(
+                    format!(
+                        "venue {} has an invalid timezone {:?}",
+                        venue.id, venue.timezone
+                    )
+                    .into(),
+                )
+            })?;
+            schedule.timezone = Some(tz);
+        }
+
+        let mut new_event_data = UpdateEventData { ..data.clone() };
+        if !schedule.is_empty() {
+            let current_tz = before.tz()?;
+            let tz = schedule.timezone.unwrap_or(current_tz);
+            let starts_at = schedule
+                .starts_at
+                .unwrap_or_else(|| before.starts_at.with_timezone(&current_tz).naive_local());
+            let ends_at = schedule
+                .ends_at
+                .unwrap_or_else(|| before.ends_at.with_timezone(&current_tz).naive_local());
+            let starts_at = local_to_utc(&tz, starts_at).ok_or(EventUpdateError::InvalidStart)?;
+            let ends_at = local_to_utc(&tz, ends_at).ok_or(EventUpdateError::InvalidEnd)?;
+            if ends_at <= starts_at {
+                return Err(EventUpdateError::EndsBeforeStart);
+            }
+            new_event_data.timezone = Some(tz.name().to_string());
+            new_event_data.starts_at = Some(starts_at);
+            new_event_data.ends_at = Some(ends_at);
+        }
+
+        let event = diesel::update(events::table.find(id))
+            .set(&new_event_data)
+            .returning(Event::as_returning())
+            .get_result::<Event>(conn)?;
+        if let Some(category_ids) = category_ids {
+            categories::set_event_categories(conn, event.id, category_ids)?;
+        }
+        if let Some(tag_names) = tag_names {
+            tags::set_event_tags(conn, event.id, tag_names)?;
+        }
+
+        if event.status == EventStatus::Published {
+            if event.eventticketprice < before.eventticketprice {
+                feed::record(
+                    conn,
+                    event.id,
+                    FeedKind::PriceDrop,
+                    Some(before.eventticketprice),
+                    Some(event.eventticketprice),
+                )?;
+            }
+            if let (Some(old_capacity), Some(new_capacity)) = (before.capacity, event.capacity) {
+                if new_capacity > old_capacity {
+                    feed::record(conn, event.id, FeedKind::TicketsReleased, None, None)?;
+                }
+            }
+        }
+        Ok(event)
+    })
+}
+
+pub enum EventTransitionError {
+    NotFound,
+    NotOwner,
+    InvalidTransition(EventStatus, EventStatus),
+    Other,
+}
+
+impl From<Error> for EventTransitionError {
+    fn from(err: Error) -> EventTransitionError {
+        match err {
+            Error::NotFound => EventTransitionError::NotFound,
+            _ => EventTransitionError::Other,
+        }
+    }
+}
+
+/// Move an event to `next`, enforcing the lifecycle in `EventStatus`.
+/// Only the organizer who created the event may change its status.
+pub fn transition(
+    conn: &mut PgConnection,
+    id: i32,
+    organizer_id: i32,
+    next: EventStatus,
+) -> Result<Event, EventTransitionError> {
+    conn.transaction(|conn| {
+        let event = events::table
+            .find(id)
+            .select(Event::as_select())
+            .for_update()
+            .get_result::<Event>(conn)?;
+
+        if event.userid != organizer_id {
+            return Err(EventTransitionError::NotOwner);
+        }
+        if !event.status.can_transition_to(next) {
+            return Err(EventTransitionError::InvalidTransition(event.status, next));
+        }
+
+        let previous = event.status;
+        let event = diesel::update(events::table.find(id))
+            .set(events::status.eq(next))
+            .returning(Event::as_returning())
+            .get_result::<Event>(conn)?;
+
+        if previous == EventStatus::Draft && next == EventStatus::Published {
+            feed::record(conn, event.id, FeedKind::EventPublished, None, None)?;
+        }
+        if next == EventStatus::Cancelled {
+            on_cancelled(conn, &event)?;
+        }
+
+        Ok(event)
+    })
+}
+
+/// Tell the attendees and queue the refund of every ticket.
+pub fn on_cancelled(conn: &mut PgConnection, event: &Event) -> QueryResult<()> {
+    notifications::notify_attendees(
+        conn,
+        event.id,
+        NotificationKind::EventCancelled,
+        "Event cancelled",
+        &format!("{} has been cancelled by the organizer.", event.eventname),
+    )?;
+    refunds::schedule_event_refunds(conn, event.id)?;
+    Ok(())
 }
 
-pub fn delete(conn: &mut PgConnection, id: i32) -> Result<usize, Error> {
-    let event_deleted = diesel::delete(events::table.filter(events::id.eq(id)))
-        .execute(conn)
-        .expect("Error deleting event");
+/// Hard-delete an event. Only drafts can be deleted; anything that has been
+/// published must be cancelled instead so buyers keep a record of it.
+pub fn delete(conn: &mut PgConnection, id: i32, userid: i32) -> Result<usize, Error> {
+    let event_deleted = diesel::delete(
+        events::table
+            .filter(events::id.eq(id))
+            .filter(events::userid.eq(userid))
+            .filter(events::status.eq(EventStatus::Draft)),
+    )
+    .execute(conn)?;
 
     println!("Deleted {} event", event_deleted);
 
@@ -0,0 +1,262 @@
+use crate::models::events::Event;
+use crate::models::feed::FeedItem;
+use crate::models::venues::Venue;
+use crate::money::Amount;
+use crate::schema::{events, feed_activities, feed_items, venues};
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{BigInt, Text};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+/// Activities fanned out per worker transaction.
+const FAN_OUT_BATCH: i64 = 100;
+
+pub const FEED_DEFAULT_LIMIT: i64 = 20;
+pub const FEED_MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedKind {
+    EventPublished,
+    PriceDrop,
+    /// The organizer raised the capacity of the event.
+    TicketsReleased,
+}
+
+impl FeedKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedKind::EventPublished => "event_published",
+            FeedKind::PriceDrop => "price_drop",
+            FeedKind::TicketsReleased => "tickets_released",
+        }
+    }
+}
+
+impl FromStr for FeedKind {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "event_published" => Ok(FeedKind::EventPublished),
+            "price_drop" => Ok(FeedKind::PriceDrop),
+            "tickets_released" => Ok(FeedKind::TicketsReleased),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for FeedKind {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for FeedKind {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedReason {
+    FollowedOrganizer,
+    FollowedVenue,
+    LikedEvent,
+    LikedCategory,
+}
+
+impl FeedReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedReason::FollowedOrganizer => "followed_organizer",
+            FeedReason::FollowedVenue => "followed_venue",
+            FeedReason::LikedEvent => "liked_event",
+            FeedReason::LikedCategory => "liked_category",
+        }
+    }
+}
+
+impl FromStr for FeedReason {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "followed_organizer" => Ok(FeedReason::FollowedOrganizer),
+            "followed_venue" => Ok(FeedReason::FollowedVenue),
+            "liked_event" => Ok(FeedReason::LikedEvent),
+            "liked_category" => Ok(FeedReason::LikedCategory),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for FeedReason {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for FeedReason {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+/// Queue an activity for fan-out. Meant to be called inside the transaction
+/// that made the change, so the feed never announces something that was
+/// rolled back.
+pub fn record(
+    conn: &mut PgConnection,
+    event_id: i32,
+    kind: FeedKind,
+    old_price: Option<Amount>,
+    new_price: Option<Amount>,
+) -> QueryResult<usize> {
+    diesel::insert_into(feed_activities::table)
+        .values((
+            feed_activities::event_id.eq(event_id),
+            feed_activities::kind.eq(kind),
+            feed_activities::old_price.eq(old_price),
+            feed_activities::new_price.eq(new_price),
+        ))
+        .execute(conn)
+}
+
+/// Copy one batch of pending activities into the feeds of everyone they
+/// concern and mark them as done. When a user qualifies for several
+/// reasons the most specific one wins, followed organizers first.
+fn fan_out_batch(conn: &mut PgConnection) -> QueryResult<usize> {
+    diesel::sql_query(
+        "WITH batch AS ( \
+             SELECT id FROM feed_activities \
+             WHERE fanned_out_at IS NULL \
+             ORDER BY id \
+             LIMIT $1 \
+             FOR UPDATE SKIP LOCKED \
+         ), \
+         activity AS ( \
+             SELECT feed_activities.id, feed_activities.kind, events.id AS event_id, \
+                    events.userid AS organizer_id, events.venue_id \
+             FROM feed_activities \
+             JOIN batch ON batch.id = feed_activities.id \
+             JOIN events ON events.id = feed_activities.event_id \
+             WHERE events.status = 'published' \
+         ), \
+         recipients AS ( \
+             SELECT activity.id AS activity_id, follows.follower_id AS user_id, \
+                    'followed_organizer' AS reason, 1 AS priority \
+             FROM activity JOIN follows ON follows.followed_user_id = activity.organizer_id \
+             UNION ALL \
+             SELECT activity.id, follows.follower_id, 'followed_venue', 2 \
+             FROM activity JOIN follows ON follows.venue_id = activity.venue_id \
+             UNION ALL \
+             SELECT activity.id, likes.user_id, 'liked_event', 3 \
+             FROM activity JOIN likes ON likes.event_id = activity.event_id \
+             WHERE activity.kind <> 'event_published' \
+             UNION ALL \
+             SELECT activity.id, likes.user_id, 'liked_category', 4 \
+             FROM activity \
+             JOIN event_categories published ON published.event_id = activity.event_id \
+             JOIN event_categories liked ON liked.category_id = published.category_id \
+                 AND liked.event_id <> activity.event_id \
+             JOIN likes ON likes.event_id = liked.event_id \
+             WHERE activity.kind = 'event_published' \
+         ), \
+         inserted AS ( \
+             INSERT INTO feed_items (user_id, activity_id, reason) \
+             SELECT DISTINCT ON (recipients.user_id, recipients.activity_id) \
+                    recipients.user_id, recipients.activity_id, recipients.reason \
+             FROM recipients \
+             JOIN activity ON activity.id = recipients.activity_id \
+             WHERE recipients.user_id <> activity.organizer_id \
+             ORDER BY recipients.user_id, recipients.activity_id, recipients.priority \
+             ON CONFLICT DO NOTHING \
+         ) \
+         UPDATE feed_activities SET fanned_out_at = now() \
+         FROM batch WHERE feed_activities.id = batch.id",
+    )
+    .bind::<BigInt, _>(FAN_OUT_BATCH)
+    .execute(conn)
+}
+
+/// Drain the queue of pending activities.
+pub fn fan_out(conn: &mut PgConnection) -> QueryResult<usize> {
+    let mut total = 0;
+    loop {
+        let processed = conn.transaction(fan_out_batch)?;
+        total += processed;
+        if processed < FAN_OUT_BATCH as usize {
+            return Ok(total);
+        }
+    }
+}
+
+/// Newest items first. `cursor` is the id of the last item of the previous
+/// page.
+pub fn get_feed(
+    conn: &mut PgConnection,
+    user_id: i32,
+    cursor: Option<i64>,
+    limit: i64,
+) -> QueryResult<Vec<FeedItem>> {
+    let mut query = feed_items::table
+        .inner_join(feed_activities::table.inner_join(events::table.inner_join(venues::table)))
+        .filter(feed_items::user_id.eq(user_id))
+        .into_boxed();
+    if let Some(cursor) = cursor {
+        query = query.filter(feed_items::id.lt(cursor));
+    }
+
+    let rows = query
+        .order(feed_items::id.desc())
+        .limit(limit.clamp(1, FEED_MAX_LIMIT))
+        .select((
+            (
+                feed_items::id,
+                feed_activities::kind,
+                feed_items::reason,
+                feed_activities::old_price,
+                feed_activities::new_price,
+                feed_items::created_at,
+            ),
+            Event::as_select(),
+            Venue::as_select(),
+        ))
+        .load::<(
+            (i64, FeedKind, FeedReason, Option<Amount>, Option<Amount>, DateTime<Utc>),
+            Event,
+            Venue,
+        )>(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |((id, kind, reason, old_price, new_price, created_at), event, venue)| FeedItem {
+                id,
+                kind,
+                reason,
+                old_price,
+                new_price,
+                created_at,
+                event,
+                venue,
+            },
+        )
+        .collect())
+}
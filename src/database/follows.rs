@@ -0,0 +1,145 @@
+use crate::models::user::{Profile, User};
+use crate::models::venues::Venue;
+use crate::schema::{follows, users, venues};
+use diesel::dsl::{count_star, exists, select};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+
+pub enum FollowError {
+    NotFound,
+    SelfFollow,
+    Other,
+}
+
+impl From<Error> for FollowError {
+    fn from(err: Error) -> FollowError {
+        match &err {
+            Error::NotFound => FollowError::NotFound,
+            Error::DatabaseError(DatabaseErrorKind::CheckViolation, info)
+                if info.constraint_name() == Some("follows_not_self") =>
+            {
+                FollowError::SelfFollow
+            }
+            _ => FollowError::Other,
+        }
+    }
+}
+
+fn find_user(conn: &mut PgConnection, username: &str) -> QueryResult<User> {
+    users::table
+        .filter(users::username.eq(username))
+        .get_result::<User>(conn)
+}
+
+pub fn user_followers_count(conn: &mut PgConnection, user_id: i32) -> QueryResult<i64> {
+    follows::table
+        .filter(follows::followed_user_id.eq(user_id))
+        .select(count_star())
+        .get_result(conn)
+}
+
+pub fn venue_followers_count(conn: &mut PgConnection, venue_id: i32) -> QueryResult<i64> {
+    follows::table
+        .filter(follows::venue_id.eq(venue_id))
+        .select(count_star())
+        .get_result(conn)
+}
+
+pub fn is_following_user(
+    conn: &mut PgConnection,
+    follower_id: Option<i32>,
+    user_id: i32,
+) -> QueryResult<bool> {
+    select(exists(
+        follows::table
+            .filter(follows::follower_id.nullable().eq(follower_id))
+            .filter(follows::followed_user_id.eq(user_id)),
+    ))
+    .get_result(conn)
+}
+
+fn to_profile(conn: &mut PgConnection, user: User, viewer: Option<i32>) -> QueryResult<Profile> {
+    let following = is_following_user(conn, viewer, user.id)?;
+    let followers_count = user_followers_count(conn, user.id)?;
+    Ok(user.to_profile(following, followers_count))
+}
+
+/// Public profile of `username`, `following` is filled in for `viewer`.
+pub fn profile(
+    conn: &mut PgConnection,
+    username: &str,
+    viewer: Option<i32>,
+) -> Result<Profile, FollowError> {
+    let user = find_user(conn, username)?;
+    Ok(to_profile(conn, user, viewer)?)
+}
+
+/// Following someone twice is not an error.
+pub fn follow_user(
+    conn: &mut PgConnection,
+    follower_id: i32,
+    username: &str,
+) -> Result<Profile, FollowError> {
+    let user = find_user(conn, username)?;
+    diesel::insert_into(follows::table)
+        .values((
+            follows::follower_id.eq(follower_id),
+            follows::followed_user_id.eq(user.id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok(to_profile(conn, user, Some(follower_id))?)
+}
+
+pub fn unfollow_user(
+    conn: &mut PgConnection,
+    follower_id: i32,
+    username: &str,
+) -> Result<Profile, FollowError> {
+    let user = find_user(conn, username)?;
+    diesel::delete(
+        follows::table
+            .filter(follows::follower_id.eq(follower_id))
+            .filter(follows::followed_user_id.eq(user.id)),
+    )
+    .execute(conn)?;
+    Ok(to_profile(conn, user, Some(follower_id))?)
+}
+
+pub fn follow_venue(
+    conn: &mut PgConnection,
+    follower_id: i32,
+    venue_id: i32,
+) -> Result<(Venue, i64), FollowError> {
+    let venue = venues::table
+        .find(venue_id)
+        .select(Venue::as_select())
+        .get_result::<Venue>(conn)?;
+    diesel::insert_into(follows::table)
+        .values((
+            follows::follower_id.eq(follower_id),
+            follows::venue_id.eq(venue_id),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    Ok((venue, venue_followers_count(conn, venue_id)?))
+}
+
+pub fn unfollow_venue(
+    conn: &mut PgConnection,
+    follower_id: i32,
+    venue_id: i32,
+) -> Result<(Venue, i64), FollowError> {
+    let venue = venues::table
+        .find(venue_id)
+        .select(Venue::as_select())
+        .get_result::<Venue>(conn)?;
+    diesel::delete(
+        follows::table
+            .filter(follows::follower_id.eq(follower_id))
+            .filter(follows::venue_id.eq(venue_id)),
+    )
+    .execute(conn)?;
+    Ok((venue, venue_followers_count(conn, venue_id)?))
+}
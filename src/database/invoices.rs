@@ -0,0 +1,129 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::config;
+use crate::database::ledger;
+use crate::models::events::Event;
+use crate::models::invoices::Invoice;
+use crate::models::orders::Order;
+use crate::models::venues::Venue;
+use crate::receipts::Receipt;
+use crate::schema::{events, invoice_counters, invoices, orders, users, venues};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error;
+
+pub enum InvoiceError {
+    NotFound,
+    NotOwner,
+    /// The order isn't paid, so it has no invoice yet.
+    NotIssued,
+    Other,
+}
+
+impl From<Error> for InvoiceError {
+    fn from(err: Error) -> InvoiceError {
+        match err {
+            Error::NotFound => InvoiceError::NotFound,
+            _ => InvoiceError::Other,
+        }
+    }
+}
+
+/// Issue the invoice of a just paid order under the next number of the
+/// organizer it was sold for. Runs in the payment's transaction: the counter
+/// row stays locked until it commits, so numbers are neither shared nor
+/// skipped.
+pub fn issue(conn: &mut PgConnection, order: &Order) -> QueryResult<Invoice> {
+    let organizer_id = order.organizer_id;
+    let number = diesel::insert_into(invoice_counters::table)
+        .values((
+            invoice_counters::organizer_id.eq(organizer_id),
+            invoice_counters::last_number.eq(1),
+        ))
+        .on_conflict(invoice_counters::organizer_id)
+        .do_update()
+        .set(invoice_counters::last_number.eq(invoice_counters::last_number + 1))
+        .returning(invoice_counters::last_number)
+        .get_result::<i32>(conn)?;
+
+    let (seller_name, seller_email) = users::table
+        .find(organizer_id)
+        .select((users::username, users::email))
+        .get_result::<(String, String)>(conn)?;
+    let (buyer_name, buyer_email) = users::table
+        .find(order.user_id)
+        .select((users::username, users::email))
+        .get_result::<(String, String)>(conn)?;
+    let (event, venue) = events::table
+        .find(order.event_id)
+        .inner_join(venues::table)
+        .select((Event::as_select(), Venue::as_select()))
+        .get_result::<(Event, Venue)>(conn)?;
+    let tax_rate_bps = config::tax_rate_bps();
+    let tax_amount = order.total_amount.included_tax(tax_rate_bps)?;
+    let fee_amount = ledger::platform_fee(order)?;
+
+    diesel::insert_into(invoices::table)
+        .values((
+            invoices::order_id.eq(order.id),
+            invoices::organizer_id.eq(organizer_id),
+            invoices::number.eq(number),
+            invoices::seller_name.eq(seller_name),
+            invoices::seller_email.eq(seller_email),
+            invoices::buyer_name.eq(buyer_name),
+            invoices::buyer_email.eq(buyer_email),
+            invoices::tax_rate_bps.eq(tax_rate_bps),
+            invoices::tax_amount.eq(tax_amount),
+            invoices::fee_amount.eq(fee_amount),
+            invoices::currency.eq(order.currency),
+            invoices::event_name.eq(event.eventname),
+            invoices::event_starts_at.eq(event.starts_at),
+            invoices::event_timezone.eq(event.timezone),
+            invoices::venue_name.eq(venue.name),
+            invoices::venue_address.eq(venue.address),
+            invoices::venue_city.eq(venue.city),
+            invoices::venue_country.eq(venue.country),
+        ))
+        .returning(Invoice::as_returning())
+        .get_result(conn)
+}
+
+/// Everything printed on the receipt of an order, `None` before it is paid.
+/// The event and venue come from the invoice, as they were when it was
+/// issued.
+pub fn receipt(conn: &mut PgConnection, order_id: i32) -> QueryResult<Option<Receipt>> {
+    let invoice = match invoices::table
+        .filter(invoices::order_id.eq(order_id))
+        .select(Invoice::as_select())
+        .first::<Invoice>(conn)
+        .optional()?
+    {
+        Some(invoice) => invoice,
+        None => return Ok(None),
+    };
+    let order = orders::table
+        .find(order_id)
+        .select(Order::as_select())
+        .get_result::<Order>(conn)?;
+    let tz = invoice.tz()?;
+
+    Ok(Some(Receipt { invoice, order, tz }))
+}
+
+/// The receipt as the buyer or the organizer who issued the invoice may
+/// download it. The event may have changed hands since; the invoice hasn't.
+pub fn receipt_for(
+    conn: &mut PgConnection,
+    order_id: i32,
+    user_id: i32,
+) -> Result<Receipt, InvoiceError> {
+    let (buyer_id, organizer_id) = orders::table
+        .find(order_id)
+        .left_join(invoices::table)
+        .select((orders::user_id, invoices::organizer_id.nullable()))
+        .get_result::<(i32, Option<i32>)>(conn)?;
+    if user_id != buyer_id && Some(user_id) != organizer_id {
+        return Err(InvoiceError::NotOwner);
+    }
+    receipt(conn, order_id)?.ok_or(InvoiceError::NotIssued)
+}
@@ -0,0 +1,329 @@
+use crate::database::refunds::EventRefunds;
+use crate::database::reminders::EventReminder;
+use crate::models::jobs::Job;
+use crate::schema::scheduled_jobs;
+use chrono::{DateTime, Duration, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::dsl::count_star;
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use rocket::form::FromFormField;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+pub const JOBS_DEFAULT_LIMIT: i64 = 50;
+pub const JOBS_MAX_LIMIT: i64 = 200;
+/// Jobs run per worker pass, so one pass can't hold a connection forever.
+const JOBS_PER_RUN: usize = 100;
+/// How long a worker owns a job it picked up. A job still running after
+/// this is assumed to belong to a dead worker and is handed out again.
+const VISIBILITY_TIMEOUT_SECONDS: i64 = 5 * 60;
+/// Retries wait 30s, 1m, 2m, ... up to an hour.
+const BACKOFF_BASE_SECONDS: i64 = 30;
+const BACKOFF_MAX_SECONDS: i64 = 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Done,
+    /// Out of attempts. Stays put until an admin retries it.
+    Dead,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Pending => "pending",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Dead => "dead",
+        }
+    }
+}
+
+impl FromStr for JobStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "pending" => Ok(JobStatus::Pending),
+            "running" => Ok(JobStatus::Running),
+            "done" => Ok(JobStatus::Done),
+            "dead" => Ok(JobStatus::Dead),
+            _ => Err(()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for JobStatus {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation("invalid job status"))
+        })
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for JobStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for JobStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+/// A kind of background work. The payload is stored as JSON and handed
+/// back to `run` typed.
+pub trait JobHandler {
+    const KIND: &'static str;
+    const MAX_ATTEMPTS: i32 = 5;
+    type Payload: Serialize + DeserializeOwned;
+
+    /// Runs inside a transaction that also marks the job done, so a job
+    /// whose work only touches the database takes effect exactly once.
+    fn run(conn: &mut PgConnection, payload: Self::Payload) -> QueryResult<()>;
+}
+
+type Handler = fn(&mut PgConnection, &serde_json::Value) -> QueryResult<()>;
+
+/// Every job kind the workers know how to run.
+const HANDLERS: &[(&str, Handler)] = &[
+    (EventReminder::KIND, dispatch::<EventReminder>),
+    (EventRefunds::KIND, dispatch::<EventRefunds>),
+];
+
+fn dispatch<H: JobHandler>(conn: &mut PgConnection, payload: &serde_json::Value) -> QueryResult<()> {
+    let payload = serde_json::from_value(payload.clone())
+        .map_err(|err| Error::DeserializationError(Box::new(err)))?;
+    H::run(conn, payload)
+}
+
+fn handler(kind: &str) -> Option<Handler> {
+    HANDLERS
+        .iter()
+        .find(|(handled, _)| *handled == kind)
+        .map(|(_, handler)| *handler)
+}
+
+/// Queue a job to run at `run_at`. Returns 0 when a job with the same
+/// `dedupe_key` already exists.
+pub fn enqueue<H: JobHandler>(
+    conn: &mut PgConnection,
+    payload: &H::Payload,
+    run_at: DateTime<Utc>,
+    dedupe_key: Option<&str>,
+) -> QueryResult<usize> {
+    let payload = serde_json::to_value(payload).map_err(|err| Error::SerializationError(Box::new(err)))?;
+    diesel::insert_into(scheduled_jobs::table)
+        .values((
+            scheduled_jobs::kind.eq(H::KIND),
+            scheduled_jobs::payload.eq(payload),
+            scheduled_jobs::run_at.eq(run_at),
+            scheduled_jobs::max_attempts.eq(H::MAX_ATTEMPTS),
+            scheduled_jobs::dedupe_key.eq(dedupe_key),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)
+}
+
+fn backoff(attempts: i32) -> Duration {
+    let seconds = BACKOFF_BASE_SECONDS * 2i64.pow((attempts - 1).clamp(0, 16) as u32);
+    Duration::seconds(seconds.min(BACKOFF_MAX_SECONDS))
+}
+
+/// Run due jobs one at a time. Safe to call from any number of workers
+/// and instances: jobs are leased with `SKIP LOCKED`, so each is handed to
+/// one worker at a time.
+pub fn run_due(conn: &mut PgConnection) -> QueryResult<usize> {
+    bury_expired(conn)?;
+
+    let mut ran = 0;
+    while ran < JOBS_PER_RUN {
+        let job = match claim(conn)? {
+            Some(job) => job,
+            None => break,
+        };
+        execute(conn, &job)?;
+        ran += 1;
+    }
+    Ok(ran)
+}
+
+/// Jobs whose last attempt timed out and that have no attempts left.
+fn bury_expired(conn: &mut PgConnection) -> QueryResult<usize> {
+    diesel::update(
+        scheduled_jobs::table
+            .filter(scheduled_jobs::status.eq(JobStatus::Running))
+            .filter(scheduled_jobs::locked_until.lt(Utc::now()))
+            .filter(scheduled_jobs::attempts.ge(scheduled_jobs::max_attempts)),
+    )
+    .set((
+        scheduled_jobs::status.eq(JobStatus::Dead),
+        scheduled_jobs::locked_until.eq(None::<DateTime<Utc>>),
+        scheduled_jobs::last_error.eq("timed out"),
+    ))
+    .execute(conn)
+}
+
+/// Lease the next due job: a pending job whose time has come, or a running
+/// job whose worker's lease ran out.
+fn claim(conn: &mut PgConnection) -> QueryResult<Option<Job>> {
+    conn.transaction(|conn| {
+        let now = Utc::now();
+        let due = scheduled_jobs::status
+            .eq(JobStatus::Pending)
+            .and(scheduled_jobs::run_at.le(now));
+        let expired = scheduled_jobs::status
+            .eq(JobStatus::Running)
+            .and(scheduled_jobs::locked_until.lt(now))
+            .and(scheduled_jobs::attempts.lt(scheduled_jobs::max_attempts));
+
+        let id = match scheduled_jobs::table
+            .filter(due.or(expired))
+            .order(scheduled_jobs::run_at.asc())
+            .select(scheduled_jobs::id)
+            .for_update()
+            .skip_locked()
+            .first::<i64>(conn)
+            .optional()?
+        {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        diesel::update(scheduled_jobs::table.find(id))
+            .set((
+                scheduled_jobs::status.eq(JobStatus::Running),
+                scheduled_jobs::attempts.eq(scheduled_jobs::attempts + 1),
+                scheduled_jobs::locked_until.eq(now + Duration::seconds(VISIBILITY_TIMEOUT_SECONDS)),
+            ))
+            .returning(Job::as_returning())
+            .get_result(conn)
+            .map(Some)
+    })
+}
+
+fn execute(conn: &mut PgConnection, job: &Job) -> QueryResult<()> {
+    let run = match handler(&job.kind) {
+        Some(run) => run,
+        None => return fail(conn, job, format!("no handler for job kind {}", job.kind)),
+    };
+
+    let result = conn.transaction(|conn| {
+        run(conn, &job.payload)?;
+        // Only the worker still holding the lease may finish the job. If it
+        // was handed to someone else in the meantime, undo this run.
+        let finished = diesel::update(
+            scheduled_jobs::table
+                .filter(scheduled_jobs::id.eq(job.id))
+                .filter(scheduled_jobs::status.eq(JobStatus::Running))
+                .filter(scheduled_jobs::locked_until.eq(job.locked_until)),
+        )
+        .set((
+            scheduled_jobs::status.eq(JobStatus::Done),
+            scheduled_jobs::locked_until.eq(None::<DateTime<Utc>>),
+            scheduled_jobs::completed_at.eq(Utc::now()),
+        ))
+        .execute(conn)?;
+        if finished == 0 {
+            return Err(Error::RollbackTransaction);
+        }
+        Ok(())
+    });
+
+    match result {
+        Ok(()) | Err(Error::RollbackTransaction) => Ok(()),
+        Err(err) => fail(conn, job, err.to_string()),
+    }
+}
+
+/// Release the lease and either schedule a retry or move the job to dead.
+fn fail(conn: &mut PgConnection, job: &Job, error: String) -> QueryResult<()> {
+    let (status, run_at) = if job.attempts >= job.max_attempts {
+        (JobStatus::Dead, job.run_at)
+    } else {
+        (JobStatus::Pending, Utc::now() + backoff(job.attempts))
+    };
+    diesel::update(
+        scheduled_jobs::table
+            .filter(scheduled_jobs::id.eq(job.id))
+            .filter(scheduled_jobs::status.eq(JobStatus::Running))
+            .filter(scheduled_jobs::locked_until.eq(job.locked_until)),
+    )
+    .set((
+        scheduled_jobs::status.eq(status),
+        scheduled_jobs::run_at.eq(run_at),
+        scheduled_jobs::locked_until.eq(None::<DateTime<Utc>>),
+        scheduled_jobs::last_error.eq(error),
+    ))
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Newest first. `cursor` is the id of the last job of the previous page.
+pub fn list(
+    conn: &mut PgConnection,
+    status: Option<JobStatus>,
+    kind: Option<&str>,
+    cursor: Option<i64>,
+    limit: i64,
+) -> QueryResult<Vec<Job>> {
+    let mut query = scheduled_jobs::table.into_boxed();
+    if let Some(status) = status {
+        query = query.filter(scheduled_jobs::status.eq(status));
+    }
+    if let Some(kind) = kind {
+        query = query.filter(scheduled_jobs::kind.eq(kind));
+    }
+    if let Some(cursor) = cursor {
+        query = query.filter(scheduled_jobs::id.lt(cursor));
+    }
+
+    query
+        .order(scheduled_jobs::id.desc())
+        .limit(limit.clamp(1, JOBS_MAX_LIMIT))
+        .select(Job::as_select())
+        .load(conn)
+}
+
+/// Number of jobs in each status.
+pub fn counts(conn: &mut PgConnection) -> QueryResult<Vec<(JobStatus, i64)>> {
+    scheduled_jobs::table
+        .group_by(scheduled_jobs::status)
+        .select((scheduled_jobs::status, count_star()))
+        .load(conn)
+}
+
+/// Give a dead job a fresh set of attempts, starting now. `None` if there
+/// is no dead job with that id.
+pub fn retry(conn: &mut PgConnection, id: i64) -> QueryResult<Option<Job>> {
+    diesel::update(
+        scheduled_jobs::table
+            .filter(scheduled_jobs::id.eq(id))
+            .filter(scheduled_jobs::status.eq(JobStatus::Dead)),
+    )
+    .set((
+        scheduled_jobs::status.eq(JobStatus::Pending),
+        scheduled_jobs::attempts.eq(0),
+        scheduled_jobs::run_at.eq(Utc::now()),
+    ))
+    .returning(Job::as_returning())
+    .get_result(conn)
+    .optional()
+}
@@ -0,0 +1,585 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::config;
+use crate::models::ledger::{
+    Balance, LedgerCheck, LedgerEntry, LedgerTransaction, Statement, StatementLine,
+};
+use crate::models::listings::Listing;
+use crate::models::orders::Order;
+use crate::models::payouts::Payout;
+use crate::models::refunds::Refund;
+use crate::money::{Amount, Currency, Money, OutOfRange};
+use crate::schema::{ledger_entries, ledger_transactions, orders};
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Longest statement returned at once.
+pub const MAX_STATEMENT_LINES: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerAccount {
+    /// Money in from ticket buyers, and back out to them as refunds.
+    Buyers,
+    PlatformFees,
+    /// What an organizer, or a resale seller, has earned and can have paid
+    /// out.
+    Organizer,
+    /// Requested payouts not sent yet.
+    PayoutsPending,
+    PaidOut,
+}
+
+impl LedgerAccount {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LedgerAccount::Buyers => "buyers",
+            LedgerAccount::PlatformFees => "platform_fees",
+            LedgerAccount::Organizer => "organizer",
+            LedgerAccount::PayoutsPending => "payouts_pending",
+            LedgerAccount::PaidOut => "paid_out",
+        }
+    }
+}
+
+impl FromStr for LedgerAccount {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "buyers" => Ok(LedgerAccount::Buyers),
+            "platform_fees" => Ok(LedgerAccount::PlatformFees),
+            "organizer" => Ok(LedgerAccount::Organizer),
+            "payouts_pending" => Ok(LedgerAccount::PayoutsPending),
+            "paid_out" => Ok(LedgerAccount::PaidOut),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for LedgerAccount {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for LedgerAccount {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionKind {
+    Sale,
+    Refund,
+    Payout,
+    PayoutSettled,
+    PayoutFailed,
+    Resale,
+}
+
+impl TransactionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionKind::Sale => "sale",
+            TransactionKind::Refund => "refund",
+            TransactionKind::Payout => "payout",
+            TransactionKind::PayoutSettled => "payout_settled",
+            TransactionKind::PayoutFailed => "payout_failed",
+            TransactionKind::Resale => "resale",
+        }
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "sale" => Ok(TransactionKind::Sale),
+            "refund" => Ok(TransactionKind::Refund),
+            "payout" => Ok(TransactionKind::Payout),
+            "payout_settled" => Ok(TransactionKind::PayoutSettled),
+            "payout_failed" => Ok(TransactionKind::PayoutFailed),
+            "resale" => Ok(TransactionKind::Resale),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for TransactionKind {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for TransactionKind {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+/// What a transaction is about; exactly one is set.
+#[derive(Default)]
+struct Source {
+    order_id: Option<i32>,
+    refund_id: Option<i32>,
+    payout_id: Option<i32>,
+    listing_id: Option<i32>,
+}
+
+/// One side of a transaction. `organizer_id` is set for organizer accounts.
+struct Posting {
+    account: LedgerAccount,
+    organizer_id: Option<i32>,
+    amount: Amount,
+}
+
+/// Book a transaction. Its postings must sum to zero. Booking the same step
+/// twice (same kind and source), or nothing at all, is a no-op.
+fn post(
+    conn: &mut PgConnection,
+    kind: TransactionKind,
+    currency: Currency,
+    source: Source,
+    postings: &[Posting],
+) -> QueryResult<Option<i32>> {
+    if postings
+        .iter()
+        .all(|posting| posting.amount == Amount::ZERO)
+    {
+        return Ok(None);
+    }
+    let total = postings
+        .iter()
+        .map(|posting| posting.amount)
+        .sum::<Amount>();
+    if total != Amount::ZERO {
+        return Err(Error::QueryBuilderError(
+            format!("unbalanced {} transaction: off by {}", kind.as_str(), total).into(),
+        ));
+    }
+
+    let transaction_id = diesel::insert_into(ledger_transactions::table)
+        .values((
+            ledger_transactions::kind.eq(kind),
+            ledger_transactions::currency.eq(currency),
+            ledger_transactions::order_id.eq(source.order_id),
+            ledger_transactions::refund_id.eq(source.refund_id),
+            ledger_transactions::payout_id.eq(source.payout_id),
+            ledger_transactions::listing_id.eq(source.listing_id),
+        ))
+        .on_conflict_do_nothing()
+        .returning(ledger_transactions::id)
+        .get_result::<i32>(conn)
+        .optional()?;
+    let transaction_id = match transaction_id {
+        Some(id) => id,
+        None => return Ok(None),
+    };
+
+    let entries = postings
+        .iter()
+        .filter(|posting| posting.amount != Amount::ZERO)
+        .map(|posting| {
+            (
+                ledger_entries::transaction_id.eq(transaction_id),
+                ledger_entries::account.eq(posting.account),
+                ledger_entries::organizer_id.eq(posting.organizer_id),
+                ledger_entries::amount.eq(posting.amount),
+                ledger_entries::currency.eq(currency),
+            )
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(ledger_entries::table)
+        .values(entries)
+        .execute(conn)?;
+    Ok(Some(transaction_id))
+}
+
+/// What the platform keeps of a paid order.
+pub fn platform_fee(order: &Order) -> Result<Amount, OutOfRange> {
+    order.total_amount.basis_points(config::platform_fee_bps())
+}
+
+/// A paid order: the buyer's money goes to the organizer it was sold for,
+/// less the platform fee.
+pub fn record_sale(conn: &mut PgConnection, order: &Order) -> QueryResult<Option<i32>> {
+    let fee = platform_fee(order)?;
+    post(
+        conn,
+        TransactionKind::Sale,
+        order.currency,
+        Source {
+            order_id: Some(order.id),
+            ..Source::default()
+        },
+        &[
+            Posting {
+                account: LedgerAccount::Buyers,
+                organizer_id: None,
+                amount: -order.total_amount,
+            },
+            Posting {
+                account: LedgerAccount::PlatformFees,
+                organizer_id: None,
+                amount: fee,
+            },
+            Posting {
+                account: LedgerAccount::Organizer,
+                organizer_id: Some(order.organizer_id),
+                amount: order.total_amount - fee,
+            },
+        ],
+    )
+}
+
+/// An approved refund comes out of the balance of the organizer the order
+/// was sold for, in full; the platform keeps its fee.
+pub fn record_refund(conn: &mut PgConnection, refund: &Refund) -> QueryResult<Option<i32>> {
+    let organizer_id = orders::table
+        .find(refund.order_id)
+        .select(orders::organizer_id)
+        .get_result::<i32>(conn)?;
+    post(
+        conn,
+        TransactionKind::Refund,
+        refund.currency,
+        Source {
+            refund_id: Some(refund.id),
+            ..Source::default()
+        },
+        &[
+            Posting {
+                account: LedgerAccount::Organizer,
+                organizer_id: Some(organizer_id),
+                amount: -refund.amount,
+            },
+            Posting {
+                account: LedgerAccount::Buyers,
+                organizer_id: None,
+                amount: refund.amount,
+            },
+        ],
+    )
+}
+
+/// A settled resale: the buyer's money goes to the seller, less the
+/// organizer's royalty and the platform fee, as split on the listing. The
+/// seller's share is theirs to have paid out like an organizer's earnings.
+pub fn record_resale(
+    conn: &mut PgConnection,
+    listing: &Listing,
+    organizer_id: i32,
+) -> QueryResult<Option<i32>> {
+    let royalty = listing.royalty_amount.unwrap_or(Amount::ZERO);
+    let seller = listing.seller_amount.unwrap_or(listing.price - royalty);
+    post(
+        conn,
+        TransactionKind::Resale,
+        listing.currency,
+        Source {
+            listing_id: Some(listing.id),
+            ..Source::default()
+        },
+        &[
+            Posting {
+                account: LedgerAccount::Buyers,
+                organizer_id: None,
+                amount: -listing.price,
+            },
+            Posting {
+                account: LedgerAccount::PlatformFees,
+                organizer_id: None,
+                amount: listing.price - royalty - seller,
+            },
+            Posting {
+                account: LedgerAccount::Organizer,
+                organizer_id: Some(organizer_id),
+                amount: royalty,
+            },
+            Posting {
+                account: LedgerAccount::Organizer,
+                organizer_id: Some(listing.seller_id),
+                amount: seller,
+            },
+        ],
+    )
+}
+
+/// Move a payout between two of the organizer's accounts.
+fn record_payout_step(
+    conn: &mut PgConnection,
+    kind: TransactionKind,
+    payout: &Payout,
+    from: LedgerAccount,
+    to: LedgerAccount,
+) -> QueryResult<Option<i32>> {
+    post(
+        conn,
+        kind,
+        payout.currency,
+        Source {
+            payout_id: Some(payout.id),
+            ..Source::default()
+        },
+        &[
+            Posting {
+                account: from,
+                organizer_id: Some(payout.organizer_id),
+                amount: -payout.amount,
+            },
+            Posting {
+                account: to,
+                organizer_id: Some(payout.organizer_id),
+                amount: payout.amount,
+            },
+        ],
+    )
+}
+
+/// A requested payout is set aside so it can't be requested twice.
+pub fn record_payout(conn: &mut PgConnection, payout: &Payout) -> QueryResult<Option<i32>> {
+    record_payout_step(
+        conn,
+        TransactionKind::Payout,
+        payout,
+        LedgerAccount::Organizer,
+        LedgerAccount::PayoutsPending,
+    )
+}
+
+pub fn record_payout_settled(conn: &mut PgConnection, payout: &Payout) -> QueryResult<Option<i32>> {
+    record_payout_step(
+        conn,
+        TransactionKind::PayoutSettled,
+        payout,
+        LedgerAccount::PayoutsPending,
+        LedgerAccount::PaidOut,
+    )
+}
+
+/// A payout that couldn't be sent goes back to the available balance.
+pub fn record_payout_failed(conn: &mut PgConnection, payout: &Payout) -> QueryResult<Option<i32>> {
+    record_payout_step(
+        conn,
+        TransactionKind::PayoutFailed,
+        payout,
+        LedgerAccount::PayoutsPending,
+        LedgerAccount::Organizer,
+    )
+}
+
+/// Balance of one of the organizer's accounts.
+pub fn account_balance(
+    conn: &mut PgConnection,
+    organizer_id: i32,
+    account: LedgerAccount,
+    currency: Currency,
+) -> QueryResult<Amount> {
+    Ok(ledger_entries::table
+        .filter(ledger_entries::organizer_id.eq(organizer_id))
+        .filter(ledger_entries::account.eq(account))
+        .filter(ledger_entries::currency.eq(currency))
+        .select(diesel::dsl::sum(ledger_entries::amount))
+        .get_result::<Option<Amount>>(conn)?
+        .unwrap_or_default())
+}
+
+/// The organizer's balances in every currency they have sold in.
+pub fn balances(conn: &mut PgConnection, organizer_id: i32) -> QueryResult<Vec<Balance>> {
+    let sums = ledger_entries::table
+        .filter(ledger_entries::organizer_id.eq(organizer_id))
+        .group_by((ledger_entries::currency, ledger_entries::account))
+        .select((
+            ledger_entries::currency,
+            ledger_entries::account,
+            diesel::dsl::sum(ledger_entries::amount),
+        ))
+        .load::<(Currency, LedgerAccount, Option<Amount>)>(conn)?;
+
+    let mut balances = BTreeMap::new();
+    for (currency, account, amount) in sums {
+        let balance = balances.entry(currency.as_str()).or_insert(Balance {
+            currency,
+            available: Amount::ZERO,
+            pending_payouts: Amount::ZERO,
+            paid_out: Amount::ZERO,
+        });
+        let amount = amount.unwrap_or_default();
+        match account {
+            LedgerAccount::Organizer => balance.available = amount,
+            LedgerAccount::PayoutsPending => balance.pending_payouts = amount,
+            LedgerAccount::PaidOut => balance.paid_out = amount,
+            LedgerAccount::Buyers | LedgerAccount::PlatformFees => {}
+        }
+    }
+    Ok(balances.into_values().collect())
+}
+
+/// Movements of the organizer's available balance in `currency` between
+/// `from` and `to`, oldest first, each with the balance after it. At most
+/// `limit` lines are returned; `cursor` is the id of the last entry of the
+/// previous page. The closing balance is the balance at `to`, whatever the
+/// page.
+pub fn statement(
+    conn: &mut PgConnection,
+    organizer_id: i32,
+    currency: Currency,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    cursor: Option<i32>,
+    limit: Option<i64>,
+) -> QueryResult<Statement> {
+    let entries = || {
+        ledger_entries::table
+            .filter(ledger_entries::organizer_id.eq(organizer_id))
+            .filter(ledger_entries::account.eq(LedgerAccount::Organizer))
+            .filter(ledger_entries::currency.eq(currency))
+            .into_boxed()
+    };
+    let in_window = || {
+        let mut query = entries();
+        if let Some(from) = from {
+            query = query.filter(ledger_entries::created_at.ge(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(ledger_entries::created_at.lt(to));
+        }
+        query
+    };
+    let total = |conn: &mut PgConnection, query: ledger_entries::BoxedQuery<'static, Pg>| {
+        query
+            .select(diesel::dsl::sum(ledger_entries::amount))
+            .get_result::<Option<Amount>>(conn)
+            .map(Option::unwrap_or_default)
+    };
+
+    let before_window = match from {
+        Some(from) => total(conn, entries().filter(ledger_entries::created_at.lt(from)))?,
+        None => Amount::ZERO,
+    };
+    let before_page = match cursor {
+        Some(cursor) => total(conn, in_window().filter(ledger_entries::id.le(cursor)))?,
+        None => Amount::ZERO,
+    };
+    let opening_balance = before_window + before_page;
+    let closing_balance = before_window + total(conn, in_window())?;
+
+    let mut query = ledger_entries::table
+        .inner_join(ledger_transactions::table)
+        .filter(ledger_entries::organizer_id.eq(organizer_id))
+        .filter(ledger_entries::account.eq(LedgerAccount::Organizer))
+        .filter(ledger_entries::currency.eq(currency))
+        .into_boxed();
+    if let Some(from) = from {
+        query = query.filter(ledger_entries::created_at.ge(from));
+    }
+    if let Some(to) = to {
+        query = query.filter(ledger_entries::created_at.lt(to));
+    }
+    if let Some(cursor) = cursor {
+        query = query.filter(ledger_entries::id.gt(cursor));
+    }
+    let limit = limit
+        .unwrap_or(MAX_STATEMENT_LINES)
+        .clamp(1, MAX_STATEMENT_LINES);
+    let rows = query
+        .order(ledger_entries::id.asc())
+        .limit(limit)
+        .select((LedgerEntry::as_select(), LedgerTransaction::as_select()))
+        .load::<(LedgerEntry, LedgerTransaction)>(conn)?;
+
+    // A full page may be followed by more.
+    let next_cursor = match rows.last() {
+        Some((entry, _)) if rows.len() as i64 == limit => Some(entry.id),
+        _ => None,
+    };
+    let mut balance = opening_balance;
+    let lines = rows
+        .into_iter()
+        .map(|(entry, transaction)| {
+            balance += entry.amount;
+            StatementLine {
+                entry,
+                transaction,
+                balance,
+            }
+        })
+        .collect();
+
+    Ok(Statement {
+        currency,
+        from,
+        to,
+        opening_balance,
+        closing_balance,
+        lines,
+        next_cursor,
+    })
+}
+
+/// Verify that every transaction, and so every currency, sums to zero.
+pub fn check(conn: &mut PgConnection) -> QueryResult<LedgerCheck> {
+    let unbalanced_transactions = ledger_entries::table
+        .group_by(ledger_entries::transaction_id)
+        .having(diesel::dsl::sum(ledger_entries::amount).ne(Amount::ZERO))
+        .select(ledger_entries::transaction_id)
+        .order(ledger_entries::transaction_id.asc())
+        .load::<i32>(conn)?;
+
+    let totals = ledger_entries::table
+        .group_by(ledger_entries::currency)
+        .select((
+            ledger_entries::currency,
+            diesel::dsl::sum(ledger_entries::amount),
+        ))
+        .order(ledger_entries::currency.asc())
+        .load::<(Currency, Option<Amount>)>(conn)?
+        .into_iter()
+        .map(|(currency, amount)| Money::new(amount.unwrap_or_default(), currency))
+        .collect();
+
+    Ok(LedgerCheck {
+        unbalanced_transactions,
+        totals,
+    })
+}
+
+/// Periodic invariant check. Problems are logged for the on-call admin;
+/// returns the number of unbalanced transactions.
+pub fn check_invariants(conn: &mut PgConnection) -> QueryResult<usize> {
+    let check = check(conn)?;
+    if !check.is_balanced() {
+        eprintln!(
+            "ledger: books don't balance: unbalanced transactions {:?}, totals {:?}",
+            check.unbalanced_transactions,
+            check
+                .totals
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+    Ok(check.unbalanced_transactions.len())
+}
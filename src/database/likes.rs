@@ -2,15 +2,18 @@ use crate::database::events::EventResult;
 use crate::models::events::Event;
 use crate::schema::events;
 use crate::models::likes::{Like, LikesFiltering};
+use crate::models::venues::Venue;
 use crate::routes::likes::LikeRequest;
 use crate::schema::likes;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::result::{DatabaseErrorKind, Error};
 use diesel::dsl::exists;
-use diesel::dsl::select;
+use diesel::dsl::{count_star, select};
 use rocket::serde::json::Json;
+use std::collections::HashMap;
 
+use super::{categories, tags};
 use super::events::EventsLogged;
 
 #[derive(Insertable, Queryable, QueryableByName)]
@@ -23,7 +26,7 @@ pub struct NewLike {
 pub enum LikeCreationError {
     NonExistUserId,
     NonExistEventId,
-    DuplicatedUserId,
+    AlreadyLiked,
     Other,
 }
 
@@ -42,8 +45,8 @@ impl From<Error> for LikeCreationError {
                 }
                 DatabaseErrorKind::UniqueViolation => {
                     if let Some(constraint) = info.constraint_name() {
-                        if constraint == "users_id_key" {
-                            return LikeCreationError::DuplicatedUserId;
+                        if constraint == "likes_pkey" {
+                            return LikeCreationError::AlreadyLiked;
                         }
                     }
                 }
@@ -67,14 +70,43 @@ pub fn create(
         .map_err(Into::into)
 }
 
-pub fn delete(conn: &mut PgConnection, user_id: i32) -> Result<usize, Error> {
-    let like_deleted = diesel::delete(likes::table.filter(likes::user_id.eq(user_id)))
-        .execute(conn)
-        .expect("Error deleting user");
+pub fn delete(conn: &mut PgConnection, user_id: i32, event_id: i32) -> Result<usize, Error> {
+    diesel::delete(likes::table.find((user_id, event_id))).execute(conn)
+}
 
-    println!("Deleted {} user", like_deleted);
+pub fn count_for_event(conn: &mut PgConnection, event_id: i32) -> QueryResult<i64> {
+    likes::table
+        .filter(likes::event_id.eq(event_id))
+        .select(count_star())
+        .get_result(conn)
+}
 
-    Ok(like_deleted)
+/// Number of likes of each of the given events, keyed by event id. Events
+/// without likes are missing from the map.
+pub fn counts_for_events(
+    conn: &mut PgConnection,
+    event_ids: &[i32],
+) -> QueryResult<HashMap<i32, i64>> {
+    Ok(likes::table
+        .filter(likes::event_id.eq_any(event_ids))
+        .group_by(likes::event_id)
+        .select((likes::event_id, count_star()))
+        .load::<(i32, i64)>(conn)?
+        .into_iter()
+        .collect())
+}
+
+/// Which of the given events `user_id` has liked.
+pub fn liked_by(
+    conn: &mut PgConnection,
+    user_id: Option<i32>,
+    event_ids: &[i32],
+) -> QueryResult<Vec<i32>> {
+    likes::table
+        .filter(likes::user_id.nullable().eq(user_id))
+        .filter(likes::event_id.eq_any(event_ids))
+        .select(likes::event_id)
+        .load(conn)
 }
 
 pub fn get_likes(
@@ -83,6 +115,7 @@ pub fn get_likes(
 ) -> Result<Vec<EventsLogged>, diesel::result::Error> {
     use crate::schema::likes::dsl::*;
     use crate::schema::events::dsl::*;
+    use crate::schema::venues;
 
     println!("filtlers {:?}", filters);
     let mut query = likes.into_boxed();
@@ -100,25 +133,29 @@ pub fn get_likes(
 
         let event_like_object = likes_result.into_iter().map(|event_like| {
 
-            let event = events
+            let (event, venue) = events
+                .inner_join(venues::table)
                 .filter(id.eq(event_like.event_id)) // Ensure to filter by event's id
-                .first::<Event>(conn)
+                .select((Event::as_select(), Venue::as_select()))
+                .first::<(Event, Venue)>(conn)
                 .unwrap();
 
+            let categories = categories::for_events(conn, &[event.id])?
+                .remove(&event.id)
+                .unwrap_or_default();
+            let tags = tags::for_events(conn, &[event.id])?
+                .remove(&event.id)
+                .unwrap_or_default();
+            let likes_count = count_for_event(conn, event.id)?;
+
             Ok(EventsLogged {
-                id: event.id,
-                userid: event.userid,
-                eventname: event.eventname,
-                eventdescription: event.eventdescription,
-                eventdate: event.eventdate,
-                eventdatetime: event.eventdatetime,
-                eventcountry: event.eventcountry,
-                eventcity: event.eventcity,
-                eventplace: event.eventplace,
-                eventimage: event.eventimage,
-                eventtype: event.eventtype,
-                eventticketprice: event.eventticketprice,
+                event,
+                venue,
+                categories,
+                tags,
+                likes_count,
                 eventliked: true,
+                display_price: None,
             })
         }).collect::<Result<Vec<EventsLogged>, diesel::result::Error>>()?;
 
@@ -142,75 +179,3 @@ pub fn is_event_liked_by_user(
     // Execute the query and return the result
     is_liked_query.get_result(conn)
 }
-
-
-// pub fn get_popular_likes(conn: &mut PgConnection) -> Result<Vec<(Like)>, Error> {
-//     use crate::schema::likes::dsl::*;
-//     use diesel::dsl::count;
-
-//     let result = likes
-//         .filter(event_id.is_not_null())
-//         .select((count(event_id)))
-//         .group_by(event_id)
-//         .order_by(count(event_id).desc())
-//         .limit(20)
-//         .load::<(Like)>(conn)
-//         .expect("Error loading likes");
-
-//     Ok(result)
-// }
-
-// pub fn get_popular_likes(conn: &mut PgConnection) -> Result<Vec<(i32, i64)>, diesel::result::Error> {
-//     use crate::schema::likes::dsl::*;
-//     use diesel::dsl::count;
-//     use diesel::sql::functions::Join;
-
-//     let subquery = likes
-//         .filter(event_id.is_not_null())
-//         .select((count(event_id)))
-//         .group_by(event_id)
-//         .as("subquery");
-
-//     likes
-//         .inner_join(subquery)
-//         .on(likes.event_id.eq(subquery.column::<i32>("event_id")))
-//         .select((likes::user_id, subquery.column::<i64>("count")))
-//         .order_by(subquery.column::<i64>("count").desc())
-//         .limit(20)
-//         .load::<(i32, i64)>(conn)
-// }
-
-// pub fn get_popular_likes(
-//     conn: &mut PgConnection,
-//     filters: Option<LikesFiltering>,
-// ) -> Result<Vec<Like>, diesel::result::Error> {
-//     use crate::schema::likes::dsl::*;
-
-//     println!("filtlers {:?}", filters);
-
-//     if let Some(f) = filters {
-//         let mut query = likes.into_boxed();
-//         if let Some(id_filter) = f.id {
-//             query = query.filter(user_id.eq(id_filter));
-//         }
-//         if let Some(eventid_filter) = f.eventid {
-//             query = query.filter(event_id.eq(eventid_filter));
-//         }
-//         if let Some(limit_filter) = f.limit {
-//             query = query.limit(limit_filter);
-//         }
-
-//         query.limit(5).load::<Like>(conn)
-//     } else {
-//         let results = likes
-//             .filter(event_id.is_not_null())
-//             .select((event_id, count(event_id)))
-//             .group_by(event_id)
-//             .order(count(event_id).desc())
-//             .limit(20)
-//             .load::<Like>(conn)
-//             .expect("Error loading popular likes");
-
-//         Ok(results)
-//     }
-// }
@@ -0,0 +1,468 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::chain::{ChainVerifier, VerifyError};
+use crate::config;
+use crate::database::chain_payments::{self, ChainPaymentStatus, NewChainPayment};
+use crate::database::ledger;
+use crate::database::notifications::{self, NewNotification, NotificationKind};
+use crate::database::orders::HOLD_MINUTES;
+use crate::database::tickets::{self, AcquiredVia, TicketStatus};
+use crate::database::transfers::TransferStatus;
+use crate::models::events::Event;
+use crate::models::listings::Listing;
+use crate::models::tickets::Ticket;
+use crate::money::{Amount, Money};
+use crate::schema::{events, ticket_listings, ticket_transfers, tickets as tickets_table, users};
+use chrono::{DateTime, Duration, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::dsl::exists;
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+pub const LISTINGS_DEFAULT_LIMIT: i64 = 20;
+pub const LISTINGS_MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum ListingStatus {
+    Active,
+    /// Held for a buyer who is paying.
+    Reserved,
+    Sold,
+    Cancelled,
+}
+
+impl ListingStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListingStatus::Active => "active",
+            ListingStatus::Reserved => "reserved",
+            ListingStatus::Sold => "sold",
+            ListingStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for ListingStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "active" => Ok(ListingStatus::Active),
+            "reserved" => Ok(ListingStatus::Reserved),
+            "sold" => Ok(ListingStatus::Sold),
+            "cancelled" => Ok(ListingStatus::Cancelled),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for ListingStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for ListingStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+pub enum ListingError {
+    /// The ticket doesn't exist, isn't the seller's or is no longer valid.
+    TicketNotFound,
+    /// The organizer disabled resale or the cutoff has passed.
+    ResaleClosed,
+    PriceAboveCap,
+    AlreadyListed,
+    TransferPending,
+    NotFound,
+    OwnListing,
+    NotAvailable,
+    /// The transaction doesn't exist, failed or doesn't pay the price.
+    PaymentNotVerified,
+    /// The transaction wasn't sent from the buyer's wallet.
+    WrongSender,
+    /// The buyer has no wallet address to pay on chain from.
+    NoWallet,
+    /// The transaction isn't buried under enough blocks yet.
+    PaymentUnconfirmed,
+    /// The transaction already paid for another order or listing.
+    PaymentReferenceUsed,
+    /// The transfer arrived but the listing can't be sold any more; its money
+    /// is owed back.
+    PaymentUnmatched,
+    /// The listing's currency can't be paid on chain.
+    OnchainUnsupported,
+    /// The chain couldn't be asked about the transaction.
+    VerificationUnavailable,
+    Other,
+}
+
+impl From<Error> for ListingError {
+    fn from(err: Error) -> ListingError {
+        if let Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) = &err {
+            match info.constraint_name() {
+                Some("ticket_listings_one_open") => return ListingError::AlreadyListed,
+                Some("chain_payments_pkey") => return ListingError::PaymentReferenceUsed,
+                _ => {}
+            }
+        }
+        ListingError::Other
+    }
+}
+
+impl From<VerifyError> for ListingError {
+    fn from(err: VerifyError) -> ListingError {
+        match err {
+            VerifyError::NotFound | VerifyError::Mismatch => ListingError::PaymentNotVerified,
+            VerifyError::WrongSender => ListingError::WrongSender,
+            VerifyError::Unconfirmed => ListingError::PaymentUnconfirmed,
+            VerifyError::UnsupportedCurrency => ListingError::OnchainUnsupported,
+            VerifyError::Unavailable(err) => {
+                eprintln!("chain: {}", err);
+                ListingError::VerificationUnavailable
+            }
+        }
+    }
+}
+
+/// Put reserved listings whose buyer didn't pay in time back on sale.
+pub fn release_expired_reservations(conn: &mut PgConnection) -> QueryResult<usize> {
+    diesel::update(
+        ticket_listings::table
+            .filter(ticket_listings::status.eq(ListingStatus::Reserved))
+            .filter(ticket_listings::reserved_until.lt(Utc::now())),
+    )
+    .set((
+        ticket_listings::status.eq(ListingStatus::Active),
+        ticket_listings::buyer_id.eq(None::<i32>),
+        ticket_listings::reserved_until.eq(None::<DateTime<Utc>>),
+    ))
+    .execute(conn)
+}
+
+fn event_of(conn: &mut PgConnection, event_id: i32) -> QueryResult<Event> {
+    events::table
+        .find(event_id)
+        .select(Event::as_select())
+        .get_result(conn)
+}
+
+/// Put a ticket on the resale market. The price may not exceed the cap the
+/// organizer set over face value.
+pub fn create(
+    conn: &mut PgConnection,
+    ticket_id: i32,
+    seller_id: i32,
+    price: Amount,
+) -> Result<Listing, ListingError> {
+    conn.transaction(|conn| {
+        let ticket = tickets_table::table
+            .find(ticket_id)
+            .select(Ticket::as_select())
+            .for_update()
+            .get_result::<Ticket>(conn)
+            .optional()?
+            .filter(|ticket| ticket.owner_id == seller_id && ticket.status == TicketStatus::Valid)
+            .ok_or(ListingError::TicketNotFound)?;
+
+        let event = event_of(conn, ticket.event_id)?;
+        if !event.resale_open(Utc::now()) {
+            return Err(ListingError::ResaleClosed);
+        }
+        if matches!(event.max_resale_price(), Ok(max) if price > max) {
+            return Err(ListingError::PriceAboveCap);
+        }
+
+        let transfer_pending = diesel::select(exists(
+            ticket_transfers::table
+                .filter(ticket_transfers::ticket_id.eq(ticket.id))
+                .filter(ticket_transfers::status.eq(TransferStatus::Pending)),
+        ))
+        .get_result::<bool>(conn)?;
+        if transfer_pending {
+            return Err(ListingError::TransferPending);
+        }
+
+        Ok(diesel::insert_into(ticket_listings::table)
+            .values((
+                ticket_listings::ticket_id.eq(ticket.id),
+                ticket_listings::event_id.eq(ticket.event_id),
+                ticket_listings::seller_id.eq(seller_id),
+                ticket_listings::price.eq(price),
+                ticket_listings::currency.eq(event.currency),
+            ))
+            .returning(Listing::as_returning())
+            .get_result(conn)?)
+    })
+}
+
+/// Whether the ticket is currently on sale or reserved by a buyer.
+pub fn is_listed(conn: &mut PgConnection, ticket_id: i32) -> QueryResult<bool> {
+    diesel::select(exists(
+        ticket_listings::table
+            .filter(ticket_listings::ticket_id.eq(ticket_id))
+            .filter(ticket_listings::status.eq_any([ListingStatus::Active, ListingStatus::Reserved])),
+    ))
+    .get_result(conn)
+}
+
+fn find_for_update(conn: &mut PgConnection, id: i32) -> Result<Listing, ListingError> {
+    ticket_listings::table
+        .find(id)
+        .select(Listing::as_select())
+        .for_update()
+        .get_result::<Listing>(conn)
+        .optional()?
+        .ok_or(ListingError::NotFound)
+}
+
+/// Take an active listing off the market.
+pub fn cancel(conn: &mut PgConnection, id: i32, seller_id: i32) -> Result<Listing, ListingError> {
+    conn.transaction(|conn| {
+        let listing = find_for_update(conn, id)?;
+        if listing.seller_id != seller_id {
+            return Err(ListingError::NotFound);
+        }
+        if listing.status != ListingStatus::Active {
+            return Err(ListingError::NotAvailable);
+        }
+
+        Ok(diesel::update(ticket_listings::table.find(id))
+            .set(ticket_listings::status.eq(ListingStatus::Cancelled))
+            .returning(Listing::as_returning())
+            .get_result(conn)?)
+    })
+}
+
+/// Hold a listing for `buyer_id` while they pay. Nobody else can buy it
+/// until the hold runs out.
+pub fn reserve(conn: &mut PgConnection, id: i32, buyer_id: i32) -> Result<Listing, ListingError> {
+    conn.transaction(|conn| {
+        release_expired_reservations(conn)?;
+
+        let listing = find_for_update(conn, id)?;
+        if listing.seller_id == buyer_id {
+            return Err(ListingError::OwnListing);
+        }
+        if listing.status != ListingStatus::Active {
+            return Err(ListingError::NotAvailable);
+        }
+        if !event_of(conn, listing.event_id)?.resale_open(Utc::now()) {
+            return Err(ListingError::ResaleClosed);
+        }
+
+        Ok(diesel::update(ticket_listings::table.find(id))
+            .set((
+                ticket_listings::status.eq(ListingStatus::Reserved),
+                ticket_listings::buyer_id.eq(buyer_id),
+                ticket_listings::reserved_until.eq(Utc::now() + Duration::minutes(HOLD_MINUTES)),
+            ))
+            .returning(Listing::as_returning())
+            .get_result(conn)?)
+    })
+}
+
+/// Whether `buyer_id` may still settle the listing: reserved for them, or
+/// back on sale at the same price after their reservation lapsed.
+fn payable_by(listing: &Listing, buyer_id: i32, price: Amount) -> bool {
+    let held = match listing.status {
+        ListingStatus::Reserved => listing.buyer_id == Some(buyer_id),
+        ListingStatus::Active => true,
+        _ => false,
+    };
+    held && listing.price == price
+}
+
+/// Settle a reserved listing paid with an on-chain transfer. `tx_hash` is
+/// the normalized hash of a transaction that must pay the platform wallet
+/// the listing's price from the buyer's wallet and be confirmed; it is
+/// checked before anything is locked. The transaction is then claimed and
+/// the claim committed, so that it pays for nothing else and its money is on
+/// record even if the listing was sold or withdrawn meanwhile; the claim is
+/// then left `unmatched` for the money to be sent back.
+pub fn pay(
+    conn: &mut PgConnection,
+    verifier: &ChainVerifier,
+    id: i32,
+    buyer_id: i32,
+    tx_hash: &str,
+) -> Result<(Listing, Ticket), ListingError> {
+    let listing = ticket_listings::table
+        .find(id)
+        .select(Listing::as_select())
+        .get_result::<Listing>(conn)
+        .optional()?
+        .ok_or(ListingError::NotFound)?;
+    if listing.seller_id == buyer_id {
+        return Err(ListingError::OwnListing);
+    }
+    let wallet = users::table
+        .find(buyer_id)
+        .select(users::wallet_address)
+        .get_result::<Option<String>>(conn)?
+        .ok_or(ListingError::NoWallet)?;
+    let price = listing.price;
+    verifier.verify(tx_hash, &wallet, Money::new(price, listing.currency))?;
+
+    let claim = NewChainPayment {
+        tx_hash,
+        order_id: None,
+        listing_id: Some(listing.id),
+        user_id: buyer_id,
+        sender: &wallet,
+        amount: price,
+        currency: listing.currency,
+        status: ChainPaymentStatus::Pending,
+    };
+    if !chain_payments::claim(conn, &claim)? {
+        return Err(ListingError::PaymentReferenceUsed);
+    }
+
+    conn.transaction(|conn| {
+        if chain_payments::lock_pending(conn, tx_hash)?.is_none() {
+            return Err(ListingError::PaymentReferenceUsed);
+        }
+        match settle(conn, id, buyer_id, price, tx_hash) {
+            Ok(sold) => {
+                chain_payments::complete(conn, tx_hash, ChainPaymentStatus::Settled)?;
+                Ok(Ok(sold))
+            }
+            Err(ListingError::NotAvailable) | Err(ListingError::TicketNotFound) => {
+                eprintln!(
+                    "listings: transaction {} paid but listing {} can no longer be sold",
+                    tx_hash, id
+                );
+                chain_payments::complete(conn, tx_hash, ChainPaymentStatus::Unmatched)?;
+                Ok(Err(ListingError::PaymentUnmatched))
+            }
+            Err(err) => Err(err),
+        }
+    })?
+}
+
+/// Sell the listing to `buyer_id` for `price`, paid by `tx_hash`. The payment
+/// is held by the platform: the ticket is reissued to the buyer and the
+/// seller's and organizer's shares are recorded in the same transaction, so
+/// either everything happens or nothing does.
+fn settle(
+    conn: &mut PgConnection,
+    id: i32,
+    buyer_id: i32,
+    price: Amount,
+    tx_hash: &str,
+) -> Result<(Listing, Ticket), ListingError> {
+    conn.transaction(|conn| {
+        let listing = find_for_update(conn, id)?;
+        if !payable_by(&listing, buyer_id, price) {
+            return Err(ListingError::NotAvailable);
+        }
+
+        let ticket = tickets_table::table
+            .find(listing.ticket_id)
+            .select(Ticket::as_select())
+            .for_update()
+            .get_result::<Ticket>(conn)?;
+        if ticket.owner_id != listing.seller_id || ticket.status != TicketStatus::Valid {
+            return Err(ListingError::TicketNotFound);
+        }
+
+        let event = event_of(conn, listing.event_id)?;
+        let royalty_amount = listing
+            .price
+            .percent(event.resale_royalty_percent)
+            .map_err(Error::from)?;
+        // The platform fee comes out of the seller's share.
+        let fee_amount = listing
+            .price
+            .basis_points(config::platform_fee_bps())
+            .map_err(Error::from)?
+            .min(listing.price - royalty_amount);
+        let listing = diesel::update(ticket_listings::table.find(id))
+            .set((
+                ticket_listings::status.eq(ListingStatus::Sold),
+                ticket_listings::buyer_id.eq(buyer_id),
+                ticket_listings::reserved_until.eq(None::<DateTime<Utc>>),
+                ticket_listings::payment_reference.eq(tx_hash),
+                ticket_listings::royalty_amount.eq(royalty_amount),
+                ticket_listings::seller_amount.eq(listing.price - royalty_amount - fee_amount),
+                ticket_listings::sold_at.eq(Utc::now()),
+            ))
+            .returning(Listing::as_returning())
+            .get_result::<Listing>(conn)?;
+
+        let ticket = tickets::reissue(conn, ticket.id, buyer_id, AcquiredVia::Resale, None)?;
+        ledger::record_resale(conn, &listing, event.userid)?;
+
+        notifications::notify(
+            conn,
+            &NewNotification {
+                user_id: listing.seller_id,
+                kind: NotificationKind::TicketTransferred,
+                title: "Ticket sold",
+                body: &format!("Your ticket for {} was sold on the resale market.", event.eventname),
+                event_id: Some(event.id),
+                order_id: None,
+            },
+        )?;
+        notifications::notify(
+            conn,
+            &NewNotification {
+                user_id: buyer_id,
+                kind: NotificationKind::TicketTransferred,
+                title: "Ticket purchased",
+                body: &format!("Your resale ticket for {} is ready.", event.eventname),
+                event_id: Some(event.id),
+                order_id: None,
+            },
+        )?;
+
+        Ok((listing, ticket))
+    })
+}
+
+/// Listings on sale for an event, cheapest first.
+pub fn for_event(
+    conn: &mut PgConnection,
+    event_id: i32,
+    max_price: Option<Amount>,
+    limit: i64,
+) -> QueryResult<Vec<Listing>> {
+    release_expired_reservations(conn)?;
+
+    let mut query = ticket_listings::table
+        .filter(ticket_listings::event_id.eq(event_id))
+        .filter(ticket_listings::status.eq(ListingStatus::Active))
+        .into_boxed();
+    if let Some(max_price) = max_price {
+        query = query.filter(ticket_listings::price.le(max_price));
+    }
+
+    query
+        .order((ticket_listings::price.asc(), ticket_listings::id.asc()))
+        .limit(limit.clamp(1, LISTINGS_MAX_LIMIT))
+        .select(Listing::as_select())
+        .load(conn)
+}
+
+/// Everything the user has listed, newest first.
+pub fn for_seller(conn: &mut PgConnection, seller_id: i32) -> QueryResult<Vec<Listing>> {
+    ticket_listings::table
+        .filter(ticket_listings::seller_id.eq(seller_id))
+        .order(ticket_listings::created_at.desc())
+        .select(Listing::as_select())
+        .load(conn)
+}
@@ -0,0 +1,473 @@
+use crate::database::orders::OrderStatus;
+use crate::database::tickets::TicketStatus;
+use crate::database::invoices;
+use crate::models::notifications::{Delivery, Notification, NotificationPreference};
+use crate::notifier::{self, Attachment};
+use crate::schema::{notification_deliveries, notification_preferences, notifications, orders, tickets};
+use chrono::Utc;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::dsl::count_star;
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::str::FromStr;
+
+pub const NOTIFICATIONS_DEFAULT_LIMIT: i64 = 20;
+pub const NOTIFICATIONS_MAX_LIMIT: i64 = 100;
+/// Deliveries handed to senders per worker run.
+const DELIVERY_BATCH: i64 = 100;
+/// A delivery is given up on after this many failed attempts.
+const MAX_DELIVERY_ATTEMPTS: i32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    OrderPaid,
+    EventCancelled,
+    TicketTransferred,
+    EventReminder,
+    RefundUpdated,
+    WaitlistOffer,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 6] = [
+        NotificationKind::OrderPaid,
+        NotificationKind::EventCancelled,
+        NotificationKind::TicketTransferred,
+        NotificationKind::EventReminder,
+        NotificationKind::RefundUpdated,
+        NotificationKind::WaitlistOffer,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::OrderPaid => "order_paid",
+            NotificationKind::EventCancelled => "event_cancelled",
+            NotificationKind::TicketTransferred => "ticket_transferred",
+            NotificationKind::EventReminder => "event_reminder",
+            NotificationKind::RefundUpdated => "refund_updated",
+            NotificationKind::WaitlistOffer => "waitlist_offer",
+        }
+    }
+}
+
+impl FromStr for NotificationKind {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "order_paid" => Ok(NotificationKind::OrderPaid),
+            "event_cancelled" => Ok(NotificationKind::EventCancelled),
+            "ticket_transferred" => Ok(NotificationKind::TicketTransferred),
+            "event_reminder" => Ok(NotificationKind::EventReminder),
+            "refund_updated" => Ok(NotificationKind::RefundUpdated),
+            "waitlist_offer" => Ok(NotificationKind::WaitlistOffer),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for NotificationKind {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for NotificationKind {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+/// Channels served by an outside sender. In-app notifications are simply
+/// rows in `notifications`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryChannel {
+    Email,
+    Push,
+}
+
+impl DeliveryChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryChannel::Email => "email",
+            DeliveryChannel::Push => "push",
+        }
+    }
+}
+
+impl FromStr for DeliveryChannel {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "email" => Ok(DeliveryChannel::Email),
+            "push" => Ok(DeliveryChannel::Push),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for DeliveryChannel {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for DeliveryChannel {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Sent,
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "sent" => Ok(DeliveryStatus::Sent),
+            "failed" => Ok(DeliveryStatus::Failed),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for DeliveryStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for DeliveryStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = notifications)]
+pub struct NewNotification<'a> {
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    pub title: &'a str,
+    pub body: &'a str,
+    pub event_id: Option<i32>,
+    pub order_id: Option<i32>,
+}
+
+pub fn preference(
+    conn: &mut PgConnection,
+    user_id: i32,
+    kind: NotificationKind,
+) -> QueryResult<NotificationPreference> {
+    Ok(notification_preferences::table
+        .find((user_id, kind))
+        .select(NotificationPreference::as_select())
+        .get_result(conn)
+        .optional()?
+        .unwrap_or_else(|| NotificationPreference::default_for(user_id, kind)))
+}
+
+/// Preferences for every kind, defaults filled in.
+pub fn preferences(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> QueryResult<Vec<NotificationPreference>> {
+    let stored = notification_preferences::table
+        .filter(notification_preferences::user_id.eq(user_id))
+        .select(NotificationPreference::as_select())
+        .load::<NotificationPreference>(conn)?;
+
+    Ok(NotificationKind::ALL
+        .into_iter()
+        .map(|kind| {
+            stored
+                .iter()
+                .find(|preference| preference.kind == kind)
+                .cloned()
+                .unwrap_or_else(|| NotificationPreference::default_for(user_id, kind))
+        })
+        .collect())
+}
+
+pub fn update_preferences(
+    conn: &mut PgConnection,
+    user_id: i32,
+    changes: &[NotificationPreference],
+) -> QueryResult<Vec<NotificationPreference>> {
+    conn.transaction(|conn| {
+        for change in changes {
+            let change = NotificationPreference {
+                user_id,
+                ..change.clone()
+            };
+            diesel::insert_into(notification_preferences::table)
+                .values(&change)
+                .on_conflict((notification_preferences::user_id, notification_preferences::kind))
+                .do_update()
+                .set(&change)
+                .execute(conn)?;
+        }
+        preferences(conn, user_id)
+    })
+}
+
+/// The single entry point for telling a user something. Stores the in-app
+/// notification and queues email and push deliveries, each only if the
+/// user's preferences allow that channel.
+pub fn notify(conn: &mut PgConnection, notification: &NewNotification) -> QueryResult<()> {
+    let preference = preference(conn, notification.user_id, notification.kind)?;
+
+    if preference.in_app {
+        diesel::insert_into(notifications::table)
+            .values(notification)
+            .execute(conn)?;
+    }
+
+    let channels = [
+        (preference.email, DeliveryChannel::Email),
+        (preference.push, DeliveryChannel::Push),
+    ];
+    for (_, channel) in channels.iter().filter(|(enabled, _)| *enabled) {
+        diesel::insert_into(notification_deliveries::table)
+            .values((
+                notification_deliveries::user_id.eq(notification.user_id),
+                notification_deliveries::channel.eq(channel),
+                notification_deliveries::kind.eq(notification.kind),
+                notification_deliveries::title.eq(notification.title),
+                notification_deliveries::body.eq(notification.body),
+                notification_deliveries::order_id.eq(notification.order_id),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Everyone holding a paid order or a valid ticket for the event.
+pub fn attendees(conn: &mut PgConnection, event_id: i32) -> QueryResult<BTreeSet<i32>> {
+    let mut attendees = orders::table
+        .filter(orders::event_id.eq(event_id))
+        .filter(orders::status.eq(OrderStatus::Paid))
+        .select(orders::user_id)
+        .distinct()
+        .load::<i32>(conn)?
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    attendees.extend(
+        tickets::table
+            .filter(tickets::event_id.eq(event_id))
+            .filter(tickets::status.eq(TicketStatus::Valid))
+            .select(tickets::owner_id)
+            .distinct()
+            .load::<i32>(conn)?,
+    );
+    Ok(attendees)
+}
+
+/// Notify everyone holding a paid order or a valid ticket for the event.
+pub fn notify_attendees(
+    conn: &mut PgConnection,
+    event_id: i32,
+    kind: NotificationKind,
+    title: &str,
+    body: &str,
+) -> QueryResult<usize> {
+    let attendees = attendees(conn, event_id)?;
+    for user_id in &attendees {
+        notify(
+            conn,
+            &NewNotification {
+                user_id: *user_id,
+                kind,
+                title,
+                body,
+                event_id: Some(event_id),
+                order_id: None,
+            },
+        )?;
+    }
+    Ok(attendees.len())
+}
+
+/// Newest first. `cursor` is the id of the last notification of the
+/// previous page.
+pub fn list(
+    conn: &mut PgConnection,
+    user_id: i32,
+    unread_only: bool,
+    cursor: Option<i64>,
+    limit: i64,
+) -> QueryResult<Vec<Notification>> {
+    let mut query = notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .into_boxed();
+    if unread_only {
+        query = query.filter(notifications::read_at.is_null());
+    }
+    if let Some(cursor) = cursor {
+        query = query.filter(notifications::id.lt(cursor));
+    }
+
+    query
+        .order(notifications::id.desc())
+        .limit(limit.clamp(1, NOTIFICATIONS_MAX_LIMIT))
+        .select(Notification::as_select())
+        .load(conn)
+}
+
+pub fn unread_count(conn: &mut PgConnection, user_id: i32) -> QueryResult<i64> {
+    notifications::table
+        .filter(notifications::user_id.eq(user_id))
+        .filter(notifications::read_at.is_null())
+        .select(count_star())
+        .get_result(conn)
+}
+
+/// Mark the given notifications of `user_id` as read. Ids of other users'
+/// notifications are ignored.
+pub fn mark_read(conn: &mut PgConnection, user_id: i32, ids: &[i64]) -> QueryResult<usize> {
+    diesel::update(
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::id.eq_any(ids))
+            .filter(notifications::read_at.is_null()),
+    )
+    .set(notifications::read_at.eq(Utc::now()))
+    .execute(conn)
+}
+
+pub fn mark_all_read(conn: &mut PgConnection, user_id: i32) -> QueryResult<usize> {
+    diesel::update(
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::read_at.is_null()),
+    )
+    .set(notifications::read_at.eq(Utc::now()))
+    .execute(conn)
+}
+
+/// Payment confirmation emails carry the order's receipt.
+fn attachments(conn: &mut PgConnection, delivery: &Delivery) -> QueryResult<Vec<Attachment>> {
+    let order_id = match (delivery.channel, delivery.kind, delivery.order_id) {
+        (DeliveryChannel::Email, NotificationKind::OrderPaid, Some(order_id)) => order_id,
+        _ => return Ok(Vec::new()),
+    };
+    Ok(invoices::receipt(conn, order_id)?
+        .map(|receipt| Attachment {
+            filename: receipt.filename("pdf"),
+            content_type: "application/pdf",
+            content: receipt.pdf(),
+        })
+        .into_iter()
+        .collect())
+}
+
+/// Hand pending email and push deliveries to their senders, up to
+/// `DELIVERY_BATCH` per run. Each delivery is claimed, sent and recorded in
+/// its own transaction, so a sent message is never rolled back to pending by
+/// a later failure and other workers only skip the row being sent. Failures
+/// are retried on the next run until `MAX_DELIVERY_ATTEMPTS` is reached.
+pub fn deliver_pending(conn: &mut PgConnection) -> QueryResult<usize> {
+    let mut sent = 0;
+    let mut after = 0;
+    for _ in 0..DELIVERY_BATCH {
+        match deliver_next(conn, after)? {
+            Some((id, delivered)) => {
+                after = id;
+                if delivered {
+                    sent += 1;
+                }
+            }
+            None => break,
+        }
+    }
+    Ok(sent)
+}
+
+/// Send the oldest pending delivery after `after` that no other worker is
+/// sending. Returns its id and whether it went out, `None` if there is none.
+fn deliver_next(conn: &mut PgConnection, after: i64) -> QueryResult<Option<(i64, bool)>> {
+    conn.transaction(|conn| {
+        let delivery = match notification_deliveries::table
+            .filter(notification_deliveries::status.eq(DeliveryStatus::Pending))
+            .filter(notification_deliveries::id.gt(after))
+            .order(notification_deliveries::id.asc())
+            .select(Delivery::as_select())
+            .for_update()
+            .skip_locked()
+            .first::<Delivery>(conn)
+            .optional()?
+        {
+            Some(delivery) => delivery,
+            None => return Ok(None),
+        };
+
+        let target = notification_deliveries::table.find(delivery.id);
+        let attachments = attachments(conn, &delivery)?;
+        match notifier::sender_for(delivery.channel).send(&delivery, &attachments) {
+            Ok(()) => {
+                diesel::update(target)
+                    .set((
+                        notification_deliveries::status.eq(DeliveryStatus::Sent),
+                        notification_deliveries::attempts.eq(delivery.attempts + 1),
+                        notification_deliveries::sent_at.eq(Utc::now()),
+                    ))
+                    .execute(conn)?;
+                Ok(Some((delivery.id, true)))
+            }
+            Err(err) => {
+                let status = if delivery.attempts + 1 >= MAX_DELIVERY_ATTEMPTS {
+                    DeliveryStatus::Failed
+                } else {
+                    DeliveryStatus::Pending
+                };
+                diesel::update(target)
+                    .set((
+                        notification_deliveries::status.eq(status),
+                        notification_deliveries::attempts.eq(delivery.attempts + 1),
+                        notification_deliveries::last_error.eq(err),
+                    ))
+                    .execute(conn)?;
+                Ok(Some((delivery.id, false)))
+            }
+        }
+    })
+}
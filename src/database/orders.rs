@@ -0,0 +1,573 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::chain::{ChainVerifier, VerifyError};
+use crate::database::chain_payments::{self, ChainPaymentStatus, NewChainPayment};
+use crate::database::events::EventStatus;
+use crate::database::invoices;
+use crate::database::ledger;
+use crate::database::notifications::{self, NewNotification, NotificationKind};
+use crate::database::payments::{self, PaymentMethod};
+use crate::database::promotions::{self, Pricing};
+use crate::database::rates::RateTable;
+use crate::database::waitlist;
+use crate::models::events::Event;
+use crate::models::orders::{Order, OrderFiltering};
+use crate::models::tickets::Ticket;
+use crate::money::{Amount, Currency};
+use crate::schema::{events, orders, users};
+use chrono::{DateTime, Duration, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use rocket::form::FromFormField;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+/// How long seats stay reserved for an unpaid order.
+pub const HOLD_MINUTES: i64 = 15;
+pub const MAX_TICKETS_PER_ORDER: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderStatus {
+    Pending,
+    Paid,
+    Expired,
+    Cancelled,
+    Refunded,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "pending",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Expired => "expired",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "pending" => Ok(OrderStatus::Pending),
+            "paid" => Ok(OrderStatus::Paid),
+            "expired" => Ok(OrderStatus::Expired),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "refunded" => Ok(OrderStatus::Refunded),
+            _ => Err(()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for OrderStatus {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation("invalid order status"))
+        })
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for OrderStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for OrderStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = orders)]
+pub struct NewOrder {
+    pub user_id: i32,
+    pub event_id: i32,
+    pub quantity: i32,
+    pub unit_price: Amount,
+    pub subtotal_amount: Amount,
+    pub discount_amount: Amount,
+    pub total_amount: Amount,
+    pub currency: Currency,
+    pub promo_code_id: Option<i32>,
+    pub early_bird_rule_id: Option<i32>,
+    pub pay_currency: Option<Currency>,
+    pub pay_amount: Option<Amount>,
+    pub expires_at: DateTime<Utc>,
+    pub organizer_id: i32,
+}
+
+pub enum OrderError {
+    NotFound,
+    NotOwner,
+    EventNotFound,
+    EventNotOnSale,
+    SoldOut,
+    NotPending,
+    HoldExpired,
+    InvalidPromoCode,
+    /// The code exists but isn't valid at this time.
+    PromoCodeExpired,
+    PromoCodeUsedUp,
+    /// No recent exchange rate to quote the order in the requested currency.
+    RateUnavailable,
+    /// A card or mobile money payment of the order is awaiting its outcome.
+    PaymentInProgress,
+    /// The transaction doesn't exist, failed or doesn't pay the amount due.
+    PaymentNotVerified,
+    /// The transaction wasn't sent from the buyer's wallet.
+    WrongSender,
+    /// The buyer has no wallet address to pay on chain from.
+    NoWallet,
+    /// The transaction isn't buried under enough blocks yet.
+    PaymentUnconfirmed,
+    /// The transaction already paid for another order or listing.
+    PaymentReferenceUsed,
+    /// The transfer arrived but the order can't be paid any more; its money
+    /// is owed back.
+    PaymentUnmatched,
+    /// The order's currency can't be paid on chain.
+    OnchainUnsupported,
+    /// The chain couldn't be asked about the transaction.
+    VerificationUnavailable,
+    Other,
+}
+
+impl From<Error> for OrderError {
+    fn from(err: Error) -> OrderError {
+        if let Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) = &err {
+            if info.constraint_name() == Some("chain_payments_pkey") {
+                return OrderError::PaymentReferenceUsed;
+            }
+        }
+        match err {
+            Error::NotFound => OrderError::NotFound,
+            _ => OrderError::Other,
+        }
+    }
+}
+
+impl From<VerifyError> for OrderError {
+    fn from(err: VerifyError) -> OrderError {
+        match err {
+            VerifyError::NotFound | VerifyError::Mismatch => OrderError::PaymentNotVerified,
+            VerifyError::WrongSender => OrderError::WrongSender,
+            VerifyError::Unconfirmed => OrderError::PaymentUnconfirmed,
+            VerifyError::UnsupportedCurrency => OrderError::OnchainUnsupported,
+            VerifyError::Unavailable(err) => {
+                eprintln!("chain: {}", err);
+                OrderError::VerificationUnavailable
+            }
+        }
+    }
+}
+
+/// Expire pending orders whose hold ran out and give their seats back.
+pub fn release_expired_holds(conn: &mut PgConnection) -> QueryResult<usize> {
+    diesel::sql_query(
+        "WITH expired AS ( \
+             UPDATE orders SET status = 'expired' \
+             WHERE status = 'pending' AND expires_at < now() \
+             RETURNING event_id, quantity \
+         ) \
+         UPDATE events SET tickets_sold = events.tickets_sold - released.quantity \
+         FROM (SELECT event_id, sum(quantity)::int AS quantity FROM expired GROUP BY event_id) released \
+         WHERE events.id = released.event_id",
+    )
+    .execute(conn)
+}
+
+/// Reserve `quantity` seats of an occurrence for `user_id`. The seats count
+/// against the event's inventory until the order is paid or the hold expires.
+/// Early-bird pricing and `promo_code` are applied here, never trusted from
+/// the client. Paying in `pay_currency` locks the exchange rate for as long
+/// as the seats are held.
+pub fn create(
+    conn: &mut PgConnection,
+    user_id: i32,
+    event_id: i32,
+    quantity: i32,
+    promo_code: Option<&str>,
+    pay_currency: Option<Currency>,
+) -> Result<Order, OrderError> {
+    conn.transaction(|conn| {
+        release_expired_holds(conn)?;
+
+        let event = events::table
+            .find(event_id)
+            .select(Event::as_select())
+            .for_update()
+            .get_result::<Event>(conn)
+            .optional()?
+            .ok_or(OrderError::EventNotFound)?;
+
+        if event.status != EventStatus::Published || event.starts_at <= Utc::now() {
+            return Err(OrderError::EventNotOnSale);
+        }
+        // Seats owed to the waitlist aren't for sale.
+        if !waitlist::can_buy(conn, &event, quantity)? {
+            return Err(OrderError::SoldOut);
+        }
+
+        let pricing = price(conn, &event, user_id, quantity, promo_code, pay_currency)?;
+        Ok(hold(conn, user_id, &event, &pricing, Duration::minutes(HOLD_MINUTES))?)
+    })
+}
+
+fn price(
+    conn: &mut PgConnection,
+    event: &Event,
+    user_id: i32,
+    quantity: i32,
+    promo_code: Option<&str>,
+    pay_currency: Option<Currency>,
+) -> Result<Pricing, OrderError> {
+    let promo = promo_code
+        .map(|code| promotions::redeemable(conn, event, user_id, code))
+        .transpose()?;
+    let mut pricing = promotions::price(conn, event, quantity, promo.as_ref())?;
+    if let Some(pay_currency) = pay_currency.filter(|currency| *currency != pricing.currency) {
+        let pay = RateTable::load(conn)?
+            .quote(pricing.total_amount, pricing.currency, pay_currency)
+            .ok_or(OrderError::RateUnavailable)?;
+        pricing.pay = Some(pay);
+    }
+    Ok(pricing)
+}
+
+/// What `user_id` would pay for `quantity` tickets of the event right now.
+/// Nothing is reserved, so the price may change before checkout.
+pub fn quote(
+    conn: &mut PgConnection,
+    event_id: i32,
+    user_id: i32,
+    quantity: i32,
+    promo_code: Option<&str>,
+    pay_currency: Option<Currency>,
+) -> Result<Pricing, OrderError> {
+    conn.transaction(|conn| {
+        let event = events::table
+            .find(event_id)
+            .select(Event::as_select())
+            .get_result::<Event>(conn)
+            .optional()?
+            .ok_or(OrderError::EventNotFound)?;
+        price(conn, &event, user_id, quantity, promo_code, pay_currency)
+    })
+}
+
+/// Take the priced seats out of the event's inventory and hold them for
+/// `user_id` in a pending order. The caller checks availability and prices
+/// the order with the event row locked.
+pub fn hold(
+    conn: &mut PgConnection,
+    user_id: i32,
+    event: &Event,
+    pricing: &Pricing,
+    hold_for: Duration,
+) -> QueryResult<Order> {
+    diesel::update(events::table.find(event.id))
+        .set(events::tickets_sold.eq(events::tickets_sold + pricing.quantity))
+        .execute(conn)?;
+
+    let new_order = NewOrder {
+        user_id,
+        event_id: event.id,
+        quantity: pricing.quantity,
+        unit_price: pricing.unit_price,
+        subtotal_amount: pricing.subtotal_amount,
+        discount_amount: pricing.discount_amount,
+        total_amount: pricing.total_amount,
+        currency: pricing.currency,
+        promo_code_id: pricing.promo_code_id,
+        early_bird_rule_id: pricing.early_bird_rule_id,
+        pay_currency: pricing.pay.map(|pay| pay.currency),
+        pay_amount: pricing.pay.map(|pay| pay.amount),
+        expires_at: Utc::now() + hold_for,
+        organizer_id: event.userid,
+    };
+
+    diesel::insert_into(orders::table)
+        .values(&new_order)
+        .returning(Order::as_returning())
+        .get_result(conn)
+}
+
+pub fn find_pending_for_update(
+    conn: &mut PgConnection,
+    id: i32,
+    user_id: i32,
+) -> Result<Order, OrderError> {
+    let order = orders::table
+        .find(id)
+        .select(Order::as_select())
+        .for_update()
+        .get_result::<Order>(conn)?;
+
+    if order.user_id != user_id {
+        return Err(OrderError::NotOwner);
+    }
+    if order.status != OrderStatus::Pending {
+        return Err(OrderError::NotPending);
+    }
+    Ok(order)
+}
+
+/// Pay an order with an on-chain transfer. `tx_hash` is the normalized hash
+/// of a transaction that must pay the platform wallet `amount_due()` from the
+/// buyer's wallet and be confirmed; it is checked before anything is locked.
+/// The transaction is then claimed and the claim committed, so that it pays
+/// for nothing else and its money is on record even if the order can't be
+/// paid any more. A hold that ran out while the transfer confirmed is taken
+/// again if the seats are still there; otherwise the claim is left
+/// `unmatched` for the money to be sent back.
+pub fn pay(
+    conn: &mut PgConnection,
+    verifier: &ChainVerifier,
+    id: i32,
+    user_id: i32,
+    tx_hash: &str,
+) -> Result<(Order, Vec<Ticket>), OrderError> {
+    let order = orders::table
+        .find(id)
+        .select(Order::as_select())
+        .get_result::<Order>(conn)?;
+    if order.user_id != user_id {
+        return Err(OrderError::NotOwner);
+    }
+    let wallet = users::table
+        .find(user_id)
+        .select(users::wallet_address)
+        .get_result::<Option<String>>(conn)?
+        .ok_or(OrderError::NoWallet)?;
+    let amount_due = order.amount_due();
+    verifier.verify(tx_hash, &wallet, amount_due)?;
+
+    let claim = NewChainPayment {
+        tx_hash,
+        order_id: Some(order.id),
+        listing_id: None,
+        user_id,
+        sender: &wallet,
+        amount: amount_due.amount,
+        currency: amount_due.currency,
+        status: ChainPaymentStatus::Pending,
+    };
+    conn.transaction(|conn| {
+        if payments::in_progress(conn, order.id)? {
+            return Err(OrderError::PaymentInProgress);
+        }
+        if !chain_payments::claim(conn, &claim)? {
+            return Err(OrderError::PaymentReferenceUsed);
+        }
+        Ok(())
+    })?;
+
+    conn.transaction(|conn| {
+        if chain_payments::lock_pending(conn, tx_hash)?.is_none() {
+            return Err(OrderError::PaymentReferenceUsed);
+        }
+        match reinstate(conn, order.id, tx_hash, PaymentMethod::Onchain) {
+            Ok(paid) => {
+                chain_payments::complete(conn, tx_hash, ChainPaymentStatus::Settled)?;
+                Ok(Ok(paid))
+            }
+            Err(OrderError::NotPending)
+            | Err(OrderError::SoldOut)
+            | Err(OrderError::EventNotOnSale) => {
+                eprintln!(
+                    "orders: transaction {} paid but order {} can no longer be paid",
+                    tx_hash, order.id
+                );
+                chain_payments::complete(conn, tx_hash, ChainPaymentStatus::Unmatched)?;
+                Ok(Err(OrderError::PaymentUnmatched))
+            }
+            Err(err) => Err(err),
+        }
+    })?
+}
+
+/// Pay a pending order whose payment a provider confirmed.
+/// `payment_reference` is the provider's id for the payment.
+pub fn settle(
+    conn: &mut PgConnection,
+    id: i32,
+    payment_reference: &str,
+    method: PaymentMethod,
+) -> Result<(Order, Vec<Ticket>), OrderError> {
+    conn.transaction(|conn| {
+        let order = orders::table
+            .find(id)
+            .select(Order::as_select())
+            .for_update()
+            .get_result::<Order>(conn)?;
+        if order.status != OrderStatus::Pending {
+            return Err(OrderError::NotPending);
+        }
+        mark_paid(conn, order, payment_reference, method)
+    })
+}
+
+/// Pay an order whose money arrived, taking its seats again if the hold ran
+/// out while the payment was being approved. The buyer was charged what the
+/// order asked for when the payment started, so the price stands.
+pub fn reinstate(
+    conn: &mut PgConnection,
+    id: i32,
+    payment_reference: &str,
+    method: PaymentMethod,
+) -> Result<(Order, Vec<Ticket>), OrderError> {
+    conn.transaction(|conn| {
+        let order = orders::table
+            .find(id)
+            .select(Order::as_select())
+            .for_update()
+            .get_result::<Order>(conn)?;
+        let event = events::table
+            .find(order.event_id)
+            .select(Event::as_select())
+            .for_update()
+            .get_result::<Event>(conn)?;
+        if event.status != EventStatus::Published || event.starts_at <= Utc::now() {
+            return Err(OrderError::EventNotOnSale);
+        }
+
+        match order.status {
+            // Not swept yet, so its seats are still counted.
+            OrderStatus::Pending => {}
+            OrderStatus::Expired => {
+                if matches!(event.tickets_available(), Some(available) if available < order.quantity)
+                {
+                    return Err(OrderError::SoldOut);
+                }
+                diesel::update(events::table.find(event.id))
+                    .set(events::tickets_sold.eq(events::tickets_sold + order.quantity))
+                    .execute(conn)?;
+            }
+            _ => return Err(OrderError::NotPending),
+        }
+
+        let order = diesel::update(orders::table.find(order.id))
+            .set((
+                orders::status.eq(OrderStatus::Pending),
+                orders::expires_at.eq(Utc::now() + Duration::minutes(HOLD_MINUTES)),
+            ))
+            .returning(Order::as_returning())
+            .get_result::<Order>(conn)?;
+        mark_paid(conn, order, payment_reference, method)
+    })
+}
+
+/// The shared end of every way to pay: the order locked and pending.
+fn mark_paid(
+    conn: &mut PgConnection,
+    order: Order,
+    payment_reference: &str,
+    method: PaymentMethod,
+) -> Result<(Order, Vec<Ticket>), OrderError> {
+    if order.expires_at <= Utc::now() {
+        return Err(OrderError::HoldExpired);
+    }
+
+    let order = diesel::update(orders::table.find(order.id))
+        .set((
+            orders::status.eq(OrderStatus::Paid),
+            orders::paid_at.eq(Utc::now()),
+            orders::payment_reference.eq(payment_reference),
+            orders::payment_method.eq(method),
+        ))
+        .returning(Order::as_returning())
+        .get_result::<Order>(conn)?;
+
+    let tickets = crate::database::tickets::issue(
+        conn,
+        order.id,
+        order.event_id,
+        order.user_id,
+        order.quantity,
+    )?;
+    waitlist::mark_purchased(conn, order.id)?;
+
+    let eventname = events::table
+        .find(order.event_id)
+        .select(events::eventname)
+        .get_result::<String>(conn)?;
+    ledger::record_sale(conn, &order)?;
+    invoices::issue(conn, &order)?;
+    notifications::notify(
+        conn,
+        &NewNotification {
+            user_id: order.user_id,
+            kind: NotificationKind::OrderPaid,
+            title: "Payment received",
+            body: &format!("Your {} ticket(s) for {} are ready.", order.quantity, eventname),
+            event_id: Some(order.event_id),
+            order_id: Some(order.id),
+        },
+    )?;
+
+    Ok((order, tickets))
+}
+
+/// Abandon a pending order and release its seats.
+pub fn cancel(conn: &mut PgConnection, id: i32, user_id: i32) -> Result<Order, OrderError> {
+    conn.transaction(|conn| {
+        let order = find_pending_for_update(conn, id, user_id)?;
+
+        diesel::update(events::table.find(order.event_id))
+            .set(events::tickets_sold.eq(events::tickets_sold - order.quantity))
+            .execute(conn)?;
+
+        let order = diesel::update(orders::table.find(id))
+            .set(orders::status.eq(OrderStatus::Cancelled))
+            .returning(Order::as_returning())
+            .get_result(conn)?;
+
+        Ok(order)
+    })
+}
+
+pub fn get_orders(
+    conn: &mut PgConnection,
+    user: i32,
+    filters: Option<OrderFiltering>,
+) -> Result<Vec<Order>, diesel::result::Error> {
+    use crate::schema::orders::dsl::*;
+
+    let mut query = orders.filter(user_id.eq(user)).into_boxed();
+    if let Some(f) = filters {
+        if let Some(id_filter) = f.id {
+            query = query.filter(id.eq(id_filter));
+        }
+        if let Some(event_id_filter) = f.event_id {
+            query = query.filter(event_id.eq(event_id_filter));
+        }
+        if let Some(status_filter) = f.status {
+            query = query.filter(status.eq(status_filter));
+        }
+        if let Some(limit_filter) = f.limit {
+            query = query.limit(limit_filter);
+        }
+    }
+
+    query
+        .order(created_at.desc())
+        .select(Order::as_select())
+        .load(conn)
+}
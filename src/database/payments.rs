@@ -0,0 +1,459 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::database::orders::{self, OrderError};
+use crate::models::payments::Payment;
+use crate::payments::{PaymentEvent, PaymentOutcome, PaymentProvider, PaymentRequest, RefundError};
+use crate::schema::{orders as orders_table, payments};
+use chrono::{Duration, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+/// Card and mobile money approvals can take a while; the hold is extended
+/// to at least this long when the first payment starts.
+const PAYMENT_HOLD_MINUTES: i64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentMethod {
+    Onchain,
+    Card,
+    MobileMoney,
+}
+
+impl PaymentMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentMethod::Onchain => "onchain",
+            PaymentMethod::Card => "card",
+            PaymentMethod::MobileMoney => "mobile_money",
+        }
+    }
+
+    /// Paid through a payment provider rather than on-chain.
+    pub fn is_fiat(&self) -> bool {
+        *self != PaymentMethod::Onchain
+    }
+}
+
+impl FromStr for PaymentMethod {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "onchain" => Ok(PaymentMethod::Onchain),
+            "card" => Ok(PaymentMethod::Card),
+            "mobile_money" => Ok(PaymentMethod::MobileMoney),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for PaymentMethod {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for PaymentMethod {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentStatus {
+    Pending,
+    Succeeded,
+    Failed,
+    /// The money arrived but the order couldn't be paid any more; it is
+    /// returned through the provider.
+    Unmatched,
+    /// The provider has been asked to refund an unmatched payment and hasn't
+    /// said yes or no yet.
+    Refunding,
+    /// The money of an unmatched payment went back to the buyer.
+    Refunded,
+}
+
+impl PaymentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentStatus::Pending => "pending",
+            PaymentStatus::Succeeded => "succeeded",
+            PaymentStatus::Failed => "failed",
+            PaymentStatus::Unmatched => "unmatched",
+            PaymentStatus::Refunding => "refunding",
+            PaymentStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl FromStr for PaymentStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "pending" => Ok(PaymentStatus::Pending),
+            "succeeded" => Ok(PaymentStatus::Succeeded),
+            "failed" => Ok(PaymentStatus::Failed),
+            "unmatched" => Ok(PaymentStatus::Unmatched),
+            "refunding" => Ok(PaymentStatus::Refunding),
+            "refunded" => Ok(PaymentStatus::Refunded),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for PaymentStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for PaymentStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+pub enum PaymentError {
+    NotFound,
+    OrderNotFound,
+    NotOwner,
+    NotPending,
+    HoldExpired,
+    /// On-chain is not a provider method.
+    UnsupportedMethod,
+    /// The provider can't charge the order's currency, e.g. crypto.
+    UnsupportedCurrency,
+    /// Another payment of the order is still in progress.
+    InProgress,
+    /// The provider refused or couldn't be reached.
+    Provider,
+    Other,
+}
+
+impl From<Error> for PaymentError {
+    fn from(err: Error) -> PaymentError {
+        match err {
+            Error::NotFound => PaymentError::NotFound,
+            Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => PaymentError::InProgress,
+            _ => PaymentError::Other,
+        }
+    }
+}
+
+impl From<OrderError> for PaymentError {
+    fn from(err: OrderError) -> PaymentError {
+        match err {
+            OrderError::NotFound => PaymentError::OrderNotFound,
+            OrderError::NotOwner => PaymentError::NotOwner,
+            OrderError::NotPending => PaymentError::NotPending,
+            OrderError::HoldExpired => PaymentError::HoldExpired,
+            OrderError::PaymentInProgress => PaymentError::InProgress,
+            _ => PaymentError::Other,
+        }
+    }
+}
+
+/// Start paying a pending order through `provider`. The payment row is
+/// committed before the provider is called so a fast webhook always finds
+/// it; a provider error marks it failed.
+pub fn start(
+    conn: &mut PgConnection,
+    provider: &dyn PaymentProvider,
+    order_id: i32,
+    user_id: i32,
+    method: PaymentMethod,
+    phone: Option<&str>,
+) -> Result<Payment, PaymentError> {
+    if !method.is_fiat() {
+        return Err(PaymentError::UnsupportedMethod);
+    }
+
+    let payment = conn.transaction(|conn| {
+        let order = orders::find_pending_for_update(conn, order_id, user_id)?;
+        let now = Utc::now();
+        if order.expires_at <= now {
+            return Err(PaymentError::HoldExpired);
+        }
+        let due = order.amount_due();
+        if !provider.supports(due.currency) {
+            return Err(PaymentError::UnsupportedCurrency);
+        }
+
+        let started_before = diesel::select(diesel::dsl::exists(
+            payments::table.filter(payments::order_id.eq(order.id)),
+        ))
+        .get_result::<bool>(conn)?;
+        let hold_until = now + Duration::minutes(PAYMENT_HOLD_MINUTES);
+        if !started_before && order.expires_at < hold_until {
+            diesel::update(orders_table::table.find(order.id))
+                .set(orders_table::expires_at.eq(hold_until))
+                .execute(conn)?;
+        }
+
+        let payment = diesel::insert_into(payments::table)
+            .values((
+                payments::order_id.eq(order.id),
+                payments::provider.eq(provider.name()),
+                payments::method.eq(method),
+                payments::amount.eq(due.amount),
+                payments::currency.eq(due.currency),
+            ))
+            .returning(Payment::as_returning())
+            .get_result::<Payment>(conn)?;
+        Ok(payment)
+    })?;
+
+    let request = PaymentRequest {
+        payment_id: payment.id,
+        amount: payment.amount,
+        currency: payment.currency,
+        method,
+        phone,
+    };
+    match provider.create(&request) {
+        Ok(checkout) => Ok(diesel::update(payments::table.find(payment.id))
+            .set((
+                payments::provider_reference.eq(checkout.reference),
+                payments::checkout_url.eq(checkout.checkout_url),
+            ))
+            .returning(Payment::as_returning())
+            .get_result::<Payment>(conn)?),
+        Err(err) => {
+            eprintln!(
+                "payments: {}: payment {}: {}",
+                provider.name(),
+                payment.id,
+                err
+            );
+            diesel::update(payments::table.find(payment.id))
+                .set((
+                    payments::status.eq(PaymentStatus::Failed),
+                    payments::completed_at.eq(Utc::now()),
+                ))
+                .execute(conn)?;
+            Err(PaymentError::Provider)
+        }
+    }
+}
+
+fn complete(conn: &mut PgConnection, id: i32, status: PaymentStatus) -> QueryResult<Payment> {
+    diesel::update(payments::table.find(id))
+        .set((
+            payments::status.eq(status),
+            payments::completed_at.eq(Utc::now()),
+        ))
+        .returning(Payment::as_returning())
+        .get_result(conn)
+}
+
+/// Apply a verified webhook. Redeliveries of an event already applied are
+/// no-ops. A successful payment pays the order like an on-chain one, taking
+/// the seats again if the hold ran out meanwhile. Money that can't pay the
+/// order any more, or doesn't match what was asked for, leaves the payment
+/// `unmatched` until `refund` returns it.
+pub fn handle_event(
+    conn: &mut PgConnection,
+    provider: &str,
+    event: &PaymentEvent,
+) -> Result<Payment, PaymentError> {
+    conn.transaction(|conn| {
+        let payment = payments::table
+            .filter(payments::provider.eq(provider))
+            .filter(payments::provider_reference.eq(&event.reference))
+            .select(Payment::as_select())
+            .for_update()
+            .get_result::<Payment>(conn)?;
+        if payment.status != PaymentStatus::Pending {
+            return Ok(payment);
+        }
+
+        if event.status == PaymentOutcome::Failed {
+            return Ok(complete(conn, payment.id, PaymentStatus::Failed)?);
+        }
+        if event.amount != payment.amount || event.currency != payment.currency {
+            eprintln!(
+                "payments: payment {} expected {} {} but received {} {}",
+                payment.id, payment.amount, payment.currency, event.amount, event.currency
+            );
+            return Ok(complete(conn, payment.id, PaymentStatus::Unmatched)?);
+        }
+
+        let settled = match orders::settle(conn, payment.order_id, &event.reference, payment.method)
+        {
+            Err(OrderError::NotPending) | Err(OrderError::HoldExpired) => {
+                orders::reinstate(conn, payment.order_id, &event.reference, payment.method)
+            }
+            settled => settled,
+        };
+        match settled {
+            Ok(_) => Ok(complete(conn, payment.id, PaymentStatus::Succeeded)?),
+            Err(OrderError::NotPending)
+            | Err(OrderError::SoldOut)
+            | Err(OrderError::EventNotOnSale) => {
+                eprintln!(
+                    "payments: payment {} succeeded but order {} can no longer be paid",
+                    payment.id, payment.order_id
+                );
+                Ok(complete(conn, payment.id, PaymentStatus::Unmatched)?)
+            }
+            Err(_) => Err(PaymentError::Other),
+        }
+    })
+}
+
+/// Return the money of an unmatched payment to the buyer. The payment is
+/// moved to `refunding` in its own statement before the provider is asked, so
+/// only one caller asks; the payment id goes along as idempotency key, so
+/// asking again about a `refunding` payment whose outcome never arrived can't
+/// refund twice. Only a refusal by the provider makes it `unmatched` again.
+pub fn refund(
+    conn: &mut PgConnection,
+    provider: &dyn PaymentProvider,
+    payment: &Payment,
+) -> QueryResult<Option<Payment>> {
+    let reference = match &payment.provider_reference {
+        Some(reference) => reference,
+        None => return Ok(None),
+    };
+    match payment.status {
+        PaymentStatus::Unmatched => {
+            let claimed = diesel::update(payments::table.find(payment.id))
+                .filter(payments::status.eq(PaymentStatus::Unmatched))
+                .set(payments::status.eq(PaymentStatus::Refunding))
+                .execute(conn)?;
+            if claimed == 0 {
+                return Ok(None);
+            }
+        }
+        PaymentStatus::Refunding => {}
+        _ => return Ok(None),
+    }
+
+    let status = match provider.refund(payment.id, reference, payment.amount, payment.currency) {
+        Ok(()) => PaymentStatus::Refunded,
+        Err(RefundError::Rejected(err)) => {
+            eprintln!(
+                "payments: {}: refund of payment {} rejected: {}",
+                provider.name(),
+                payment.id,
+                err
+            );
+            PaymentStatus::Unmatched
+        }
+        Err(RefundError::Unknown(err)) => {
+            eprintln!(
+                "payments: {}: refund of payment {} pending: {}",
+                provider.name(),
+                payment.id,
+                err
+            );
+            return Ok(None);
+        }
+    };
+    let payment = diesel::update(payments::table.find(payment.id))
+        .filter(payments::status.eq(PaymentStatus::Refunding))
+        .set(payments::status.eq(status))
+        .returning(Payment::as_returning())
+        .get_result::<Payment>(conn)
+        .optional()?;
+    Ok(payment.filter(|payment| payment.status == PaymentStatus::Refunded))
+}
+
+/// Retry refunds of unmatched payments of the configured provider, and of
+/// refunding ones whose outcome never arrived.
+pub fn refund_unmatched(conn: &mut PgConnection) -> QueryResult<usize> {
+    let provider = match crate::payments::provider() {
+        Ok(Some(provider)) => provider,
+        Ok(None) => return Ok(0),
+        Err(err) => {
+            eprintln!("payments: {}", err);
+            return Ok(0);
+        }
+    };
+    let unmatched = payments::table
+        .filter(payments::provider.eq(provider.name()))
+        .filter(payments::status.eq_any([PaymentStatus::Unmatched, PaymentStatus::Refunding]))
+        .order(payments::id.asc())
+        .select(Payment::as_select())
+        .load::<Payment>(conn)?;
+
+    let mut refunded = 0;
+    for payment in &unmatched {
+        if refund(conn, provider.as_ref(), payment)?.is_some() {
+            refunded += 1;
+        }
+    }
+    Ok(refunded)
+}
+
+pub fn find(conn: &mut PgConnection, id: i32) -> QueryResult<Payment> {
+    payments::table
+        .find(id)
+        .select(Payment::as_select())
+        .get_result(conn)
+}
+
+/// Payments of an order, newest first. Only the buyer sees them.
+pub fn for_order(
+    conn: &mut PgConnection,
+    order_id: i32,
+    user_id: i32,
+) -> Result<Vec<Payment>, PaymentError> {
+    let owner = orders_table::table
+        .find(order_id)
+        .select(orders_table::user_id)
+        .get_result::<i32>(conn)
+        .map_err(|err| match err {
+            Error::NotFound => PaymentError::OrderNotFound,
+            _ => PaymentError::Other,
+        })?;
+    if owner != user_id {
+        return Err(PaymentError::NotOwner);
+    }
+    Ok(payments::table
+        .filter(payments::order_id.eq(order_id))
+        .order(payments::created_at.desc())
+        .select(Payment::as_select())
+        .load(conn)?)
+}
+
+/// Whether a provider payment of the order is still waiting for its
+/// outcome. The order can't be paid another way meanwhile.
+pub fn in_progress(conn: &mut PgConnection, order_id: i32) -> QueryResult<bool> {
+    diesel::select(diesel::dsl::exists(
+        payments::table
+            .filter(payments::order_id.eq(order_id))
+            .filter(payments::status.eq(PaymentStatus::Pending)),
+    ))
+    .get_result(conn)
+}
+
+/// What a payment event would carry, for simulating webhooks.
+pub fn event_for(payment: &Payment, status: PaymentOutcome) -> Option<PaymentEvent> {
+    Some(PaymentEvent {
+        reference: payment.provider_reference.clone()?,
+        status,
+        amount: payment.amount,
+        currency: payment.currency,
+    })
+}
@@ -0,0 +1,240 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::database::ledger::{self, LedgerAccount};
+use crate::models::payouts::Payout;
+use crate::money::{Amount, Currency};
+use crate::schema::{payouts, users};
+use chrono::Utc;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use rocket::form::FromFormField;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum PayoutMethod {
+    Bank,
+    Wallet,
+}
+
+impl PayoutMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayoutMethod::Bank => "bank",
+            PayoutMethod::Wallet => "wallet",
+        }
+    }
+}
+
+impl FromStr for PayoutMethod {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "bank" => Ok(PayoutMethod::Bank),
+            "wallet" => Ok(PayoutMethod::Wallet),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for PayoutMethod {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for PayoutMethod {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum PayoutStatus {
+    Pending,
+    Paid,
+    Failed,
+}
+
+impl PayoutStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayoutStatus::Pending => "pending",
+            PayoutStatus::Paid => "paid",
+            PayoutStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for PayoutStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "pending" => Ok(PayoutStatus::Pending),
+            "paid" => Ok(PayoutStatus::Paid),
+            "failed" => Ok(PayoutStatus::Failed),
+            _ => Err(()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for PayoutStatus {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation("invalid payout status"))
+        })
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for PayoutStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for PayoutStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+pub enum PayoutError {
+    NotFound,
+    InsufficientBalance,
+    InvalidStatus,
+    Other,
+}
+
+impl From<Error> for PayoutError {
+    fn from(err: Error) -> PayoutError {
+        match err {
+            Error::NotFound => PayoutError::NotFound,
+            _ => PayoutError::Other,
+        }
+    }
+}
+
+/// Ask for part of the available balance to be paid out. The amount is set
+/// aside in the ledger until an admin settles the payout.
+pub fn request(
+    conn: &mut PgConnection,
+    organizer_id: i32,
+    amount: Amount,
+    currency: Currency,
+    method: PayoutMethod,
+    destination: &str,
+) -> Result<Payout, PayoutError> {
+    conn.transaction(|conn| {
+        // Serializes payout requests of one organizer so the balance can't
+        // be spent twice.
+        users::table
+            .find(organizer_id)
+            .select(users::id)
+            .for_update()
+            .get_result::<i32>(conn)?;
+
+        let available =
+            ledger::account_balance(conn, organizer_id, LedgerAccount::Organizer, currency)?;
+        if amount > available {
+            return Err(PayoutError::InsufficientBalance);
+        }
+
+        let payout = diesel::insert_into(payouts::table)
+            .values((
+                payouts::organizer_id.eq(organizer_id),
+                payouts::amount.eq(amount),
+                payouts::currency.eq(currency),
+                payouts::method.eq(method),
+                payouts::destination.eq(destination),
+            ))
+            .returning(Payout::as_returning())
+            .get_result::<Payout>(conn)?;
+        ledger::record_payout(conn, &payout)?;
+        Ok(payout)
+    })
+}
+
+pub fn for_organizer(conn: &mut PgConnection, organizer_id: i32) -> QueryResult<Vec<Payout>> {
+    payouts::table
+        .filter(payouts::organizer_id.eq(organizer_id))
+        .order(payouts::created_at.desc())
+        .select(Payout::as_select())
+        .load(conn)
+}
+
+/// Payouts of every organizer, oldest first, for admins to work through.
+pub fn list(conn: &mut PgConnection, status: Option<PayoutStatus>) -> QueryResult<Vec<Payout>> {
+    let mut query = payouts::table.into_boxed();
+    if let Some(status) = status {
+        query = query.filter(payouts::status.eq(status));
+    }
+    query
+        .order(payouts::created_at.asc())
+        .select(Payout::as_select())
+        .load(conn)
+}
+
+fn find_pending_for_update(conn: &mut PgConnection, id: i32) -> Result<Payout, PayoutError> {
+    let payout = payouts::table
+        .find(id)
+        .select(Payout::as_select())
+        .for_update()
+        .get_result::<Payout>(conn)?;
+    if payout.status != PayoutStatus::Pending {
+        return Err(PayoutError::InvalidStatus);
+    }
+    Ok(payout)
+}
+
+/// Record that the money was sent. `reference` is the bank transfer
+/// reference or transaction hash.
+pub fn settle(conn: &mut PgConnection, id: i32, reference: &str) -> Result<Payout, PayoutError> {
+    conn.transaction(|conn| {
+        find_pending_for_update(conn, id)?;
+        let payout = diesel::update(payouts::table.find(id))
+            .set((
+                payouts::status.eq(PayoutStatus::Paid),
+                payouts::reference.eq(reference),
+                payouts::settled_at.eq(Utc::now()),
+            ))
+            .returning(Payout::as_returning())
+            .get_result::<Payout>(conn)?;
+        ledger::record_payout_settled(conn, &payout)?;
+        Ok(payout)
+    })
+}
+
+/// The payout couldn't be sent; its amount becomes available again.
+pub fn fail(conn: &mut PgConnection, id: i32) -> Result<Payout, PayoutError> {
+    conn.transaction(|conn| {
+        find_pending_for_update(conn, id)?;
+        let payout = diesel::update(payouts::table.find(id))
+            .set((
+                payouts::status.eq(PayoutStatus::Failed),
+                payouts::settled_at.eq(Utc::now()),
+            ))
+            .returning(Payout::as_returning())
+            .get_result::<Payout>(conn)?;
+        ledger::record_payout_failed(conn, &payout)?;
+        Ok(payout)
+    })
+}
@@ -0,0 +1,420 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::database::orders::{OrderError, OrderStatus};
+use crate::models::events::Event;
+use crate::models::promotions::{EarlyBirdRule, PromoCode, PromoCodeUsage};
+use crate::money::{Amount, Currency, Money};
+use crate::schema::{early_bird_rules, events, orders, promo_codes};
+use chrono::{DateTime, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::dsl::count_star;
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::str::FromStr;
+
+pub const MIN_CODE_LENGTH: usize = 3;
+pub const MAX_CODE_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum DiscountKind {
+    /// `percent_off` percent off.
+    Percent,
+    /// `amount_off` off each ticket.
+    Fixed,
+}
+
+impl DiscountKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscountKind::Percent => "percent",
+            DiscountKind::Fixed => "fixed",
+        }
+    }
+}
+
+impl FromStr for DiscountKind {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "percent" => Ok(DiscountKind::Percent),
+            "fixed" => Ok(DiscountKind::Fixed),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for DiscountKind {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for DiscountKind {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discount {
+    Percent(i32),
+    /// Off each ticket, in the currency of the order.
+    Fixed(Amount),
+}
+
+impl Discount {
+    pub fn new(kind: DiscountKind, percent_off: Option<i32>, amount_off: Option<Amount>) -> Discount {
+        match kind {
+            DiscountKind::Percent => Discount::Percent(percent_off.unwrap_or(0)),
+            DiscountKind::Fixed => Discount::Fixed(amount_off.unwrap_or_default()),
+        }
+    }
+
+    /// Amount taken off `amount`, the price of `quantity` tickets. Never
+    /// more than the amount itself.
+    pub fn apply(&self, amount: Amount, quantity: i32) -> Amount {
+        let discount = match self {
+            Discount::Percent(percent) => amount.percent(*percent),
+            Discount::Fixed(amount_off) => amount_off.times(quantity),
+        };
+        // Too large to represent is more than the amount, so all of it.
+        discount.unwrap_or(amount).clamp(Amount::ZERO, amount)
+    }
+}
+
+pub enum PromoError {
+    EventNotFound,
+    /// A fixed discount must be in the currency of the event it's for.
+    CurrencyMismatch,
+    DuplicateCode,
+    NotFound,
+    Other,
+}
+
+impl From<Error> for PromoError {
+    fn from(err: Error) -> PromoError {
+        if let Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) = &err {
+            if info.constraint_name() == Some("promo_codes_organizer_code") {
+                return PromoError::DuplicateCode;
+            }
+        }
+        PromoError::Other
+    }
+}
+
+/// Codes are stored uppercased so "summer25" and "SUMMER25" are the same
+/// code. Returns `None` for codes that are too short, too long or contain
+/// anything but letters, digits, '-' and '_'.
+pub fn normalize_code(code: &str) -> Option<String> {
+    let code = code.trim().to_uppercase();
+    let valid = (MIN_CODE_LENGTH..=MAX_CODE_LENGTH).contains(&code.len())
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Some(code)
+    } else {
+        None
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewPromoCodeData {
+    /// Limits the code to one of the organizer's events.
+    pub event_id: Option<i32>,
+    pub code: String,
+    pub discount_kind: DiscountKind,
+    pub percent_off: Option<i32>,
+    /// Minor units of `currency`, required for fixed discounts.
+    pub amount_off: Option<Amount>,
+    pub currency: Option<Currency>,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_user: Option<i32>,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct NewEarlyBirdRuleData {
+    pub discount_kind: DiscountKind,
+    pub percent_off: Option<i32>,
+    /// Minor units of the event's currency.
+    pub amount_off: Option<Amount>,
+    pub ends_at: Option<DateTime<Utc>>,
+    pub max_tickets: Option<i32>,
+}
+
+/// What an order costs, worked out on the server at checkout.
+#[derive(Serialize, Debug)]
+pub struct Pricing {
+    pub unit_price: Amount,
+    pub quantity: i32,
+    /// Face value of the tickets.
+    pub subtotal_amount: Amount,
+    pub early_bird_discount: Amount,
+    pub promo_discount: Amount,
+    pub discount_amount: Amount,
+    pub total_amount: Amount,
+    pub currency: Currency,
+    pub early_bird_rule_id: Option<i32>,
+    pub promo_code_id: Option<i32>,
+    /// The total converted into the currency the buyer pays in, when that
+    /// isn't the event's.
+    pub pay: Option<Money>,
+}
+
+/// Currency of the event, `None` unless it belongs to `organizer_id`.
+fn organizer_event_currency(
+    conn: &mut PgConnection,
+    event_id: i32,
+    organizer_id: i32,
+) -> QueryResult<Option<Currency>> {
+    events::table
+        .find(event_id)
+        .filter(events::userid.eq(organizer_id))
+        .select(events::currency)
+        .get_result(conn)
+        .optional()
+}
+
+/// Statuses of orders that count as a use of their promo code.
+const USED_STATUSES: [OrderStatus; 3] = [OrderStatus::Pending, OrderStatus::Paid, OrderStatus::Refunded];
+
+/// Times the code was used, by anyone or by `user_id`.
+fn uses(conn: &mut PgConnection, promo_code_id: i32, user_id: Option<i32>) -> QueryResult<i64> {
+    let mut query = orders::table
+        .filter(orders::promo_code_id.eq(promo_code_id))
+        .filter(orders::status.eq_any(USED_STATUSES))
+        .into_boxed();
+    if let Some(user_id) = user_id {
+        query = query.filter(orders::user_id.eq(user_id));
+    }
+    query.select(count_star()).get_result(conn)
+}
+
+pub fn create_code(
+    conn: &mut PgConnection,
+    organizer_id: i32,
+    code: &str,
+    data: &NewPromoCodeData,
+) -> Result<PromoCode, PromoError> {
+    if let Some(event_id) = data.event_id {
+        let currency = organizer_event_currency(conn, event_id, organizer_id)?
+            .ok_or(PromoError::EventNotFound)?;
+        if data.discount_kind == DiscountKind::Fixed && data.currency != Some(currency) {
+            return Err(PromoError::CurrencyMismatch);
+        }
+    }
+
+    Ok(diesel::insert_into(promo_codes::table)
+        .values((
+            promo_codes::organizer_id.eq(organizer_id),
+            promo_codes::event_id.eq(data.event_id),
+            promo_codes::code.eq(code),
+            promo_codes::discount_kind.eq(data.discount_kind),
+            promo_codes::percent_off.eq(data.percent_off),
+            promo_codes::amount_off.eq(data.amount_off),
+            promo_codes::currency.eq(data.currency),
+            promo_codes::max_uses.eq(data.max_uses),
+            promo_codes::max_uses_per_user.eq(data.max_uses_per_user),
+            promo_codes::valid_from.eq(data.valid_from),
+            promo_codes::valid_until.eq(data.valid_until),
+        ))
+        .returning(PromoCode::as_returning())
+        .get_result(conn)?)
+}
+
+/// The organizer's codes with how often each was used, newest first.
+pub fn codes_for_organizer(conn: &mut PgConnection, organizer_id: i32) -> QueryResult<Vec<PromoCodeUsage>> {
+    let codes = promo_codes::table
+        .filter(promo_codes::organizer_id.eq(organizer_id))
+        .order(promo_codes::created_at.desc())
+        .select(PromoCode::as_select())
+        .load::<PromoCode>(conn)?;
+
+    let ids = codes.iter().map(|code| code.id).collect::<Vec<_>>();
+    let counts = orders::table
+        .filter(orders::promo_code_id.eq_any(ids))
+        .filter(orders::status.eq_any(USED_STATUSES))
+        .group_by(orders::promo_code_id)
+        .select((orders::promo_code_id, count_star()))
+        .load::<(Option<i32>, i64)>(conn)?
+        .into_iter()
+        .filter_map(|(id, uses)| id.map(|id| (id, uses)))
+        .collect::<HashMap<_, _>>();
+
+    Ok(codes
+        .into_iter()
+        .map(|promo_code| PromoCodeUsage {
+            uses: counts.get(&promo_code.id).copied().unwrap_or(0),
+            promo_code,
+        })
+        .collect())
+}
+
+/// Stop accepting a code. Orders that already used it keep their discount.
+pub fn deactivate_code(conn: &mut PgConnection, id: i32, organizer_id: i32) -> Result<PromoCode, PromoError> {
+    diesel::update(
+        promo_codes::table
+            .filter(promo_codes::id.eq(id))
+            .filter(promo_codes::organizer_id.eq(organizer_id)),
+    )
+    .set(promo_codes::active.eq(false))
+    .returning(PromoCode::as_returning())
+    .get_result(conn)
+    .optional()?
+    .ok_or(PromoError::NotFound)
+}
+
+pub fn create_rule(
+    conn: &mut PgConnection,
+    event_id: i32,
+    organizer_id: i32,
+    data: &NewEarlyBirdRuleData,
+) -> Result<EarlyBirdRule, PromoError> {
+    if organizer_event_currency(conn, event_id, organizer_id)?.is_none() {
+        return Err(PromoError::EventNotFound);
+    }
+
+    Ok(diesel::insert_into(early_bird_rules::table)
+        .values((
+            early_bird_rules::event_id.eq(event_id),
+            early_bird_rules::discount_kind.eq(data.discount_kind),
+            early_bird_rules::percent_off.eq(data.percent_off),
+            early_bird_rules::amount_off.eq(data.amount_off),
+            early_bird_rules::ends_at.eq(data.ends_at),
+            early_bird_rules::max_tickets.eq(data.max_tickets),
+        ))
+        .returning(EarlyBirdRule::as_returning())
+        .get_result(conn)?)
+}
+
+pub fn rules_for_event(conn: &mut PgConnection, event_id: i32) -> QueryResult<Vec<EarlyBirdRule>> {
+    early_bird_rules::table
+        .filter(early_bird_rules::event_id.eq(event_id))
+        .order(early_bird_rules::id.asc())
+        .select(EarlyBirdRule::as_select())
+        .load(conn)
+}
+
+pub fn delete_rule(conn: &mut PgConnection, id: i32, organizer_id: i32) -> Result<(), PromoError> {
+    let deleted = diesel::delete(
+        early_bird_rules::table.filter(early_bird_rules::id.eq(id)).filter(
+            early_bird_rules::event_id.eq_any(
+                events::table
+                    .filter(events::userid.eq(organizer_id))
+                    .select(events::id),
+            ),
+        ),
+    )
+    .execute(conn)?;
+    if deleted == 0 {
+        return Err(PromoError::NotFound);
+    }
+    Ok(())
+}
+
+/// Look up a code entered at checkout and check that `user_id` may use it
+/// on `event` right now. The code is locked so concurrent checkouts can't
+/// push it past its usage caps.
+pub fn redeemable(
+    conn: &mut PgConnection,
+    event: &Event,
+    user_id: i32,
+    code: &str,
+) -> Result<PromoCode, OrderError> {
+    let code = normalize_code(code).ok_or(OrderError::InvalidPromoCode)?;
+    let promo = promo_codes::table
+        .filter(promo_codes::organizer_id.eq(event.userid))
+        .filter(promo_codes::code.eq(code))
+        .filter(promo_codes::event_id.is_null().or(promo_codes::event_id.eq(event.id)))
+        .filter(promo_codes::currency.is_null().or(promo_codes::currency.eq(event.currency)))
+        .filter(promo_codes::active.eq(true))
+        .select(PromoCode::as_select())
+        .for_update()
+        .first::<PromoCode>(conn)
+        .optional()?
+        .ok_or(OrderError::InvalidPromoCode)?;
+
+    let now = Utc::now();
+    if matches!(promo.valid_from, Some(from) if now < from)
+        || matches!(promo.valid_until, Some(until) if now >= until)
+    {
+        return Err(OrderError::PromoCodeExpired);
+    }
+    if let Some(max_uses) = promo.max_uses {
+        if uses(conn, promo.id, None)? >= max_uses as i64 {
+            return Err(OrderError::PromoCodeUsedUp);
+        }
+    }
+    if let Some(max_uses_per_user) = promo.max_uses_per_user {
+        if uses(conn, promo.id, Some(user_id))? >= max_uses_per_user as i64 {
+            return Err(OrderError::PromoCodeUsedUp);
+        }
+    }
+    Ok(promo)
+}
+
+/// Price `quantity` tickets of `event` as of now. The best early-bird rule
+/// still open applies first, then the promo code on what is left. Rules
+/// capped by tickets sold only apply when the whole order fits under the
+/// cap.
+pub fn price(
+    conn: &mut PgConnection,
+    event: &Event,
+    quantity: i32,
+    promo: Option<&PromoCode>,
+) -> QueryResult<Pricing> {
+    let subtotal_amount = event.eventticketprice.times(quantity)?;
+
+    let now = Utc::now();
+    let early_bird = early_bird_rules::table
+        .filter(early_bird_rules::event_id.eq(event.id))
+        .filter(early_bird_rules::ends_at.is_null().or(early_bird_rules::ends_at.gt(now)))
+        .filter(
+            early_bird_rules::max_tickets
+                .is_null()
+                .or(early_bird_rules::max_tickets.ge(event.tickets_sold + quantity)),
+        )
+        .select(EarlyBirdRule::as_select())
+        .load::<EarlyBirdRule>(conn)?
+        .into_iter()
+        .map(|rule| (rule.id, rule.discount().apply(subtotal_amount, quantity)))
+        .filter(|(_, discount)| *discount > Amount::ZERO)
+        .max_by_key(|(_, discount)| *discount);
+    let early_bird_discount = early_bird.map_or(Amount::ZERO, |(_, discount)| discount);
+
+    let promo_discount = promo.map_or(Amount::ZERO, |promo| {
+        promo
+            .discount()
+            .apply(subtotal_amount - early_bird_discount, quantity)
+    });
+
+    let discount_amount = early_bird_discount + promo_discount;
+    Ok(Pricing {
+        unit_price: event.eventticketprice,
+        quantity,
+        subtotal_amount,
+        early_bird_discount,
+        promo_discount,
+        discount_amount,
+        total_amount: subtotal_amount - discount_amount,
+        currency: event.currency,
+        early_bird_rule_id: early_bird.map(|(id, _)| id),
+        promo_code_id: promo.map(|promo| promo.id),
+        pay: None,
+    })
+}
@@ -0,0 +1,150 @@
+use crate::database::events::EventStatus;
+use crate::database::{categories, tags};
+use crate::models::events::Event;
+use crate::models::rankings::{EventRanking, PopularEvent};
+use crate::models::venues::Venue;
+use crate::schema::{event_rankings, event_views, events, venues};
+use chrono::{Duration, DurationRound, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::sql_types::{Double, Integer};
+
+/// A signal loses half of its weight every `HALF_LIFE_HOURS`.
+const HALF_LIFE_HOURS: f64 = 72.0;
+const LIKE_WEIGHT: f64 = 1.0;
+const TICKET_WEIGHT: f64 = 3.0;
+const VIEW_WEIGHT: f64 = 0.1;
+/// Views older than this don't count towards the ranking.
+const VIEW_WINDOW_DAYS: i32 = 7;
+/// Views are kept a little longer than the window for other consumers.
+const VIEW_RETENTION_DAYS: i32 = 30;
+/// Repeated views of an event by the same viewer count once per this long.
+const VIEW_BUCKET_MINUTES: i64 = 60;
+
+pub const POPULAR_DEFAULT_LIMIT: i64 = 20;
+pub const POPULAR_MAX_LIMIT: i64 = 100;
+
+pub enum ViewError {
+    NonExistEvent,
+    Other,
+}
+
+impl From<Error> for ViewError {
+    fn from(err: Error) -> ViewError {
+        if let Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) = &err {
+            if let Some("event_views_event_id_fkey") = info.constraint_name() {
+                return ViewError::NonExistEvent;
+            }
+        }
+        ViewError::Other
+    }
+}
+
+/// Count a view of the event unless `viewer_key` already viewed it in the
+/// current bucket. Returns 0 for such repeats.
+pub fn record_view(
+    conn: &mut PgConnection,
+    event_id: i32,
+    user_id: Option<i32>,
+    viewer_key: &str,
+) -> Result<usize, ViewError> {
+    let now = Utc::now();
+    let bucket = now
+        .duration_trunc(Duration::minutes(VIEW_BUCKET_MINUTES))
+        .unwrap_or(now);
+    diesel::insert_into(event_views::table)
+        .values((
+            event_views::event_id.eq(event_id),
+            event_views::user_id.eq(user_id),
+            event_views::viewed_at.eq(now),
+            event_views::viewer_key.eq(viewer_key),
+            event_views::bucket.eq(bucket),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .map_err(Into::into)
+}
+
+/// Rebuild `event_rankings` for published events that haven't ended yet.
+///
+/// Every like, paid ticket and recent view adds its weight to the score,
+/// decayed by its age, so an event liked a hundred times last month ranks
+/// below one that sold out this morning.
+pub fn refresh(conn: &mut PgConnection) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        diesel::sql_query(
+            "DELETE FROM event_views WHERE viewed_at < now() - make_interval(days => $1)",
+        )
+        .bind::<Integer, _>(VIEW_RETENTION_DAYS)
+        .execute(conn)?;
+
+        diesel::delete(event_rankings::table).execute(conn)?;
+
+        diesel::sql_query(
+            "INSERT INTO event_rankings \
+                 (event_id, score, likes_count, tickets_sold, recent_views, computed_at) \
+             SELECT events.id, \
+                    coalesce(liked.score, 0) * $1 \
+                        + coalesce(sold.score, 0) * $2 \
+                        + coalesce(viewed.score, 0) * $3, \
+                    coalesce(liked.total, 0), \
+                    coalesce(sold.total, 0), \
+                    coalesce(viewed.total, 0), \
+                    now() \
+             FROM events \
+             LEFT JOIN ( \
+                 SELECT event_id, count(*) AS total, \
+                        sum(power(0.5::float8, extract(epoch FROM now() - created_at)::float8 / 3600 / $4)) AS score \
+                 FROM likes GROUP BY event_id \
+             ) liked ON liked.event_id = events.id \
+             LEFT JOIN ( \
+                 SELECT event_id, sum(quantity)::bigint AS total, \
+                        sum(quantity * power(0.5::float8, extract(epoch FROM now() - paid_at)::float8 / 3600 / $4)) AS score \
+                 FROM orders WHERE status = 'paid' GROUP BY event_id \
+             ) sold ON sold.event_id = events.id \
+             LEFT JOIN ( \
+                 SELECT event_id, count(*) AS total, \
+                        sum(power(0.5::float8, extract(epoch FROM now() - viewed_at)::float8 / 3600 / $4)) AS score \
+                 FROM event_views WHERE viewed_at > now() - make_interval(days => $5) \
+                 GROUP BY event_id \
+             ) viewed ON viewed.event_id = events.id \
+             WHERE events.status = 'published' AND events.ends_at > now() \
+               AND (liked.event_id IS NOT NULL OR sold.event_id IS NOT NULL OR viewed.event_id IS NOT NULL)",
+        )
+        .bind::<Double, _>(LIKE_WEIGHT)
+        .bind::<Double, _>(TICKET_WEIGHT)
+        .bind::<Double, _>(VIEW_WEIGHT)
+        .bind::<Double, _>(HALF_LIFE_HOURS)
+        .bind::<Integer, _>(VIEW_WINDOW_DAYS)
+        .execute(conn)
+    })
+}
+
+/// Highest ranked events as of the last refresh. Events that were cancelled
+/// or ended since then are skipped.
+pub fn get_popular(conn: &mut PgConnection, limit: i64) -> QueryResult<Vec<PopularEvent>> {
+    let rows = event_rankings::table
+        .inner_join(events::table.inner_join(venues::table))
+        .filter(events::status.eq(EventStatus::Published))
+        .filter(events::ends_at.gt(Utc::now()))
+        .order(event_rankings::score.desc())
+        .limit(limit.clamp(1, POPULAR_MAX_LIMIT))
+        .select((EventRanking::as_select(), Event::as_select(), Venue::as_select()))
+        .load::<(EventRanking, Event, Venue)>(conn)?;
+
+    let event_ids = rows.iter().map(|(_, event, _)| event.id).collect::<Vec<_>>();
+    let mut categories_by_event = categories::for_events(conn, &event_ids)?;
+    let mut tags_by_event = tags::for_events(conn, &event_ids)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(ranking, event, venue)| PopularEvent {
+            categories: categories_by_event.remove(&event.id).unwrap_or_default(),
+            tags: tags_by_event.remove(&event.id).unwrap_or_default(),
+            event,
+            venue,
+            ranking,
+        })
+        .collect())
+}
@@ -0,0 +1,123 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::models::rates::ExchangeRate;
+use crate::money::{Amount, Currency, Money, Rate};
+use crate::rates;
+use crate::schema::exchange_rates;
+use chrono::{Duration, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+/// Older rates are still shown next to prices but can't lock a checkout
+/// quote.
+pub const MAX_RATE_AGE_MINUTES: i64 = 60;
+
+/// Pull the latest rates from the configured provider into the cache. A
+/// provider that is down leaves the previous rates in place.
+pub fn refresh(conn: &mut PgConnection) -> QueryResult<usize> {
+    let provider = match rates::provider() {
+        Ok(Some(provider)) => provider,
+        Ok(None) => return Ok(0),
+        Err(err) => {
+            eprintln!("exchange rates: {}", err);
+            return Ok(0);
+        }
+    };
+    let fetched = match provider.fetch() {
+        Ok(fetched) => fetched,
+        Err(err) => {
+            eprintln!("exchange rates: {}: {}", provider.source(), err);
+            return Ok(0);
+        }
+    };
+
+    let now = Utc::now();
+    let mut updated = 0;
+    for (currency, rate) in fetched {
+        if currency == Currency::Usd {
+            continue;
+        }
+        updated += diesel::insert_into(exchange_rates::table)
+            .values((
+                exchange_rates::currency.eq(currency),
+                exchange_rates::per_usd.eq(rate),
+                exchange_rates::source.eq(provider.source()),
+                exchange_rates::fetched_at.eq(now),
+            ))
+            .on_conflict(exchange_rates::currency)
+            .do_update()
+            .set((
+                exchange_rates::per_usd.eq(rate),
+                exchange_rates::source.eq(provider.source()),
+                exchange_rates::fetched_at.eq(now),
+            ))
+            .execute(conn)?;
+    }
+    Ok(updated)
+}
+
+pub fn all(conn: &mut PgConnection) -> QueryResult<Vec<ExchangeRate>> {
+    exchange_rates::table
+        .order(exchange_rates::currency.asc())
+        .select(ExchangeRate::as_select())
+        .load(conn)
+}
+
+/// Cached rates, loaded once per request.
+pub struct RateTable {
+    rates: HashMap<Currency, ExchangeRate>,
+}
+
+impl RateTable {
+    pub fn load(conn: &mut PgConnection) -> QueryResult<RateTable> {
+        let rates = all(conn)?
+            .into_iter()
+            .map(|rate| (rate.currency, rate))
+            .collect();
+        Ok(RateTable { rates })
+    }
+
+    fn per_usd(&self, currency: Currency, max_age: Option<Duration>) -> Option<Rate> {
+        if currency == Currency::Usd {
+            return Some(Rate::ONE);
+        }
+        let rate = self.rates.get(&currency)?;
+        match max_age {
+            Some(max_age) if rate.fetched_at < Utc::now() - max_age => None,
+            _ => Some(rate.per_usd),
+        }
+    }
+
+    fn convert(
+        &self,
+        amount: Amount,
+        from: Currency,
+        to: Currency,
+        max_age: Option<Duration>,
+    ) -> Option<Money> {
+        if from == to {
+            return Some(Money::new(amount, to));
+        }
+        let from_rate = self.per_usd(from, max_age)?;
+        let to_rate = self.per_usd(to, max_age)?;
+        Rate::convert(amount, from, from_rate, to, to_rate)
+            .map(|converted| Money::new(converted, to))
+    }
+
+    /// An indicative price in `to`, at whatever rate is cached.
+    pub fn display(&self, amount: Amount, from: Currency, to: Currency) -> Option<Money> {
+        self.convert(amount, from, to, None)
+    }
+
+    /// What to charge in `to`. Only recent rates are used; `None` when there
+    /// is none.
+    pub fn quote(&self, amount: Amount, from: Currency, to: Currency) -> Option<Money> {
+        self.convert(
+            amount,
+            from,
+            to,
+            Some(Duration::minutes(MAX_RATE_AGE_MINUTES)),
+        )
+    }
+}
@@ -0,0 +1,175 @@
+use crate::database::events::EventStatus;
+use crate::database::orders::OrderStatus;
+use crate::database::{categories, tags};
+use crate::models::events::Event;
+use crate::models::recommendations::RecommendedEvent;
+use crate::models::venues::Venue;
+use crate::schema::{events, likes, orders, user_recommendations, venues};
+use chrono::Utc;
+use diesel::dsl::{exists, not};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Double};
+
+/// Weight of "people who liked or bought what you did also went to this".
+const CO_OCCURRENCE_WEIGHT: f64 = 1.0;
+/// Weight of the share of the user's events in the candidate's categories.
+const CATEGORY_WEIGHT: f64 = 2.0;
+/// Weight of the share of the user's events in the candidate's city.
+const CITY_WEIGHT: f64 = 1.0;
+/// Recommendations kept per user.
+const CACHED_PER_USER: i64 = 50;
+
+pub const RECOMMENDATIONS_DEFAULT_LIMIT: i64 = 20;
+
+/// Rebuild the cached recommendations of every user with at least one like
+/// or paid order, the only ones we have a signal for, in one statement.
+///
+/// Candidates are upcoming published events the user neither organizes,
+/// likes nor holds an order for. Each is scored by how often it shares likes
+/// or purchases with the user's own events (item co-occurrence), plus how
+/// much of the user's history falls into its categories and its city. Only
+/// pairs with some signal are scored, never every user against every event.
+pub fn refresh(conn: &mut PgConnection) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        diesel::delete(user_recommendations::table).execute(conn)?;
+
+        diesel::sql_query(
+            "WITH interactions AS ( \
+                 SELECT user_id, event_id FROM likes \
+                 UNION \
+                 SELECT user_id, event_id FROM orders WHERE status = 'paid' \
+             ), \
+             history AS ( \
+                 SELECT user_id, count(*)::float8 AS total FROM interactions GROUP BY user_id \
+             ), \
+             candidates AS ( \
+                 SELECT events.id, events.userid, venues.city \
+                 FROM events JOIN venues ON venues.id = events.venue_id \
+                 WHERE events.status = 'published' AND events.starts_at > now() \
+             ), \
+             co_occurrence AS ( \
+                 SELECT mine.user_id, theirs.event_id, count(*) AS shared \
+                 FROM interactions mine \
+                 JOIN interactions others \
+                     ON others.event_id = mine.event_id AND others.user_id <> mine.user_id \
+                 JOIN interactions theirs ON theirs.user_id = others.user_id \
+                 JOIN candidates ON candidates.id = theirs.event_id \
+                 GROUP BY mine.user_id, theirs.event_id \
+             ), \
+             category_affinity AS ( \
+                 SELECT mine.user_id, event_categories.category_id, \
+                        count(*) / history.total AS affinity \
+                 FROM interactions mine \
+                 JOIN event_categories ON event_categories.event_id = mine.event_id \
+                 JOIN history ON history.user_id = mine.user_id \
+                 GROUP BY mine.user_id, event_categories.category_id, history.total \
+             ), \
+             category_score AS ( \
+                 SELECT category_affinity.user_id, event_categories.event_id, \
+                        sum(category_affinity.affinity) AS affinity \
+                 FROM category_affinity \
+                 JOIN event_categories USING (category_id) \
+                 JOIN candidates ON candidates.id = event_categories.event_id \
+                 GROUP BY category_affinity.user_id, event_categories.event_id \
+             ), \
+             city_affinity AS ( \
+                 SELECT mine.user_id, venues.city, count(*) / history.total AS affinity \
+                 FROM interactions mine \
+                 JOIN events ON events.id = mine.event_id \
+                 JOIN venues ON venues.id = events.venue_id \
+                 JOIN history ON history.user_id = mine.user_id \
+                 GROUP BY mine.user_id, venues.city, history.total \
+             ), \
+             pairs AS ( \
+                 SELECT user_id, event_id FROM co_occurrence \
+                 UNION \
+                 SELECT user_id, event_id FROM category_score \
+                 UNION \
+                 SELECT city_affinity.user_id, candidates.id \
+                 FROM city_affinity JOIN candidates ON candidates.city = city_affinity.city \
+             ), \
+             scored AS ( \
+                 SELECT pairs.user_id, pairs.event_id, \
+                        $1 * ln(1 + coalesce(co_occurrence.shared, 0))::float8 \
+                          + $2 * coalesce(category_score.affinity, 0) \
+                          + $3 * coalesce(city_affinity.affinity, 0) AS score \
+                 FROM pairs \
+                 JOIN candidates ON candidates.id = pairs.event_id \
+                 LEFT JOIN co_occurrence USING (user_id, event_id) \
+                 LEFT JOIN category_score USING (user_id, event_id) \
+                 LEFT JOIN city_affinity \
+                     ON city_affinity.user_id = pairs.user_id \
+                    AND city_affinity.city = candidates.city \
+                 WHERE candidates.userid <> pairs.user_id \
+                   AND NOT EXISTS ( \
+                       SELECT 1 FROM likes \
+                       WHERE likes.user_id = pairs.user_id AND likes.event_id = pairs.event_id \
+                   ) \
+                   AND NOT EXISTS ( \
+                       SELECT 1 FROM orders \
+                       WHERE orders.user_id = pairs.user_id AND orders.event_id = pairs.event_id \
+                         AND orders.status IN ('pending', 'paid') \
+                   ) \
+             ), \
+             ranked AS ( \
+                 SELECT user_id, event_id, score, \
+                        row_number() OVER (PARTITION BY user_id ORDER BY score DESC, event_id) \
+                            AS position \
+                 FROM scored \
+                 WHERE score > 0 \
+             ) \
+             INSERT INTO user_recommendations (user_id, event_id, score) \
+             SELECT user_id, event_id, score FROM ranked WHERE position <= $4",
+        )
+        .bind::<Double, _>(CO_OCCURRENCE_WEIGHT)
+        .bind::<Double, _>(CATEGORY_WEIGHT)
+        .bind::<Double, _>(CITY_WEIGHT)
+        .bind::<BigInt, _>(CACHED_PER_USER)
+        .execute(conn)
+    })
+}
+
+/// Cached recommendations of `user_id`, best first. Events that stopped
+/// being eligible since the last refresh, or were liked since, are skipped.
+pub fn for_user(
+    conn: &mut PgConnection,
+    user_id: i32,
+    limit: i64,
+) -> QueryResult<Vec<RecommendedEvent>> {
+    let rows = user_recommendations::table
+        .inner_join(events::table.inner_join(venues::table))
+        .filter(user_recommendations::user_id.eq(user_id))
+        .filter(events::status.eq(EventStatus::Published))
+        .filter(events::starts_at.gt(Utc::now()))
+        .filter(not(exists(
+            likes::table
+                .filter(likes::user_id.eq(user_id))
+                .filter(likes::event_id.eq(events::id)),
+        )))
+        .filter(not(exists(
+            orders::table
+                .filter(orders::user_id.eq(user_id))
+                .filter(orders::event_id.eq(events::id))
+                .filter(orders::status.eq_any([OrderStatus::Pending, OrderStatus::Paid])),
+        )))
+        .order(user_recommendations::score.desc())
+        .limit(limit.clamp(1, CACHED_PER_USER))
+        .select((user_recommendations::score, Event::as_select(), Venue::as_select()))
+        .load::<(f64, Event, Venue)>(conn)?;
+
+    let event_ids = rows.iter().map(|(_, event, _)| event.id).collect::<Vec<_>>();
+    let mut categories_by_event = categories::for_events(conn, &event_ids)?;
+    let mut tags_by_event = tags::for_events(conn, &event_ids)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(score, event, venue)| RecommendedEvent {
+            categories: categories_by_event.remove(&event.id).unwrap_or_default(),
+            tags: tags_by_event.remove(&event.id).unwrap_or_default(),
+            event,
+            venue,
+            score,
+        })
+        .collect())
+}
@@ -0,0 +1,555 @@
+#![deny(clippy::float_arithmetic)]
+
+use crate::database::jobs::{self, JobHandler};
+use crate::database::ledger;
+use crate::database::listings::ListingStatus;
+use crate::database::notifications::{self, NewNotification, NotificationKind};
+use crate::database::orders::OrderStatus;
+use crate::database::tickets::TicketStatus;
+use crate::database::transfers::TransferStatus;
+use crate::models::events::Event;
+use crate::models::orders::Order;
+use crate::models::refunds::Refund;
+use crate::models::tickets::Ticket;
+use crate::schema::{events, orders, refunds, ticket_listings, ticket_transfers, tickets};
+use chrono::Utc;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::dsl::{exists, not};
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use rocket::form::FromFormField;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum RefundReason {
+    Requested,
+    EventCancelled,
+}
+
+impl RefundReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefundReason::Requested => "requested",
+            RefundReason::EventCancelled => "event_cancelled",
+        }
+    }
+}
+
+impl FromStr for RefundReason {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "requested" => Ok(RefundReason::Requested),
+            "event_cancelled" => Ok(RefundReason::EventCancelled),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for RefundReason {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for RefundReason {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum RefundStatus {
+    Requested,
+    /// The ticket is void and the money is owed to the holder.
+    Approved,
+    Rejected,
+    /// The money has been sent back.
+    Paid,
+}
+
+impl RefundStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefundStatus::Requested => "requested",
+            RefundStatus::Approved => "approved",
+            RefundStatus::Rejected => "rejected",
+            RefundStatus::Paid => "paid",
+        }
+    }
+}
+
+impl FromStr for RefundStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "requested" => Ok(RefundStatus::Requested),
+            "approved" => Ok(RefundStatus::Approved),
+            "rejected" => Ok(RefundStatus::Rejected),
+            "paid" => Ok(RefundStatus::Paid),
+            _ => Err(()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for RefundStatus {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation("invalid refund status"))
+        })
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for RefundStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for RefundStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum RefundMethod {
+    Onchain,
+    Fiat,
+}
+
+impl RefundMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefundMethod::Onchain => "onchain",
+            RefundMethod::Fiat => "fiat",
+        }
+    }
+}
+
+impl FromStr for RefundMethod {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "onchain" => Ok(RefundMethod::Onchain),
+            "fiat" => Ok(RefundMethod::Fiat),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for RefundMethod {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for RefundMethod {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+pub enum RefundError {
+    /// The ticket doesn't exist, isn't the user's or is no longer valid.
+    TicketNotFound,
+    /// The event's refund policy gives nothing back at this point.
+    NotRefundable,
+    AlreadyRequested,
+    NotFound,
+    /// The refund is not in a state that allows this change.
+    InvalidStatus,
+    Other,
+}
+
+impl From<Error> for RefundError {
+    fn from(err: Error) -> RefundError {
+        if let Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) = &err {
+            if info.constraint_name() == Some("refunds_one_per_ticket") {
+                return RefundError::AlreadyRequested;
+            }
+        }
+        RefundError::Other
+    }
+}
+
+/// Money goes back the way it came: through the payment provider for card
+/// and mobile money orders, on-chain otherwise.
+fn method_for(order: &Order) -> RefundMethod {
+    match order.payment_method {
+        Some(method) if method.is_fiat() => RefundMethod::Fiat,
+        _ => RefundMethod::Onchain,
+    }
+}
+
+/// Ask for a refund of one ticket. The amount follows the event's policy at
+/// the time of the request; the organizer approves or rejects it.
+pub fn request(conn: &mut PgConnection, ticket_id: i32, user_id: i32) -> Result<Refund, RefundError> {
+    conn.transaction(|conn| {
+        let ticket = tickets::table
+            .find(ticket_id)
+            .select(Ticket::as_select())
+            .for_update()
+            .get_result::<Ticket>(conn)
+            .optional()?
+            .filter(|ticket| ticket.owner_id == user_id && ticket.status == TicketStatus::Valid)
+            .ok_or(RefundError::TicketNotFound)?;
+        let order = orders::table
+            .find(ticket.order_id)
+            .select(Order::as_select())
+            .get_result::<Order>(conn)?;
+        let event = events::table
+            .find(ticket.event_id)
+            .select(Event::as_select())
+            .get_result::<Event>(conn)?;
+
+        let percent = event.refund_percent(Utc::now());
+        if percent == 0 {
+            return Err(RefundError::NotRefundable);
+        }
+
+        Ok(diesel::insert_into(refunds::table)
+            .values((
+                refunds::ticket_id.eq(ticket.id),
+                refunds::order_id.eq(order.id),
+                refunds::event_id.eq(event.id),
+                refunds::user_id.eq(user_id),
+                refunds::amount.eq(order.paid_per_ticket().percent(percent).map_err(Error::from)?),
+                refunds::currency.eq(order.currency),
+                refunds::reason.eq(RefundReason::Requested),
+                refunds::method.eq(method_for(&order)),
+            ))
+            .returning(Refund::as_returning())
+            .get_result(conn)?)
+    })
+}
+
+/// A refund of the organizer's event, locked.
+fn find_for_organizer(
+    conn: &mut PgConnection,
+    id: i32,
+    organizer_id: i32,
+) -> Result<(Refund, Event), RefundError> {
+    let refund = refunds::table
+        .find(id)
+        .select(Refund::as_select())
+        .for_update()
+        .get_result::<Refund>(conn)
+        .optional()?
+        .ok_or(RefundError::NotFound)?;
+    let event = events::table
+        .find(refund.event_id)
+        .select(Event::as_select())
+        .get_result::<Event>(conn)?;
+    if event.userid != organizer_id {
+        return Err(RefundError::NotFound);
+    }
+    Ok((refund, event))
+}
+
+/// Void the tickets, give their seats back and take them off the resale
+/// market. Orders left without valid tickets are marked refunded.
+fn void_tickets(conn: &mut PgConnection, ticket_ids: &[i32]) -> QueryResult<usize> {
+    let voided = diesel::update(
+        tickets::table
+            .filter(tickets::id.eq_any(ticket_ids))
+            .filter(tickets::status.eq(TicketStatus::Valid)),
+    )
+    .set(tickets::status.eq(TicketStatus::Void))
+    .returning((tickets::event_id, tickets::order_id))
+    .get_results::<(i32, i32)>(conn)?;
+
+    for (event_id, _) in &voided {
+        diesel::update(events::table.find(event_id))
+            .set(events::tickets_sold.eq(events::tickets_sold - 1))
+            .execute(conn)?;
+    }
+
+    diesel::update(
+        ticket_listings::table
+            .filter(ticket_listings::ticket_id.eq_any(ticket_ids))
+            .filter(ticket_listings::status.eq_any([ListingStatus::Active, ListingStatus::Reserved])),
+    )
+    .set(ticket_listings::status.eq(ListingStatus::Cancelled))
+    .execute(conn)?;
+    diesel::update(
+        ticket_transfers::table
+            .filter(ticket_transfers::ticket_id.eq_any(ticket_ids))
+            .filter(ticket_transfers::status.eq(TransferStatus::Pending)),
+    )
+    .set((
+        ticket_transfers::status.eq(TransferStatus::Cancelled),
+        ticket_transfers::responded_at.eq(Utc::now()),
+    ))
+    .execute(conn)?;
+
+    let order_ids = voided
+        .iter()
+        .map(|(_, order_id)| *order_id)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    diesel::update(
+        orders::table
+            .filter(orders::id.eq_any(order_ids))
+            .filter(orders::status.eq(OrderStatus::Paid))
+            .filter(not(exists(
+                tickets::table
+                    .filter(tickets::order_id.eq(orders::id))
+                    .filter(tickets::status.eq(TicketStatus::Valid)),
+            ))),
+    )
+    .set(orders::status.eq(OrderStatus::Refunded))
+    .execute(conn)?;
+
+    Ok(voided.len())
+}
+
+fn notify_refund(conn: &mut PgConnection, refund: &Refund, title: &str, body: &str) -> QueryResult<()> {
+    notifications::notify(
+        conn,
+        &NewNotification {
+            user_id: refund.user_id,
+            kind: NotificationKind::RefundUpdated,
+            title,
+            body,
+            event_id: Some(refund.event_id),
+            order_id: Some(refund.order_id),
+        },
+    )
+}
+
+/// Approve or reject a requested refund. Approving voids the ticket; the
+/// holder must still own it.
+pub fn decide(
+    conn: &mut PgConnection,
+    id: i32,
+    organizer_id: i32,
+    approve: bool,
+) -> Result<Refund, RefundError> {
+    conn.transaction(|conn| {
+        let (refund, event) = find_for_organizer(conn, id, organizer_id)?;
+        if refund.status != RefundStatus::Requested {
+            return Err(RefundError::InvalidStatus);
+        }
+
+        let status = if approve {
+            let still_held = tickets::table
+                .find(refund.ticket_id)
+                .select(Ticket::as_select())
+                .for_update()
+                .get_result::<Ticket>(conn)?;
+            if still_held.owner_id != refund.user_id || still_held.status != TicketStatus::Valid {
+                return Err(RefundError::TicketNotFound);
+            }
+            void_tickets(conn, &[refund.ticket_id])?;
+            RefundStatus::Approved
+        } else {
+            RefundStatus::Rejected
+        };
+
+        let refund = diesel::update(refunds::table.find(id))
+            .set((refunds::status.eq(status), refunds::decided_at.eq(Utc::now())))
+            .returning(Refund::as_returning())
+            .get_result::<Refund>(conn)?;
+
+        if approve {
+            ledger::record_refund(conn, &refund)?;
+            let body = format!("Your refund for {} is on its way.", event.eventname);
+            notify_refund(conn, &refund, "Refund approved", &body)?;
+        } else {
+            let body = format!("Your refund request for {} was rejected.", event.eventname);
+            notify_refund(conn, &refund, "Refund rejected", &body)?;
+        }
+        Ok(refund)
+    })
+}
+
+/// Record that the money of an approved refund has been sent back.
+pub fn mark_paid(
+    conn: &mut PgConnection,
+    id: i32,
+    organizer_id: i32,
+    payout_reference: &str,
+) -> Result<Refund, RefundError> {
+    conn.transaction(|conn| {
+        let (refund, _) = find_for_organizer(conn, id, organizer_id)?;
+        if refund.status != RefundStatus::Approved {
+            return Err(RefundError::InvalidStatus);
+        }
+
+        Ok(diesel::update(refunds::table.find(id))
+            .set((
+                refunds::status.eq(RefundStatus::Paid),
+                refunds::payout_reference.eq(payout_reference),
+                refunds::paid_at.eq(Utc::now()),
+            ))
+            .returning(Refund::as_returning())
+            .get_result(conn)?)
+    })
+}
+
+/// Refund every valid ticket of a cancelled event in full. Requests still
+/// waiting for a decision are approved at the full price.
+pub fn refund_event(conn: &mut PgConnection, event_id: i32) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        let event = events::table
+            .find(event_id)
+            .select(Event::as_select())
+            .get_result::<Event>(conn)?;
+
+        let held = tickets::table
+            .inner_join(orders::table)
+            .filter(tickets::event_id.eq(event_id))
+            .filter(tickets::status.eq(TicketStatus::Valid))
+            .select((Ticket::as_select(), Order::as_select()))
+            .for_update()
+            .load::<(Ticket, Order)>(conn)?;
+
+        let now = Utc::now();
+        let mut refunded = Vec::with_capacity(held.len());
+        for (ticket, order) in &held {
+            let upgraded = diesel::update(
+                refunds::table
+                    .filter(refunds::ticket_id.eq(ticket.id))
+                    .filter(refunds::status.eq(RefundStatus::Requested)),
+            )
+            .set((
+                refunds::status.eq(RefundStatus::Approved),
+                refunds::reason.eq(RefundReason::EventCancelled),
+                refunds::user_id.eq(ticket.owner_id),
+                refunds::amount.eq(order.paid_per_ticket()),
+                refunds::decided_at.eq(now),
+            ))
+            .returning(Refund::as_returning())
+            .get_result::<Refund>(conn)
+            .optional()?;
+
+            let refund = match upgraded {
+                Some(refund) => Some(refund),
+                None => diesel::insert_into(refunds::table)
+                    .values((
+                        refunds::ticket_id.eq(ticket.id),
+                        refunds::order_id.eq(order.id),
+                        refunds::event_id.eq(event_id),
+                        refunds::user_id.eq(ticket.owner_id),
+                        refunds::amount.eq(order.paid_per_ticket()),
+                        refunds::currency.eq(order.currency),
+                        refunds::reason.eq(RefundReason::EventCancelled),
+                        refunds::status.eq(RefundStatus::Approved),
+                        refunds::method.eq(method_for(order)),
+                        refunds::decided_at.eq(now),
+                    ))
+                    .on_conflict_do_nothing()
+                    .returning(Refund::as_returning())
+                    .get_result::<Refund>(conn)
+                    .optional()?,
+            };
+            refunded.extend(refund);
+        }
+
+        let ticket_ids = held.iter().map(|(ticket, _)| ticket.id).collect::<Vec<_>>();
+        void_tickets(conn, &ticket_ids)?;
+
+        let body = format!("{} was cancelled and your tickets are being refunded.", event.eventname);
+        let mut notified = BTreeSet::new();
+        for refund in &refunded {
+            ledger::record_refund(conn, refund)?;
+            if notified.insert(refund.user_id) {
+                notify_refund(conn, refund, "Refund approved", &body)?;
+            }
+        }
+        Ok(refunded.len())
+    })
+}
+
+/// Refunds of an organizer's event, newest first.
+pub fn for_event(
+    conn: &mut PgConnection,
+    event_id: i32,
+    organizer_id: i32,
+    status: Option<RefundStatus>,
+) -> Result<Vec<Refund>, RefundError> {
+    let organizer = events::table
+        .find(event_id)
+        .select(events::userid)
+        .get_result::<i32>(conn)
+        .optional()?;
+    if organizer != Some(organizer_id) {
+        return Err(RefundError::NotFound);
+    }
+
+    let mut query = refunds::table
+        .filter(refunds::event_id.eq(event_id))
+        .into_boxed();
+    if let Some(status) = status {
+        query = query.filter(refunds::status.eq(status));
+    }
+    Ok(query
+        .order(refunds::created_at.desc())
+        .select(Refund::as_select())
+        .load(conn)?)
+}
+
+/// The user's refunds, newest first.
+pub fn for_user(conn: &mut PgConnection, user_id: i32) -> QueryResult<Vec<Refund>> {
+    refunds::table
+        .filter(refunds::user_id.eq(user_id))
+        .order(refunds::created_at.desc())
+        .select(Refund::as_select())
+        .load(conn)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EventRefundsPayload {
+    event_id: i32,
+}
+
+/// Mass refund of a cancelled event, run in the background so cancelling a
+/// large event doesn't hold up the organizer's request.
+pub struct EventRefunds;
+
+impl JobHandler for EventRefunds {
+    const KIND: &'static str = "event_refunds";
+    type Payload = EventRefundsPayload;
+
+    fn run(conn: &mut PgConnection, payload: EventRefundsPayload) -> QueryResult<()> {
+        refund_event(conn, payload.event_id).map(|_| ())
+    }
+}
+
+/// Queue the mass refund of a cancelled event.
+pub fn schedule_event_refunds(conn: &mut PgConnection, event_id: i32) -> QueryResult<usize> {
+    jobs::enqueue::<EventRefunds>(
+        conn,
+        &EventRefundsPayload { event_id },
+        Utc::now(),
+        Some(&format!("{}:{}", EventRefunds::KIND, event_id)),
+    )
+}
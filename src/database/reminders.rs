@@ -0,0 +1,126 @@
+use crate::database::events::EventStatus;
+use crate::database::jobs::{self, JobHandler};
+use crate::database::notifications::{self, NewNotification, NotificationKind};
+use crate::models::events::Event;
+use crate::schema::{events, likes};
+use chrono::{DateTime, Duration, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Hours before `starts_at` at which buyers and likers are reminded.
+const REMINDER_LEADS_HOURS: [i64; 2] = [24, 1];
+/// The sweep looks a little further ahead than the longest lead so every
+/// reminder is queued before it is due.
+const SCHEDULE_AHEAD_HOURS: i64 = 25;
+/// Reminders that should have gone out longer ago than this are not queued,
+/// e.g. the 24h reminder of an event published two hours before it starts.
+const LATE_GRACE_MINUTES: i64 = 15;
+
+#[derive(Serialize, Deserialize)]
+pub struct ReminderPayload {
+    event_id: i32,
+    /// Start time the reminder was scheduled for. If the event is moved the
+    /// job is skipped and the sweep queues new ones for the new time.
+    starts_at: DateTime<Utc>,
+    lead_hours: i64,
+}
+
+/// Queue reminder jobs for published events starting soon. Safe to run as
+/// often as needed: each reminder has a dedupe key so it is queued once.
+pub fn schedule_upcoming(conn: &mut PgConnection) -> QueryResult<usize> {
+    let now = Utc::now();
+    let upcoming = events::table
+        .filter(events::status.eq(EventStatus::Published))
+        .filter(events::starts_at.gt(now))
+        .filter(events::starts_at.le(now + Duration::hours(SCHEDULE_AHEAD_HOURS)))
+        .select((events::id, events::starts_at))
+        .load::<(i32, DateTime<Utc>)>(conn)?;
+
+    let mut scheduled = 0;
+    for (event_id, starts_at) in upcoming {
+        for lead_hours in REMINDER_LEADS_HOURS {
+            let run_at = starts_at - Duration::hours(lead_hours);
+            if run_at < now - Duration::minutes(LATE_GRACE_MINUTES) {
+                continue;
+            }
+            let payload = ReminderPayload {
+                event_id,
+                starts_at,
+                lead_hours,
+            };
+            let dedupe_key = format!(
+                "{}:{}:{}:{}",
+                EventReminder::KIND,
+                event_id,
+                lead_hours,
+                starts_at.timestamp()
+            );
+            scheduled += jobs::enqueue::<EventReminder>(conn, &payload, run_at, Some(&dedupe_key))?;
+        }
+    }
+    Ok(scheduled)
+}
+
+/// Reminds everyone who bought or liked the event.
+pub struct EventReminder;
+
+impl JobHandler for EventReminder {
+    const KIND: &'static str = "event_reminder";
+    type Payload = ReminderPayload;
+
+    fn run(conn: &mut PgConnection, payload: ReminderPayload) -> QueryResult<()> {
+        send(conn, &payload)
+    }
+}
+
+fn send(conn: &mut PgConnection, payload: &ReminderPayload) -> QueryResult<()> {
+    let event = match events::table
+        .find(payload.event_id)
+        .select(Event::as_select())
+        .first::<Event>(conn)
+        .optional()?
+    {
+        Some(event) => event,
+        None => return Ok(()),
+    };
+    if event.status != EventStatus::Published
+        || event.starts_at != payload.starts_at
+        || event.starts_at <= Utc::now()
+    {
+        return Ok(());
+    }
+
+    let mut recipients = notifications::attendees(conn, event.id)?;
+    recipients.extend(
+        likes::table
+            .filter(likes::event_id.eq(event.id))
+            .select(likes::user_id)
+            .load::<i32>(conn)?,
+    );
+
+    let title = if payload.lead_hours == 1 {
+        "Starting in 1 hour".to_string()
+    } else {
+        format!("Starting in {} hours", payload.lead_hours)
+    };
+    let body = format!(
+        "{} starts at {}.",
+        event.eventname,
+        event.starts_at.with_timezone(&event.tz()?).format("%a %e %b %H:%M %Z")
+    );
+    for user_id in recipients {
+        notifications::notify(
+            conn,
+            &NewNotification {
+                user_id,
+                kind: NotificationKind::EventReminder,
+                title: &title,
+                body: &body,
+                event_id: Some(event.id),
+                order_id: None,
+            },
+        )?;
+    }
+    Ok(())
+}
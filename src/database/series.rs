@@ -0,0 +1,300 @@
+use crate::database::events::{EventStatus, NewEvent};
+use crate::database::feed::{self, FeedKind};
+use crate::models::events::Event;
+use crate::models::series::EventSeries;
+use crate::money::{Amount, Currency};
+use crate::recurrence::{RRule, RRuleError};
+use crate::schema::{event_categories, event_series, events};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+
+/// Series without COUNT or UNTIL are expanded this far ahead of `dtstart`.
+const EXPANSION_HORIZON_DAYS: i64 = 365;
+
+#[derive(Insertable)]
+#[diesel(table_name = event_series)]
+pub struct NewSeries<'a> {
+    pub userid: i32,
+    pub eventname: &'a str,
+    pub eventdescription: &'a str,
+    pub eventimage: &'a str,
+    pub eventticketprice: Amount,
+    pub currency: Currency,
+    pub venue_id: i32,
+    pub timezone: &'a str,
+    pub rrule: &'a str,
+    pub dtstart: NaiveDateTime,
+    pub duration_minutes: i32,
+    pub capacity: Option<i32>,
+    pub category_ids: &'a [i32],
+}
+
+pub enum SeriesCreationError {
+    InvalidRule(RRuleError),
+    NoOccurrences,
+    InvalidTimezone,
+    NonExistUsername,
+    NonExistVenue,
+    NonExistCategory,
+    Other,
+}
+
+impl From<Error> for SeriesCreationError {
+    fn from(err: Error) -> SeriesCreationError {
+        if let Error::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) = &err {
+            match info.constraint_name() {
+                Some("event_series_userid_fkey") => return SeriesCreationError::NonExistUsername,
+                Some("event_series_venue_id_fkey") => return SeriesCreationError::NonExistVenue,
+                Some("event_categories_category_id_fkey") => {
+                    return SeriesCreationError::NonExistCategory
+                }
+                _ => {}
+            }
+        }
+        SeriesCreationError::Other
+    }
+}
+
+/// Resolve a local occurrence time. A start that falls into a DST gap is
+/// moved forward by the gap, a repeated hour takes its first instance.
+fn occurrence_start(tz: &Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map(|datetime| datetime.with_timezone(&Utc))
+}
+
+/// Create a series and materialize its occurrences as draft events.
+pub fn create(
+    conn: &mut PgConnection,
+    new_series: &NewSeries,
+) -> Result<(EventSeries, Vec<Event>), SeriesCreationError> {
+    let rule = RRule::parse(new_series.rrule).map_err(SeriesCreationError::InvalidRule)?;
+    let tz: Tz = new_series
+        .timezone
+        .parse()
+        .map_err(|_| SeriesCreationError::InvalidTimezone)?;
+    let horizon = new_series.dtstart + Duration::days(EXPANSION_HORIZON_DAYS);
+    let duration = Duration::minutes(new_series.duration_minutes as i64);
+
+    let starts = rule
+        .expand(&tz, new_series.dtstart, horizon)
+        .into_iter()
+        .filter_map(|local| occurrence_start(&tz, local))
+        .collect::<Vec<_>>();
+    if starts.is_empty() {
+        return Err(SeriesCreationError::NoOccurrences);
+    }
+
+    conn.transaction(|conn| {
+        let series = diesel::insert_into(event_series::table)
+            .values(new_series)
+            .returning(EventSeries::as_returning())
+            .get_result(conn)?;
+
+        let occurrences = starts
+            .iter()
+            .map(|starts_at| NewEvent {
+                userid: series.userid,
+                eventname: &series.eventname,
+                eventdescription: &series.eventdescription,
+                eventimage: &series.eventimage,
+                eventticketprice: series.eventticketprice,
+                currency: series.currency,
+                venue_id: series.venue_id,
+                timezone: &series.timezone,
+                starts_at: *starts_at,
+                ends_at: *starts_at + duration,
+                capacity: series.capacity,
+                series_id: Some(series.id),
+                recurrence_id: Some(*starts_at),
+            })
+            .collect::<Vec<_>>();
+
+        let events = diesel::insert_into(events::table)
+            .values(&occurrences)
+            .returning(Event::as_returning())
+            .get_results::<Event>(conn)?;
+
+        let categories = events
+            .iter()
+            .flat_map(|event| {
+                series.category_ids.iter().map(move |category_id| {
+                    (
+                        event_categories::event_id.eq(event.id),
+                        event_categories::category_id.eq(*category_id),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(event_categories::table)
+            .values(&categories)
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        Ok((series, events))
+    })
+}
+
+/// Series with at least one occurrence visible to `viewer` overlapping the
+/// window.
+pub fn get_series(
+    conn: &mut PgConnection,
+    window_start: Option<DateTime<Utc>>,
+    window_end: Option<DateTime<Utc>>,
+    viewer: Option<i32>,
+) -> Result<Vec<EventSeries>, Error> {
+    // A correlated subquery can't be boxed, so the matching series ids are
+    // looked up first.
+    let mut occurrences = events::table
+        .filter(
+            events::status
+                .ne(EventStatus::Draft)
+                .or(events::userid.nullable().eq(viewer)),
+        )
+        .into_boxed();
+    if let Some(window_start) = window_start {
+        occurrences = occurrences.filter(events::ends_at.gt(window_start));
+    }
+    if let Some(window_end) = window_end {
+        occurrences = occurrences.filter(events::starts_at.lt(window_end));
+    }
+    let series_ids = occurrences
+        .select(events::series_id)
+        .filter(events::series_id.is_not_null())
+        .distinct()
+        .load::<Option<i32>>(conn)?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    event_series::table
+        .filter(event_series::id.eq_any(series_ids))
+        .order(event_series::dtstart.asc())
+        .select(EventSeries::as_select())
+        .load(conn)
+}
+
+pub enum SeriesTransitionError {
+    NotFound,
+    NotOwner,
+    Other,
+}
+
+impl From<Error> for SeriesTransitionError {
+    fn from(err: Error) -> SeriesTransitionError {
+        match err {
+            Error::NotFound => SeriesTransitionError::NotFound,
+            _ => SeriesTransitionError::Other,
+        }
+    }
+}
+
+/// Apply a status change to every upcoming occurrence that allows it, e.g.
+/// publish a whole tour or cancel the remaining dates. Occurrences that were
+/// already cancelled or overridden into another state are left alone.
+pub fn transition(
+    conn: &mut PgConnection,
+    id: i32,
+    organizer_id: i32,
+    next: EventStatus,
+) -> Result<Vec<Event>, SeriesTransitionError> {
+    conn.transaction(|conn| {
+        let series = event_series::table
+            .find(id)
+            .select(EventSeries::as_select())
+            .get_result::<EventSeries>(conn)?;
+        if series.userid != organizer_id {
+            return Err(SeriesTransitionError::NotOwner);
+        }
+
+        let upcoming = events::table
+            .filter(events::series_id.eq(id))
+            .filter(events::starts_at.gt(Utc::now()))
+            .select(Event::as_select())
+            .for_update()
+            .load::<Event>(conn)?;
+
+        let ids = upcoming
+            .iter()
+            .filter(|event| event.status.can_transition_to(next))
+            .map(|event| event.id)
+            .collect::<Vec<_>>();
+
+        let updated = diesel::update(events::table.filter(events::id.eq_any(&ids)))
+            .set(events::status.eq(next))
+            .returning(Event::as_returning())
+            .get_results::<Event>(conn)?;
+
+        // Announce only the first newly published date, not every occurrence.
+        let first_published = upcoming
+            .iter()
+            .filter(|event| event.status == EventStatus::Draft && ids.contains(&event.id))
+            .min_by_key(|event| event.starts_at);
+        if let (EventStatus::Published, Some(event)) = (next, first_published) {
+            feed::record(conn, event.id, FeedKind::EventPublished, None, None)?;
+        }
+        if next == EventStatus::Cancelled {
+            for event in &updated {
+                crate::database::events::on_cancelled(conn, event)?;
+            }
+        }
+
+        Ok(updated)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recurrence::RRule;
+
+    fn local(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        local(value).and_utc()
+    }
+
+    #[test]
+    fn occurrences_keep_local_time_across_dst() {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let rule = RRule::parse("FREQ=WEEKLY;COUNT=2").unwrap();
+        // Berlin moves from UTC+1 to UTC+2 on 2025-03-30.
+        let dtstart = local("2025-03-23 20:00");
+        let horizon = dtstart + Duration::days(EXPANSION_HORIZON_DAYS);
+        let starts = rule
+            .expand(&tz, dtstart, horizon)
+            .into_iter()
+            .filter_map(|local| occurrence_start(&tz, local))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            starts,
+            vec![utc("2025-03-23 19:00"), utc("2025-03-30 18:00")]
+        );
+    }
+
+    #[test]
+    fn start_in_a_dst_gap_moves_forward() {
+        // 02:30 doesn't exist in Berlin on 2025-03-30.
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        assert_eq!(
+            occurrence_start(&tz, local("2025-03-30 02:30")),
+            Some(utc("2025-03-30 01:30"))
+        );
+    }
+
+    #[test]
+    fn start_in_a_repeated_hour_takes_the_first() {
+        // 02:30 happens twice in Berlin on 2025-10-26, first at UTC+2.
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        assert_eq!(
+            occurrence_start(&tz, local("2025-10-26 02:30")),
+            Some(utc("2025-10-26 00:30"))
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use crate::models::tags::{Tag, TagSuggestion};
+use crate::schema::{event_tags, tags};
+use diesel::dsl::count;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+pub const MAX_TAG_LENGTH: usize = 32;
+pub const MAX_TAGS_PER_EVENT: usize = 10;
+pub const AUTOCOMPLETE_DEFAULT_LIMIT: i64 = 10;
+pub const AUTOCOMPLETE_MAX_LIMIT: i64 = 50;
+
+/// Tags are stored lowercased with inner whitespace collapsed, so "Outdoor"
+/// and " outdoor " are the same tag. Returns `None` for blank or overlong
+/// input.
+pub fn normalize(name: &str) -> Option<String> {
+    let name = name
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    if name.is_empty() || name.chars().count() > MAX_TAG_LENGTH {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Replace the tags of an event, creating tags that don't exist yet.
+/// `names` must already be normalized.
+pub fn set_event_tags(
+    conn: &mut PgConnection,
+    event_id: i32,
+    names: &[String],
+) -> QueryResult<Vec<Tag>> {
+    let new_tags = names
+        .iter()
+        .map(|name| tags::name.eq(name))
+        .collect::<Vec<_>>();
+    diesel::insert_into(tags::table)
+        .values(&new_tags)
+        .on_conflict(tags::name)
+        .do_nothing()
+        .execute(conn)?;
+
+    let event_tags = tags::table
+        .filter(tags::name.eq_any(names))
+        .order(tags::name.asc())
+        .select(Tag::as_select())
+        .load::<Tag>(conn)?;
+
+    diesel::delete(event_tags::table.filter(event_tags::event_id.eq(event_id)))
+        .execute(conn)?;
+    let rows = event_tags
+        .iter()
+        .map(|tag| (event_tags::event_id.eq(event_id), event_tags::tag_id.eq(tag.id)))
+        .collect::<Vec<_>>();
+    diesel::insert_into(event_tags::table)
+        .values(&rows)
+        .execute(conn)?;
+
+    Ok(event_tags)
+}
+
+/// Tags of each of the given events, keyed by event id.
+pub fn for_events(conn: &mut PgConnection, event_ids: &[i32]) -> QueryResult<HashMap<i32, Vec<Tag>>> {
+    let rows = event_tags::table
+        .inner_join(tags::table)
+        .filter(event_tags::event_id.eq_any(event_ids))
+        .order(tags::name.asc())
+        .select((event_tags::event_id, Tag::as_select()))
+        .load::<(i32, Tag)>(conn)?;
+
+    let mut by_event: HashMap<i32, Vec<Tag>> = HashMap::new();
+    for (event_id, tag) in rows {
+        by_event.entry(event_id).or_default().push(tag);
+    }
+    Ok(by_event)
+}
+
+/// Tags starting with `prefix`, most used first.
+pub fn autocomplete(
+    conn: &mut PgConnection,
+    prefix: &str,
+    limit: i64,
+) -> QueryResult<Vec<TagSuggestion>> {
+    let pattern = format!(
+        "{}%",
+        prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+    );
+
+    tags::table
+        .left_join(event_tags::table)
+        .filter(tags::name.like(pattern))
+        .group_by((tags::id, tags::name))
+        .select((tags::id, tags::name, count(event_tags::event_id.nullable())))
+        .order((count(event_tags::event_id.nullable()).desc(), tags::name.asc()))
+        .limit(limit.clamp(1, AUTOCOMPLETE_MAX_LIMIT))
+        .load::<TagSuggestion>(conn)
+}
@@ -0,0 +1,252 @@
+use crate::models::tickets::{Ticket, TicketFiltering, TicketOwner};
+use crate::schema::{ticket_owners, tickets, users};
+use chrono::Utc;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use rocket::form::FromFormField;
+use scrypt::password_hash::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum TicketStatus {
+    Valid,
+    Void,
+}
+
+impl TicketStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TicketStatus::Valid => "valid",
+            TicketStatus::Void => "void",
+        }
+    }
+}
+
+impl FromStr for TicketStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "valid" => Ok(TicketStatus::Valid),
+            "void" => Ok(TicketStatus::Void),
+            _ => Err(()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for TicketStatus {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation("invalid ticket status"))
+        })
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for TicketStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for TicketStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+/// How a holder came to own a ticket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum AcquiredVia {
+    Purchase,
+    Transfer,
+    Resale,
+}
+
+impl AcquiredVia {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AcquiredVia::Purchase => "purchase",
+            AcquiredVia::Transfer => "transfer",
+            AcquiredVia::Resale => "resale",
+        }
+    }
+}
+
+impl FromStr for AcquiredVia {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "purchase" => Ok(AcquiredVia::Purchase),
+            "transfer" => Ok(AcquiredVia::Transfer),
+            "resale" => Ok(AcquiredVia::Resale),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for AcquiredVia {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for AcquiredVia {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tickets)]
+pub struct NewTicket {
+    pub order_id: i32,
+    pub event_id: i32,
+    pub owner_id: i32,
+    pub qr_token: String,
+}
+
+/// 256 random bits, hex encoded. The token carries no meaning by itself and
+/// is looked up when the QR code is scanned at the door.
+pub fn generate_qr_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Issue `quantity` tickets for a paid order.
+pub fn issue(
+    conn: &mut PgConnection,
+    order_id: i32,
+    event_id: i32,
+    owner_id: i32,
+    quantity: i32,
+) -> QueryResult<Vec<Ticket>> {
+    let new_tickets = (0..quantity)
+        .map(|_| NewTicket {
+            order_id,
+            event_id,
+            owner_id,
+            qr_token: generate_qr_token(),
+        })
+        .collect::<Vec<_>>();
+
+    let issued = diesel::insert_into(tickets::table)
+        .values(&new_tickets)
+        .returning(Ticket::as_returning())
+        .get_results::<Ticket>(conn)?;
+
+    let owners = issued
+        .iter()
+        .map(|ticket| {
+            (
+                ticket_owners::ticket_id.eq(ticket.id),
+                ticket_owners::owner_id.eq(owner_id),
+                ticket_owners::acquired_via.eq(AcquiredVia::Purchase),
+            )
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(ticket_owners::table)
+        .values(&owners)
+        .execute(conn)?;
+
+    Ok(issued)
+}
+
+/// Hand a ticket to a new owner. The old QR code stops working and the
+/// change is appended to the ticket's ownership history.
+pub fn reissue(
+    conn: &mut PgConnection,
+    ticket_id: i32,
+    new_owner_id: i32,
+    via: AcquiredVia,
+    transfer_id: Option<i32>,
+) -> QueryResult<Ticket> {
+    let now = Utc::now();
+    diesel::update(
+        ticket_owners::table
+            .filter(ticket_owners::ticket_id.eq(ticket_id))
+            .filter(ticket_owners::released_at.is_null()),
+    )
+    .set(ticket_owners::released_at.eq(now))
+    .execute(conn)?;
+
+    diesel::insert_into(ticket_owners::table)
+        .values((
+            ticket_owners::ticket_id.eq(ticket_id),
+            ticket_owners::owner_id.eq(new_owner_id),
+            ticket_owners::acquired_via.eq(via),
+            ticket_owners::transfer_id.eq(transfer_id),
+            ticket_owners::acquired_at.eq(now),
+        ))
+        .execute(conn)?;
+
+    diesel::update(tickets::table.find(ticket_id))
+        .set((
+            tickets::owner_id.eq(new_owner_id),
+            tickets::qr_token.eq(generate_qr_token()),
+        ))
+        .returning(Ticket::as_returning())
+        .get_result(conn)
+}
+
+/// Everyone who has held the ticket, oldest first.
+pub fn history(conn: &mut PgConnection, ticket_id: i32) -> QueryResult<Vec<TicketOwner>> {
+    ticket_owners::table
+        .inner_join(users::table)
+        .filter(ticket_owners::ticket_id.eq(ticket_id))
+        .order(ticket_owners::acquired_at.asc())
+        .select((
+            users::username,
+            ticket_owners::acquired_via,
+            ticket_owners::acquired_at,
+            ticket_owners::released_at,
+        ))
+        .load(conn)
+}
+
+pub fn get_tickets(
+    conn: &mut PgConnection,
+    owner: i32,
+    filters: Option<TicketFiltering>,
+) -> Result<Vec<Ticket>, diesel::result::Error> {
+    use crate::schema::tickets::dsl::*;
+
+    let mut query = tickets.filter(owner_id.eq(owner)).into_boxed();
+    if let Some(f) = filters {
+        if let Some(id_filter) = f.id {
+            query = query.filter(id.eq(id_filter));
+        }
+        if let Some(event_id_filter) = f.event_id {
+            query = query.filter(event_id.eq(event_id_filter));
+        }
+        if let Some(status_filter) = f.status {
+            query = query.filter(status.eq(status_filter));
+        }
+        if let Some(limit_filter) = f.limit {
+            query = query.limit(limit_filter);
+        }
+    }
+
+    query
+        .order(created_at.desc())
+        .select(Ticket::as_select())
+        .load(conn)
+}
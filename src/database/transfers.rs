@@ -0,0 +1,298 @@
+use crate::database::listings;
+use crate::database::notifications::{self, NewNotification, NotificationKind};
+use crate::database::tickets::{self, AcquiredVia, TicketStatus};
+use crate::models::events::Event;
+use crate::models::tickets::{Ticket, TicketOwner};
+use crate::models::transfers::TicketTransfer;
+use crate::schema::{events, ticket_transfers, tickets as tickets_table, users};
+use chrono::Utc;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Cancelled,
+}
+
+impl TransferStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransferStatus::Pending => "pending",
+            TransferStatus::Accepted => "accepted",
+            TransferStatus::Declined => "declined",
+            TransferStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for TransferStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "pending" => Ok(TransferStatus::Pending),
+            "accepted" => Ok(TransferStatus::Accepted),
+            "declined" => Ok(TransferStatus::Declined),
+            "cancelled" => Ok(TransferStatus::Cancelled),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for TransferStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for TransferStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+pub enum TransferError {
+    /// The ticket doesn't exist, isn't the user's or is no longer valid.
+    TicketNotFound,
+    RecipientNotFound,
+    SelfTransfer,
+    /// The organizer disabled transfers or the cutoff has passed.
+    TransfersClosed,
+    AlreadyPending,
+    /// The ticket is on the resale market.
+    TicketListed,
+    NotFound,
+    Other,
+}
+
+impl From<Error> for TransferError {
+    fn from(err: Error) -> TransferError {
+        if let Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) = &err {
+            if info.constraint_name() == Some("ticket_transfers_one_pending") {
+                return TransferError::AlreadyPending;
+            }
+        }
+        TransferError::Other
+    }
+}
+
+/// `to` is a username, an email address or a 0x wallet address.
+fn find_recipient(conn: &mut PgConnection, to: &str) -> QueryResult<Option<(i32, String)>> {
+    let to = to.trim();
+    let query = users::table.select((users::id, users::username)).into_boxed();
+    let query = if to.starts_with("0x") {
+        query.filter(users::wallet_address.eq(to.to_lowercase()))
+    } else if to.contains('@') {
+        query.filter(users::email.eq(to))
+    } else {
+        query.filter(users::username.eq(to))
+    };
+    query.first(conn).optional()
+}
+
+fn username(conn: &mut PgConnection, user_id: i32) -> QueryResult<String> {
+    users::table
+        .find(user_id)
+        .select(users::username)
+        .get_result(conn)
+}
+
+/// The user's valid ticket and its event, both locked.
+fn owned_ticket_for_update(
+    conn: &mut PgConnection,
+    ticket_id: i32,
+    owner_id: i32,
+) -> Result<(Ticket, Event), TransferError> {
+    let ticket = tickets_table::table
+        .find(ticket_id)
+        .select(Ticket::as_select())
+        .for_update()
+        .get_result::<Ticket>(conn)
+        .optional()?
+        .filter(|ticket| ticket.owner_id == owner_id && ticket.status == TicketStatus::Valid)
+        .ok_or(TransferError::TicketNotFound)?;
+    let event = events::table
+        .find(ticket.event_id)
+        .select(Event::as_select())
+        .get_result::<Event>(conn)?;
+    Ok((ticket, event))
+}
+
+/// Offer a ticket to another user. Ownership only changes once the
+/// recipient accepts.
+pub fn initiate(
+    conn: &mut PgConnection,
+    ticket_id: i32,
+    from_user_id: i32,
+    to: &str,
+) -> Result<TicketTransfer, TransferError> {
+    conn.transaction(|conn| {
+        let (ticket, event) = owned_ticket_for_update(conn, ticket_id, from_user_id)?;
+        if !event.transfers_open(Utc::now()) {
+            return Err(TransferError::TransfersClosed);
+        }
+        if listings::is_listed(conn, ticket.id)? {
+            return Err(TransferError::TicketListed);
+        }
+        let (to_user_id, _) = find_recipient(conn, to)?.ok_or(TransferError::RecipientNotFound)?;
+        if to_user_id == from_user_id {
+            return Err(TransferError::SelfTransfer);
+        }
+
+        let transfer = diesel::insert_into(ticket_transfers::table)
+            .values((
+                ticket_transfers::ticket_id.eq(ticket.id),
+                ticket_transfers::from_user_id.eq(from_user_id),
+                ticket_transfers::to_user_id.eq(to_user_id),
+            ))
+            .returning(TicketTransfer::as_returning())
+            .get_result::<TicketTransfer>(conn)?;
+
+        let sender = username(conn, from_user_id)?;
+        notifications::notify(
+            conn,
+            &NewNotification {
+                user_id: to_user_id,
+                kind: NotificationKind::TicketTransferred,
+                title: "Ticket offered to you",
+                body: &format!("{} wants to send you a ticket for {}.", sender, event.eventname),
+                event_id: Some(event.id),
+                order_id: None,
+            },
+        )?;
+
+        Ok(transfer)
+    })
+}
+
+fn pending_for_update(conn: &mut PgConnection, id: i32) -> Result<TicketTransfer, TransferError> {
+    ticket_transfers::table
+        .find(id)
+        .filter(ticket_transfers::status.eq(TransferStatus::Pending))
+        .select(TicketTransfer::as_select())
+        .for_update()
+        .get_result::<TicketTransfer>(conn)
+        .optional()?
+        .ok_or(TransferError::NotFound)
+}
+
+fn close(conn: &mut PgConnection, id: i32, status: TransferStatus) -> QueryResult<TicketTransfer> {
+    diesel::update(ticket_transfers::table.find(id))
+        .set((
+            ticket_transfers::status.eq(status),
+            ticket_transfers::responded_at.eq(Utc::now()),
+        ))
+        .returning(TicketTransfer::as_returning())
+        .get_result(conn)
+}
+
+/// The recipient takes the ticket. It is reissued with a new QR code so
+/// the one the sender still has stops working.
+pub fn accept(conn: &mut PgConnection, id: i32, user_id: i32) -> Result<Ticket, TransferError> {
+    conn.transaction(|conn| {
+        let transfer = pending_for_update(conn, id)?;
+        if transfer.to_user_id != user_id {
+            return Err(TransferError::NotFound);
+        }
+        let (ticket, event) = owned_ticket_for_update(conn, transfer.ticket_id, transfer.from_user_id)?;
+        if !event.transfers_open(Utc::now()) {
+            return Err(TransferError::TransfersClosed);
+        }
+
+        close(conn, transfer.id, TransferStatus::Accepted)?;
+        let ticket = tickets::reissue(conn, ticket.id, user_id, AcquiredVia::Transfer, Some(transfer.id))?;
+
+        let recipient = username(conn, user_id)?;
+        notifications::notify(
+            conn,
+            &NewNotification {
+                user_id: transfer.from_user_id,
+                kind: NotificationKind::TicketTransferred,
+                title: "Ticket transferred",
+                body: &format!("{} accepted your ticket for {}.", recipient, event.eventname),
+                event_id: Some(event.id),
+                order_id: None,
+            },
+        )?;
+
+        Ok(ticket)
+    })
+}
+
+/// The recipient turns the offer down.
+pub fn decline(conn: &mut PgConnection, id: i32, user_id: i32) -> Result<TicketTransfer, TransferError> {
+    conn.transaction(|conn| {
+        let transfer = pending_for_update(conn, id)?;
+        if transfer.to_user_id != user_id {
+            return Err(TransferError::NotFound);
+        }
+        Ok(close(conn, transfer.id, TransferStatus::Declined)?)
+    })
+}
+
+/// The sender withdraws the offer.
+pub fn cancel(conn: &mut PgConnection, id: i32, user_id: i32) -> Result<TicketTransfer, TransferError> {
+    conn.transaction(|conn| {
+        let transfer = pending_for_update(conn, id)?;
+        if transfer.from_user_id != user_id {
+            return Err(TransferError::NotFound);
+        }
+        Ok(close(conn, transfer.id, TransferStatus::Cancelled)?)
+    })
+}
+
+/// Pending transfers sent to and by the user.
+pub fn pending(
+    conn: &mut PgConnection,
+    user_id: i32,
+) -> QueryResult<(Vec<TicketTransfer>, Vec<TicketTransfer>)> {
+    let incoming = ticket_transfers::table
+        .filter(ticket_transfers::to_user_id.eq(user_id))
+        .filter(ticket_transfers::status.eq(TransferStatus::Pending))
+        .order(ticket_transfers::created_at.desc())
+        .select(TicketTransfer::as_select())
+        .load(conn)?;
+    let outgoing = ticket_transfers::table
+        .filter(ticket_transfers::from_user_id.eq(user_id))
+        .filter(ticket_transfers::status.eq(TransferStatus::Pending))
+        .order(ticket_transfers::created_at.desc())
+        .select(TicketTransfer::as_select())
+        .load(conn)?;
+    Ok((incoming, outgoing))
+}
+
+/// Ownership history of a ticket, visible to its current holder and to the
+/// event's organizer.
+pub fn ownership_history(
+    conn: &mut PgConnection,
+    ticket_id: i32,
+    user_id: i32,
+) -> Result<Vec<TicketOwner>, TransferError> {
+    let (owner_id, organizer_id) = tickets_table::table
+        .inner_join(events::table)
+        .filter(tickets_table::id.eq(ticket_id))
+        .select((tickets_table::owner_id, events::userid))
+        .get_result::<(i32, i32)>(conn)
+        .optional()?
+        .ok_or(TransferError::TicketNotFound)?;
+    if user_id != owner_id && user_id != organizer_id {
+        return Err(TransferError::TicketNotFound);
+    }
+    Ok(tickets::history(conn, ticket_id)?)
+}
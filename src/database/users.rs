@@ -1,4 +1,5 @@
 use crate::models::user::{User, UserFiltering};
+use crate::money::Currency;
 use crate::schema::users;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
@@ -139,6 +140,14 @@ pub fn find(conn: &mut PgConnection, id: i32) -> Option<User> {
         .ok()
 }
 
+pub fn is_admin(conn: &mut PgConnection, id: i32) -> bool {
+    users::table
+        .find(id)
+        .select(users::is_admin)
+        .get_result(conn)
+        .unwrap_or(false)
+}
+
 // TODO: remove clone when diesel will allow skipping fields
 #[derive(Deserialize, AsChangeset, Default, Clone, Validate)]
 #[table_name = "users"]
@@ -146,6 +155,8 @@ pub struct UpdateUserData {
     username: Option<String>,
     email: Option<String>,
     image: Option<String>,
+    wallet_address: Option<String>,
+    preferred_currency: Option<Currency>,
 
     // hack to skip the field
     #[column_name = "hash"]
@@ -155,6 +166,7 @@ pub struct UpdateUserData {
 pub fn update(conn: &mut PgConnection, id: i32, data: &UpdateUserData) -> Option<User> {
     let data = &UpdateUserData {
         password: None,
+        wallet_address: data.wallet_address.as_ref().map(|address| address.to_lowercase()),
         ..data.clone()
     };
     diesel::update(users::table.find(id))
@@ -163,6 +175,16 @@ pub fn update(conn: &mut PgConnection, id: i32, data: &UpdateUserData) -> Option
         .ok()
 }
 
+/// Currency the user wants prices shown in, if they picked one.
+pub fn preferred_currency(conn: &mut PgConnection, id: i32) -> QueryResult<Option<Currency>> {
+    Ok(users::table
+        .find(id)
+        .select(users::preferred_currency)
+        .get_result::<Option<Currency>>(conn)
+        .optional()?
+        .flatten())
+}
+
 pub fn delete(conn: &mut PgConnection, id: i32) -> Result<usize, Error>{
     let user_deleted = diesel::delete(users::table.filter(users::id.eq(id)))
         .execute(conn)
@@ -0,0 +1,133 @@
+use crate::database::users;
+use crate::models::venues::{Venue, VenueFiltering};
+use crate::schema::venues;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use serde::Deserialize;
+
+#[derive(Insertable)]
+#[diesel(table_name = venues)]
+pub struct NewVenue<'a> {
+    pub name: &'a str,
+    pub address: &'a str,
+    pub city: &'a str,
+    pub country: &'a str,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub capacity: Option<i32>,
+    pub timezone: &'a str,
+    pub created_by: i32,
+}
+
+pub enum VenueCreationError {
+    DuplicatedVenue,
+    Other,
+}
+
+impl From<Error> for VenueCreationError {
+    fn from(err: Error) -> VenueCreationError {
+        if let Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) = &err {
+            if let Some("venues_name_city_country_key") = info.constraint_name() {
+                return VenueCreationError::DuplicatedVenue;
+            }
+        }
+        VenueCreationError::Other
+    }
+}
+
+pub fn create(conn: &mut PgConnection, new_venue: &NewVenue) -> Result<Venue, VenueCreationError> {
+    diesel::insert_into(venues::table)
+        .values(new_venue)
+        .returning(Venue::as_returning())
+        .get_result(conn)
+        .map_err(Into::into)
+}
+
+pub fn get_venues(
+    conn: &mut PgConnection,
+    filters: Option<VenueFiltering>,
+) -> Result<Vec<Venue>, diesel::result::Error> {
+    use crate::schema::venues::dsl::*;
+
+    let mut query = venues.into_boxed();
+    if let Some(f) = filters {
+        if let Some(id_filter) = f.id {
+            query = query.filter(id.eq(id_filter));
+        }
+        if let Some(name_filter) = f.name {
+            query = query.filter(name.ilike(format!("%{}%", name_filter)));
+        }
+        if let Some(city_filter) = f.city {
+            query = query.filter(city.ilike(city_filter));
+        }
+        if let Some(country_filter) = f.country {
+            query = query.filter(country.ilike(country_filter));
+        }
+        if let Some(limit_filter) = f.limit {
+            query = query.limit(limit_filter);
+        }
+    }
+
+    query.order(name.asc()).select(Venue::as_select()).load(conn)
+}
+
+pub fn find(conn: &mut PgConnection, id: i32) -> QueryResult<Venue> {
+    venues::table
+        .find(id)
+        .select(Venue::as_select())
+        .get_result(conn)
+}
+
+#[derive(Deserialize, AsChangeset, Default, Clone, Validate)]
+#[diesel(table_name = venues)]
+pub struct UpdateVenueData {
+    name: Option<String>,
+    address: Option<String>,
+    city: Option<String>,
+    country: Option<String>,
+    #[validate(range(min = -90.0, max = 90.0))]
+    pub latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0))]
+    pub longitude: Option<f64>,
+    capacity: Option<i32>,
+    pub timezone: Option<String>,
+}
+
+pub enum VenueUpdateError {
+    NotFound,
+    /// Only the user who added the venue, or an admin, may edit it.
+    Forbidden,
+    Other,
+}
+
+impl From<Error> for VenueUpdateError {
+    fn from(err: Error) -> VenueUpdateError {
+        match err {
+            Error::NotFound => VenueUpdateError::NotFound,
+            _ => VenueUpdateError::Other,
+        }
+    }
+}
+
+pub fn update(
+    conn: &mut PgConnection,
+    id: i32,
+    user_id: i32,
+    data: &UpdateVenueData,
+) -> Result<Venue, VenueUpdateError> {
+    conn.transaction(|conn| {
+        let created_by = venues::table
+            .find(id)
+            .select(venues::created_by)
+            .for_update()
+            .get_result::<Option<i32>>(conn)?;
+        if created_by != Some(user_id) && !users::is_admin(conn, user_id) {
+            return Err(VenueUpdateError::Forbidden);
+        }
+        Ok(diesel::update(venues::table.find(id))
+            .set(data)
+            .returning(Venue::as_returning())
+            .get_result(conn)?)
+    })
+}
@@ -0,0 +1,341 @@
+use crate::database::events::EventStatus;
+use crate::database::notifications::{self, NewNotification, NotificationKind};
+use crate::database::orders::{self, OrderStatus, MAX_TICKETS_PER_ORDER};
+use crate::database::promotions;
+use crate::models::events::Event;
+use crate::models::waitlist::{WaitlistEntry, WaitlistPosition, WaitlistSummary};
+use crate::schema::{events, orders as orders_table, waitlist_entries};
+use chrono::{Duration, Utc};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::dsl::count_star;
+use diesel::expression::AsExpression;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::str::FromStr;
+
+/// How long offered seats are held for the person at the front of the line.
+pub const OFFER_MINUTES: i64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Deserialize, Serialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum WaitlistStatus {
+    Waiting,
+    /// Seats are held in a pending order until the offer runs out.
+    Offered,
+    Purchased,
+    Expired,
+    Left,
+}
+
+impl WaitlistStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WaitlistStatus::Waiting => "waiting",
+            WaitlistStatus::Offered => "offered",
+            WaitlistStatus::Purchased => "purchased",
+            WaitlistStatus::Expired => "expired",
+            WaitlistStatus::Left => "left",
+        }
+    }
+}
+
+impl FromStr for WaitlistStatus {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "waiting" => Ok(WaitlistStatus::Waiting),
+            "offered" => Ok(WaitlistStatus::Offered),
+            "purchased" => Ok(WaitlistStatus::Purchased),
+            "expired" => Ok(WaitlistStatus::Expired),
+            "left" => Ok(WaitlistStatus::Left),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for WaitlistStatus {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for WaitlistStatus {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized enum variant".into())
+    }
+}
+
+pub enum WaitlistError {
+    EventNotFound,
+    EventNotOnSale,
+    /// There are enough seats to buy right away.
+    NotSoldOut,
+    /// More seats than one order may hold, or none.
+    InvalidQuantity,
+    AlreadyWaiting,
+    NotWaiting,
+    Other,
+}
+
+impl From<Error> for WaitlistError {
+    fn from(err: Error) -> WaitlistError {
+        if let Error::DatabaseError(DatabaseErrorKind::UniqueViolation, info) = &err {
+            if info.constraint_name() == Some("waitlist_entries_one_open") {
+                return WaitlistError::AlreadyWaiting;
+            }
+        }
+        WaitlistError::Other
+    }
+}
+
+/// Seats owed to the people waiting for the event. Seats that come back go
+/// to them before anyone else can buy.
+pub fn owed_seats(conn: &mut PgConnection, event_id: i32) -> QueryResult<i64> {
+    waitlist_entries::table
+        .filter(waitlist_entries::event_id.eq(event_id))
+        .filter(waitlist_entries::status.eq(WaitlistStatus::Waiting))
+        .select(diesel::dsl::sum(waitlist_entries::quantity))
+        .get_result::<Option<i64>>(conn)
+        .map(|owed| owed.unwrap_or(0))
+}
+
+/// Whether `quantity` seats can be bought right away, after the line's.
+pub fn can_buy(conn: &mut PgConnection, event: &Event, quantity: i32) -> QueryResult<bool> {
+    Ok(match event.tickets_available() {
+        Some(available) => available as i64 - owed_seats(conn, event.id)? >= quantity as i64,
+        None => true,
+    })
+}
+
+/// Get in line for a sold-out event.
+pub fn join(
+    conn: &mut PgConnection,
+    event_id: i32,
+    user_id: i32,
+    quantity: i32,
+) -> Result<WaitlistEntry, WaitlistError> {
+    if !(1..=MAX_TICKETS_PER_ORDER).contains(&quantity) {
+        return Err(WaitlistError::InvalidQuantity);
+    }
+
+    conn.transaction(|conn| {
+        let event = events::table
+            .find(event_id)
+            .select(Event::as_select())
+            .get_result::<Event>(conn)
+            .optional()?
+            .ok_or(WaitlistError::EventNotFound)?;
+        if event.status != EventStatus::Published || event.starts_at <= Utc::now() {
+            return Err(WaitlistError::EventNotOnSale);
+        }
+        if can_buy(conn, &event, quantity)? {
+            return Err(WaitlistError::NotSoldOut);
+        }
+
+        Ok(diesel::insert_into(waitlist_entries::table)
+            .values((
+                waitlist_entries::event_id.eq(event_id),
+                waitlist_entries::user_id.eq(user_id),
+                waitlist_entries::quantity.eq(quantity),
+            ))
+            .returning(WaitlistEntry::as_returning())
+            .get_result(conn)?)
+    })
+}
+
+/// Step out of line. Offered seats are given up by cancelling the order.
+pub fn leave(conn: &mut PgConnection, event_id: i32, user_id: i32) -> Result<WaitlistEntry, WaitlistError> {
+    diesel::update(
+        waitlist_entries::table
+            .filter(waitlist_entries::event_id.eq(event_id))
+            .filter(waitlist_entries::user_id.eq(user_id))
+            .filter(waitlist_entries::status.eq(WaitlistStatus::Waiting)),
+    )
+    .set(waitlist_entries::status.eq(WaitlistStatus::Left))
+    .returning(WaitlistEntry::as_returning())
+    .get_result(conn)
+    .optional()?
+    .ok_or(WaitlistError::NotWaiting)
+}
+
+/// The user's place in line for the event.
+pub fn position(
+    conn: &mut PgConnection,
+    event_id: i32,
+    user_id: i32,
+) -> Result<WaitlistPosition, WaitlistError> {
+    let entry = waitlist_entries::table
+        .filter(waitlist_entries::event_id.eq(event_id))
+        .filter(waitlist_entries::user_id.eq(user_id))
+        .filter(waitlist_entries::status.eq_any([WaitlistStatus::Waiting, WaitlistStatus::Offered]))
+        .select(WaitlistEntry::as_select())
+        .first::<WaitlistEntry>(conn)
+        .optional()?
+        .ok_or(WaitlistError::NotWaiting)?;
+
+    let position = if entry.status == WaitlistStatus::Waiting {
+        Some(
+            waitlist_entries::table
+                .filter(waitlist_entries::event_id.eq(event_id))
+                .filter(waitlist_entries::status.eq(WaitlistStatus::Waiting))
+                .filter(waitlist_entries::id.le(entry.id))
+                .select(count_star())
+                .get_result::<i64>(conn)?,
+        )
+    } else {
+        None
+    };
+    Ok(WaitlistPosition { entry, position })
+}
+
+/// Size of the line for one of the organizer's events.
+pub fn summary(
+    conn: &mut PgConnection,
+    event_id: i32,
+    organizer_id: i32,
+) -> Result<WaitlistSummary, WaitlistError> {
+    let organizer = events::table
+        .find(event_id)
+        .select(events::userid)
+        .get_result::<i32>(conn)
+        .optional()?;
+    if organizer != Some(organizer_id) {
+        return Err(WaitlistError::EventNotFound);
+    }
+
+    let (waiting, waiting_quantity) = waitlist_entries::table
+        .filter(waitlist_entries::event_id.eq(event_id))
+        .filter(waitlist_entries::status.eq(WaitlistStatus::Waiting))
+        .select((count_star(), diesel::dsl::sum(waitlist_entries::quantity)))
+        .get_result::<(i64, Option<i64>)>(conn)?;
+    let offered = waitlist_entries::table
+        .filter(waitlist_entries::event_id.eq(event_id))
+        .filter(waitlist_entries::status.eq(WaitlistStatus::Offered))
+        .select(count_star())
+        .get_result::<i64>(conn)?;
+
+    Ok(WaitlistSummary {
+        event_id,
+        waiting,
+        waiting_quantity: waiting_quantity.unwrap_or(0),
+        offered,
+    })
+}
+
+/// Called when an order is paid; closes the offer it came from, if any.
+pub fn mark_purchased(conn: &mut PgConnection, order_id: i32) -> QueryResult<usize> {
+    diesel::update(
+        waitlist_entries::table
+            .filter(waitlist_entries::order_id.eq(order_id))
+            .filter(waitlist_entries::status.eq(WaitlistStatus::Offered)),
+    )
+    .set(waitlist_entries::status.eq(WaitlistStatus::Purchased))
+    .execute(conn)
+}
+
+/// Hand seats that came back (refunds, expired holds, capacity increases)
+/// to the people waiting, in order. Offers that weren't paid in time lapse
+/// first so their seats move on to the next in line.
+pub fn offer_available(conn: &mut PgConnection) -> QueryResult<usize> {
+    orders::release_expired_holds(conn)?;
+
+    diesel::update(
+        waitlist_entries::table
+            .filter(waitlist_entries::status.eq(WaitlistStatus::Offered))
+            .filter(
+                waitlist_entries::order_id.eq_any(
+                    orders_table::table
+                        .filter(orders_table::status.eq_any([OrderStatus::Expired, OrderStatus::Cancelled]))
+                        .select(orders_table::id.nullable()),
+                ),
+            ),
+    )
+    .set(waitlist_entries::status.eq(WaitlistStatus::Expired))
+    .execute(conn)?;
+
+    let event_ids = waitlist_entries::table
+        .filter(waitlist_entries::status.eq(WaitlistStatus::Waiting))
+        .select(waitlist_entries::event_id)
+        .distinct()
+        .load::<i32>(conn)?;
+
+    let mut offered = 0;
+    for event_id in event_ids {
+        offered += offer_for_event(conn, event_id)?;
+    }
+    Ok(offered)
+}
+
+fn offer_for_event(conn: &mut PgConnection, event_id: i32) -> QueryResult<usize> {
+    conn.transaction(|conn| {
+        let event = events::table
+            .find(event_id)
+            .select(Event::as_select())
+            .for_update()
+            .get_result::<Event>(conn)?;
+
+        let waiting = waitlist_entries::table
+            .filter(waitlist_entries::event_id.eq(event_id))
+            .filter(waitlist_entries::status.eq(WaitlistStatus::Waiting));
+        if event.status != EventStatus::Published || event.starts_at <= Utc::now() {
+            diesel::update(waiting)
+                .set(waitlist_entries::status.eq(WaitlistStatus::Expired))
+                .execute(conn)?;
+            return Ok(0);
+        }
+
+        let line = waiting
+            .order(waitlist_entries::id.asc())
+            .select(WaitlistEntry::as_select())
+            .for_update()
+            .load::<WaitlistEntry>(conn)?;
+
+        let mut available = event.tickets_available();
+        let mut offered = 0;
+        for entry in line {
+            // In order, but a request that doesn't fit keeps its place
+            // without holding up smaller ones behind it.
+            if matches!(available, Some(seats) if seats < entry.quantity) {
+                continue;
+            }
+
+            let pricing = promotions::price(conn, &event, entry.quantity, None)?;
+            let order = orders::hold(conn, entry.user_id, &event, &pricing, Duration::minutes(OFFER_MINUTES))?;
+            diesel::update(waitlist_entries::table.find(entry.id))
+                .set((
+                    waitlist_entries::status.eq(WaitlistStatus::Offered),
+                    waitlist_entries::order_id.eq(order.id),
+                    waitlist_entries::offered_at.eq(Utc::now()),
+                ))
+                .execute(conn)?;
+            notifications::notify(
+                conn,
+                &NewNotification {
+                    user_id: entry.user_id,
+                    kind: NotificationKind::WaitlistOffer,
+                    title: "Tickets available",
+                    body: &format!(
+                        "{} ticket(s) for {} are held for you for {} minutes.",
+                        entry.quantity, event.eventname, OFFER_MINUTES
+                    ),
+                    event_id: Some(event.id),
+                    order_id: Some(order.id),
+                },
+            )?;
+
+            available = available.map(|seats| seats - entry.quantity);
+            offered += 1;
+        }
+        Ok(offered)
+    })
+}
@@ -18,11 +18,19 @@ use rocket::fs::FileServer;
 use dotenvy::dotenv;
 
 mod auth;
+mod chain;
 mod config;
 mod database;
 mod errors;
 mod models;
+mod money;
+mod notifier;
+mod payments;
+mod rates;
+mod receipts;
+mod recurrence;
 mod routes;
+mod scheduler;
 mod schema;
 pub mod uploadFile;
 
@@ -53,17 +61,97 @@ pub fn rocket() -> rocket::Rocket<rocket::Build> {
                 routes::users::delete_user,
                 routes::events::add_event,
                 routes::events::get_events,
+                routes::events::search_events,
+                routes::events::nearby_events,
                 routes::events::update_event,
                 routes::events::delete_event,
+                routes::events::update_event_status,
                 routes::likes::like_event,
                 routes::likes::delete_like,
                 routes::likes::get_likes,
-                routes::likes::is_event_liked_by_user
+                routes::likes::is_event_liked_by_user,
+                routes::venues::add_venue,
+                routes::venues::get_venues,
+                routes::venues::update_venue,
+                routes::series::add_series,
+                routes::series::update_series_status,
+                routes::categories::get_categories,
+                routes::categories::add_category,
+                routes::categories::update_category,
+                routes::categories::delete_category,
+                routes::tags::autocomplete_tags,
+                routes::rankings::get_popular_events,
+                routes::rankings::record_event_view,
+                routes::recommendations::get_recommendations,
+                routes::follows::get_profile,
+                routes::follows::follow,
+                routes::follows::unfollow,
+                routes::follows::follow_venue,
+                routes::follows::unfollow_venue,
+                routes::feed::get_feed,
+                routes::notifications::get_notifications,
+                routes::notifications::mark_read,
+                routes::notifications::mark_all_read,
+                routes::notifications::get_preferences,
+                routes::notifications::update_preferences,
+                routes::jobs::get_jobs,
+                routes::jobs::retry_job,
+                routes::orders::add_order,
+                routes::orders::pay_order,
+                routes::orders::cancel_order,
+                routes::orders::get_orders,
+                routes::orders::quote_order,
+                routes::tickets::get_tickets,
+                routes::transfers::transfer_ticket,
+                routes::transfers::get_transfers,
+                routes::transfers::accept_transfer,
+                routes::transfers::decline_transfer,
+                routes::transfers::cancel_transfer,
+                routes::transfers::get_ticket_history,
+                routes::listings::add_listing,
+                routes::listings::get_event_listings,
+                routes::listings::get_my_listings,
+                routes::listings::cancel_listing,
+                routes::listings::reserve_listing,
+                routes::listings::pay_listing,
+                routes::refunds::request_refund,
+                routes::refunds::get_my_refunds,
+                routes::refunds::get_event_refunds,
+                routes::refunds::approve_refund,
+                routes::refunds::reject_refund,
+                routes::refunds::mark_refund_paid,
+                routes::waitlist::join_waitlist,
+                routes::waitlist::leave_waitlist,
+                routes::waitlist::get_waitlist_position,
+                routes::waitlist::get_waitlist_summary,
+                routes::promotions::add_promo_code,
+                routes::promotions::get_my_promo_codes,
+                routes::promotions::deactivate_promo_code,
+                routes::promotions::add_early_bird_rule,
+                routes::promotions::get_early_bird_rules,
+                routes::promotions::delete_early_bird_rule,
+                routes::rates::get_rates,
+                routes::payouts::request_payout,
+                routes::payouts::get_my_payouts,
+                routes::payouts::get_payouts,
+                routes::payouts::settle_payout,
+                routes::payouts::fail_payout,
+                routes::ledger::get_balance,
+                routes::ledger::get_statement,
+                routes::ledger::check_ledger,
+                routes::payments::start_payment,
+                routes::payments::get_order_payments,
+                routes::payments::payment_webhook,
+                routes::payments::complete_fake_payment,
+                routes::chain_payments::get_chain_payments,
+                routes::chain_payments::refund_chain_payment,
+                routes::invoices::get_receipt
             ],
         )
         .mount("/media", FileServer::from("media"))
         .attach(database::Db::fairing())
         .attach(cors_fairing())
         .attach(config::AppState::manage())
+        .attach(scheduler::fairing())
         .register("/", catchers![not_found])
 }
@@ -0,0 +1,25 @@
+use rocket::serde::Deserialize;
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::schema::categories;
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = categories)]
+pub struct Category {
+    pub id: i32,
+    pub parent_id: Option<i32>,
+    pub slug: String,
+    pub name: String,
+    pub position: i32,
+}
+
+/// A category with its subcategories and the number of public events filed
+/// under it, directly or through a subcategory.
+#[derive(Serialize, Debug)]
+pub struct CategoryNode {
+    #[serde(flatten)]
+    pub category: Category,
+    pub event_count: i64,
+    pub children: Vec<CategoryNode>,
+}
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::chain_payments::ChainPaymentStatus;
+use crate::money::{Amount, Currency};
+use crate::schema::chain_payments;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = chain_payments)]
+pub struct ChainPayment {
+    pub tx_hash: String,
+    pub order_id: Option<i32>,
+    pub listing_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    /// The buyer who handed in the transaction.
+    pub user_id: i32,
+    /// The wallet the money came from, and goes back to if unmatched.
+    pub sender: Option<String>,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub status: ChainPaymentStatus,
+    /// Transaction hash of the refund once sent.
+    pub refund_reference: Option<String>,
+    pub refunded_at: Option<DateTime<Utc>>,
+}
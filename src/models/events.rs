@@ -1,26 +1,181 @@
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use rocket::serde::Deserialize;
 
-use diesel::Queryable;
-use serde::Serialize;
-use rocket::form::FromForm;
+use diesel::sql_types::{Float4, Text};
+use diesel::{Queryable, QueryableByName, Selectable};
+use serde::{Serialize, Serializer};
+use rocket::form::{FromForm, FromFormField};
 
-use crate::database::events::EventType;
+use crate::database::events::EventStatus;
+use crate::database::rates::RateTable;
+use crate::models::categories::Category;
+use crate::models::tags::Tag;
+use crate::models::venues::Venue;
+use crate::money::{Amount, Currency, Money, OutOfRange};
+use crate::schema::events;
 
-#[derive(Queryable, Serialize, Deserialize, Debug)]
+#[derive(Queryable, Selectable, QueryableByName, Deserialize, Debug)]
+#[diesel(table_name = events)]
 pub struct Event {
     pub id: i32,
     pub userid: i32,
     pub eventname: String,
     pub eventdescription: String,
-    pub eventdate: NaiveDate,
-    pub eventdatetime: NaiveDateTime,
-    pub eventtype: EventType,
-    pub eventcountry: String,
-    pub eventcity: String,
-    pub eventplace: String,
     pub eventimage: String,
-    pub eventticketprice: i32,
+    /// In minor units of `currency`.
+    pub eventticketprice: Amount,
+    pub venue_id: i32,
+    pub status: EventStatus,
+    /// IANA timezone the event takes place in, e.g. "Africa/Dar_es_Salaam".
+    pub timezone: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub series_id: Option<i32>,
+    /// Original start of this occurrence within its series.
+    pub recurrence_id: Option<DateTime<Utc>>,
+    pub capacity: Option<i32>,
+    pub tickets_sold: i32,
+    pub transfers_enabled: bool,
+    /// Transfers close this many hours before the start.
+    pub transfer_cutoff_hours: i32,
+    pub resale_enabled: bool,
+    /// Resale price cap, in percent over `eventticketprice`.
+    pub resale_max_markup_percent: i32,
+    /// Share of each resale that goes to the organizer.
+    pub resale_royalty_percent: i32,
+    /// Full refunds are given until this time.
+    pub refund_full_until: Option<DateTime<Utc>>,
+    /// Share of the price refunded after `refund_full_until`.
+    pub refund_partial_percent: i32,
+    /// No refunds this many hours before the start.
+    pub refund_cutoff_hours: i32,
+    /// Currency of every amount paid for the event.
+    pub currency: Currency,
+}
+
+impl Event {
+    /// Every write validates the timezone, so an unknown one is a corrupt
+    /// row rather than something to paper over with UTC.
+    pub fn tz(&self) -> Result<Tz, diesel::result::Error> {
+        self.timezone.parse().map_err(|_| {
+            diesel::result::Error::DeserializationError(
+                format!("event {} has an invalid timezone {:?}", self.id, self.timezone).into(),
+            )
+        })
+    }
+
+    /// Seats left, `None` when the event has no capacity limit.
+    pub fn tickets_available(&self) -> Option<i32> {
+        self.capacity
+            .map(|capacity| (capacity - self.tickets_sold).max(0))
+    }
+
+    /// Whether tickets may change hands at `now` under the organizer's rules.
+    pub fn transfers_open(&self, now: DateTime<Utc>) -> bool {
+        self.transfers_enabled
+            && now < self.starts_at - chrono::Duration::hours(self.transfer_cutoff_hours as i64)
+    }
+
+    /// Whether tickets may be listed and bought on the resale market at `now`.
+    /// Resale closes at the same cutoff as transfers.
+    pub fn resale_open(&self, now: DateTime<Utc>) -> bool {
+        self.resale_enabled
+            && self.status == EventStatus::Published
+            && now < self.starts_at - chrono::Duration::hours(self.transfer_cutoff_hours as i64)
+    }
+
+    /// Percentage of the price an attendee gets back when asking for a
+    /// refund at `now`.
+    pub fn refund_percent(&self, now: DateTime<Utc>) -> i32 {
+        if now >= self.starts_at - chrono::Duration::hours(self.refund_cutoff_hours as i64) {
+            0
+        } else if matches!(self.refund_full_until, Some(until) if now < until) {
+            100
+        } else {
+            self.refund_partial_percent
+        }
+    }
+
+    /// Highest price a ticket may be resold for. Out of range only for a
+    /// cap too high to matter.
+    pub fn max_resale_price(&self) -> Result<Amount, OutOfRange> {
+        self.eventticketprice
+            .percent(self.resale_max_markup_percent.saturating_add(100))
+    }
+}
+
+/// Events are rendered with both UTC and venue-local start and end times.
+impl Serialize for Event {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct EventJson<'a> {
+            id: i32,
+            userid: i32,
+            eventname: &'a str,
+            eventdescription: &'a str,
+            eventimage: &'a str,
+            eventticketprice: Amount,
+            currency: Currency,
+            venue_id: i32,
+            status: EventStatus,
+            timezone: &'a str,
+            starts_at: DateTime<Utc>,
+            ends_at: DateTime<Utc>,
+            starts_at_local: String,
+            ends_at_local: String,
+            series_id: Option<i32>,
+            recurrence_id: Option<DateTime<Utc>>,
+            capacity: Option<i32>,
+            tickets_sold: i32,
+            tickets_available: Option<i32>,
+            transfers_enabled: bool,
+            transfer_cutoff_hours: i32,
+            resale_enabled: bool,
+            resale_max_markup_percent: i32,
+            resale_royalty_percent: i32,
+            max_resale_price: Option<Amount>,
+            refund_full_until: Option<DateTime<Utc>>,
+            refund_partial_percent: i32,
+            refund_cutoff_hours: i32,
+        }
+
+        let tz = self.tz().map_err(serde::ser::Error::custom)?;
+        EventJson {
+            id: self.id,
+            userid: self.userid,
+            eventname: &self.eventname,
+            eventdescription: &self.eventdescription,
+            eventimage: &self.eventimage,
+            eventticketprice: self.eventticketprice,
+            currency: self.currency,
+            venue_id: self.venue_id,
+            status: self.status,
+            timezone: &self.timezone,
+            starts_at: self.starts_at,
+            ends_at: self.ends_at,
+            starts_at_local: self.starts_at.with_timezone(&tz).to_rfc3339(),
+            ends_at_local: self.ends_at.with_timezone(&tz).to_rfc3339(),
+            series_id: self.series_id,
+            recurrence_id: self.recurrence_id,
+            capacity: self.capacity,
+            tickets_sold: self.tickets_sold,
+            tickets_available: self.tickets_available(),
+            transfers_enabled: self.transfers_enabled,
+            transfer_cutoff_hours: self.transfer_cutoff_hours,
+            resale_enabled: self.resale_enabled,
+            resale_max_markup_percent: self.resale_max_markup_percent,
+            resale_royalty_percent: self.resale_royalty_percent,
+            max_resale_price: self.max_resale_price().ok(),
+            refund_full_until: self.refund_full_until,
+            refund_partial_percent: self.refund_partial_percent,
+            refund_cutoff_hours: self.refund_cutoff_hours,
+        }
+        .serialize(serializer)
+    }
 }
 
 #[derive(Queryable, Serialize, Deserialize)]
@@ -29,29 +184,219 @@ pub struct EventLogged {
     pub userid: i32,
     pub eventname: String,
     pub eventdescription: String,
-    pub eventdate: NaiveDate,
-    pub eventdatetime: NaiveDateTime,
-    pub eventtype: EventType,
-    pub eventcountry: String,
-    pub eventcity: String,
-    pub eventplace: String,
     pub eventimage: String,
-    pub eventticketprice: i32,
+    pub eventticketprice: Amount,
+    pub venue_id: i32,
+    pub status: EventStatus,
+    pub timezone: String,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub series_id: Option<i32>,
+    pub recurrence_id: Option<DateTime<Utc>>,
+    pub capacity: Option<i32>,
+    pub tickets_sold: i32,
     pub eventliked: bool,
 }
 
+#[derive(QueryableByName, Serialize)]
+pub struct EventSearchResult {
+    #[diesel(embed)]
+    #[serde(flatten)]
+    pub event: Event,
+    #[diesel(sql_type = Float4)]
+    pub rank: f32,
+    /// Matched fragment as escaped HTML, the search terms wrapped in `<mark>`
+    /// tags.
+    #[diesel(sql_type = Text)]
+    pub snippet: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EventDetails {
+    #[serde(flatten)]
+    pub event: Event,
+    pub venue: Venue,
+    pub categories: Vec<Category>,
+    pub tags: Vec<Tag>,
+    pub likes_count: i64,
+    /// Ticket price in the currency the viewer asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_price: Option<Money>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct NearbyEvent {
+    #[serde(flatten)]
+    pub event: Event,
+    pub venue: Venue,
+    pub categories: Vec<Category>,
+    pub tags: Vec<Tag>,
+    pub distance_km: f64,
+}
+
+#[derive(FromFormField, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventView {
+    Occurrences,
+    Series,
+}
+
+/// Price bands are ranges of this currency, so that events priced in
+/// different currencies are banded alike.
+pub const PRICE_BAND_CURRENCY: Currency = Currency::Usd;
+
+/// Ticket price ranges offered as a search facet.
+#[derive(FromFormField, Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PriceBand {
+    #[field(value = "free")]
+    #[serde(rename = "free")]
+    Free,
+    #[field(value = "under-25")]
+    #[serde(rename = "under-25")]
+    Under25,
+    #[field(value = "25-50")]
+    #[serde(rename = "25-50")]
+    From25To50,
+    #[field(value = "50-100")]
+    #[serde(rename = "50-100")]
+    From50To100,
+    #[field(value = "100-plus")]
+    #[serde(rename = "100-plus")]
+    Over100,
+}
+
+impl PriceBand {
+    pub const ALL: [PriceBand; 5] = [
+        PriceBand::Free,
+        PriceBand::Under25,
+        PriceBand::From25To50,
+        PriceBand::From50To100,
+        PriceBand::Over100,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriceBand::Free => "free",
+            PriceBand::Under25 => "under-25",
+            PriceBand::From25To50 => "25-50",
+            PriceBand::From50To100 => "50-100",
+            PriceBand::Over100 => "100-plus",
+        }
+    }
+
+    /// The band of a price, `None` when its currency has no exchange rate.
+    pub fn of(price: Amount, currency: Currency, rates: &RateTable) -> Option<PriceBand> {
+        PriceBand::ALL
+            .into_iter()
+            .find(|band| band.contains(price, currency, rates))
+    }
+
+    /// Inclusive lower and exclusive upper bound of the band in minor units
+    /// of `currency`. Bands are whole units of `PRICE_BAND_CURRENCY`,
+    /// converted at the cached rate; `None` when there is no rate.
+    pub fn bounds(
+        &self,
+        currency: Currency,
+        rates: &RateTable,
+    ) -> Option<(Amount, Option<Amount>)> {
+        let major = |units: i128| {
+            let amount = Amount::new(units * PRICE_BAND_CURRENCY.minor_per_major());
+            rates
+                .display(amount, PRICE_BAND_CURRENCY, currency)
+                .map(|money| money.amount)
+        };
+        Some(match self {
+            PriceBand::Free => (Amount::ZERO, Some(Amount::new(1))),
+            PriceBand::Under25 => (Amount::new(1), Some(major(25)?)),
+            PriceBand::From25To50 => (major(25)?, Some(major(50)?)),
+            PriceBand::From50To100 => (major(50)?, Some(major(100)?)),
+            PriceBand::Over100 => (major(100)?, None),
+        })
+    }
+
+    pub fn contains(&self, price: Amount, currency: Currency, rates: &RateTable) -> bool {
+        match self.bounds(currency, rates) {
+            Some((min, max)) => price >= min && max.is_none_or(|max| price < max),
+            None => false,
+        }
+    }
+
+    /// SQL condition matching events in the band whatever their currency.
+    /// Built from constants and cached rates only.
+    pub fn sql_condition(&self, rates: &RateTable) -> String {
+        let bands = Currency::ALL
+            .into_iter()
+            .filter_map(|currency| {
+                let (min, max) = self.bounds(currency, rates)?;
+                let max = max
+                    .map(|max| format!(" AND events.eventticketprice < {}", max))
+                    .unwrap_or_default();
+                Some(format!(
+                    "(events.currency = '{}' AND events.eventticketprice >= {}{})",
+                    currency, min, max
+                ))
+            })
+            .collect::<Vec<_>>();
+        if bands.is_empty() {
+            return "FALSE".to_string();
+        }
+        format!("({})", bands.join(" OR "))
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PriceBand::Free => "Free",
+            PriceBand::Under25 => "Under $25",
+            PriceBand::From25To50 => "$25 to $50",
+            PriceBand::From50To100 => "$50 to $100",
+            PriceBand::Over100 => "$100 and more",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct FacetCount {
+    /// What to pass back as the filter parameter.
+    pub value: String,
+    pub label: String,
+    pub count: i64,
+}
+
+/// Counts over every event matching the current filters, before `limit`.
+#[derive(Serialize, Debug, Default)]
+pub struct EventFacets {
+    pub categories: Vec<FacetCount>,
+    pub cities: Vec<FacetCount>,
+    pub tags: Vec<FacetCount>,
+    pub price_bands: Vec<FacetCount>,
+}
 
-#[derive(FromForm, Deserialize, Debug)]
+#[derive(FromForm, Deserialize, Debug, Default)]
 pub struct EventFiltering {
     pub id: Option<i32>,
     pub userid: Option<i32>,
     pub eventname: Option<String>,
     pub eventdate: Option<String>,
-    pub eventtype: Option<EventType>,
+    /// Category slug, subcategories included.
+    pub category: Option<String>,
+    pub tag: Option<String>,
+    pub price_band: Option<PriceBand>,
     pub eventcountry: Option<String>,
     pub eventcity: Option<String>,
     pub eventplace: Option<String>,
+    pub venue_id: Option<i32>,
+    pub status: Option<EventStatus>,
+    pub series_id: Option<i32>,
+    /// Date window, either RFC 3339 timestamps or YYYY-MM-DD (UTC).
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Return whole series instead of their individual occurrences.
+    pub view: Option<EventView>,
+    /// Also show prices in this currency. Defaults to the viewer's
+    /// preferred currency.
+    pub currency: Option<Currency>,
     pub limit: Option<i64>,
+    pub offset: Option<i64>,
     pub logged_user: Option<i32>
 }
 
@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::database::feed::{FeedKind, FeedReason};
+use crate::models::events::Event;
+use crate::models::venues::Venue;
+use crate::money::Amount;
+
+#[derive(Serialize, Debug)]
+pub struct FeedItem {
+    /// Pass the last id back as `cursor` to fetch the next page.
+    pub id: i64,
+    pub kind: FeedKind,
+    /// Why the item is in this user's feed.
+    pub reason: FeedReason,
+    /// Set for price drops, in the event's currency.
+    pub old_price: Option<Amount>,
+    pub new_price: Option<Amount>,
+    pub created_at: DateTime<Utc>,
+    pub event: Event,
+    pub venue: Venue,
+}
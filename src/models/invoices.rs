@@ -0,0 +1,56 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::money::{Amount, Currency};
+use crate::schema::invoices;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = invoices)]
+pub struct Invoice {
+    pub id: i32,
+    pub order_id: i32,
+    pub organizer_id: i32,
+    /// Sequential per organizer, starting at 1.
+    pub number: i32,
+    pub seller_name: String,
+    pub seller_email: String,
+    pub buyer_name: String,
+    pub buyer_email: String,
+    /// Tax included in the total, in hundredths of a percent.
+    pub tax_rate_bps: i32,
+    pub tax_amount: Amount,
+    /// Platform fee kept from the total.
+    pub fee_amount: Amount,
+    pub currency: Currency,
+    pub issued_at: DateTime<Utc>,
+    /// The event and its venue as they were at issue.
+    pub event_name: String,
+    pub event_starts_at: DateTime<Utc>,
+    pub event_timezone: String,
+    pub venue_name: String,
+    pub venue_address: String,
+    pub venue_city: String,
+    pub venue_country: String,
+}
+
+impl Invoice {
+    /// "INV-12-000034": organizer 12's 34th invoice.
+    pub fn display_number(&self) -> String {
+        format!("INV-{}-{:06}", self.organizer_id, self.number)
+    }
+
+    /// The event's timezone at issue, for its date.
+    pub fn tz(&self) -> Result<Tz, diesel::result::Error> {
+        self.event_timezone.parse().map_err(|_| {
+            diesel::result::Error::DeserializationError(
+                format!(
+                    "invoice {} has an invalid timezone {:?}",
+                    self.id, self.event_timezone
+                )
+                .into(),
+            )
+        })
+    }
+}
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::jobs::JobStatus;
+use crate::schema::scheduled_jobs;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = scheduled_jobs)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub run_at: DateTime<Utc>,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub last_error: Option<String>,
+    /// End of the current worker's lease while the job is running.
+    pub locked_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::ledger::{LedgerAccount, TransactionKind};
+use crate::money::{Amount, Currency, Money};
+use crate::schema::{ledger_entries, ledger_transactions};
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = ledger_entries)]
+pub struct LedgerEntry {
+    pub id: i32,
+    pub transaction_id: i32,
+    pub account: LedgerAccount,
+    pub organizer_id: Option<i32>,
+    /// Positive amounts credit the account.
+    pub amount: Amount,
+    pub currency: Currency,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = ledger_transactions)]
+pub struct LedgerTransaction {
+    pub id: i32,
+    pub kind: TransactionKind,
+    pub currency: Currency,
+    pub order_id: Option<i32>,
+    pub refund_id: Option<i32>,
+    pub payout_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub listing_id: Option<i32>,
+}
+
+/// What the platform owes an organizer in one currency.
+#[derive(Serialize, Debug)]
+pub struct Balance {
+    pub currency: Currency,
+    /// Sales minus fees, refunds and payouts; what can be paid out.
+    pub available: Amount,
+    /// Requested payouts not sent yet.
+    pub pending_payouts: Amount,
+    pub paid_out: Amount,
+}
+
+/// A movement of an organizer's available balance.
+#[derive(Serialize, Debug)]
+pub struct StatementLine {
+    #[serde(flatten)]
+    pub entry: LedgerEntry,
+    pub transaction: LedgerTransaction,
+    /// Available balance after this entry.
+    pub balance: Amount,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Statement {
+    pub currency: Currency,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Balance before the first line.
+    pub opening_balance: Amount,
+    /// Balance at `to`, past the last line if there are more pages.
+    pub closing_balance: Amount,
+    pub lines: Vec<StatementLine>,
+    /// Pass as `cursor` for the next page; `None` on the last one.
+    pub next_cursor: Option<i32>,
+}
+
+/// Result of checking that the books balance.
+#[derive(Serialize, Debug)]
+pub struct LedgerCheck {
+    /// Transactions whose entries don't sum to zero.
+    pub unbalanced_transactions: Vec<i32>,
+    /// Sum of every account per currency; all zero when the books balance.
+    pub totals: Vec<Money>,
+}
+
+impl LedgerCheck {
+    pub fn is_balanced(&self) -> bool {
+        self.unbalanced_transactions.is_empty()
+            && self.totals.iter().all(|total| total.amount == Amount::ZERO)
+    }
+}
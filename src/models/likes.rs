@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use rocket::serde::Deserialize;
 use diesel::Queryable;
 use serde::Serialize;
@@ -8,6 +9,7 @@ use rocket::form::FromForm;
 pub struct Like {
     pub user_id: i32,
     pub event_id: i32,
+    pub created_at: DateTime<Utc>,
 }
 
 #[derive(FromForm, Deserialize, Debug)]
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::listings::ListingStatus;
+use crate::money::{Amount, Currency};
+use crate::schema::ticket_listings;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = ticket_listings)]
+pub struct Listing {
+    pub id: i32,
+    pub ticket_id: i32,
+    pub event_id: i32,
+    pub seller_id: i32,
+    pub price: Amount,
+    pub status: ListingStatus,
+    pub buyer_id: Option<i32>,
+    /// End of the current buyer's hold while the listing is reserved.
+    pub reserved_until: Option<DateTime<Utc>>,
+    pub royalty_amount: Option<Amount>,
+    pub seller_amount: Option<Amount>,
+    pub created_at: DateTime<Utc>,
+    pub sold_at: Option<DateTime<Utc>>,
+    pub currency: Currency,
+}
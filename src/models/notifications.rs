@@ -0,0 +1,60 @@
+use chrono::{DateTime, Utc};
+use rocket::serde::Deserialize;
+
+use diesel::{AsChangeset, Insertable, Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::notifications::{DeliveryChannel, NotificationKind};
+use crate::schema::{notification_deliveries, notification_preferences, notifications};
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = notifications)]
+pub struct Notification {
+    pub id: i64,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: String,
+    pub event_id: Option<i32>,
+    pub order_id: Option<i32>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Which channels a user wants a kind of notification on.
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = notification_preferences)]
+pub struct NotificationPreference {
+    #[serde(skip)]
+    pub user_id: i32,
+    pub kind: NotificationKind,
+    pub in_app: bool,
+    pub email: bool,
+    pub push: bool,
+}
+
+impl NotificationPreference {
+    pub fn default_for(user_id: i32, kind: NotificationKind) -> NotificationPreference {
+        NotificationPreference {
+            user_id,
+            kind,
+            in_app: true,
+            email: true,
+            push: false,
+        }
+    }
+}
+
+/// An email or push message waiting for, or handed to, its sender.
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(table_name = notification_deliveries)]
+pub struct Delivery {
+    pub id: i64,
+    pub user_id: i32,
+    pub channel: DeliveryChannel,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: String,
+    pub attempts: i32,
+    /// The order an email is about; its receipt is attached.
+    pub order_id: Option<i32>,
+}
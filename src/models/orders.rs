@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use rocket::serde::Deserialize;
+
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+use rocket::form::FromForm;
+
+use crate::database::orders::OrderStatus;
+use crate::database::payments::PaymentMethod;
+use crate::money::{Amount, Currency, Money};
+use crate::schema::orders;
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = orders)]
+pub struct Order {
+    pub id: i32,
+    pub user_id: i32,
+    pub event_id: i32,
+    pub quantity: i32,
+    /// Face value of one ticket.
+    pub unit_price: Amount,
+    /// Face value of all tickets, before discounts.
+    pub subtotal_amount: Amount,
+    /// Early-bird and promo code discounts together.
+    pub discount_amount: Amount,
+    /// What the buyer is charged.
+    pub total_amount: Amount,
+    /// Currency of every amount on the order.
+    pub currency: Currency,
+    pub promo_code_id: Option<i32>,
+    pub early_bird_rule_id: Option<i32>,
+    pub status: OrderStatus,
+    /// Seats are held for a pending order until this time.
+    pub expires_at: DateTime<Utc>,
+    pub payment_reference: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub paid_at: Option<DateTime<Utc>>,
+    /// Set when paying in another currency; the rate is locked until
+    /// `expires_at`.
+    pub pay_currency: Option<Currency>,
+    pub pay_amount: Option<Amount>,
+    /// How the order was paid, once it is.
+    pub payment_method: Option<PaymentMethod>,
+    /// Who the tickets were sold for: the event's organizer at checkout.
+    pub organizer_id: i32,
+}
+
+impl Order {
+    /// What the buyer paid for one ticket after discounts. Any remainder of
+    /// an uneven split stays with the organizer.
+    pub fn paid_per_ticket(&self) -> Amount {
+        self.total_amount.split(self.quantity)
+    }
+
+    /// What to charge: the locked quote if there is one, else the total.
+    pub fn amount_due(&self) -> Money {
+        match (self.pay_amount, self.pay_currency) {
+            (Some(amount), Some(currency)) => Money::new(amount, currency),
+            _ => Money::new(self.total_amount, self.currency),
+        }
+    }
+}
+
+#[derive(FromForm, Deserialize, Debug)]
+pub struct OrderFiltering {
+    pub id: Option<i32>,
+    pub event_id: Option<i32>,
+    pub status: Option<OrderStatus>,
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,26 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::payments::{PaymentMethod, PaymentStatus};
+use crate::money::{Amount, Currency};
+use crate::schema::payments;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = payments)]
+pub struct Payment {
+    pub id: i32,
+    pub order_id: i32,
+    /// Name of the provider that handles the payment.
+    pub provider: String,
+    /// The provider's id for the payment, once created there.
+    pub provider_reference: Option<String>,
+    pub method: PaymentMethod,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub status: PaymentStatus,
+    /// Where to send the buyer to complete the payment.
+    pub checkout_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
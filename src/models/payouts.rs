@@ -0,0 +1,24 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::payouts::{PayoutMethod, PayoutStatus};
+use crate::money::{Amount, Currency};
+use crate::schema::payouts;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = payouts)]
+pub struct Payout {
+    pub id: i32,
+    pub organizer_id: i32,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub method: PayoutMethod,
+    /// Bank account number or wallet address.
+    pub destination: String,
+    pub status: PayoutStatus,
+    /// Bank transfer reference or transaction hash once paid.
+    pub reference: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub settled_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,64 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::promotions::{Discount, DiscountKind};
+use crate::money::{Amount, Currency};
+use crate::schema::{early_bird_rules, promo_codes};
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = promo_codes)]
+pub struct PromoCode {
+    pub id: i32,
+    pub organizer_id: i32,
+    /// `None` when the code works for all of the organizer's events.
+    pub event_id: Option<i32>,
+    pub code: String,
+    pub discount_kind: DiscountKind,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_user: Option<i32>,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub percent_off: Option<i32>,
+    pub amount_off: Option<Amount>,
+    /// Set for fixed discounts; the code only applies to events priced in it.
+    pub currency: Option<Currency>,
+}
+
+impl PromoCode {
+    pub fn discount(&self) -> Discount {
+        Discount::new(self.discount_kind, self.percent_off, self.amount_off)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PromoCodeUsage {
+    #[serde(flatten)]
+    pub promo_code: PromoCode,
+    /// Orders that used the code and weren't abandoned.
+    pub uses: i64,
+}
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = early_bird_rules)]
+pub struct EarlyBirdRule {
+    pub id: i32,
+    pub event_id: i32,
+    pub discount_kind: DiscountKind,
+    /// The rule stops applying at this time...
+    pub ends_at: Option<DateTime<Utc>>,
+    /// ...or once this many tickets have been sold, whichever comes first.
+    pub max_tickets: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub percent_off: Option<i32>,
+    /// In the event's currency.
+    pub amount_off: Option<Amount>,
+}
+
+impl EarlyBirdRule {
+    pub fn discount(&self) -> Discount {
+        Discount::new(self.discount_kind, self.percent_off, self.amount_off)
+    }
+}
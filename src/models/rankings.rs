@@ -0,0 +1,30 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::models::categories::Category;
+use crate::models::events::Event;
+use crate::models::tags::Tag;
+use crate::models::venues::Venue;
+use crate::schema::event_rankings;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = event_rankings)]
+pub struct EventRanking {
+    pub score: f64,
+    pub likes_count: i64,
+    pub tickets_sold: i64,
+    /// Views within the ranking window.
+    pub recent_views: i64,
+    pub computed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PopularEvent {
+    #[serde(flatten)]
+    pub event: Event,
+    pub venue: Venue,
+    pub categories: Vec<Category>,
+    pub tags: Vec<Tag>,
+    pub ranking: EventRanking,
+}
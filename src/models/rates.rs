@@ -0,0 +1,16 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::money::{Currency, Rate};
+use crate::schema::exchange_rates;
+
+#[derive(Queryable, Selectable, Serialize, Debug, Clone)]
+#[diesel(table_name = exchange_rates)]
+pub struct ExchangeRate {
+    pub currency: Currency,
+    /// Units of the currency one US dollar buys.
+    pub per_usd: Rate,
+    pub source: String,
+    pub fetched_at: DateTime<Utc>,
+}
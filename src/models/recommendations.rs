@@ -0,0 +1,16 @@
+use serde::Serialize;
+
+use crate::models::categories::Category;
+use crate::models::events::Event;
+use crate::models::tags::Tag;
+use crate::models::venues::Venue;
+
+#[derive(Serialize, Debug)]
+pub struct RecommendedEvent {
+    #[serde(flatten)]
+    pub event: Event,
+    pub venue: Venue,
+    pub categories: Vec<Category>,
+    pub tags: Vec<Tag>,
+    pub score: f64,
+}
@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::refunds::{RefundMethod, RefundReason, RefundStatus};
+use crate::money::{Amount, Currency};
+use crate::schema::refunds;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = refunds)]
+pub struct Refund {
+    pub id: i32,
+    pub ticket_id: i32,
+    pub order_id: i32,
+    pub event_id: i32,
+    pub user_id: i32,
+    pub amount: Amount,
+    pub reason: RefundReason,
+    pub status: RefundStatus,
+    pub method: RefundMethod,
+    /// Transaction hash or provider reference of the repayment.
+    pub payout_reference: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub paid_at: Option<DateTime<Utc>>,
+    pub currency: Currency,
+}
@@ -0,0 +1,30 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rocket::serde::Deserialize;
+
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::money::{Amount, Currency};
+use crate::schema::event_series;
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = event_series)]
+pub struct EventSeries {
+    pub id: i32,
+    pub userid: i32,
+    pub eventname: String,
+    pub eventdescription: String,
+    pub eventimage: String,
+    pub eventticketprice: Amount,
+    pub venue_id: i32,
+    pub timezone: String,
+    pub rrule: String,
+    /// Wall-clock start of the first occurrence in `timezone`.
+    pub dtstart: NaiveDateTime,
+    pub duration_minutes: i32,
+    pub capacity: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    /// Categories each occurrence is created with.
+    pub category_ids: Vec<i32>,
+    pub currency: Currency,
+}
@@ -0,0 +1,20 @@
+use rocket::serde::Deserialize;
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::schema::tags;
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = tags)]
+pub struct Tag {
+    pub id: i32,
+    pub name: String,
+}
+
+/// Autocompletion entry, most used tags first.
+#[derive(Queryable, Serialize, Debug)]
+pub struct TagSuggestion {
+    pub id: i32,
+    pub name: String,
+    pub event_count: i64,
+}
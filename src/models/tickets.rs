@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use rocket::serde::Deserialize;
+
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+use rocket::form::FromForm;
+
+use crate::database::tickets::{AcquiredVia, TicketStatus};
+use crate::schema::tickets;
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = tickets)]
+pub struct Ticket {
+    pub id: i32,
+    pub order_id: i32,
+    pub event_id: i32,
+    pub owner_id: i32,
+    /// Opaque value encoded in the ticket's QR code.
+    pub qr_token: String,
+    pub status: TicketStatus,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(FromForm, Deserialize, Debug)]
+pub struct TicketFiltering {
+    pub id: Option<i32>,
+    pub event_id: Option<i32>,
+    pub status: Option<TicketStatus>,
+    pub limit: Option<i64>,
+}
+
+/// One entry of a ticket's ownership history.
+#[derive(Queryable, Serialize, Debug)]
+pub struct TicketOwner {
+    pub username: String,
+    pub acquired_via: AcquiredVia,
+    pub acquired_at: DateTime<Utc>,
+    pub released_at: Option<DateTime<Utc>>,
+}
@@ -0,0 +1,18 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::transfers::TransferStatus;
+use crate::schema::ticket_transfers;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = ticket_transfers)]
+pub struct TicketTransfer {
+    pub id: i32,
+    pub ticket_id: i32,
+    pub from_user_id: i32,
+    pub to_user_id: i32,
+    pub status: TransferStatus,
+    pub created_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+}
@@ -1,4 +1,5 @@
 use crate::auth::Auth;
+use crate::money::Currency;
 use chrono::{Duration, Utc};
 use rocket::serde::Deserialize;
 
@@ -15,6 +16,12 @@ pub struct User {
     pub image: Option<Url>,
     #[serde(skip_serializing)]
     pub hash: String,
+    #[serde(skip_serializing)]
+    pub is_admin: bool,
+    /// Lowercase 0x address tickets can be sent to.
+    pub wallet_address: Option<String>,
+    /// Currency ticket prices are shown in.
+    pub preferred_currency: Option<Currency>,
 }
 
 #[derive(FromForm, Deserialize, Debug)]
@@ -39,6 +46,7 @@ pub struct Profile {
     username: String,
     image: Option<String>,
     following: bool,
+    followers_count: i64,
 }
 
 impl User {
@@ -60,11 +68,12 @@ impl User {
         }
     }
 
-    pub fn to_profile(self, following: bool) -> Profile {
+    pub fn to_profile(self, following: bool, followers_count: i64) -> Profile {
         Profile {
             username: self.username,
             image: self.image,
             following,
+            followers_count,
         }
     }
 }
@@ -0,0 +1,41 @@
+use rocket::serde::Deserialize;
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+use rocket::form::FromForm;
+
+use crate::schema::venues;
+
+#[derive(Queryable, Selectable, Serialize, Deserialize, Debug, Clone)]
+#[diesel(table_name = venues)]
+pub struct Venue {
+    pub id: i32,
+    pub name: String,
+    pub address: String,
+    pub city: String,
+    pub country: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub capacity: Option<i32>,
+    /// IANA timezone name, e.g. "Africa/Dar_es_Salaam".
+    pub timezone: String,
+    /// The user who added the venue and may edit it.
+    #[serde(skip_deserializing)]
+    pub created_by: Option<i32>,
+}
+
+#[derive(FromForm, Deserialize, Debug)]
+pub struct VenueFiltering {
+    pub id: Option<i32>,
+    pub name: Option<String>,
+    pub city: Option<String>,
+    pub country: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(FromForm, Deserialize, Debug)]
+pub struct NearbyFiltering {
+    pub lat: f64,
+    pub lng: f64,
+    pub km: f64,
+    pub limit: Option<i64>,
+}
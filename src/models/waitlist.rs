@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use diesel::{Queryable, Selectable};
+use serde::Serialize;
+
+use crate::database::waitlist::WaitlistStatus;
+use crate::schema::waitlist_entries;
+
+#[derive(Queryable, Selectable, Serialize, Debug)]
+#[diesel(table_name = waitlist_entries)]
+pub struct WaitlistEntry {
+    pub id: i32,
+    pub event_id: i32,
+    pub user_id: i32,
+    pub quantity: i32,
+    pub status: WaitlistStatus,
+    /// Pending order holding the offered seats.
+    pub order_id: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub offered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WaitlistPosition {
+    #[serde(flatten)]
+    pub entry: WaitlistEntry,
+    /// 1 for the front of the line, `None` once seats have been offered.
+    pub position: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WaitlistSummary {
+    pub event_id: i32,
+    pub waiting: i64,
+    /// Seats asked for by everyone waiting.
+    pub waiting_quantity: i64,
+    pub offered: i64,
+}
@@ -0,0 +1,880 @@
+//! Amounts of money as whole minor units of a currency.
+//!
+//! Prices are stored as `NUMERIC(38, 0)` and handled as `i128`, which covers
+//! 18-decimal tokens like ETH (1 ETH = 10^18 wei) as well as fiat cents.
+//! Floats never touch money: percentages are applied with integer math and
+//! round down, exchange rates are exact decimals.
+#![deny(clippy::float_arithmetic)]
+
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::data_types::PgNumeric;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::{Numeric, Text};
+use rocket::form::FromFormField;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::io::Write;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow, Deserialize, Serialize,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    Tzs,
+    Usd,
+    Usdc,
+    Eth,
+}
+
+/// Currency of events created without one, and of prices from before
+/// currencies were tracked.
+pub const DEFAULT_CURRENCY: Currency = Currency::Usd;
+
+impl Currency {
+    pub const ALL: [Currency; 4] = [Currency::Tzs, Currency::Usd, Currency::Usdc, Currency::Eth];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Currency::Tzs => "TZS",
+            Currency::Usd => "USD",
+            Currency::Usdc => "USDC",
+            Currency::Eth => "ETH",
+        }
+    }
+
+    /// Number of decimal places of the minor unit.
+    pub fn exponent(&self) -> u32 {
+        match self {
+            Currency::Tzs | Currency::Usd => 2,
+            Currency::Usdc => 6,
+            Currency::Eth => 18,
+        }
+    }
+
+    /// Minor units in one major unit, e.g. 100 cents in a dollar.
+    pub fn minor_per_major(&self) -> i128 {
+        10i128.pow(self.exponent())
+    }
+
+    pub fn is_crypto(&self) -> bool {
+        matches!(self, Currency::Usdc | Currency::Eth)
+    }
+}
+
+impl FromStr for Currency {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_uppercase().as_str() {
+            "TZS" => Ok(Currency::Tzs),
+            "USD" => Ok(Currency::Usd),
+            "USDC" => Ok(Currency::Usdc),
+            "ETH" => Ok(Currency::Eth),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for Currency {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation("unsupported currency"))
+        })
+    }
+}
+
+impl ToSql<Text, diesel::pg::Pg> for Currency {
+    fn to_sql(&self, out: &mut Output<diesel::pg::Pg>) -> serialize::Result {
+        out.write_all(self.as_str().as_bytes())?;
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, diesel::pg::Pg> for Currency {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        std::str::from_utf8(bytes.as_bytes())?
+            .parse()
+            .map_err(|_| "Unrecognized currency".into())
+    }
+}
+
+/// Whole minor units of some currency. The currency is kept next to the
+/// amount (usually a column of the same row), so only add up amounts known
+/// to be in the same currency.
+///
+/// Serialized as a string of digits: JSON numbers lose precision past 2^53,
+/// which a price in wei easily exceeds.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, AsExpression, FromSqlRow,
+)]
+#[diesel(sql_type = Numeric)]
+pub struct Amount(i128);
+
+/// Largest amount a `NUMERIC(38, 0)` column holds.
+pub const MAX_AMOUNT: Amount = Amount(10i128.pow(38) - 1);
+
+/// Largest price accepted for a ticket, a listing or a fixed discount:
+/// a billion ETH in wei. Far above any real price, and small enough that
+/// price x quantity x percentages stays well inside `i128`.
+pub const MAX_PRICE: Amount = Amount(10i128.pow(27));
+
+/// An amount computed from others doesn't fit a `NUMERIC(38, 0)` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("amount out of range")
+    }
+}
+
+impl std::error::Error for OutOfRange {}
+
+impl From<OutOfRange> for diesel::result::Error {
+    fn from(err: OutOfRange) -> diesel::result::Error {
+        diesel::result::Error::SerializationError(Box::new(err))
+    }
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub const fn new(minor_units: i128) -> Amount {
+        Amount(minor_units)
+    }
+
+    pub fn minor_units(&self) -> i128 {
+        self.0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    fn checked(minor_units: i128) -> Result<Amount, OutOfRange> {
+        if !(-MAX_AMOUNT.0..=MAX_AMOUNT.0).contains(&minor_units) {
+            return Err(OutOfRange);
+        }
+        Ok(Amount(minor_units))
+    }
+
+    /// `self * numerator / denominator`, rounded toward zero. Split into
+    /// quotient and remainder first, so a fraction up to 1 never overflows.
+    fn scale(&self, numerator: i128, denominator: i128) -> Result<Amount, OutOfRange> {
+        let quotient = self.0 / denominator;
+        let remainder = self.0 % denominator;
+        let scaled = quotient
+            .checked_mul(numerator)
+            .zip(remainder.checked_mul(numerator))
+            .and_then(|(whole, part)| whole.checked_add(part / denominator))
+            .ok_or(OutOfRange)?;
+        Amount::checked(scaled)
+    }
+
+    pub fn times(&self, quantity: i32) -> Result<Amount, OutOfRange> {
+        self.0
+            .checked_mul(quantity as i128)
+            .ok_or(OutOfRange)
+            .and_then(Amount::checked)
+    }
+
+    /// `percent` percent of the amount, rounded down.
+    pub fn percent(&self, percent: i32) -> Result<Amount, OutOfRange> {
+        self.scale(percent as i128, 100)
+    }
+
+    /// `bps` hundredths of a percent of the amount, rounded down.
+    pub fn basis_points(&self, bps: i32) -> Result<Amount, OutOfRange> {
+        self.scale(bps as i128, 10_000)
+    }
+
+    /// The part of a tax-inclusive amount that is tax at `bps` hundredths of
+    /// a percent, rounded down.
+    pub fn included_tax(&self, bps: i32) -> Result<Amount, OutOfRange> {
+        if bps < 0 {
+            return Err(OutOfRange);
+        }
+        self.scale(bps as i128, 10_000 + bps as i128)
+    }
+
+    /// One of `parts` equal shares, rounded down.
+    pub fn split(&self, parts: i32) -> Amount {
+        Amount(self.0 / parts.max(1) as i128)
+    }
+
+    pub fn min(self, other: Amount) -> Amount {
+        Ord::min(self, other)
+    }
+
+    pub fn clamp(self, min: Amount, max: Amount) -> Amount {
+        Ord::clamp(self, min, max)
+    }
+}
+
+impl Add for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        Amount(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Amount {
+    fn add_assign(&mut self, other: Amount) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, other: Amount) -> Amount {
+        Amount(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Amount {
+    fn sub_assign(&mut self, other: Amount) {
+        self.0 -= other.0;
+    }
+}
+
+impl Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        Amount(-self.0)
+    }
+}
+
+impl Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, Add::add)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Amount {
+    type Err = ();
+
+    /// Whole minor units, optionally negative. No decimal point.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let digits = input.strip_prefix('-').unwrap_or(input);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(());
+        }
+        let amount = input.parse::<i128>().map(Amount).map_err(|_| ())?;
+        if !(Amount(-MAX_AMOUNT.0)..=MAX_AMOUNT).contains(&amount) {
+            return Err(());
+        }
+        Ok(amount)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts a string of digits, or a plain integer for small amounts.
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D>(deserializer: D) -> Result<Amount, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Integer(i64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Integer(minor_units) => Ok(Amount(minor_units as i128)),
+            Raw::Text(text) => text
+                .parse()
+                .map_err(|_| serde::de::Error::custom("expected a whole number of minor units")),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for Amount {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation(
+                "expected a whole number of minor units",
+            ))
+        })
+    }
+}
+
+/// Postgres sends numerics as base-10000 digits, most significant first.
+const NBASE: i128 = 10_000;
+
+impl From<Amount> for PgNumeric {
+    fn from(amount: Amount) -> PgNumeric {
+        let mut rest = amount.0.unsigned_abs();
+        let mut digits = Vec::new();
+        while rest > 0 {
+            digits.push((rest % NBASE as u128) as i16);
+            rest /= NBASE as u128;
+        }
+        let weight = digits.len().saturating_sub(1) as i16;
+        digits.reverse();
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        if amount.0 < 0 {
+            PgNumeric::Negative {
+                weight,
+                scale: 0,
+                digits,
+            }
+        } else {
+            PgNumeric::Positive {
+                weight,
+                scale: 0,
+                digits,
+            }
+        }
+    }
+}
+
+impl TryFrom<PgNumeric> for Amount {
+    type Error = &'static str;
+
+    fn try_from(numeric: PgNumeric) -> Result<Amount, Self::Error> {
+        let (negative, weight, digits) = match numeric {
+            PgNumeric::Positive { weight, digits, .. } => (false, weight, digits),
+            PgNumeric::Negative { weight, digits, .. } => (true, weight, digits),
+            PgNumeric::NaN => return Err("NaN is not an amount"),
+        };
+
+        let mut value: i128 = 0;
+        for (i, digit) in digits.iter().enumerate() {
+            if i as i32 > weight as i32 {
+                if *digit != 0 {
+                    return Err("amount has a fractional part");
+                }
+                continue;
+            }
+            value = value
+                .checked_mul(NBASE)
+                .and_then(|value| value.checked_add(*digit as i128))
+                .ok_or("amount out of range")?;
+        }
+        // Trailing zero digits are left out of the wire format.
+        for _ in digits.len() as i32..=weight as i32 {
+            value = value.checked_mul(NBASE).ok_or("amount out of range")?;
+        }
+
+        Ok(Amount(if negative { -value } else { value }))
+    }
+}
+
+impl ToSql<Numeric, diesel::pg::Pg> for Amount {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, diesel::pg::Pg>) -> serialize::Result {
+        let numeric = PgNumeric::from(*self);
+        ToSql::<Numeric, diesel::pg::Pg>::to_sql(&numeric, &mut out.reborrow())
+    }
+}
+
+impl FromSql<Numeric, diesel::pg::Pg> for Amount {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        let numeric = PgNumeric::from_sql(bytes)?;
+        Ok(Amount::try_from(numeric)?)
+    }
+}
+
+/// Most decimal places kept for an exchange rate.
+pub const RATE_SCALE: u32 = 18;
+/// Most significant digits of a rate, leaving room to align it to
+/// base-10000 digits for Postgres.
+const RATE_DIGITS: u32 = 34;
+
+/// A positive exact decimal, `mantissa / 10^scale`. Used for exchange rates,
+/// stored as `NUMERIC(38, 18)` and serialized as a decimal string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Numeric)]
+pub struct Rate {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Rate {
+    pub const ONE: Rate = Rate {
+        mantissa: 1,
+        scale: 0,
+    };
+
+    /// Drop trailing zeros so equal rates compare equal.
+    fn normalized(mut mantissa: i128, mut scale: u32) -> Rate {
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        Rate { mantissa, scale }
+    }
+
+    /// Convert `amount` of `from` into `to`, where both rates are units of
+    /// their currency per the same base. Rounds up, so a converted price is
+    /// never less than the original. `None` if the result doesn't fit.
+    pub fn convert(
+        amount: Amount,
+        from: Currency,
+        from_rate: Rate,
+        to: Currency,
+        to_rate: Rate,
+    ) -> Option<Amount> {
+        // amount / 10^from_exp / from_rate * to_rate * 10^to_exp
+        let mut numerator = amount.0.checked_mul(to_rate.mantissa)?;
+        let mut denominator = from_rate.mantissa;
+        let shift = to.exponent() as i32 + from_rate.scale as i32
+            - from.exponent() as i32
+            - to_rate.scale as i32;
+        if shift >= 0 {
+            numerator = numerator.checked_mul(10i128.checked_pow(shift as u32)?)?;
+        } else {
+            denominator = denominator.checked_mul(10i128.checked_pow(shift.unsigned_abs())?)?;
+        }
+
+        let mut converted = numerator / denominator;
+        if numerator % denominator != 0 && numerator > 0 {
+            converted += 1;
+        }
+        let converted = Amount(converted);
+        if converted > MAX_AMOUNT {
+            return None;
+        }
+        Some(converted)
+    }
+}
+
+impl FromStr for Rate {
+    type Err = ();
+
+    /// A positive decimal like "2650.5", "0.00031" or "3.1e-4". Digits past
+    /// the 18th decimal place are dropped.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        let (number, exponent) = match input.find(['e', 'E']) {
+            Some(at) => (
+                &input[..at],
+                input[at + 1..].parse::<i32>().map_err(|_| ())?,
+            ),
+            None => (input, 0),
+        };
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(());
+        }
+        if !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return Err(());
+        }
+
+        // Decimal places of the written digits once the exponent is applied.
+        let mut scale = fraction.len() as i32 - exponent;
+        let mut digits = format!("{}{}", whole, fraction);
+        if scale < 0 {
+            digits.extend(std::iter::repeat_n('0', scale.unsigned_abs() as usize));
+            scale = 0;
+        }
+        if scale > RATE_SCALE as i32 {
+            let keep = digits
+                .len()
+                .saturating_sub((scale - RATE_SCALE as i32) as usize);
+            digits.truncate(keep);
+            scale = RATE_SCALE as i32;
+        }
+        let digits = digits.trim_start_matches('0');
+        if digits.is_empty() || digits.len() > RATE_DIGITS as usize {
+            return Err(());
+        }
+
+        let mantissa = digits.parse::<i128>().map_err(|_| ())?;
+        Ok(Rate::normalized(mantissa, scale as u32))
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.to_string();
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return f.write_str(&digits);
+        }
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}.{}", whole, fraction)
+    }
+}
+
+impl Serialize for Rate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl From<Rate> for PgNumeric {
+    fn from(rate: Rate) -> PgNumeric {
+        // Line the decimal point up with a base-10000 digit boundary.
+        let padding = (4 - rate.scale % 4) % 4;
+        let mut rest = rate.mantissa.unsigned_abs() * 10u128.pow(padding);
+        let mut digits = Vec::new();
+        while rest > 0 {
+            digits.push((rest % NBASE as u128) as i16);
+            rest /= NBASE as u128;
+        }
+        let weight = digits.len() as i16 - 1 - ((rate.scale + padding) / 4) as i16;
+        digits.reverse();
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        PgNumeric::Positive {
+            weight,
+            scale: rate.scale as u16,
+            digits,
+        }
+    }
+}
+
+impl TryFrom<PgNumeric> for Rate {
+    type Error = &'static str;
+
+    fn try_from(numeric: PgNumeric) -> Result<Rate, Self::Error> {
+        let (weight, digits) = match numeric {
+            PgNumeric::Positive { weight, digits, .. } => (weight, digits),
+            PgNumeric::Negative { .. } => return Err("a rate can't be negative"),
+            PgNumeric::NaN => return Err("NaN is not a rate"),
+        };
+        if digits.is_empty() {
+            return Err("a rate can't be zero");
+        }
+
+        let mut mantissa: i128 = 0;
+        for digit in &digits {
+            mantissa = mantissa
+                .checked_mul(NBASE)
+                .and_then(|mantissa| mantissa.checked_add(*digit as i128))
+                .ok_or("rate out of range")?;
+        }
+        // Power of ten of the last digit sent.
+        let exponent = 4 * (weight as i32 - (digits.len() as i32 - 1));
+        if exponent >= 0 {
+            mantissa = 10i128
+                .checked_pow(exponent as u32)
+                .and_then(|power| mantissa.checked_mul(power))
+                .ok_or("rate out of range")?;
+        }
+        let rate = Rate::normalized(mantissa, exponent.min(0).unsigned_abs());
+        if rate.mantissa >= 10i128.pow(RATE_DIGITS) || rate.scale > RATE_SCALE {
+            return Err("rate out of range");
+        }
+        Ok(rate)
+    }
+}
+
+impl ToSql<Numeric, diesel::pg::Pg> for Rate {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, diesel::pg::Pg>) -> serialize::Result {
+        let numeric = PgNumeric::from(*self);
+        ToSql::<Numeric, diesel::pg::Pg>::to_sql(&numeric, &mut out.reborrow())
+    }
+}
+
+impl FromSql<Numeric, diesel::pg::Pg> for Rate {
+    fn from_sql(bytes: diesel::backend::RawValue<diesel::pg::Pg>) -> deserialize::Result<Self> {
+        let numeric = PgNumeric::from_sql(bytes)?;
+        Ok(Rate::try_from(numeric)?)
+    }
+}
+
+/// An amount together with its currency, for display and for APIs that
+/// mix currencies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: Amount,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: Amount, currency: Currency) -> Money {
+        Money { amount, currency }
+    }
+}
+
+/// "1500.00 TZS", "0.05 ETH". Fiat always shows its minor digits, crypto
+/// drops trailing zeros past the second decimal.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let per_major = self.currency.minor_per_major();
+        let units = self.amount.0.unsigned_abs();
+        let sign = if self.amount.0 < 0 { "-" } else { "" };
+        let major = units / per_major as u128;
+        let mut minor = format!(
+            "{:0width$}",
+            units % per_major as u128,
+            width = self.currency.exponent() as usize
+        );
+        while minor.len() > 2 && minor.ends_with('0') {
+            minor.pop();
+        }
+        write!(f, "{}{}.{} {}", sign, major, minor, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(input: &str) -> Rate {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn amount_numeric_round_trip() {
+        let wei = 10i128.pow(18);
+        for minor_units in [
+            0,
+            1,
+            -1,
+            9_999,
+            10_000,
+            10_001,
+            -10_000,
+            99_990_000,
+            100_000_000,
+            wei,
+            -wei,
+            1_234_567_890_123_456_789,
+            MAX_AMOUNT.0,
+            -MAX_AMOUNT.0,
+        ] {
+            let amount = Amount(minor_units);
+            assert_eq!(Amount::try_from(PgNumeric::from(amount)), Ok(amount));
+        }
+    }
+
+    #[test]
+    fn amount_numeric_leaves_out_trailing_zero_digits() {
+        assert_eq!(
+            PgNumeric::from(Amount(100_000_000)),
+            PgNumeric::Positive {
+                weight: 2,
+                scale: 0,
+                digits: vec![1],
+            }
+        );
+        assert_eq!(
+            PgNumeric::from(Amount(0)),
+            PgNumeric::Positive {
+                weight: 0,
+                scale: 0,
+                digits: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn amount_numeric_rejects_fractions() {
+        let fractional = PgNumeric::Positive {
+            weight: 0,
+            scale: 2,
+            digits: vec![1, 5000],
+        };
+        assert!(Amount::try_from(fractional).is_err());
+        let whole = PgNumeric::Negative {
+            weight: 0,
+            scale: 2,
+            digits: vec![15, 0],
+        };
+        assert_eq!(Amount::try_from(whole), Ok(Amount(-15)));
+        assert!(Amount::try_from(PgNumeric::NaN).is_err());
+    }
+
+    #[test]
+    fn amount_from_str() {
+        assert_eq!("1500".parse::<Amount>(), Ok(Amount(1500)));
+        assert_eq!(" -20 ".parse::<Amount>(), Ok(Amount(-20)));
+        assert!("15.00".parse::<Amount>().is_err());
+        assert!("1e3".parse::<Amount>().is_err());
+        assert!("".parse::<Amount>().is_err());
+        assert!("-".parse::<Amount>().is_err());
+        assert!("+5".parse::<Amount>().is_err());
+        assert!("100000000000000000000000000000000000000"
+            .parse::<Amount>()
+            .is_err());
+    }
+
+    #[test]
+    fn amount_arithmetic_rounds_toward_zero() {
+        assert_eq!(Amount(999).percent(10), Ok(Amount(99)));
+        assert_eq!(Amount(-999).percent(10), Ok(Amount(-99)));
+        assert_eq!(Amount(10_000).basis_points(250), Ok(Amount(250)));
+        assert_eq!(Amount(9_999).basis_points(1), Ok(Amount(0)));
+        // 118.00 including 18% tax is 100.00 plus 18.00 tax.
+        assert_eq!(Amount(11_800).included_tax(1_800), Ok(Amount(1_800)));
+        assert_eq!(Amount(11_800).included_tax(0), Ok(Amount(0)));
+        assert_eq!(Amount(1_000).times(3), Ok(Amount(3_000)));
+    }
+
+    #[test]
+    fn amount_arithmetic_is_checked() {
+        assert_eq!(MAX_AMOUNT.times(2), Err(OutOfRange));
+        assert_eq!(Amount(i128::MAX / 2).times(3), Err(OutOfRange));
+        assert_eq!(MAX_AMOUNT.percent(200), Err(OutOfRange));
+        assert_eq!(MAX_AMOUNT.percent(100), Ok(MAX_AMOUNT));
+        assert_eq!(MAX_AMOUNT.basis_points(10_000), Ok(MAX_AMOUNT));
+        assert!(MAX_AMOUNT.included_tax(i32::MAX).is_ok());
+        assert_eq!(Amount(100).included_tax(-1), Err(OutOfRange));
+        // The largest price at the largest markup and quantity still fits.
+        assert!(MAX_PRICE.times(10).unwrap().percent(i32::MAX).is_ok());
+    }
+
+    #[test]
+    fn rate_from_str() {
+        assert_eq!(rate("2650.5"), Rate::normalized(26_505, 1));
+        assert_eq!(rate("0.00031"), Rate::normalized(31, 5));
+        assert_eq!(rate("3.1e-4"), Rate::normalized(31, 5));
+        assert_eq!(rate("3.1E2"), Rate::normalized(310, 0));
+        assert_eq!(rate("1.50"), rate("1.5"));
+        assert_eq!(rate(".5"), Rate::normalized(5, 1));
+        // Past the 18th decimal place is dropped.
+        assert_eq!(rate("1.0000000000000000009"), Rate::ONE);
+        for invalid in [
+            "",
+            ".",
+            "0",
+            "0.0000000000000000001",
+            "-1",
+            "1e",
+            "abc",
+            "1.2.3",
+        ] {
+            assert!(invalid.parse::<Rate>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn rate_numeric_round_trip() {
+        for input in [
+            "1",
+            "2650.5",
+            "0.0004",
+            "0.000000000000000001",
+            "12345678.9",
+            "10000",
+        ] {
+            let rate = rate(input);
+            assert_eq!(Rate::try_from(PgNumeric::from(rate)), Ok(rate), "{}", input);
+        }
+        assert!(Rate::try_from(PgNumeric::Negative {
+            weight: 0,
+            scale: 0,
+            digits: vec![1],
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn rate_convert_rounds_up() {
+        // 1.00 USD at 2650.5 TZS per USD.
+        assert_eq!(
+            Rate::convert(
+                Amount(100),
+                Currency::Usd,
+                Rate::ONE,
+                Currency::Tzs,
+                rate("2650.5")
+            ),
+            Some(Amount(265_050))
+        );
+        // 0.01 TZS is a fraction of a cent, charged as a whole one.
+        assert_eq!(
+            Rate::convert(
+                Amount(1),
+                Currency::Tzs,
+                rate("2650.5"),
+                Currency::Usd,
+                Rate::ONE
+            ),
+            Some(Amount(1))
+        );
+        // 1.00 USD at 0.0004 ETH per USD, in wei.
+        assert_eq!(
+            Rate::convert(
+                Amount(100),
+                Currency::Usd,
+                Rate::ONE,
+                Currency::Eth,
+                rate("0.0004")
+            ),
+            Some(Amount(400_000_000_000_000))
+        );
+        assert_eq!(
+            Rate::convert(
+                MAX_AMOUNT,
+                Currency::Usd,
+                Rate::ONE,
+                Currency::Eth,
+                rate("2")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn money_display() {
+        assert_eq!(
+            Money::new(Amount(150_000), Currency::Tzs).to_string(),
+            "1500.00 TZS"
+        );
+        assert_eq!(
+            Money::new(Amount(-250), Currency::Usd).to_string(),
+            "-2.50 USD"
+        );
+        assert_eq!(
+            Money::new(Amount(1_500_000), Currency::Usdc).to_string(),
+            "1.50 USDC"
+        );
+        assert_eq!(
+            Money::new(Amount(50_000_000_000_000_000), Currency::Eth).to_string(),
+            "0.05 ETH"
+        );
+        assert_eq!(
+            Money::new(Amount(1), Currency::Eth).to_string(),
+            "0.000000000000000001 ETH"
+        );
+    }
+}
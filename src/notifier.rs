@@ -0,0 +1,47 @@
+//! Outgoing email and push senders used by the notification worker.
+
+use crate::database::notifications::DeliveryChannel;
+use crate::models::notifications::Delivery;
+
+/// A file sent along with an email, such as a receipt.
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: &'static str,
+    pub content: Vec<u8>,
+}
+
+pub trait Sender {
+    fn send(&self, delivery: &Delivery, attachments: &[Attachment]) -> Result<(), String>;
+}
+
+/// Writes deliveries to the log. Stands in for the email and push
+/// providers until they are configured.
+pub struct LogSender {
+    channel: DeliveryChannel,
+}
+
+impl Sender for LogSender {
+    fn send(&self, delivery: &Delivery, attachments: &[Attachment]) -> Result<(), String> {
+        println!(
+            "[{}] to user {}: {} - {}",
+            self.channel.as_str(),
+            delivery.user_id,
+            delivery.title,
+            delivery.body
+        );
+        for attachment in attachments {
+            println!(
+                "[{}]   attached {} ({}, {} bytes)",
+                self.channel.as_str(),
+                attachment.filename,
+                attachment.content_type,
+                attachment.content.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+pub fn sender_for(channel: DeliveryChannel) -> Box<dyn Sender> {
+    Box::new(LogSender { channel })
+}
@@ -0,0 +1,327 @@
+//! Card and mobile money payment providers. A payment is created with the
+//! provider, the buyer completes it on the provider's side and the outcome
+//! arrives later as a signed webhook.
+//!
+//! `PAYMENT_GATEWAY_URL`, `PAYMENT_GATEWAY_KEY` and `PAYMENT_WEBHOOK_SECRET`
+//! configure the gateway. Without them, `PAYMENT_FAKE=1` opts in to
+//! `FakeProvider`, whose payments are completed by hand through the API.
+//! Never set it where real tickets are sold: anyone can pay for anything.
+
+use crate::database::payments::PaymentMethod;
+use crate::money::{Amount, Currency};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::env;
+use std::time::Duration;
+
+/// Secret for signing fake webhooks; only used with `PAYMENT_FAKE=1`.
+const FAKE_WEBHOOK_SECRET: &str = "fake-webhook-secret";
+
+pub struct PaymentRequest<'a> {
+    /// Our id for the payment, sent as the merchant reference.
+    pub payment_id: i32,
+    pub amount: Amount,
+    pub currency: Currency,
+    pub method: PaymentMethod,
+    /// Mobile money number to send the approval prompt to.
+    pub phone: Option<&'a str>,
+}
+
+pub struct Checkout {
+    /// The provider's id for the payment; webhooks refer to it.
+    pub reference: String,
+    /// Page where the buyer enters card details, if the method needs one.
+    pub checkout_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// Body of a webhook: the final state of one payment.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PaymentEvent {
+    pub reference: String,
+    pub status: PaymentOutcome,
+    /// Minor units actually charged.
+    pub amount: Amount,
+    pub currency: Currency,
+}
+
+/// Why a refund didn't go through.
+#[derive(Debug)]
+pub enum RefundError {
+    /// The provider answered and refused it; nothing was sent back.
+    Rejected(String),
+    /// No answer, so it may or may not have happened. Asking again with the
+    /// same payment id is safe.
+    Unknown(String),
+}
+
+pub trait PaymentProvider {
+    /// Stored with every payment and part of the webhook URL.
+    fn name(&self) -> &'static str;
+    fn supports(&self, currency: Currency) -> bool {
+        !currency.is_crypto()
+    }
+    fn create(&self, request: &PaymentRequest) -> Result<Checkout, String>;
+    /// Send a succeeded payment's money back to the buyer, in full.
+    /// `payment_id` is our id for the payment and makes repeated requests for
+    /// the same refund idempotent.
+    fn refund(
+        &self,
+        payment_id: i32,
+        reference: &str,
+        amount: Amount,
+        currency: Currency,
+    ) -> Result<(), RefundError>;
+    /// Check the webhook signature over the raw body.
+    fn verify(&self, body: &[u8], signature: &str) -> bool;
+    fn parse_event(&self, body: &[u8]) -> Result<PaymentEvent, String> {
+        serde_json::from_slice(body).map_err(|err| err.to_string())
+    }
+}
+
+/// Hex HMAC-SHA256 of `body`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Constant-time check of a hex HMAC-SHA256 signature.
+fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature.trim()) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key size");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Card and mobile money gateway speaking JSON over HTTPS.
+pub struct GatewayProvider {
+    url: String,
+    api_key: String,
+    webhook_secret: String,
+    client: reqwest::blocking::Client,
+}
+
+#[derive(Serialize)]
+struct GatewayRequest<'a> {
+    merchant_reference: String,
+    amount: Amount,
+    currency: Currency,
+    method: PaymentMethod,
+    phone: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct GatewayRefund {
+    amount: Amount,
+    currency: Currency,
+}
+
+#[derive(Deserialize)]
+struct GatewayResponse {
+    reference: String,
+    checkout_url: Option<String>,
+}
+
+impl GatewayProvider {
+    pub fn new(
+        url: String,
+        api_key: String,
+        webhook_secret: String,
+    ) -> Result<GatewayProvider, String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(15))
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok(GatewayProvider {
+            url,
+            api_key,
+            webhook_secret,
+            client,
+        })
+    }
+}
+
+impl PaymentProvider for GatewayProvider {
+    fn name(&self) -> &'static str {
+        "gateway"
+    }
+
+    fn create(&self, request: &PaymentRequest) -> Result<Checkout, String> {
+        let body = serde_json::to_string(&GatewayRequest {
+            merchant_reference: format!("payment-{}", request.payment_id),
+            amount: request.amount,
+            currency: request.currency,
+            method: request.method,
+            phone: request.phone,
+        })
+        .map_err(|err| err.to_string())?;
+        let response = self
+            .client
+            .post(format!("{}/payments", self.url.trim_end_matches('/')))
+            .bearer_auth(&self.api_key)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|err| err.to_string())?;
+        let response: GatewayResponse =
+            serde_json::from_str(&response).map_err(|err| err.to_string())?;
+        Ok(Checkout {
+            reference: response.reference,
+            checkout_url: response.checkout_url,
+        })
+    }
+
+    fn refund(
+        &self,
+        payment_id: i32,
+        reference: &str,
+        amount: Amount,
+        currency: Currency,
+    ) -> Result<(), RefundError> {
+        let body = serde_json::to_string(&GatewayRefund { amount, currency })
+            .map_err(|err| RefundError::Rejected(err.to_string()))?;
+        let response = self
+            .client
+            .post(format!(
+                "{}/payments/{}/refunds",
+                self.url.trim_end_matches('/'),
+                reference
+            ))
+            .bearer_auth(&self.api_key)
+            .header("Content-Type", "application/json")
+            .header("Idempotency-Key", format!("refund-{}", payment_id))
+            .body(body)
+            .send()
+            .map_err(|err| RefundError::Unknown(err.to_string()))?;
+        // A 4xx is a refusal; a 5xx may have been processed anyway.
+        match response.error_for_status() {
+            Ok(_) => Ok(()),
+            Err(err) if err.status().is_some_and(|status| status.is_client_error()) => {
+                Err(RefundError::Rejected(err.to_string()))
+            }
+            Err(err) => Err(RefundError::Unknown(err.to_string())),
+        }
+    }
+
+    fn verify(&self, body: &[u8], signature: &str) -> bool {
+        verify_signature(&self.webhook_secret, body, signature)
+    }
+}
+
+/// Accepts every payment right away; the outcome is sent by hand with
+/// `POST /api/payments/<id>/fake`, signed like a real webhook.
+pub struct FakeProvider;
+
+impl FakeProvider {
+    /// A signed webhook body, as the provider would send it.
+    pub fn webhook(event: &PaymentEvent) -> (String, String) {
+        let body = serde_json::to_string(event).expect("payment events serialize");
+        let signature = sign(FAKE_WEBHOOK_SECRET, body.as_bytes());
+        (body, signature)
+    }
+}
+
+impl PaymentProvider for FakeProvider {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn create(&self, request: &PaymentRequest) -> Result<Checkout, String> {
+        Ok(Checkout {
+            reference: format!("fake-{}", request.payment_id),
+            checkout_url: None,
+        })
+    }
+
+    fn refund(
+        &self,
+        _payment_id: i32,
+        _reference: &str,
+        _amount: Amount,
+        _currency: Currency,
+    ) -> Result<(), RefundError> {
+        Ok(())
+    }
+
+    fn verify(&self, body: &[u8], signature: &str) -> bool {
+        verify_signature(FAKE_WEBHOOK_SECRET, body, signature)
+    }
+}
+
+/// The configured provider, if any.
+pub fn provider() -> Result<Option<Box<dyn PaymentProvider>>, String> {
+    if let Ok(url) = env::var("PAYMENT_GATEWAY_URL") {
+        let api_key =
+            env::var("PAYMENT_GATEWAY_KEY").map_err(|_| "PAYMENT_GATEWAY_KEY is not set")?;
+        let webhook_secret =
+            env::var("PAYMENT_WEBHOOK_SECRET").map_err(|_| "PAYMENT_WEBHOOK_SECRET is not set")?;
+        return Ok(Some(Box::new(GatewayProvider::new(
+            url,
+            api_key,
+            webhook_secret,
+        )?)));
+    }
+    if env::var("PAYMENT_FAKE").is_ok_and(|fake| fake == "1") {
+        return Ok(Some(Box::new(FakeProvider)));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "webhook-secret";
+    const BODY: &[u8] =
+        br#"{"reference":"pay_1","status":"succeeded","amount":"1500","currency":"TZS"}"#;
+
+    #[test]
+    fn verifies_own_signature() {
+        let signature = sign(SECRET, BODY);
+        assert!(verify_signature(SECRET, BODY, &signature));
+        assert!(verify_signature(SECRET, BODY, &signature.to_uppercase()));
+        assert!(verify_signature(SECRET, BODY, &format!(" {}\n", signature)));
+    }
+
+    #[test]
+    fn rejects_tampered_body_or_signature() {
+        let signature = sign(SECRET, BODY);
+        let tampered =
+            br#"{"reference":"pay_1","status":"succeeded","amount":"1","currency":"TZS"}"#;
+        assert!(!verify_signature(SECRET, tampered, &signature));
+        assert!(!verify_signature("another-secret", BODY, &signature));
+
+        let mut flipped = signature.into_bytes();
+        flipped[0] = if flipped[0] == b'0' { b'1' } else { b'0' };
+        assert!(!verify_signature(
+            SECRET,
+            BODY,
+            &String::from_utf8(flipped).unwrap()
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        let signature = sign(SECRET, BODY);
+        assert!(!verify_signature(SECRET, BODY, ""));
+        assert!(!verify_signature(SECRET, BODY, "not hex"));
+        assert!(!verify_signature(SECRET, BODY, &signature[..63]));
+        assert!(!verify_signature(SECRET, BODY, &signature[..62]));
+        assert!(!verify_signature(SECRET, BODY, &format!("{}00", signature)));
+    }
+}
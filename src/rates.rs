@@ -0,0 +1,130 @@
+//! Where exchange rates come from. Rates are units of a currency per US
+//! dollar and are cached in the database by `database::rates::refresh`.
+//!
+//! `EXCHANGE_RATES_URL` selects the HTTP provider. Otherwise fixed rates can
+//! be given as `EXCHANGE_RATES=TZS=2650,USDC=1,ETH=0.00031`, which is what
+//! local setups and tests use.
+
+use crate::money::{Currency, Rate};
+use serde_json::Value;
+use std::env;
+use std::time::Duration;
+
+pub trait RateProvider {
+    /// Recorded next to every rate the provider returns.
+    fn source(&self) -> &'static str;
+    fn fetch(&self) -> Result<Vec<(Currency, Rate)>, String>;
+}
+
+/// Rates fixed at startup.
+pub struct StaticProvider {
+    rates: Vec<(Currency, Rate)>,
+}
+
+impl StaticProvider {
+    pub fn new(rates: Vec<(Currency, Rate)>) -> StaticProvider {
+        StaticProvider { rates }
+    }
+
+    /// Parse `CURRENCY=rate` pairs separated by commas.
+    pub fn from_config(config: &str) -> Result<StaticProvider, String> {
+        let mut rates = Vec::new();
+        for pair in config
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let (currency, rate) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected CURRENCY=rate, got {:?}", pair))?;
+            let currency = currency
+                .parse::<Currency>()
+                .map_err(|_| format!("unsupported currency {:?}", currency))?;
+            let rate = rate
+                .parse::<Rate>()
+                .map_err(|_| format!("invalid rate {:?}", rate))?;
+            rates.push((currency, rate));
+        }
+        Ok(StaticProvider::new(rates))
+    }
+}
+
+impl RateProvider for StaticProvider {
+    fn source(&self) -> &'static str {
+        "static"
+    }
+
+    fn fetch(&self) -> Result<Vec<(Currency, Rate)>, String> {
+        Ok(self.rates.clone())
+    }
+}
+
+/// Fetches `{"rates": {"TZS": 2650.5, "ETH": 0.00031, ...}}` quoted against
+/// USD, the shape most rate APIs answer with. Currencies we don't support
+/// are ignored.
+pub struct HttpProvider {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl HttpProvider {
+    pub fn new(url: String) -> Result<HttpProvider, String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok(HttpProvider { url, client })
+    }
+}
+
+impl RateProvider for HttpProvider {
+    fn source(&self) -> &'static str {
+        "http"
+    }
+
+    fn fetch(&self) -> Result<Vec<(Currency, Rate)>, String> {
+        let body = self
+            .client
+            .get(&self.url)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|err| err.to_string())?;
+        let body: Value = serde_json::from_str(&body).map_err(|err| err.to_string())?;
+        let rates = body
+            .get("rates")
+            .and_then(Value::as_object)
+            .ok_or("response has no rates object")?;
+
+        let mut fetched = Vec::new();
+        for (code, value) in rates {
+            let currency = match code.parse::<Currency>() {
+                Ok(currency) => currency,
+                Err(_) => continue,
+            };
+            // `Number` prints the shortest decimal that reads back the same,
+            // which is parsed exactly; no arithmetic happens on a float.
+            let text = match value {
+                Value::Number(number) => number.to_string(),
+                Value::String(text) => text.clone(),
+                _ => return Err(format!("rate for {} is not a number", code)),
+            };
+            let rate = text
+                .parse::<Rate>()
+                .map_err(|_| format!("invalid rate for {}: {}", code, text))?;
+            fetched.push((currency, rate));
+        }
+        Ok(fetched)
+    }
+}
+
+/// The configured provider, if any.
+pub fn provider() -> Result<Option<Box<dyn RateProvider>>, String> {
+    if let Ok(url) = env::var("EXCHANGE_RATES_URL") {
+        return Ok(Some(Box::new(HttpProvider::new(url)?)));
+    }
+    match env::var("EXCHANGE_RATES") {
+        Ok(config) => Ok(Some(Box::new(StaticProvider::from_config(&config)?))),
+        Err(_) => Ok(None),
+    }
+}
@@ -0,0 +1,356 @@
+//! Receipts of paid orders, rendered as HTML for the browser and as PDF for
+//! downloads and email attachments. The PDF is written by hand: one A4 page
+//! of text in the standard Helvetica font, which every reader has built in.
+
+use crate::database::payments::PaymentMethod;
+use crate::models::invoices::Invoice;
+use crate::models::orders::Order;
+use crate::money::{Amount, Money};
+use chrono_tz::Tz;
+use rocket::form::FromFormField;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReceiptFormat {
+    Html,
+    Pdf,
+}
+
+impl FromStr for ReceiptFormat {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_lowercase().as_str() {
+            "html" => Ok(ReceiptFormat::Html),
+            "pdf" => Ok(ReceiptFormat::Pdf),
+            _ => Err(()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for ReceiptFormat {
+    fn from_value(field: rocket::form::ValueField<'v>) -> rocket::form::Result<'v, Self> {
+        field.value.parse::<Self>().map_err(|_| {
+            rocket::form::Errors::from(rocket::form::Error::validation("invalid receipt format"))
+        })
+    }
+}
+
+pub struct Receipt {
+    pub invoice: Invoice,
+    pub order: Order,
+    /// The event's timezone, for its date.
+    pub tz: Tz,
+}
+
+/// One row of the amounts table.
+struct Line {
+    label: String,
+    amount: String,
+    /// Totals are printed in bold.
+    strong: bool,
+}
+
+/// "18%", "7.5%".
+fn format_bps(bps: i32) -> String {
+    match bps % 100 {
+        0 => format!("{}%", bps / 100),
+        rest if rest % 10 == 0 => format!("{}.{}%", bps / 100, rest / 10),
+        rest => format!("{}.{:02}%", bps / 100, rest),
+    }
+}
+
+impl Receipt {
+    pub fn filename(&self, extension: &str) -> String {
+        format!("receipt-{}.{}", self.invoice.display_number(), extension)
+    }
+
+    fn money(&self, amount: Amount) -> String {
+        Money::new(amount, self.order.currency).to_string()
+    }
+
+    fn payment_method(&self) -> &'static str {
+        match self.order.payment_method {
+            Some(PaymentMethod::Card) => "Card",
+            Some(PaymentMethod::MobileMoney) => "Mobile money",
+            Some(PaymentMethod::Onchain) | None => "On-chain",
+        }
+    }
+
+    /// Label and value pairs above the amounts.
+    fn details(&self) -> Vec<(&'static str, String)> {
+        let invoice = &self.invoice;
+        let mut details = vec![
+            ("Invoice number", invoice.display_number()),
+            (
+                "Issued",
+                invoice.issued_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+            ),
+            ("Order", format!("#{}", self.order.id)),
+            (
+                "Seller",
+                format!("{} <{}>", invoice.seller_name, invoice.seller_email),
+            ),
+            (
+                "Billed to",
+                format!("{} <{}>", invoice.buyer_name, invoice.buyer_email),
+            ),
+            ("Event", invoice.event_name.clone()),
+            (
+                "Date",
+                invoice
+                    .event_starts_at
+                    .with_timezone(&self.tz)
+                    .format("%Y-%m-%d %H:%M %Z")
+                    .to_string(),
+            ),
+            (
+                "Venue",
+                format!(
+                    "{}, {}, {}, {}",
+                    invoice.venue_name,
+                    invoice.venue_address,
+                    invoice.venue_city,
+                    invoice.venue_country
+                ),
+            ),
+            ("Payment method", self.payment_method().to_string()),
+        ];
+        if let Some(reference) = &self.order.payment_reference {
+            details.push(("Payment reference", reference.clone()));
+        }
+        details
+    }
+
+    fn lines(&self) -> Vec<Line> {
+        let order = &self.order;
+        let invoice = &self.invoice;
+        let mut lines = vec![Line {
+            label: format!(
+                "Ticket, {} x {}",
+                order.quantity,
+                self.money(order.unit_price)
+            ),
+            amount: self.money(order.subtotal_amount),
+            strong: false,
+        }];
+        if order.discount_amount > Amount::ZERO {
+            lines.push(Line {
+                label: "Discount".to_string(),
+                amount: self.money(-order.discount_amount),
+                strong: false,
+            });
+        }
+        lines.push(Line {
+            label: "Total".to_string(),
+            amount: self.money(order.total_amount),
+            strong: true,
+        });
+        if let (Some(amount), Some(currency)) = (order.pay_amount, order.pay_currency) {
+            lines.push(Line {
+                label: "Paid".to_string(),
+                amount: Money::new(amount, currency).to_string(),
+                strong: true,
+            });
+        }
+        if invoice.tax_rate_bps > 0 {
+            lines.push(Line {
+                label: format!("Includes tax ({})", format_bps(invoice.tax_rate_bps)),
+                amount: self.money(invoice.tax_amount),
+                strong: false,
+            });
+        }
+        if invoice.fee_amount > Amount::ZERO {
+            lines.push(Line {
+                label: "Includes platform fee".to_string(),
+                amount: self.money(invoice.fee_amount),
+                strong: false,
+            });
+        }
+        lines
+    }
+
+    pub fn html(&self) -> String {
+        let mut html = String::new();
+        let title = format!("Receipt {}", self.invoice.display_number());
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title>\
+             <style>body{{font-family:Helvetica,Arial,sans-serif;max-width:40em;margin:2em auto}}\
+             th{{text-align:left;padding-right:2em}}td.amount{{text-align:right}}\
+             table{{border-collapse:collapse;margin-bottom:2em}}tr.total td{{font-weight:bold}}</style>\
+             </head><body>\n<h1>{}</h1>\n<table>\n",
+            escape(&title),
+            escape(&title)
+        );
+        for (label, value) in self.details() {
+            let _ = writeln!(
+                html,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                label,
+                escape(&value)
+            );
+        }
+        html.push_str("</table>\n<table>\n");
+        for line in self.lines() {
+            let _ = writeln!(
+                html,
+                "<tr{}><td>{}</td><td class=\"amount\">{}</td></tr>",
+                if line.strong { " class=\"total\"" } else { "" },
+                escape(&line.label),
+                escape(&line.amount)
+            );
+        }
+        html.push_str("</table>\n</body></html>\n");
+        html
+    }
+
+    pub fn pdf(&self) -> Vec<u8> {
+        let mut page = PdfPage::new();
+        page.text(
+            BOLD,
+            18,
+            &format!("Receipt {}", self.invoice.display_number()),
+        );
+        page.skip(10);
+        for (label, value) in self.details() {
+            page.row(REGULAR, 10, label, &value);
+        }
+        page.skip(16);
+        for line in self.lines() {
+            let font = if line.strong { BOLD } else { REGULAR };
+            page.row(font, 11, &line.label, &line.amount);
+        }
+        page.finish()
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const REGULAR: &str = "F1";
+const BOLD: &str = "F2";
+/// A4 in points.
+const PAGE_WIDTH: i32 = 595;
+const PAGE_HEIGHT: i32 = 842;
+const MARGIN: i32 = 50;
+/// Where the value column of a row starts.
+const VALUE_X: i32 = 200;
+
+/// Text laid out top to bottom on a single page.
+struct PdfPage {
+    content: Vec<u8>,
+    y: i32,
+}
+
+impl PdfPage {
+    fn new() -> PdfPage {
+        PdfPage {
+            content: Vec::new(),
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    fn skip(&mut self, points: i32) {
+        self.y -= points;
+    }
+
+    fn show(&mut self, font: &str, size: i32, x: i32, text: &str) {
+        let _ = write!(
+            self.content,
+            "BT /{} {} Tf {} {} Td (",
+            font, size, x, self.y
+        );
+        self.content.extend(pdf_string(text));
+        self.content.extend_from_slice(b") Tj ET\n");
+    }
+
+    fn text(&mut self, font: &str, size: i32, text: &str) {
+        self.y -= size;
+        self.show(font, size, MARGIN, text);
+        self.y -= size / 2;
+    }
+
+    fn row(&mut self, font: &str, size: i32, label: &str, value: &str) {
+        self.y -= size;
+        self.show(font, size, MARGIN, label);
+        self.show(font, size, VALUE_X, value);
+        self.y -= size / 2;
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let objects: Vec<Vec<u8>> = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /{} 4 0 R /{} 5 0 R >> >> /Contents 6 0 R >>",
+                PAGE_WIDTH, PAGE_HEIGHT, REGULAR, BOLD
+            )
+            .into_bytes(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_vec(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+                .to_vec(),
+            {
+                let mut stream = format!("<< /Length {} >>\nstream\n", self.content.len()).into_bytes();
+                stream.extend(&self.content);
+                stream.extend_from_slice(b"\nendstream");
+                stream
+            },
+        ];
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            let _ = writeln!(pdf, "{} 0 obj", index + 1);
+            pdf.extend(object);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        let _ = writeln!(pdf, "xref\n0 {}\n0000000000 65535 f ", objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(pdf, "{:010} 00000 n ", offset);
+        }
+        let _ = writeln!(
+            pdf,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+            objects.len() + 1,
+            xref
+        );
+        pdf
+    }
+}
+
+/// Text for a PDF string literal in WinAnsi encoding. Latin-1 characters
+/// map to themselves; anything the standard fonts can't show becomes '?'.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                bytes.push(b'\\');
+                bytes.push(c as u8);
+            }
+            ' '..='~' | '\u{a0}'..='\u{ff}' => bytes.push(c as u32 as u8),
+            _ => bytes.push(b'?'),
+        }
+    }
+    bytes
+}
@@ -0,0 +1,358 @@
+//! Subset of RFC 5545 recurrence rules used by event series.
+//!
+//! Supported parts: `FREQ` (DAILY, WEEKLY, MONTHLY), `INTERVAL`, `COUNT`,
+//! `UNTIL` and, for weekly rules, `BYDAY` without ordinals. Expansion happens
+//! on venue-local wall-clock times so a 20:00 show stays at 20:00 across DST
+//! changes.
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+
+/// Hard cap on how many occurrences a single series may expand to. Larger
+/// `COUNT`s are rejected rather than cut short.
+pub const MAX_OCCURRENCES: usize = 366;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<Until>,
+    pub by_day: Vec<Weekday>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Until {
+    /// `UNTIL=20250131T200000Z`: an instant in UTC.
+    Utc(NaiveDateTime),
+    /// A date, or a date-time without `Z`: venue-local wall-clock time.
+    Local(NaiveDateTime),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RRuleError {
+    MissingFreq,
+    UnsupportedFreq,
+    InvalidPart(String),
+    UnsupportedPart(String),
+    CountAndUntil,
+    /// `COUNT` asks for more than `MAX_OCCURRENCES`.
+    TooManyOccurrences,
+}
+
+impl RRule {
+    pub fn parse(input: &str) -> Result<RRule, RRuleError> {
+        let input = input.trim();
+        let input = input.strip_prefix("RRULE:").unwrap_or(input);
+
+        let mut freq = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        let mut by_day = Vec::new();
+
+        for part in input.split(';').filter(|part| !part.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| RRuleError::InvalidPart(part.to_string()))?;
+            let invalid = || RRuleError::InvalidPart(part.to_string());
+
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        _ => return Err(RRuleError::UnsupportedFreq),
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value.parse().map_err(|_| invalid())?;
+                    if interval == 0 {
+                        return Err(invalid());
+                    }
+                }
+                "COUNT" => {
+                    let value: u32 = value.parse().map_err(|_| invalid())?;
+                    if value == 0 {
+                        return Err(invalid());
+                    }
+                    if value as usize > MAX_OCCURRENCES {
+                        return Err(RRuleError::TooManyOccurrences);
+                    }
+                    count = Some(value);
+                }
+                "UNTIL" => until = Some(parse_until(value).ok_or_else(invalid)?),
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        by_day.push(parse_weekday(day).ok_or_else(invalid)?);
+                    }
+                }
+                "WKST" => {}
+                _ => return Err(RRuleError::UnsupportedPart(part.to_string())),
+            }
+        }
+
+        if count.is_some() && until.is_some() {
+            return Err(RRuleError::CountAndUntil);
+        }
+        let freq = freq.ok_or(RRuleError::MissingFreq)?;
+        if !by_day.is_empty() && freq != Frequency::Weekly {
+            return Err(RRuleError::UnsupportedPart("BYDAY".to_string()));
+        }
+        by_day.sort_by_key(|day| day.num_days_from_monday());
+        by_day.dedup();
+
+        Ok(RRule {
+            freq,
+            interval,
+            count,
+            until,
+            by_day,
+        })
+    }
+
+    /// Local start times of the occurrences at or after `dtstart` until the
+    /// rule ends or `horizon` is reached. `dtstart` is the first of them only
+    /// if the rule produces it: a Wednesday start of a `BYDAY=MO` rule begins
+    /// on the following Monday. `tz` places a UTC `UNTIL` on the local clock.
+    pub fn expand(
+        &self,
+        tz: &Tz,
+        dtstart: NaiveDateTime,
+        horizon: NaiveDateTime,
+    ) -> Vec<NaiveDateTime> {
+        let limit = self
+            .count
+            .map(|count| count as usize)
+            .unwrap_or(MAX_OCCURRENCES);
+        let end = match self.until {
+            Some(Until::Utc(until)) => tz.from_utc_datetime(&until).naive_local().min(horizon),
+            Some(Until::Local(until)) => until.min(horizon),
+            None => horizon,
+        };
+
+        let mut occurrences = Vec::new();
+        let mut push = |candidate: NaiveDateTime| -> bool {
+            if candidate > end || occurrences.len() >= limit {
+                return false;
+            }
+            if candidate >= dtstart {
+                occurrences.push(candidate);
+            }
+            true
+        };
+
+        let time = dtstart.time();
+        match self.freq {
+            Frequency::Daily => {
+                let mut day = dtstart.date();
+                while push(day.and_time(time)) {
+                    day += Duration::days(self.interval as i64);
+                }
+            }
+            Frequency::Weekly => {
+                let days = if self.by_day.is_empty() {
+                    vec![dtstart.weekday()]
+                } else {
+                    self.by_day.clone()
+                };
+                let mut week = dtstart.date()
+                    - Duration::days(dtstart.weekday().num_days_from_monday() as i64);
+                'weeks: loop {
+                    for day in &days {
+                        let date = week + Duration::days(day.num_days_from_monday() as i64);
+                        if !push(date.and_time(time)) {
+                            break 'weeks;
+                        }
+                    }
+                    week += Duration::weeks(self.interval as i64);
+                }
+            }
+            Frequency::Monthly => {
+                let day = dtstart.day();
+                let mut months = 0;
+                loop {
+                    let (year, month) = add_months(dtstart.year(), dtstart.month(), months);
+                    // Months without that day (e.g. the 31st) are skipped.
+                    if let Some(date) = NaiveDate::from_ymd_opt(year, month, day) {
+                        if !push(date.and_time(time)) {
+                            break;
+                        }
+                    } else if NaiveDate::from_ymd_opt(year, month, 1)
+                        .is_none_or(|first| first.and_time(time) > end)
+                    {
+                        break;
+                    }
+                    months += self.interval;
+                }
+            }
+        }
+
+        occurrences
+    }
+}
+
+fn add_months(year: i32, month: u32, months: u32) -> (i32, u32) {
+    let zero_based = month - 1 + months;
+    (year + (zero_based / 12) as i32, zero_based % 12 + 1)
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day.trim().to_ascii_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// `UNTIL` as a date (`20250131`, through the end of that day), a local
+/// date-time (`20250131T200000`) or a UTC one (`20250131T200000Z`).
+fn parse_until(value: &str) -> Option<Until> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(Until::Utc);
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .or_else(|| {
+            let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+            Some(date.and_time(NaiveTime::from_hms_opt(23, 59, 59)?))
+        })
+        .map(Until::Local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn expand(rule: &str, tz: Tz, dtstart: &str) -> Vec<NaiveDateTime> {
+        let dtstart = local(dtstart);
+        RRule::parse(rule)
+            .unwrap()
+            .expand(&tz, dtstart, dtstart + Duration::days(365))
+    }
+
+    #[test]
+    fn weekly_by_day_starts_on_the_first_matching_day() {
+        // 2025-01-01 is a Wednesday.
+        let starts = expand(
+            "FREQ=WEEKLY;BYDAY=FR,MO;COUNT=4",
+            Tz::UTC,
+            "2025-01-01 20:00",
+        );
+        assert_eq!(
+            starts,
+            vec![
+                local("2025-01-03 20:00"),
+                local("2025-01-06 20:00"),
+                local("2025-01-10 20:00"),
+                local("2025-01-13 20:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn weekly_interval_skips_weeks() {
+        let starts = expand(
+            "FREQ=WEEKLY;INTERVAL=2;COUNT=3",
+            Tz::UTC,
+            "2025-01-01 20:00",
+        );
+        assert_eq!(
+            starts,
+            vec![
+                local("2025-01-01 20:00"),
+                local("2025-01-15 20:00"),
+                local("2025-01-29 20:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn count_limits_occurrences() {
+        let starts = expand("FREQ=DAILY;COUNT=5", Tz::UTC, "2025-01-01 20:00");
+        assert_eq!(starts.len(), 5);
+        assert_eq!(starts.last(), Some(&local("2025-01-05 20:00")));
+    }
+
+    #[test]
+    fn count_above_the_cap_is_rejected() {
+        assert_eq!(
+            RRule::parse("FREQ=DAILY;COUNT=367"),
+            Err(RRuleError::TooManyOccurrences)
+        );
+        assert!(RRule::parse("FREQ=DAILY;COUNT=366").is_ok());
+        assert!(matches!(
+            RRule::parse("FREQ=DAILY;COUNT=0"),
+            Err(RRuleError::InvalidPart(_))
+        ));
+    }
+
+    #[test]
+    fn until_in_utc_is_an_instant() {
+        // 19:00 UTC is 22:00 in Dar es Salaam, so the 20:00 show that day is
+        // included and the next one isn't.
+        let tz: Tz = "Africa/Dar_es_Salaam".parse().unwrap();
+        let starts = expand("FREQ=DAILY;UNTIL=20250103T190000Z", tz, "2025-01-01 20:00");
+        assert_eq!(starts.last(), Some(&local("2025-01-03 20:00")));
+
+        // 16:00 UTC is 19:00 there, before that day's show.
+        let starts = expand("FREQ=DAILY;UNTIL=20250103T160000Z", tz, "2025-01-01 20:00");
+        assert_eq!(starts.last(), Some(&local("2025-01-02 20:00")));
+    }
+
+    #[test]
+    fn until_without_z_is_local() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        let starts = expand("FREQ=DAILY;UNTIL=20250103T200000", tz, "2025-01-01 20:00");
+        assert_eq!(starts.last(), Some(&local("2025-01-03 20:00")));
+        let starts = expand("FREQ=DAILY;UNTIL=20250103", tz, "2025-01-01 20:00");
+        assert_eq!(starts.last(), Some(&local("2025-01-03 20:00")));
+    }
+
+    #[test]
+    fn wall_clock_is_kept_across_dst() {
+        // New York springs forward on 2025-03-09.
+        let tz: Tz = "America/New_York".parse().unwrap();
+        let starts = expand("FREQ=WEEKLY;COUNT=3", tz, "2025-03-02 20:00");
+        assert_eq!(
+            starts,
+            vec![
+                local("2025-03-02 20:00"),
+                local("2025-03-09 20:00"),
+                local("2025-03-16 20:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn monthly_skips_months_without_the_day() {
+        let starts = expand("FREQ=MONTHLY;COUNT=3", Tz::UTC, "2025-01-31 20:00");
+        assert_eq!(
+            starts,
+            vec![
+                local("2025-01-31 20:00"),
+                local("2025-03-31 20:00"),
+                local("2025-05-31 20:00"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use crate::auth::Admin;
+use crate::database::categories::{CategoryError, NewCategory as NewCategoryRow};
+use crate::database::{self, Db};
+use crate::errors::{Errors, FieldValidator};
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+#[derive(Deserialize, Validate)]
+struct NewCategoryData {
+    parent_id: Option<i32>,
+    #[validate(length(min = 1))]
+    slug: Option<String>,
+    #[validate(length(min = 1))]
+    name: Option<String>,
+    position: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct NewCategory {
+    category: NewCategoryData,
+}
+
+fn category_error(error: CategoryError) -> Errors {
+    match error {
+        CategoryError::DuplicatedSlug => Errors::new(&[("slug", "has already been taken")]),
+        CategoryError::InvalidSlug => {
+            Errors::new(&[("slug", "may only contain lowercase letters, digits and dashes")])
+        }
+        CategoryError::NonExistParent => Errors::new(&[("parent_id", "does not exist")]),
+        CategoryError::CyclicParent => {
+            Errors::new(&[("parent_id", "can't be the category itself or one of its subcategories")])
+        }
+        CategoryError::InUse => {
+            Errors::new(&[("id", "category still has events or subcategories")])
+        }
+        CategoryError::NotFound => Errors::new(&[("id", "category does not exist")]),
+        CategoryError::Other => Errors::new(&[("database", "failed to save category")]),
+    }
+}
+
+#[get("/categories")]
+pub async fn get_categories(db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::categories::get_tree(conn)
+            .map(|categories| json!({ "categories": categories }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch categories")]))
+    })
+    .await
+}
+
+#[post("/categories", format = "json", data = "<new_category>")]
+pub async fn add_category(
+    _admin: Admin,
+    new_category: Json<NewCategory>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let new_category = new_category.into_inner().category;
+
+    let mut extractor = FieldValidator::validate(&new_category);
+    let slug = extractor.extract("slug", new_category.slug);
+    let name = extractor.extract("name", new_category.name);
+    extractor.check()?;
+
+    db.run(move |conn| {
+        let row = NewCategoryRow {
+            parent_id: new_category.parent_id,
+            slug: &slug,
+            name: &name,
+            position: new_category.position.unwrap_or(0),
+        };
+        database::categories::create(conn, &row)
+            .map(|category| json!({ "category": category }))
+            .map_err(category_error)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCategory {
+    id: i32,
+    category: database::categories::UpdateCategoryData,
+}
+
+#[put("/categories", format = "json", data = "<category>")]
+pub async fn update_category(
+    _admin: Admin,
+    category: Json<UpdateCategory>,
+    db: Db,
+) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::categories::update(conn, category.id, &category.category)
+            .map(|category| json!({ "category": category }))
+            .map_err(category_error)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct DeleteCategory {
+    id: i32,
+}
+
+#[delete("/categories", format = "json", data = "<category>")]
+pub async fn delete_category(
+    _admin: Admin,
+    category: Json<DeleteCategory>,
+    db: Db,
+) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::categories::delete(conn, category.id)
+            .map(|deleted| json!({ "deleted": deleted }))
+            .map_err(category_error)
+    })
+    .await
+}
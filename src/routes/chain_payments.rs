@@ -0,0 +1,58 @@
+use crate::auth::Admin;
+use crate::chain;
+use crate::database::chain_payments::{ChainPaymentError, ChainPaymentStatus};
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+fn chain_payment_error(error: ChainPaymentError) -> Errors {
+    match error {
+        ChainPaymentError::NotFound => Errors::new(&[("tx_hash", "payment does not exist")]),
+        ChainPaymentError::InvalidStatus => {
+            Errors::new(&[("status", "payment is not owed back")])
+        }
+        ChainPaymentError::Other => Errors::new(&[("database", "failed to process payment")]),
+    }
+}
+
+#[get("/admin/chain_payments?<status>")]
+pub async fn get_chain_payments(
+    _admin: Admin,
+    status: Option<ChainPaymentStatus>,
+    db: Db,
+) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::chain_payments::list(conn, status)
+            .map(|payments| json!({ "chain_payments": payments }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch payments")]))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct ChainRefund {
+    reference: String,
+}
+
+#[post("/admin/chain_payments/<tx_hash>/refunded", format = "json", data = "<refund>")]
+pub async fn refund_chain_payment(
+    _admin: Admin,
+    tx_hash: &str,
+    refund: Json<ChainRefund>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let tx_hash = chain::normalize_tx_hash(tx_hash)
+        .ok_or_else(|| Errors::new(&[("tx_hash", "is not a transaction hash")]))?;
+    let reference = chain::normalize_tx_hash(&refund.reference)
+        .ok_or_else(|| Errors::new(&[("reference", "is not a transaction hash")]))?;
+
+    db.run(move |conn| {
+        database::chain_payments::mark_refunded(conn, &tx_hash, &reference)
+            .map(|payment| json!({ "chain_payment": payment }))
+            .map_err(chain_payment_error)
+    })
+    .await
+}
@@ -1,10 +1,18 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-use crate::database::{self, events::EventType, users::UserCreationError, Db};
+use crate::auth::Auth;
+use crate::database::events::{
+    local_to_utc, EventCreationError, EventStatus, EventTransitionError, EventUpdateError,
+    ScheduleChange,
+};
+use crate::database::{self, users::UserCreationError, Db};
 use crate::errors::{Errors, FieldValidator};
 use crate::models::events::EventFiltering;
+use crate::models::venues::NearbyFiltering;
+use crate::money::{Amount, Currency, DEFAULT_CURRENCY, MAX_PRICE};
 use crate::uploadFile::upload_image;
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
 use diesel::{r2d2::event, Queryable};
 use rocket::serde::{
     json::{json, Json, Value},
@@ -14,17 +22,22 @@ use rocket::serde::{
 #[derive(Queryable, Deserialize)]
 struct NewEventData {
     id: Option<i32>,
-    userid: i32,
     eventname: String,
     eventdescription: String,
-    eventdate: String,
-    eventdatetime: String,
-    eventtype: EventType,
-    eventcountry: String,
-    eventcity: String,
-    eventplace: String,
+    /// Wall-clock time at the venue, "YYYY-MM-DD HH:MM:SS".
+    starts_at: String,
+    ends_at: String,
+    /// IANA timezone, defaults to the venue's timezone.
+    timezone: Option<String>,
+    category_ids: Vec<i32>,
+    #[serde(default)]
+    tags: Vec<String>,
+    venue_id: i32,
     eventimage: String,
-    eventticketprice: i32
+    /// Minor units of `currency`.
+    eventticketprice: Amount,
+    currency: Option<Currency>,
+    capacity: Option<i32>,
 }
 
 #[derive(Deserialize)]
@@ -32,53 +45,110 @@ pub struct NewEvent {
     event: NewEventData,
 }
 
+pub const LOCAL_DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+fn normalize_tags(names: &[String]) -> Result<Vec<String>, Errors> {
+    let mut tags = names
+        .iter()
+        .map(|name| database::tags::normalize(name))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| Errors::new(&[("tags", "must be between 1 and 32 characters")]))?;
+    tags.sort();
+    tags.dedup();
+    if tags.len() > database::tags::MAX_TAGS_PER_EVENT {
+        return Err(Errors::new(&[("tags", "at most 10 tags per event")]));
+    }
+    Ok(tags)
+}
+
 #[post("/event", format = "json", data = "<new_event>")]
-pub async fn add_event(new_event: Json<NewEvent>, db: Db) -> Result<Value, Errors> {
+pub async fn add_event(auth: Auth, new_event: Json<NewEvent>, db: Db) -> Result<Value, Errors> {
     let new_event = new_event.into_inner().event;
-    // Parse `eventdate` into NaiveDate
-    let parsed_eventdate = NaiveDate::parse_from_str(&new_event.eventdate, "%Y-%m-%d")
-        .map_err(|_| Errors::new(&[("eventdate", "invalid date format, expected YYYY-MM-DD")]))?;
-
-    // Parse `eventdatetime` into NaiveDateTime
-    let parsed_eventdatetime =
-        NaiveDateTime::parse_from_str(&new_event.eventdatetime, "%Y-%m-%d %H:%M:%S").map_err(
-            |_| {
-                Errors::new(&[(
-                    "eventdatetime",
-                    "invalid datetime format, expected YYYY-MM-DD HH:MM:SS",
-                )])
-            },
-        )?;
+
+    let starts_at = NaiveDateTime::parse_from_str(&new_event.starts_at, LOCAL_DATETIME_FORMAT)
+        .map_err(|_| {
+            Errors::new(&[("starts_at", "invalid datetime format, expected YYYY-MM-DD HH:MM:SS")])
+        })?;
+    let ends_at = NaiveDateTime::parse_from_str(&new_event.ends_at, LOCAL_DATETIME_FORMAT)
+        .map_err(|_| {
+            Errors::new(&[("ends_at", "invalid datetime format, expected YYYY-MM-DD HH:MM:SS")])
+        })?;
+    if ends_at <= starts_at {
+        return Err(Errors::new(&[("ends_at", "must be after starts_at")]));
+    }
+    if new_event.eventticketprice.is_negative() {
+        return Err(Errors::new(&[("eventticketprice", "can't be negative")]));
+    }
+    if new_event.eventticketprice > MAX_PRICE {
+        return Err(Errors::new(&[("eventticketprice", "is too large")]));
+    }
+    if new_event.category_ids.is_empty() {
+        return Err(Errors::new(&[("category_ids", "can't be blank")]));
+    }
+    let tags = normalize_tags(&new_event.tags)?;
 
     // let image_path = upload_image(&new_event.eventimage).await;
 
     db.run(move |conn| {
+        let timezone = match new_event.timezone {
+            Some(timezone) => timezone,
+            None => database::venues::find(conn, new_event.venue_id)
+                .map(|venue| venue.timezone)
+                .map_err(|err| match err {
+                    diesel::result::Error::NotFound => {
+                        Errors::new(&[("venue_id", "does not exist")])
+                    }
+                    _ => Errors::new(&[("database", "failed to fetch venue")]),
+                })?,
+        };
+        let tz: Tz = timezone
+            .parse()
+            .map_err(|_| Errors::new(&[("timezone", "is not a valid IANA timezone")]))?;
+        let starts_at = local_to_utc(&tz, starts_at)
+            .ok_or_else(|| Errors::new(&[("starts_at", "does not exist or is ambiguous in timezone")]))?;
+        let ends_at = local_to_utc(&tz, ends_at)
+            .ok_or_else(|| Errors::new(&[("ends_at", "does not exist or is ambiguous in timezone")]))?;
+
         database::events::create(
             conn,
-            new_event.userid,
+            auth.id,
             &new_event.eventname,
             &new_event.eventdescription,
-            parsed_eventdate,
-            parsed_eventdatetime,
-            new_event.eventtype,
-            &new_event.eventcountry,
-            &new_event.eventcity,
-            &new_event.eventplace,
-            &image_path.unwrap(),
+            &new_event.category_ids,
+            &tags,
+            new_event.venue_id,
+            &new_event.eventimage,
             new_event.eventticketprice,
+            new_event.currency.unwrap_or(DEFAULT_CURRENCY),
+            tz.name(),
+            starts_at,
+            ends_at,
+            new_event.capacity,
         )
         .map(|event| json!({ "event": event }))
-        .map_err(|_| Errors::new(&[("database", "failed to create event")]))
+        .map_err(|error| match error {
+            EventCreationError::NonExistUsername => Errors::new(&[("userid", "does not exist")]),
+            EventCreationError::NonExistVenue => Errors::new(&[("venue_id", "does not exist")]),
+            EventCreationError::NonExistCategory => {
+                Errors::new(&[("category_ids", "contains a category that does not exist")])
+            }
+            EventCreationError::Other => Errors::new(&[("database", "failed to create event")]),
+        })
     })
     .await
 }
 
 #[get("/get_events?<filters..>")]
-pub async fn get_events(db: Db, filters: Option<EventFiltering>) -> Result<Value, Errors> {
+pub async fn get_events(
+    db: Db,
+    auth: Option<Auth>,
+    filters: Option<EventFiltering>,
+) -> Result<Value, Errors> {
     // let filters = filters.map(|f| f.into_inner());
+    let viewer = auth.map(|auth| auth.id);
     db.run(move |conn| {
-        database::events::get_events(conn, filters)
-            .map(|events| json!({ "events": events }))
+        database::events::get_events(conn, filters, viewer)
+            .map(|(events, facets)| json!({ "events": events, "facets": facets }))
             .map_err(|_| Errors::new(&[("database", "failed to fetch events")]))
     })
     .await
@@ -86,17 +156,156 @@ pub async fn get_events(db: Db, filters: Option<EventFiltering>) -> Result<Value
     // Ok(format!("events"))
 }
 
+#[get("/events/search?<q>&<limit>")]
+pub async fn search_events(db: Db, q: String, limit: Option<i64>) -> Result<Value, Errors> {
+    let terms = q.trim().to_string();
+    if terms.is_empty() {
+        return Err(Errors::new(&[("q", "can't be blank")]));
+    }
+    let limit = limit.unwrap_or(database::events::SEARCH_DEFAULT_LIMIT);
+
+    db.run(move |conn| {
+        database::events::search(conn, &terms, limit)
+            .map(|events| json!({ "events": events }))
+            .map_err(|_| Errors::new(&[("database", "failed to search events")]))
+    })
+    .await
+}
+
+#[get("/events/nearby?<filters..>")]
+pub async fn nearby_events(db: Db, filters: NearbyFiltering) -> Result<Value, Errors> {
+    if !(-90.0..=90.0).contains(&filters.lat) {
+        return Err(Errors::new(&[("lat", "must be between -90 and 90")]));
+    }
+    if !(-180.0..=180.0).contains(&filters.lng) {
+        return Err(Errors::new(&[("lng", "must be between -180 and 180")]));
+    }
+    if !(filters.km > 0.0 && filters.km <= 500.0) {
+        return Err(Errors::new(&[("km", "must be between 0 and 500")]));
+    }
+
+    db.run(move |conn| {
+        database::events::nearby(conn, filters.lat, filters.lng, filters.km, filters.limit)
+            .map(|events| json!({ "events": events }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch nearby events")]))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+struct UpdateEventFields {
+    #[serde(flatten)]
+    data: database::events::UpdateEventData,
+    /// Wall-clock time at the venue, "YYYY-MM-DD HH:MM:SS", like `add_event`.
+    starts_at: Option<String>,
+    ends_at: Option<String>,
+    /// IANA timezone. Changing it keeps the wall-clock times.
+    timezone: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct UpdateEvent {
     id: i32,
-    event: database::events::UpdateEventData,
+    event: UpdateEventFields,
+    /// Replace the event's categories and tags when present.
+    category_ids: Option<Vec<i32>>,
+    tags: Option<Vec<String>>,
 }
 
 #[put("/event", format = "json", data = "<event>")]
-pub async fn update_event(event: Json<UpdateEvent>, db: Db) -> Option<Value> {
-    db.run(move |conn| database::events::update(conn, event.id, &event.event))
-        .await
+pub async fn update_event(
+    auth: Auth,
+    event: Json<UpdateEvent>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let fields = &event.event;
+    let schedule = ScheduleChange {
+        timezone: fields
+            .timezone
+            .as_deref()
+            .map(str::parse::<Tz>)
+            .transpose()
+            .map_err(|_| Errors::new(&[("timezone", "is not a valid IANA timezone")]))?,
+        starts_at: fields
+            .starts_at
+            .as_deref()
+            .map(|starts_at| NaiveDateTime::parse_from_str(starts_at, LOCAL_DATETIME_FORMAT))
+            .transpose()
+            .map_err(|_| {
+                Errors::new(&[("starts_at", "invalid datetime format, expected YYYY-MM-DD HH:MM:SS")])
+            })?,
+        ends_at: fields
+            .ends_at
+            .as_deref()
+            .map(|ends_at| NaiveDateTime::parse_from_str(ends_at, LOCAL_DATETIME_FORMAT))
+            .transpose()
+            .map_err(|_| {
+                Errors::new(&[("ends_at", "invalid datetime format, expected YYYY-MM-DD HH:MM:SS")])
+            })?,
+    };
+
+    let data = &fields.data;
+
+    if matches!(data.eventticketprice, Some(price) if price.is_negative()) {
+        return Err(Errors::new(&[("eventticketprice", "can't be negative")]));
+    }
+    if matches!(data.eventticketprice, Some(price) if price > MAX_PRICE) {
+        return Err(Errors::new(&[("eventticketprice", "is too large")]));
+    }
+    if matches!(data.transfer_cutoff_hours, Some(hours) if hours < 0) {
+        return Err(Errors::new(&[("transfer_cutoff_hours", "can't be negative")]));
+    }
+    if matches!(data.resale_max_markup_percent, Some(percent) if percent < 0) {
+        return Err(Errors::new(&[("resale_max_markup_percent", "can't be negative")]));
+    }
+    if matches!(data.resale_royalty_percent, Some(percent) if !(0..=100).contains(&percent)) {
+        return Err(Errors::new(&[("resale_royalty_percent", "must be between 0 and 100")]));
+    }
+    if matches!(data.refund_partial_percent, Some(percent) if !(0..=100).contains(&percent)) {
+        return Err(Errors::new(&[("refund_partial_percent", "must be between 0 and 100")]));
+    }
+    if matches!(data.refund_cutoff_hours, Some(hours) if hours < 0) {
+        return Err(Errors::new(&[("refund_cutoff_hours", "can't be negative")]));
+    }
+
+    if matches!(event.category_ids, Some(ref category_ids) if category_ids.is_empty()) {
+        return Err(Errors::new(&[("category_ids", "can't be blank")]));
+    }
+    let tags = event.tags.as_deref().map(normalize_tags).transpose()?;
+
+    db.run(move |conn| {
+        database::events::update(
+            conn,
+            event.id,
+            auth.id,
+            &event.event.data,
+            schedule,
+            event.category_ids.as_deref(),
+            tags.as_deref(),
+        )
         .map(|event| json!({ "event": event }))
+        .map_err(|error| match error {
+            EventUpdateError::NotFound => Errors::new(&[("id", "event does not exist")]),
+            EventUpdateError::NotOwner => {
+                Errors::new(&[("id", "only the organizer can edit the event")])
+            }
+            EventUpdateError::InvalidStart => {
+                Errors::new(&[("starts_at", "does not exist or is ambiguous in timezone")])
+            }
+            EventUpdateError::InvalidEnd => {
+                Errors::new(&[("ends_at", "does not exist or is ambiguous in timezone")])
+            }
+            EventUpdateError::EndsBeforeStart => {
+                Errors::new(&[("ends_at", "must be after starts_at")])
+            }
+            EventUpdateError::NonExistVenue => Errors::new(&[("venue_id", "does not exist")]),
+            EventUpdateError::NonExistCategory => {
+                Errors::new(&[("category_ids", "contains a category that does not exist")])
+            }
+            EventUpdateError::Other => Errors::new(&[("database", "failed to update event")]),
+        })
+    })
+    .await
 }
 
 #[derive(Deserialize)]
@@ -105,9 +314,45 @@ pub struct DeleteEvent {
 }
 
 #[delete("/event", format = "json", data = "<event>")]
-pub async fn delete_event(event: Json<DeleteEvent>, db: Db) -> Result<Value, Errors> {
-    db.run(move |conn| database::events::delete(conn, event.id))
-        .await
-        .map(|_| json!({ "message": "event deleted successfully" }))
-        .map_err(|_| Errors::new(&[("database", "failed to delete event")]))
+pub async fn delete_event(auth: Auth, event: Json<DeleteEvent>, db: Db) -> Result<Value, Errors> {
+    match db.run(move |conn| database::events::delete(conn, event.id, auth.id)).await {
+        Ok(0) => Err(Errors::new(&[(
+            "status",
+            "only your own draft events can be deleted, cancel the event instead",
+        )])),
+        Ok(_) => Ok(json!({ "message": "event deleted successfully" })),
+        Err(_) => Err(Errors::new(&[("database", "failed to delete event")])),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EventStatusChange {
+    id: i32,
+    status: EventStatus,
+}
+
+#[put("/event/status", format = "json", data = "<change>")]
+pub async fn update_event_status(
+    auth: Auth,
+    change: Json<EventStatusChange>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let change = change.into_inner();
+    db.run(move |conn| {
+        database::events::transition(conn, change.id, auth.id, change.status)
+            .map(|event| json!({ "event": event }))
+            .map_err(|error| match error {
+                EventTransitionError::NotFound => Errors::new(&[("id", "event does not exist")]),
+                EventTransitionError::NotOwner => {
+                    Errors::new(&[("id", "only the organizer can change the status")])
+                }
+                EventTransitionError::InvalidTransition(_, _) => {
+                    Errors::new(&[("status", "transition is not allowed from the current status")])
+                }
+                EventTransitionError::Other => {
+                    Errors::new(&[("database", "failed to update event status")])
+                }
+            })
+    })
+    .await
 }
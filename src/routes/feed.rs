@@ -0,0 +1,31 @@
+use crate::auth::Auth;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::json::{json, Value};
+
+#[get("/me/feed?<cursor>&<limit>")]
+pub async fn get_feed(
+    auth: Auth,
+    db: Db,
+    cursor: Option<i64>,
+    limit: Option<i64>,
+) -> Result<Value, Errors> {
+    // Clamped here too, so a short page is judged against the real limit.
+    let limit = limit
+        .unwrap_or(database::feed::FEED_DEFAULT_LIMIT)
+        .clamp(1, database::feed::FEED_MAX_LIMIT);
+
+    db.run(move |conn| {
+        database::feed::get_feed(conn, auth.id, cursor, limit)
+            .map(|items| {
+                // A short page means there is nothing older left.
+                let next_cursor = match items.last() {
+                    Some(last) if items.len() as i64 >= limit => Some(last.id),
+                    _ => None,
+                };
+                json!({ "items": items, "next_cursor": next_cursor })
+            })
+            .map_err(|_| Errors::new(&[("database", "failed to fetch feed")]))
+    })
+    .await
+}
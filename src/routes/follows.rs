@@ -0,0 +1,74 @@
+use crate::auth::Auth;
+use crate::database::follows::FollowError;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::json::{json, Value};
+
+fn follow_error(error: FollowError) -> Errors {
+    match error {
+        FollowError::NotFound => Errors::new(&[("profile", "does not exist")]),
+        FollowError::SelfFollow => Errors::new(&[("profile", "you can't follow yourself")]),
+        FollowError::Other => Errors::new(&[("database", "failed to update follow")]),
+    }
+}
+
+#[get("/profiles/<username>")]
+pub async fn get_profile(username: String, auth: Option<Auth>, db: Db) -> Result<Value, Errors> {
+    let viewer = auth.map(|auth| auth.id);
+    db.run(move |conn| {
+        database::follows::profile(conn, &username, viewer)
+            .map(|profile| json!({ "profile": profile }))
+            .map_err(follow_error)
+    })
+    .await
+}
+
+#[post("/profiles/<username>/follow")]
+pub async fn follow(username: String, auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::follows::follow_user(conn, auth.id, &username)
+            .map(|profile| json!({ "profile": profile }))
+            .map_err(follow_error)
+    })
+    .await
+}
+
+#[delete("/profiles/<username>/follow")]
+pub async fn unfollow(username: String, auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::follows::unfollow_user(conn, auth.id, &username)
+            .map(|profile| json!({ "profile": profile }))
+            .map_err(follow_error)
+    })
+    .await
+}
+
+#[post("/venues/<id>/follow")]
+pub async fn follow_venue(id: i32, auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::follows::follow_venue(conn, auth.id, id)
+            .map(|(venue, followers_count)| {
+                json!({ "venue": venue, "following": true, "followers_count": followers_count })
+            })
+            .map_err(|error| match error {
+                FollowError::NotFound => Errors::new(&[("venue", "does not exist")]),
+                error => follow_error(error),
+            })
+    })
+    .await
+}
+
+#[delete("/venues/<id>/follow")]
+pub async fn unfollow_venue(id: i32, auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::follows::unfollow_venue(conn, auth.id, id)
+            .map(|(venue, followers_count)| {
+                json!({ "venue": venue, "following": false, "followers_count": followers_count })
+            })
+            .map_err(|error| match error {
+                FollowError::NotFound => Errors::new(&[("venue", "does not exist")]),
+                error => follow_error(error),
+            })
+    })
+    .await
+}
@@ -0,0 +1,47 @@
+use crate::auth::Auth;
+use crate::database::invoices::InvoiceError;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::receipts::ReceiptFormat;
+use rocket::http::{ContentType, Header};
+
+fn invoice_error(error: InvoiceError) -> Errors {
+    match error {
+        InvoiceError::NotFound => Errors::new(&[("id", "order does not exist")]),
+        InvoiceError::NotOwner => Errors::new(&[("id", "order belongs to another user")]),
+        InvoiceError::NotIssued => Errors::new(&[("status", "order is not paid")]),
+        InvoiceError::Other => Errors::new(&[("database", "failed to fetch receipt")]),
+    }
+}
+
+#[derive(Responder)]
+pub struct ReceiptDocument {
+    content: (ContentType, Vec<u8>),
+    disposition: Header<'static>,
+}
+
+/// The receipt of a paid order, for its buyer or the event's organizer.
+/// PDF unless `format=html` is asked for.
+#[get("/orders/<id>/receipt?<format>")]
+pub async fn get_receipt(
+    auth: Auth,
+    id: i32,
+    format: Option<ReceiptFormat>,
+    db: Db,
+) -> Result<ReceiptDocument, Errors> {
+    db.run(move |conn| {
+        let receipt = database::invoices::receipt_for(conn, id, auth.id).map_err(invoice_error)?;
+        let (content_type, extension, body) = match format.unwrap_or(ReceiptFormat::Pdf) {
+            ReceiptFormat::Html => (ContentType::HTML, "html", receipt.html().into_bytes()),
+            ReceiptFormat::Pdf => (ContentType::PDF, "pdf", receipt.pdf()),
+        };
+        Ok(ReceiptDocument {
+            content: (content_type, body),
+            disposition: Header::new(
+                "Content-Disposition",
+                format!("inline; filename=\"{}\"", receipt.filename(extension)),
+            ),
+        })
+    })
+    .await
+}
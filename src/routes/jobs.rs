@@ -0,0 +1,52 @@
+use crate::auth::Admin;
+use crate::database::jobs::JobStatus;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::json::{json, Value};
+use std::collections::HashMap;
+
+#[get("/admin/jobs?<status>&<kind>&<cursor>&<limit>")]
+pub async fn get_jobs(
+    _admin: Admin,
+    db: Db,
+    status: Option<JobStatus>,
+    kind: Option<String>,
+    cursor: Option<i64>,
+    limit: Option<i64>,
+) -> Result<Value, Errors> {
+    let limit = limit
+        .unwrap_or(database::jobs::JOBS_DEFAULT_LIMIT)
+        .clamp(1, database::jobs::JOBS_MAX_LIMIT);
+
+    db.run(move |conn| {
+        let jobs = database::jobs::list(conn, status, kind.as_deref(), cursor, limit)
+            .map_err(|_| Errors::new(&[("database", "failed to fetch jobs")]))?;
+        let counts = database::jobs::counts(conn)
+            .map_err(|_| Errors::new(&[("database", "failed to fetch jobs")]))?
+            .into_iter()
+            .map(|(status, count)| (status.as_str(), count))
+            .collect::<HashMap<_, _>>();
+        let next_cursor = match jobs.last() {
+            Some(last) if jobs.len() as i64 >= limit => Some(last.id),
+            _ => None,
+        };
+        Ok(json!({
+            "jobs": jobs,
+            "counts": counts,
+            "next_cursor": next_cursor,
+        }))
+    })
+    .await
+}
+
+#[post("/admin/jobs/<id>/retry")]
+pub async fn retry_job(_admin: Admin, id: i64, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        match database::jobs::retry(conn, id) {
+            Ok(Some(job)) => Ok(json!({ "job": job })),
+            Ok(None) => Err(Errors::new(&[("id", "no dead job with this id")])),
+            Err(_) => Err(Errors::new(&[("database", "failed to retry job")])),
+        }
+    })
+    .await
+}
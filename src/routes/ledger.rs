@@ -0,0 +1,63 @@
+use crate::auth::{Admin, Auth};
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::money::Currency;
+use rocket::serde::json::{json, Value};
+
+/// What the platform owes the organizer, per currency.
+#[get("/me/balance")]
+pub async fn get_balance(auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::ledger::balances(conn, auth.id)
+            .map(|balances| json!({ "balances": balances }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch balance")]))
+    })
+    .await
+}
+
+/// Movements of the available balance. `from` and `to` are RFC 3339
+/// timestamps or YYYY-MM-DD (UTC); `cursor` is the previous page's
+/// `next_cursor`.
+#[get("/me/statement?<currency>&<from>&<to>&<cursor>&<limit>")]
+pub async fn get_statement(
+    auth: Auth,
+    currency: Currency,
+    from: Option<String>,
+    to: Option<String>,
+    cursor: Option<i32>,
+    limit: Option<i64>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let from = match from.as_deref() {
+        Some(from) => Some(
+            database::events::parse_window_bound(from)
+                .ok_or_else(|| Errors::new(&[("from", "is not a valid date")]))?,
+        ),
+        None => None,
+    };
+    let to = match to.as_deref() {
+        Some(to) => Some(
+            database::events::parse_window_bound(to)
+                .ok_or_else(|| Errors::new(&[("to", "is not a valid date")]))?,
+        ),
+        None => None,
+    };
+
+    db.run(move |conn| {
+        database::ledger::statement(conn, auth.id, currency, from, to, cursor, limit)
+            .map(|statement| json!({ "statement": statement }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch statement")]))
+    })
+    .await
+}
+
+/// Run the ledger invariant check on demand.
+#[get("/admin/ledger/check")]
+pub async fn check_ledger(_admin: Admin, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::ledger::check(conn)
+            .map(|check| json!({ "balanced": check.is_balanced(), "check": check }))
+            .map_err(|_| Errors::new(&[("database", "failed to check ledger")]))
+    })
+    .await
+}
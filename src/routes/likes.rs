@@ -1,5 +1,6 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+use crate::auth::Auth;
 use crate::database::likes::LikeCreationError;
 use crate::database::{self, Db};
 use crate::errors::Errors;
@@ -14,7 +15,6 @@ use rocket::serde::{
 #[derive(Queryable, Deserialize)]
 struct NewLikeData {
     id: Option<i32>,
-    userid: i32,
     eventid: i32,
 }
 
@@ -24,21 +24,23 @@ pub struct NewLike {
 }
 
 #[post("/like", format = "json", data = "<new_like>")]
-pub async fn like_event(new_like: Json<NewLike>, db: Db) -> Result<Value, Errors> {
+pub async fn like_event(auth: Auth, new_like: Json<NewLike>, db: Db) -> Result<Value, Errors> {
     let new_like = new_like.into_inner().like;
 
     db.run(move |conn| {
-        database::likes::create(conn, new_like.userid, new_like.eventid)
-            .map(|like| json!({ "like": like }))
-            .map_err(|error| {
-                let field = match error {
-                    LikeCreationError::DuplicatedUserId => "new_like.userid",
-                    LikeCreationError::NonExistEventId => "new_like.eventid",
-                    LikeCreationError::NonExistUserId => "new_like.userid",
-                    LikeCreationError::Other => "other error",
-                };
-                Errors::new(&[(field, "has already liked or id does not exist")])
-            })
+        let like = database::likes::create(conn, auth.id, new_like.eventid).map_err(
+            |error| match error {
+                LikeCreationError::AlreadyLiked => {
+                    Errors::new(&[("eventid", "has already been liked by this user")])
+                }
+                LikeCreationError::NonExistEventId => Errors::new(&[("eventid", "does not exist")]),
+                LikeCreationError::NonExistUserId => Errors::new(&[("userid", "does not exist")]),
+                LikeCreationError::Other => Errors::new(&[("database", "failed to like event")]),
+            },
+        )?;
+        let likes_count = database::likes::count_for_event(conn, like.event_id)
+            .map_err(|_| Errors::new(&[("database", "failed to count likes")]))?;
+        Ok(json!({ "like": like, "likes_count": likes_count }))
     })
     .await
 }
@@ -55,15 +57,22 @@ pub async fn get_likes(db: Db, filters: Option<LikesFiltering>) -> Result<Value,
 
 #[derive(Deserialize)]
 pub struct DeleteLike {
-    id: i32,
+    eventid: i32,
 }
 
 #[delete("/like", format = "json", data = "<like>")]
-pub async fn delete_like(like: Json<DeleteLike>, db: Db) -> Result<Value, Errors> {
-    db.run(move |conn| database::likes::delete(conn, like.id))
-        .await
-        .map(|_| json!({ "message": "like deleted successfully" }))
-        .map_err(|_| Errors::new(&[("database", "failed to delete like")]))
+pub async fn delete_like(auth: Auth, like: Json<DeleteLike>, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        let deleted = database::likes::delete(conn, auth.id, like.eventid)
+            .map_err(|_| Errors::new(&[("database", "failed to delete like")]))?;
+        if deleted == 0 {
+            return Err(Errors::new(&[("eventid", "is not liked by this user")]));
+        }
+        let likes_count = database::likes::count_for_event(conn, like.eventid)
+            .map_err(|_| Errors::new(&[("database", "failed to count likes")]))?;
+        Ok(json!({ "message": "like deleted successfully", "likes_count": likes_count }))
+    })
+    .await
 }
 
 #[derive(Deserialize)]
@@ -81,13 +90,3 @@ pub async fn is_event_liked_by_user(db: Db, like_request: Json<LikeRequest>) ->
     })
    .await
 }
-
-// #[get("/get_popular_likes?<filters..>")]
-// pub async fn get_popular_likes(db: Db, filters: Option<LikesFiltering>) -> Result<Value, Errors> {
-//     db.run(|conn| {
-//         database::likes::get_popular_likes(conn)
-//            .map(|events| json!({ "events": events }))
-//            .map_err(|_| Errors::new(&[("database", "failed to fetch popular events")]))
-//     })
-//     .await
-// }
\ No newline at end of file
@@ -0,0 +1,158 @@
+use crate::auth::Auth;
+use crate::chain;
+use crate::database::listings::ListingError;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::money::{Amount, MAX_PRICE};
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+fn listing_error(error: ListingError) -> Errors {
+    match error {
+        ListingError::TicketNotFound => Errors::new(&[("ticket_id", "ticket does not exist")]),
+        ListingError::ResaleClosed => Errors::new(&[("event_id", "resale is closed for this event")]),
+        ListingError::PriceAboveCap => {
+            Errors::new(&[("price", "is above the event's max_resale_price")])
+        }
+        ListingError::AlreadyListed => Errors::new(&[("ticket_id", "ticket is already listed")]),
+        ListingError::TransferPending => {
+            Errors::new(&[("ticket_id", "ticket has a pending transfer")])
+        }
+        ListingError::NotFound => Errors::new(&[("id", "listing does not exist")]),
+        ListingError::OwnListing => Errors::new(&[("id", "can't buy your own listing")]),
+        ListingError::NotAvailable => Errors::new(&[("status", "listing is not available")]),
+        ListingError::PaymentNotVerified => Errors::new(&[(
+            "payment_reference",
+            "is not a successful transfer of the price to the platform wallet",
+        )]),
+        ListingError::WrongSender => {
+            Errors::new(&[("payment_reference", "was not sent from your wallet_address")])
+        }
+        ListingError::NoWallet => Errors::new(&[("wallet_address", "must be set to pay on chain")]),
+        ListingError::PaymentUnconfirmed => {
+            Errors::new(&[("payment_reference", "is not confirmed yet, try again shortly")])
+        }
+        ListingError::PaymentReferenceUsed => {
+            Errors::new(&[("payment_reference", "has already been used")])
+        }
+        ListingError::PaymentUnmatched => Errors::new(&[(
+            "payment_reference",
+            "arrived after the listing could no longer be sold and will be refunded",
+        )]),
+        ListingError::OnchainUnsupported => {
+            Errors::new(&[("currency", "can't be paid on chain")])
+        }
+        ListingError::VerificationUnavailable => {
+            Errors::new(&[("payment", "on-chain payments can't be checked right now")])
+        }
+        ListingError::Other => Errors::new(&[("database", "failed to process listing")]),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewListing {
+    price: Amount,
+}
+
+#[post("/tickets/<id>/listing", format = "json", data = "<listing>")]
+pub async fn add_listing(
+    auth: Auth,
+    id: i32,
+    listing: Json<NewListing>,
+    db: Db,
+) -> Result<Value, Errors> {
+    if listing.price.is_negative() {
+        return Err(Errors::new(&[("price", "can't be negative")]));
+    }
+    if listing.price > MAX_PRICE {
+        return Err(Errors::new(&[("price", "is too large")]));
+    }
+
+    db.run(move |conn| {
+        database::listings::create(conn, id, auth.id, listing.price)
+            .map(|listing| json!({ "listing": listing }))
+            .map_err(listing_error)
+    })
+    .await
+}
+
+#[get("/events/<id>/listings?<max_price>&<limit>")]
+pub async fn get_event_listings(
+    db: Db,
+    id: i32,
+    max_price: Option<Amount>,
+    limit: Option<i64>,
+) -> Result<Value, Errors> {
+    let limit = limit.unwrap_or(database::listings::LISTINGS_DEFAULT_LIMIT);
+
+    db.run(move |conn| {
+        database::listings::for_event(conn, id, max_price, limit)
+            .map(|listings| json!({ "listings": listings }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch listings")]))
+    })
+    .await
+}
+
+#[get("/me/listings")]
+pub async fn get_my_listings(auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::listings::for_seller(conn, auth.id)
+            .map(|listings| json!({ "listings": listings }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch listings")]))
+    })
+    .await
+}
+
+#[post("/listings/<id>/cancel")]
+pub async fn cancel_listing(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::listings::cancel(conn, id, auth.id)
+            .map(|listing| json!({ "listing": listing }))
+            .map_err(listing_error)
+    })
+    .await
+}
+
+#[post("/listings/<id>/reserve")]
+pub async fn reserve_listing(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::listings::reserve(conn, id, auth.id)
+            .map(|listing| json!({ "listing": listing }))
+            .map_err(listing_error)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct PayListing {
+    payment_reference: String,
+}
+
+#[post("/listings/<id>/pay", format = "json", data = "<payment>")]
+pub async fn pay_listing(
+    auth: Auth,
+    id: i32,
+    payment: Json<PayListing>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let payment = payment.into_inner();
+    let tx_hash = chain::normalize_tx_hash(&payment.payment_reference)
+        .ok_or_else(|| Errors::new(&[("payment_reference", "is not a transaction hash")]))?;
+    let verifier = match chain::verifier() {
+        Ok(Some(verifier)) => verifier,
+        Ok(None) => return Err(Errors::new(&[("payment", "on-chain payments are not enabled")])),
+        Err(err) => {
+            eprintln!("chain: {}", err);
+            return Err(Errors::new(&[("payment", "on-chain payments are not enabled")]));
+        }
+    };
+
+    db.run(move |conn| {
+        database::listings::pay(conn, &verifier, id, auth.id, &tx_hash)
+            .map(|(listing, ticket)| json!({ "listing": listing, "ticket": ticket }))
+            .map_err(listing_error)
+    })
+    .await
+}
@@ -1,3 +1,26 @@
 pub mod users;
 pub mod events;
-pub mod likes;
\ No newline at end of file
+pub mod likes;
+pub mod venues;
+pub mod series;
+pub mod orders;
+pub mod tickets;
+pub mod categories;
+pub mod tags;
+pub mod rankings;
+pub mod recommendations;
+pub mod follows;
+pub mod feed;
+pub mod notifications;
+pub mod jobs;
+pub mod transfers;
+pub mod listings;
+pub mod refunds;
+pub mod waitlist;
+pub mod promotions;
+pub mod rates;
+pub mod payouts;
+pub mod ledger;
+pub mod payments;
+pub mod chain_payments;
+pub mod invoices;
\ No newline at end of file
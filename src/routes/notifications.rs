@@ -0,0 +1,99 @@
+use crate::auth::Auth;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::models::notifications::NotificationPreference;
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+#[get("/me/notifications?<unread>&<cursor>&<limit>")]
+pub async fn get_notifications(
+    auth: Auth,
+    db: Db,
+    unread: Option<bool>,
+    cursor: Option<i64>,
+    limit: Option<i64>,
+) -> Result<Value, Errors> {
+    // The query clamps as well; next_cursor must see the same limit.
+    let limit = limit
+        .unwrap_or(database::notifications::NOTIFICATIONS_DEFAULT_LIMIT)
+        .clamp(1, database::notifications::NOTIFICATIONS_MAX_LIMIT);
+
+    db.run(move |conn| {
+        let notifications = database::notifications::list(
+            conn,
+            auth.id,
+            unread.unwrap_or(false),
+            cursor,
+            limit,
+        )
+        .map_err(|_| Errors::new(&[("database", "failed to fetch notifications")]))?;
+        let unread_count = database::notifications::unread_count(conn, auth.id)
+            .map_err(|_| Errors::new(&[("database", "failed to fetch notifications")]))?;
+        let next_cursor = match notifications.last() {
+            Some(last) if notifications.len() as i64 >= limit => Some(last.id),
+            _ => None,
+        };
+        Ok(json!({
+            "notifications": notifications,
+            "unread_count": unread_count,
+            "next_cursor": next_cursor,
+        }))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct MarkRead {
+    ids: Vec<i64>,
+}
+
+#[post("/me/notifications/read", format = "json", data = "<read>")]
+pub async fn mark_read(auth: Auth, read: Json<MarkRead>, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::notifications::mark_read(conn, auth.id, &read.ids)
+            .map(|updated| json!({ "updated": updated }))
+            .map_err(|_| Errors::new(&[("database", "failed to update notifications")]))
+    })
+    .await
+}
+
+#[post("/me/notifications/read_all")]
+pub async fn mark_all_read(auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::notifications::mark_all_read(conn, auth.id)
+            .map(|updated| json!({ "updated": updated }))
+            .map_err(|_| Errors::new(&[("database", "failed to update notifications")]))
+    })
+    .await
+}
+
+#[get("/me/notification_preferences")]
+pub async fn get_preferences(auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::notifications::preferences(conn, auth.id)
+            .map(|preferences| json!({ "preferences": preferences }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch preferences")]))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct UpdatePreferences {
+    preferences: Vec<NotificationPreference>,
+}
+
+#[put("/me/notification_preferences", format = "json", data = "<update>")]
+pub async fn update_preferences(
+    auth: Auth,
+    update: Json<UpdatePreferences>,
+    db: Db,
+) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::notifications::update_preferences(conn, auth.id, &update.preferences)
+            .map(|preferences| json!({ "preferences": preferences }))
+            .map_err(|_| Errors::new(&[("database", "failed to update preferences")]))
+    })
+    .await
+}
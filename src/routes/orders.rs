@@ -0,0 +1,166 @@
+use crate::auth::Auth;
+use crate::chain;
+use crate::database::orders::{OrderError, MAX_TICKETS_PER_ORDER};
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::models::orders::OrderFiltering;
+use crate::money::Currency;
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+fn order_error(error: OrderError) -> Errors {
+    match error {
+        OrderError::NotFound => Errors::new(&[("id", "order does not exist")]),
+        OrderError::NotOwner => Errors::new(&[("id", "order belongs to another user")]),
+        OrderError::EventNotFound => Errors::new(&[("event_id", "does not exist")]),
+        OrderError::EventNotOnSale => Errors::new(&[("event_id", "is not on sale")]),
+        OrderError::SoldOut => Errors::new(&[("quantity", "not enough tickets left")]),
+        OrderError::NotPending => Errors::new(&[("status", "order is no longer pending")]),
+        OrderError::HoldExpired => Errors::new(&[("expires_at", "ticket hold has expired")]),
+        OrderError::InvalidPromoCode => Errors::new(&[("promo_code", "is not valid for this event")]),
+        OrderError::PromoCodeExpired => Errors::new(&[("promo_code", "is not valid at this time")]),
+        OrderError::PromoCodeUsedUp => Errors::new(&[("promo_code", "has reached its usage limit")]),
+        OrderError::RateUnavailable => Errors::new(&[("pay_currency", "has no current exchange rate")]),
+        OrderError::PaymentInProgress => {
+            Errors::new(&[("payment", "a card or mobile money payment is in progress")])
+        }
+        OrderError::PaymentNotVerified => Errors::new(&[(
+            "payment_reference",
+            "is not a successful transfer of the amount due to the platform wallet",
+        )]),
+        OrderError::WrongSender => {
+            Errors::new(&[("payment_reference", "was not sent from your wallet_address")])
+        }
+        OrderError::NoWallet => Errors::new(&[("wallet_address", "must be set to pay on chain")]),
+        OrderError::PaymentUnconfirmed => {
+            Errors::new(&[("payment_reference", "is not confirmed yet, try again shortly")])
+        }
+        OrderError::PaymentReferenceUsed => {
+            Errors::new(&[("payment_reference", "has already been used")])
+        }
+        OrderError::PaymentUnmatched => Errors::new(&[(
+            "payment_reference",
+            "arrived after the order could no longer be paid and will be refunded",
+        )]),
+        OrderError::OnchainUnsupported => {
+            Errors::new(&[("currency", "can't be paid on chain")])
+        }
+        OrderError::VerificationUnavailable => {
+            Errors::new(&[("payment", "on-chain payments can't be checked right now")])
+        }
+        OrderError::Other => Errors::new(&[("database", "failed to process order")]),
+    }
+}
+
+#[derive(Deserialize)]
+struct NewOrderData {
+    event_id: i32,
+    quantity: i32,
+    promo_code: Option<String>,
+    /// Pay in this currency instead of the event's.
+    pay_currency: Option<Currency>,
+}
+
+#[derive(Deserialize)]
+pub struct NewOrder {
+    order: NewOrderData,
+}
+
+#[post("/orders", format = "json", data = "<new_order>")]
+pub async fn add_order(auth: Auth, new_order: Json<NewOrder>, db: Db) -> Result<Value, Errors> {
+    let new_order = new_order.into_inner().order;
+    if !(1..=MAX_TICKETS_PER_ORDER).contains(&new_order.quantity) {
+        return Err(Errors::new(&[("quantity", "must be between 1 and 10")]));
+    }
+
+    db.run(move |conn| {
+        database::orders::create(
+            conn,
+            auth.id,
+            new_order.event_id,
+            new_order.quantity,
+            new_order.promo_code.as_deref(),
+            new_order.pay_currency,
+        )
+        .map(|order| json!({ "order": order }))
+        .map_err(order_error)
+    })
+    .await
+}
+
+/// Price an order before placing it, with the same rules checkout applies.
+#[get("/events/<id>/quote?<quantity>&<promo_code>&<pay_currency>")]
+pub async fn quote_order(
+    auth: Auth,
+    id: i32,
+    quantity: i32,
+    promo_code: Option<String>,
+    pay_currency: Option<Currency>,
+    db: Db,
+) -> Result<Value, Errors> {
+    if !(1..=MAX_TICKETS_PER_ORDER).contains(&quantity) {
+        return Err(Errors::new(&[("quantity", "must be between 1 and 10")]));
+    }
+
+    db.run(move |conn| {
+        database::orders::quote(conn, id, auth.id, quantity, promo_code.as_deref(), pay_currency)
+            .map(|pricing| json!({ "quote": pricing }))
+            .map_err(order_error)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct PayOrder {
+    id: i32,
+    payment_reference: String,
+}
+
+#[post("/orders/pay", format = "json", data = "<payment>")]
+pub async fn pay_order(auth: Auth, payment: Json<PayOrder>, db: Db) -> Result<Value, Errors> {
+    let payment = payment.into_inner();
+    let tx_hash = chain::normalize_tx_hash(&payment.payment_reference)
+        .ok_or_else(|| Errors::new(&[("payment_reference", "is not a transaction hash")]))?;
+    let verifier = match chain::verifier() {
+        Ok(Some(verifier)) => verifier,
+        Ok(None) => return Err(Errors::new(&[("payment", "on-chain payments are not enabled")])),
+        Err(err) => {
+            eprintln!("chain: {}", err);
+            return Err(Errors::new(&[("payment", "on-chain payments are not enabled")]));
+        }
+    };
+
+    db.run(move |conn| {
+        database::orders::pay(conn, &verifier, payment.id, auth.id, &tx_hash)
+            .map(|(order, tickets)| json!({ "order": order, "tickets": tickets }))
+            .map_err(order_error)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct CancelOrder {
+    id: i32,
+}
+
+#[post("/orders/cancel", format = "json", data = "<order>")]
+pub async fn cancel_order(auth: Auth, order: Json<CancelOrder>, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::orders::cancel(conn, order.id, auth.id)
+            .map(|order| json!({ "order": order }))
+            .map_err(order_error)
+    })
+    .await
+}
+
+#[get("/get_orders?<filters..>")]
+pub async fn get_orders(auth: Auth, db: Db, filters: Option<OrderFiltering>) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::orders::get_orders(conn, auth.id, filters)
+            .map(|orders| json!({ "orders": orders }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch orders")]))
+    })
+    .await
+}
@@ -0,0 +1,219 @@
+use crate::auth::Auth;
+use crate::database::payments::{PaymentError, PaymentMethod};
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::models::payments::Payment;
+use crate::payments::{self, FakeProvider, PaymentOutcome, PaymentProvider};
+use diesel::pg::PgConnection;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest, Request};
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+/// Webhook bodies are small JSON documents.
+const WEBHOOK_BODY_LIMIT_KIB: u64 = 64;
+
+fn payment_error(error: PaymentError) -> Errors {
+    match error {
+        PaymentError::NotFound => Errors::new(&[("id", "payment does not exist")]),
+        PaymentError::OrderNotFound => Errors::new(&[("id", "order does not exist")]),
+        PaymentError::NotOwner => Errors::new(&[("id", "order belongs to another user")]),
+        PaymentError::NotPending => Errors::new(&[("status", "order is not pending")]),
+        PaymentError::HoldExpired => Errors::new(&[("expires_at", "seat hold has expired")]),
+        PaymentError::UnsupportedMethod => {
+            Errors::new(&[("method", "must be card or mobile_money")])
+        }
+        PaymentError::UnsupportedCurrency => {
+            Errors::new(&[("currency", "can't be paid by card or mobile money")])
+        }
+        PaymentError::InProgress => Errors::new(&[("payment", "another payment is in progress")]),
+        PaymentError::Provider => {
+            Errors::new(&[("payment", "provider could not start the payment")])
+        }
+        PaymentError::Other => Errors::new(&[("database", "failed to process payment")]),
+    }
+}
+
+fn configured_provider() -> Option<Box<dyn PaymentProvider>> {
+    match payments::provider() {
+        Ok(provider) => provider,
+        Err(err) => {
+            eprintln!("payments: {}", err);
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct NewPaymentData {
+    method: PaymentMethod,
+    /// Mobile money number to prompt.
+    phone: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewPayment {
+    payment: NewPaymentData,
+}
+
+/// Pay a pending order by card or mobile money. The buyer follows
+/// `checkout_url` when there is one; the order is paid once the provider's
+/// webhook confirms.
+#[post("/orders/<id>/payments", format = "json", data = "<new_payment>")]
+pub async fn start_payment(
+    auth: Auth,
+    id: i32,
+    new_payment: Json<NewPayment>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let new_payment = new_payment.into_inner().payment;
+    let phone = new_payment
+        .phone
+        .map(|phone| phone.trim().to_string())
+        .filter(|phone| !phone.is_empty());
+    if new_payment.method == PaymentMethod::MobileMoney && phone.is_none() {
+        return Err(Errors::new(&[("phone", "can't be blank for mobile money")]));
+    }
+
+    db.run(move |conn| {
+        let provider = configured_provider()
+            .ok_or_else(|| Errors::new(&[("payment", "card and mobile money are unavailable")]))?;
+        database::payments::start(
+            conn,
+            provider.as_ref(),
+            id,
+            auth.id,
+            new_payment.method,
+            phone.as_deref(),
+        )
+        .map(|payment| json!({ "payment": payment }))
+        .map_err(payment_error)
+    })
+    .await
+}
+
+#[get("/orders/<id>/payments")]
+pub async fn get_order_payments(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::payments::for_order(conn, id, auth.id)
+            .map(|payments| json!({ "payments": payments }))
+            .map_err(payment_error)
+    })
+    .await
+}
+
+/// The `X-Signature` header of a webhook; checked against the body by the
+/// provider.
+pub struct WebhookSignature(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebhookSignature {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<WebhookSignature, Self::Error> {
+        match req.headers().get_one("X-Signature") {
+            Some(signature) => Outcome::Success(WebhookSignature(signature.to_string())),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Verify and apply one webhook delivery, the same way for real and
+/// simulated ones.
+fn receive(
+    conn: &mut PgConnection,
+    provider: &dyn PaymentProvider,
+    body: &[u8],
+    signature: &str,
+) -> Result<Payment, Status> {
+    if !provider.verify(body, signature) {
+        return Err(Status::Unauthorized);
+    }
+    let event = provider.parse_event(body).map_err(|err| {
+        eprintln!("payments: {}: malformed webhook: {}", provider.name(), err);
+        Status::BadRequest
+    })?;
+    let payment =
+        database::payments::handle_event(conn, provider.name(), &event).map_err(|err| match err {
+            PaymentError::NotFound => Status::NotFound,
+            _ => Status::InternalServerError,
+        })?;
+    match database::payments::refund(conn, provider, &payment) {
+        Ok(Some(refunded)) => Ok(refunded),
+        Ok(None) => Ok(payment),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// Outcome of a payment, sent by the provider. Anything but a 2xx makes the
+/// provider deliver it again, so replays must be harmless.
+#[post("/payments/webhook/<provider>", data = "<body>")]
+pub async fn payment_webhook(
+    provider: String,
+    signature: WebhookSignature,
+    body: Data<'_>,
+    db: Db,
+) -> Result<Value, Status> {
+    let body = body
+        .open(WEBHOOK_BODY_LIMIT_KIB.kibibytes())
+        .into_bytes()
+        .await
+        .map_err(|_| Status::BadRequest)?;
+    if !body.is_complete() {
+        return Err(Status::PayloadTooLarge);
+    }
+    let body = body.into_inner();
+
+    db.run(move |conn| {
+        let configured = configured_provider().ok_or(Status::NotFound)?;
+        if configured.name() != provider {
+            return Err(Status::NotFound);
+        }
+        receive(conn, configured.as_ref(), &body, &signature.0)
+            .map(|payment| json!({ "status": payment.status }))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct FakeOutcome {
+    status: PaymentOutcome,
+}
+
+/// Finish a payment of the fake provider as if its webhook arrived. Only
+/// available while the fake provider is in use.
+#[post("/payments/<id>/fake", format = "json", data = "<outcome>")]
+pub async fn complete_fake_payment(
+    auth: Auth,
+    id: i32,
+    outcome: Json<FakeOutcome>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let status = outcome.into_inner().status;
+    db.run(move |conn| {
+        let provider = configured_provider()
+            .filter(|provider| provider.name() == FakeProvider.name())
+            .ok_or_else(|| Errors::new(&[("provider", "fake payments are disabled")]))?;
+        let payment = database::payments::find(conn, id)
+            .map_err(|err| payment_error(PaymentError::from(err)))?;
+        if payment.provider != provider.name() {
+            return Err(Errors::new(&[(
+                "provider",
+                "payment is not a fake payment",
+            )]));
+        }
+        database::payments::for_order(conn, payment.order_id, auth.id).map_err(payment_error)?;
+        let event = database::payments::event_for(&payment, status)
+            .ok_or_else(|| Errors::new(&[("status", "payment was never started")]))?;
+
+        let (body, signature) = FakeProvider::webhook(&event);
+        receive(conn, provider.as_ref(), body.as_bytes(), &signature)
+            .map(|payment| json!({ "payment": payment }))
+            .map_err(|_| Errors::new(&[("database", "failed to process payment")]))
+    })
+    .await
+}
@@ -0,0 +1,121 @@
+use crate::auth::{Admin, Auth};
+use crate::database::payouts::{PayoutError, PayoutMethod, PayoutStatus};
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::money::{Amount, Currency};
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+fn payout_error(error: PayoutError) -> Errors {
+    match error {
+        PayoutError::NotFound => Errors::new(&[("id", "payout does not exist")]),
+        PayoutError::InsufficientBalance => {
+            Errors::new(&[("amount", "is more than the available balance")])
+        }
+        PayoutError::InvalidStatus => Errors::new(&[("status", "payout is no longer pending")]),
+        PayoutError::Other => Errors::new(&[("database", "failed to process payout")]),
+    }
+}
+
+#[derive(Deserialize)]
+struct NewPayoutData {
+    amount: Amount,
+    currency: Currency,
+    method: PayoutMethod,
+    destination: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewPayout {
+    payout: NewPayoutData,
+}
+
+#[post("/me/payouts", format = "json", data = "<new_payout>")]
+pub async fn request_payout(
+    auth: Auth,
+    new_payout: Json<NewPayout>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let new_payout = new_payout.into_inner().payout;
+    if new_payout.amount <= Amount::ZERO {
+        return Err(Errors::new(&[("amount", "must be positive")]));
+    }
+    if new_payout.destination.trim().is_empty() {
+        return Err(Errors::new(&[("destination", "can't be blank")]));
+    }
+
+    db.run(move |conn| {
+        database::payouts::request(
+            conn,
+            auth.id,
+            new_payout.amount,
+            new_payout.currency,
+            new_payout.method,
+            new_payout.destination.trim(),
+        )
+        .map(|payout| json!({ "payout": payout }))
+        .map_err(payout_error)
+    })
+    .await
+}
+
+#[get("/me/payouts")]
+pub async fn get_my_payouts(auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::payouts::for_organizer(conn, auth.id)
+            .map(|payouts| json!({ "payouts": payouts }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch payouts")]))
+    })
+    .await
+}
+
+#[get("/admin/payouts?<status>")]
+pub async fn get_payouts(
+    _admin: Admin,
+    status: Option<PayoutStatus>,
+    db: Db,
+) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::payouts::list(conn, status)
+            .map(|payouts| json!({ "payouts": payouts }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch payouts")]))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct PayoutSettlement {
+    reference: String,
+}
+
+#[post("/admin/payouts/<id>/paid", format = "json", data = "<settlement>")]
+pub async fn settle_payout(
+    _admin: Admin,
+    id: i32,
+    settlement: Json<PayoutSettlement>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let settlement = settlement.into_inner();
+    if settlement.reference.trim().is_empty() {
+        return Err(Errors::new(&[("reference", "can't be blank")]));
+    }
+
+    db.run(move |conn| {
+        database::payouts::settle(conn, id, settlement.reference.trim())
+            .map(|payout| json!({ "payout": payout }))
+            .map_err(payout_error)
+    })
+    .await
+}
+
+#[post("/admin/payouts/<id>/failed")]
+pub async fn fail_payout(_admin: Admin, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::payouts::fail(conn, id)
+            .map(|payout| json!({ "payout": payout }))
+            .map_err(payout_error)
+    })
+    .await
+}
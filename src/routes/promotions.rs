@@ -0,0 +1,158 @@
+use crate::auth::Auth;
+use crate::database::promotions::{DiscountKind, NewEarlyBirdRuleData, NewPromoCodeData, PromoError};
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::money::{Amount, MAX_PRICE};
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+fn promo_error(error: PromoError) -> Errors {
+    match error {
+        PromoError::EventNotFound => Errors::new(&[("event_id", "does not exist")]),
+        PromoError::CurrencyMismatch => Errors::new(&[("currency", "must be the event's currency")]),
+        PromoError::DuplicateCode => Errors::new(&[("code", "you already have a promo code with this name")]),
+        PromoError::NotFound => Errors::new(&[("id", "does not exist")]),
+        PromoError::Other => Errors::new(&[("database", "failed to process promotion")]),
+    }
+}
+
+fn validate_discount(
+    kind: DiscountKind,
+    percent_off: Option<i32>,
+    amount_off: Option<Amount>,
+) -> Result<(), Errors> {
+    match kind {
+        DiscountKind::Percent => {
+            if !matches!(percent_off, Some(percent) if (1..=100).contains(&percent)) {
+                return Err(Errors::new(&[("percent_off", "must be between 1 and 100")]));
+            }
+            if amount_off.is_some() {
+                return Err(Errors::new(&[("amount_off", "only applies to fixed discounts")]));
+            }
+        }
+        DiscountKind::Fixed => {
+            if !matches!(amount_off, Some(amount) if amount > Amount::ZERO) {
+                return Err(Errors::new(&[("amount_off", "must be positive")]));
+            }
+            if matches!(amount_off, Some(amount) if amount > MAX_PRICE) {
+                return Err(Errors::new(&[("amount_off", "is too large")]));
+            }
+            if percent_off.is_some() {
+                return Err(Errors::new(&[("percent_off", "only applies to percent discounts")]));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct NewPromoCode {
+    promo_code: NewPromoCodeData,
+}
+
+#[post("/promo_codes", format = "json", data = "<new_code>")]
+pub async fn add_promo_code(auth: Auth, new_code: Json<NewPromoCode>, db: Db) -> Result<Value, Errors> {
+    let data = new_code.into_inner().promo_code;
+    let code = database::promotions::normalize_code(&data.code).ok_or_else(|| {
+        Errors::new(&[("code", "must be 3 to 32 letters, digits, '-' or '_'")])
+    })?;
+    validate_discount(data.discount_kind, data.percent_off, data.amount_off)?;
+    match (data.discount_kind, data.currency) {
+        (DiscountKind::Fixed, None) => {
+            return Err(Errors::new(&[("currency", "is required for fixed discounts")]))
+        }
+        (DiscountKind::Percent, Some(_)) => {
+            return Err(Errors::new(&[("currency", "only applies to fixed discounts")]))
+        }
+        _ => {}
+    }
+    if matches!(data.max_uses, Some(uses) if uses <= 0) {
+        return Err(Errors::new(&[("max_uses", "must be positive")]));
+    }
+    if matches!(data.max_uses_per_user, Some(uses) if uses <= 0) {
+        return Err(Errors::new(&[("max_uses_per_user", "must be positive")]));
+    }
+    if let (Some(from), Some(until)) = (data.valid_from, data.valid_until) {
+        if until <= from {
+            return Err(Errors::new(&[("valid_until", "must be after valid_from")]));
+        }
+    }
+
+    db.run(move |conn| {
+        database::promotions::create_code(conn, auth.id, &code, &data)
+            .map(|promo_code| json!({ "promo_code": promo_code }))
+            .map_err(promo_error)
+    })
+    .await
+}
+
+#[get("/me/promo_codes")]
+pub async fn get_my_promo_codes(auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::promotions::codes_for_organizer(conn, auth.id)
+            .map(|promo_codes| json!({ "promo_codes": promo_codes }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch promo codes")]))
+    })
+    .await
+}
+
+#[post("/promo_codes/<id>/deactivate")]
+pub async fn deactivate_promo_code(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::promotions::deactivate_code(conn, id, auth.id)
+            .map(|promo_code| json!({ "promo_code": promo_code }))
+            .map_err(promo_error)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct NewEarlyBirdRule {
+    early_bird_rule: NewEarlyBirdRuleData,
+}
+
+#[post("/events/<id>/early_bird_rules", format = "json", data = "<new_rule>")]
+pub async fn add_early_bird_rule(
+    auth: Auth,
+    id: i32,
+    new_rule: Json<NewEarlyBirdRule>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let data = new_rule.into_inner().early_bird_rule;
+    validate_discount(data.discount_kind, data.percent_off, data.amount_off)?;
+    if data.ends_at.is_none() && data.max_tickets.is_none() {
+        return Err(Errors::new(&[("ends_at", "either ends_at or max_tickets is required")]));
+    }
+    if matches!(data.max_tickets, Some(tickets) if tickets <= 0) {
+        return Err(Errors::new(&[("max_tickets", "must be positive")]));
+    }
+
+    db.run(move |conn| {
+        database::promotions::create_rule(conn, id, auth.id, &data)
+            .map(|rule| json!({ "early_bird_rule": rule }))
+            .map_err(promo_error)
+    })
+    .await
+}
+
+#[get("/events/<id>/early_bird_rules")]
+pub async fn get_early_bird_rules(id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::promotions::rules_for_event(conn, id)
+            .map(|rules| json!({ "early_bird_rules": rules }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch early bird rules")]))
+    })
+    .await
+}
+
+#[delete("/early_bird_rules/<id>")]
+pub async fn delete_early_bird_rule(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::promotions::delete_rule(conn, id, auth.id)
+            .map(|_| json!({ "deleted": id }))
+            .map_err(promo_error)
+    })
+    .await
+}
@@ -0,0 +1,69 @@
+use crate::auth::Auth;
+use crate::config::AppState;
+use crate::database::rankings::ViewError;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use hmac::{Hmac, Mac};
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+use rocket::State;
+use sha2::Sha256;
+use std::net::IpAddr;
+
+#[get("/get_popular_events?<limit>")]
+pub async fn get_popular_events(db: Db, limit: Option<i64>) -> Result<Value, Errors> {
+    let limit = limit.unwrap_or(database::rankings::POPULAR_DEFAULT_LIMIT);
+
+    db.run(move |conn| {
+        database::rankings::get_popular(conn, limit)
+            .map(|events| json!({ "events": events }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch popular events")]))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct EventView {
+    event_id: i32,
+}
+
+/// Who a view is counted for: the user, else the client address hashed with
+/// the app secret so that addresses aren't stored.
+fn viewer_key(user_id: Option<i32>, client_ip: Option<IpAddr>, secret: &[u8]) -> String {
+    match (user_id, client_ip) {
+        (Some(user_id), _) => format!("user:{}", user_id),
+        (None, Some(ip)) => {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes any key size");
+            mac.update(ip.to_string().as_bytes());
+            format!("ip:{}", hex::encode(mac.finalize().into_bytes()))
+        }
+        (None, None) => "anonymous".to_string(),
+    }
+}
+
+/// Called by the frontend when an event page is opened. Anonymous views
+/// count as well. Reloads by the same viewer within the hour count once.
+#[post("/event/view", format = "json", data = "<view>")]
+pub async fn record_event_view(
+    auth: Option<Auth>,
+    client_ip: Option<IpAddr>,
+    state: &State<AppState>,
+    view: Json<EventView>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let viewer = auth.map(|auth| auth.id);
+    let key = viewer_key(viewer, client_ip, &state.secret);
+
+    db.run(move |conn| {
+        database::rankings::record_view(conn, view.event_id, viewer, &key)
+            .map(|_| json!({ "message": "view recorded" }))
+            .map_err(|error| match error {
+                ViewError::NonExistEvent => Errors::new(&[("event_id", "does not exist")]),
+                ViewError::Other => Errors::new(&[("database", "failed to record view")]),
+            })
+    })
+    .await
+}
@@ -0,0 +1,23 @@
+use crate::database::rates::MAX_RATE_AGE_MINUTES;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::money::Currency;
+use rocket::serde::json::{json, Value};
+
+/// Cached exchange rates against the US dollar. Checkout only quotes with
+/// rates younger than `max_age_minutes`.
+#[get("/rates")]
+pub async fn get_rates(db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::rates::all(conn)
+            .map(|rates| {
+                json!({
+                    "base": Currency::Usd,
+                    "rates": rates,
+                    "max_age_minutes": MAX_RATE_AGE_MINUTES,
+                })
+            })
+            .map_err(|_| Errors::new(&[("database", "failed to fetch exchange rates")]))
+    })
+    .await
+}
@@ -0,0 +1,28 @@
+use crate::auth::Auth;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::json::{json, Value};
+
+/// Falls back to the trending list for users the recommendations job has
+/// nothing for yet, e.g. right after signing up.
+#[get("/me/recommendations?<limit>")]
+pub async fn get_recommendations(
+    auth: Auth,
+    db: Db,
+    limit: Option<i64>,
+) -> Result<Value, Errors> {
+    let limit = limit.unwrap_or(database::recommendations::RECOMMENDATIONS_DEFAULT_LIMIT);
+
+    db.run(move |conn| {
+        let recommended = database::recommendations::for_user(conn, auth.id, limit)
+            .map_err(|_| Errors::new(&[("database", "failed to fetch recommendations")]))?;
+        if !recommended.is_empty() {
+            return Ok(json!({ "events": recommended, "personalized": true }));
+        }
+
+        database::rankings::get_popular(conn, limit)
+            .map(|events| json!({ "events": events, "personalized": false }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch recommendations")]))
+    })
+    .await
+}
@@ -0,0 +1,106 @@
+use crate::auth::Auth;
+use crate::database::refunds::{RefundError, RefundStatus};
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+fn refund_error(error: RefundError) -> Errors {
+    match error {
+        RefundError::TicketNotFound => Errors::new(&[("ticket_id", "ticket does not exist")]),
+        RefundError::NotRefundable => {
+            Errors::new(&[("ticket_id", "the event's refund policy no longer allows a refund")])
+        }
+        RefundError::AlreadyRequested => {
+            Errors::new(&[("ticket_id", "a refund was already requested for this ticket")])
+        }
+        RefundError::NotFound => Errors::new(&[("id", "refund does not exist")]),
+        RefundError::InvalidStatus => {
+            Errors::new(&[("status", "refund can't be changed in its current status")])
+        }
+        RefundError::Other => Errors::new(&[("database", "failed to process refund")]),
+    }
+}
+
+#[post("/tickets/<id>/refund")]
+pub async fn request_refund(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::refunds::request(conn, id, auth.id)
+            .map(|refund| json!({ "refund": refund }))
+            .map_err(refund_error)
+    })
+    .await
+}
+
+#[get("/me/refunds")]
+pub async fn get_my_refunds(auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::refunds::for_user(conn, auth.id)
+            .map(|refunds| json!({ "refunds": refunds }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch refunds")]))
+    })
+    .await
+}
+
+#[get("/events/<id>/refunds?<status>")]
+pub async fn get_event_refunds(
+    auth: Auth,
+    id: i32,
+    status: Option<RefundStatus>,
+    db: Db,
+) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::refunds::for_event(conn, id, auth.id, status)
+            .map(|refunds| json!({ "refunds": refunds }))
+            .map_err(refund_error)
+    })
+    .await
+}
+
+#[post("/refunds/<id>/approve")]
+pub async fn approve_refund(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::refunds::decide(conn, id, auth.id, true)
+            .map(|refund| json!({ "refund": refund }))
+            .map_err(refund_error)
+    })
+    .await
+}
+
+#[post("/refunds/<id>/reject")]
+pub async fn reject_refund(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::refunds::decide(conn, id, auth.id, false)
+            .map(|refund| json!({ "refund": refund }))
+            .map_err(refund_error)
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct RefundPayout {
+    /// Transaction hash or provider reference of the repayment.
+    payout_reference: String,
+}
+
+#[post("/refunds/<id>/paid", format = "json", data = "<payout>")]
+pub async fn mark_refund_paid(
+    auth: Auth,
+    id: i32,
+    payout: Json<RefundPayout>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let payout = payout.into_inner();
+    if payout.payout_reference.trim().is_empty() {
+        return Err(Errors::new(&[("payout_reference", "can't be blank")]));
+    }
+
+    db.run(move |conn| {
+        database::refunds::mark_paid(conn, id, auth.id, payout.payout_reference.trim())
+            .map(|refund| json!({ "refund": refund }))
+            .map_err(refund_error)
+    })
+    .await
+}
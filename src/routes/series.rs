@@ -0,0 +1,149 @@
+use crate::auth::Auth;
+use crate::database::events::EventStatus;
+use crate::database::series::{NewSeries as NewSeriesRow, SeriesCreationError, SeriesTransitionError};
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::money::{Amount, Currency, DEFAULT_CURRENCY, MAX_PRICE};
+use crate::recurrence::RRuleError;
+use crate::routes::events::LOCAL_DATETIME_FORMAT;
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+#[derive(Deserialize)]
+struct NewSeriesData {
+    eventname: String,
+    eventdescription: String,
+    category_ids: Vec<i32>,
+    eventimage: String,
+    /// Minor units of `currency`.
+    eventticketprice: Amount,
+    currency: Option<Currency>,
+    venue_id: i32,
+    /// IANA timezone, defaults to the venue's timezone.
+    timezone: Option<String>,
+    /// RFC 5545 RRULE, e.g. "FREQ=WEEKLY;BYDAY=FR,SA;COUNT=10".
+    rrule: String,
+    /// Wall-clock start of the first occurrence, "YYYY-MM-DD HH:MM:SS".
+    starts_at: String,
+    duration_minutes: i32,
+    /// Seats per occurrence.
+    capacity: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct NewSeries {
+    series: NewSeriesData,
+}
+
+#[post("/event_series", format = "json", data = "<new_series>")]
+pub async fn add_series(auth: Auth, new_series: Json<NewSeries>, db: Db) -> Result<Value, Errors> {
+    let new_series = new_series.into_inner().series;
+
+    let dtstart = NaiveDateTime::parse_from_str(&new_series.starts_at, LOCAL_DATETIME_FORMAT)
+        .map_err(|_| {
+            Errors::new(&[("starts_at", "invalid datetime format, expected YYYY-MM-DD HH:MM:SS")])
+        })?;
+    if new_series.category_ids.is_empty() {
+        return Err(Errors::new(&[("category_ids", "can't be blank")]));
+    }
+    if new_series.eventticketprice.is_negative() {
+        return Err(Errors::new(&[("eventticketprice", "can't be negative")]));
+    }
+    if new_series.eventticketprice > MAX_PRICE {
+        return Err(Errors::new(&[("eventticketprice", "is too large")]));
+    }
+    if new_series.duration_minutes <= 0 {
+        return Err(Errors::new(&[("duration_minutes", "must be positive")]));
+    }
+
+    db.run(move |conn| {
+        let timezone = match new_series.timezone {
+            Some(timezone) => timezone,
+            None => database::venues::find(conn, new_series.venue_id)
+                .map(|venue| venue.timezone)
+                .map_err(|err| match err {
+                    diesel::result::Error::NotFound => {
+                        Errors::new(&[("venue_id", "does not exist")])
+                    }
+                    _ => Errors::new(&[("database", "failed to fetch venue")]),
+                })?,
+        };
+        if timezone.parse::<Tz>().is_err() {
+            return Err(Errors::new(&[("timezone", "is not a valid IANA timezone")]));
+        }
+
+        let row = NewSeriesRow {
+            userid: auth.id,
+            eventname: &new_series.eventname,
+            eventdescription: &new_series.eventdescription,
+            eventimage: &new_series.eventimage,
+            eventticketprice: new_series.eventticketprice,
+            currency: new_series.currency.unwrap_or(DEFAULT_CURRENCY),
+            venue_id: new_series.venue_id,
+            timezone: &timezone,
+            rrule: &new_series.rrule,
+            dtstart,
+            duration_minutes: new_series.duration_minutes,
+            capacity: new_series.capacity,
+            category_ids: &new_series.category_ids,
+        };
+        database::series::create(conn, &row)
+            .map(|(series, occurrences)| json!({ "series": series, "events": occurrences }))
+            .map_err(|error| match error {
+                SeriesCreationError::InvalidRule(RRuleError::TooManyOccurrences) => {
+                    Errors::new(&[("rrule", "COUNT can't be more than 366")])
+                }
+                SeriesCreationError::InvalidRule(_) => {
+                    Errors::new(&[("rrule", "is not a supported recurrence rule")])
+                }
+                SeriesCreationError::NoOccurrences => {
+                    Errors::new(&[("rrule", "does not produce any occurrence")])
+                }
+                SeriesCreationError::InvalidTimezone => {
+                    Errors::new(&[("timezone", "is not a valid IANA timezone")])
+                }
+                SeriesCreationError::NonExistUsername => Errors::new(&[("userid", "does not exist")]),
+                SeriesCreationError::NonExistVenue => Errors::new(&[("venue_id", "does not exist")]),
+                SeriesCreationError::NonExistCategory => {
+                    Errors::new(&[("category_ids", "contains a category that does not exist")])
+                }
+                SeriesCreationError::Other => {
+                    Errors::new(&[("database", "failed to create event series")])
+                }
+            })
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct SeriesStatusChange {
+    id: i32,
+    status: EventStatus,
+}
+
+#[put("/event_series/status", format = "json", data = "<change>")]
+pub async fn update_series_status(
+    auth: Auth,
+    change: Json<SeriesStatusChange>,
+    db: Db,
+) -> Result<Value, Errors> {
+    let change = change.into_inner();
+    db.run(move |conn| {
+        database::series::transition(conn, change.id, auth.id, change.status)
+            .map(|events| json!({ "events": events }))
+            .map_err(|error| match error {
+                SeriesTransitionError::NotFound => Errors::new(&[("id", "series does not exist")]),
+                SeriesTransitionError::NotOwner => {
+                    Errors::new(&[("id", "only the organizer can change the status")])
+                }
+                SeriesTransitionError::Other => {
+                    Errors::new(&[("database", "failed to update series status")])
+                }
+            })
+    })
+    .await
+}
@@ -0,0 +1,17 @@
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::json::{json, Value};
+
+#[get("/tags/autocomplete?<q>&<limit>")]
+pub async fn autocomplete_tags(db: Db, q: String, limit: Option<i64>) -> Result<Value, Errors> {
+    let prefix = database::tags::normalize(&q)
+        .ok_or_else(|| Errors::new(&[("q", "can't be blank")]))?;
+    let limit = limit.unwrap_or(database::tags::AUTOCOMPLETE_DEFAULT_LIMIT);
+
+    db.run(move |conn| {
+        database::tags::autocomplete(conn, &prefix, limit)
+            .map(|tags| json!({ "tags": tags }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch tags")]))
+    })
+    .await
+}
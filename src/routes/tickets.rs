@@ -0,0 +1,15 @@
+use crate::auth::Auth;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use crate::models::tickets::TicketFiltering;
+use rocket::serde::json::{json, Value};
+
+#[get("/get_tickets?<filters..>")]
+pub async fn get_tickets(auth: Auth, db: Db, filters: Option<TicketFiltering>) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::tickets::get_tickets(conn, auth.id, filters)
+            .map(|tickets| json!({ "tickets": tickets }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch tickets")]))
+    })
+    .await
+}
@@ -0,0 +1,102 @@
+use crate::auth::Auth;
+use crate::database::transfers::TransferError;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+fn transfer_error(error: TransferError) -> Errors {
+    match error {
+        TransferError::TicketNotFound => Errors::new(&[("ticket_id", "ticket does not exist")]),
+        TransferError::RecipientNotFound => Errors::new(&[("to", "no user with this username, email or wallet")]),
+        TransferError::SelfTransfer => Errors::new(&[("to", "can't transfer a ticket to yourself")]),
+        TransferError::TransfersClosed => {
+            Errors::new(&[("ticket_id", "transfers are closed for this event")])
+        }
+        TransferError::AlreadyPending => {
+            Errors::new(&[("ticket_id", "ticket already has a pending transfer")])
+        }
+        TransferError::TicketListed => {
+            Errors::new(&[("ticket_id", "cancel the resale listing before transferring")])
+        }
+        TransferError::NotFound => Errors::new(&[("id", "transfer does not exist")]),
+        TransferError::Other => Errors::new(&[("database", "failed to process transfer")]),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NewTransfer {
+    /// Username, email or wallet address of the recipient.
+    to: String,
+}
+
+#[post("/tickets/<id>/transfer", format = "json", data = "<transfer>")]
+pub async fn transfer_ticket(
+    auth: Auth,
+    id: i32,
+    transfer: Json<NewTransfer>,
+    db: Db,
+) -> Result<Value, Errors> {
+    if transfer.to.trim().is_empty() {
+        return Err(Errors::new(&[("to", "can't be blank")]));
+    }
+
+    db.run(move |conn| {
+        database::transfers::initiate(conn, id, auth.id, &transfer.to)
+            .map(|transfer| json!({ "transfer": transfer }))
+            .map_err(transfer_error)
+    })
+    .await
+}
+
+#[get("/me/ticket_transfers")]
+pub async fn get_transfers(auth: Auth, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::transfers::pending(conn, auth.id)
+            .map(|(incoming, outgoing)| json!({ "incoming": incoming, "outgoing": outgoing }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch transfers")]))
+    })
+    .await
+}
+
+#[post("/ticket_transfers/<id>/accept")]
+pub async fn accept_transfer(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::transfers::accept(conn, id, auth.id)
+            .map(|ticket| json!({ "ticket": ticket }))
+            .map_err(transfer_error)
+    })
+    .await
+}
+
+#[post("/ticket_transfers/<id>/decline")]
+pub async fn decline_transfer(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::transfers::decline(conn, id, auth.id)
+            .map(|transfer| json!({ "transfer": transfer }))
+            .map_err(transfer_error)
+    })
+    .await
+}
+
+#[post("/ticket_transfers/<id>/cancel")]
+pub async fn cancel_transfer(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::transfers::cancel(conn, id, auth.id)
+            .map(|transfer| json!({ "transfer": transfer }))
+            .map_err(transfer_error)
+    })
+    .await
+}
+
+#[get("/tickets/<id>/history")]
+pub async fn get_ticket_history(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::transfers::ownership_history(conn, id, auth.id)
+            .map(|owners| json!({ "owners": owners }))
+            .map_err(transfer_error)
+    })
+    .await
+}
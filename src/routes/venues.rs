@@ -0,0 +1,117 @@
+use crate::auth::Auth;
+use crate::database::venues::{NewVenue as NewVenueRow, VenueCreationError, VenueUpdateError};
+use crate::database::{self, Db};
+use crate::errors::{Errors, FieldValidator};
+use crate::models::venues::VenueFiltering;
+use chrono_tz::Tz;
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+#[derive(Deserialize, Validate)]
+struct NewVenueData {
+    #[validate(length(min = 1))]
+    name: Option<String>,
+    #[validate(length(min = 1))]
+    address: Option<String>,
+    #[validate(length(min = 1))]
+    city: Option<String>,
+    #[validate(length(min = 1))]
+    country: Option<String>,
+    #[validate(range(min = -90.0, max = 90.0))]
+    latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0))]
+    longitude: Option<f64>,
+    #[validate(range(min = 1))]
+    capacity: Option<i32>,
+    timezone: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewVenue {
+    venue: NewVenueData,
+}
+
+#[post("/venue", format = "json", data = "<new_venue>")]
+pub async fn add_venue(auth: Auth, new_venue: Json<NewVenue>, db: Db) -> Result<Value, Errors> {
+    let new_venue = new_venue.into_inner().venue;
+
+    let mut extractor = FieldValidator::validate(&new_venue);
+    let name = extractor.extract("name", new_venue.name);
+    let address = extractor.extract("address", new_venue.address);
+    let city = extractor.extract("city", new_venue.city);
+    let country = extractor.extract("country", new_venue.country);
+    extractor.check()?;
+
+    if new_venue.latitude.is_some() != new_venue.longitude.is_some() {
+        return Err(Errors::new(&[("latitude", "latitude and longitude go together")]));
+    }
+    let timezone = new_venue.timezone.unwrap_or_else(|| "UTC".to_string());
+    if timezone.parse::<Tz>().is_err() {
+        return Err(Errors::new(&[("timezone", "is not a valid IANA timezone")]));
+    }
+
+    db.run(move |conn| {
+        let row = NewVenueRow {
+            name: &name,
+            address: &address,
+            city: &city,
+            country: &country,
+            latitude: new_venue.latitude,
+            longitude: new_venue.longitude,
+            capacity: new_venue.capacity,
+            timezone: &timezone,
+            created_by: auth.id,
+        };
+        database::venues::create(conn, &row)
+            .map(|venue| json!({ "venue": venue }))
+            .map_err(|error| match error {
+                VenueCreationError::DuplicatedVenue => {
+                    Errors::new(&[("name", "venue already exists in this city")])
+                }
+                VenueCreationError::Other => Errors::new(&[("database", "failed to create venue")]),
+            })
+    })
+    .await
+}
+
+#[get("/get_venues?<filters..>")]
+pub async fn get_venues(db: Db, filters: Option<VenueFiltering>) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::venues::get_venues(conn, filters)
+            .map(|venues| json!({ "venues": venues }))
+            .map_err(|_| Errors::new(&[("database", "failed to fetch venues")]))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct UpdateVenue {
+    id: i32,
+    venue: database::venues::UpdateVenueData,
+}
+
+#[put("/venue", format = "json", data = "<venue>")]
+pub async fn update_venue(auth: Auth, venue: Json<UpdateVenue>, db: Db) -> Result<Value, Errors> {
+    FieldValidator::validate(&venue.venue).check()?;
+    if venue.venue.latitude.is_some() != venue.venue.longitude.is_some() {
+        return Err(Errors::new(&[("latitude", "latitude and longitude go together")]));
+    }
+    if matches!(venue.venue.timezone, Some(ref timezone) if timezone.parse::<Tz>().is_err()) {
+        return Err(Errors::new(&[("timezone", "is not a valid IANA timezone")]));
+    }
+
+    db.run(move |conn| {
+        database::venues::update(conn, venue.id, auth.id, &venue.venue)
+            .map(|venue| json!({ "venue": venue }))
+            .map_err(|error| match error {
+                VenueUpdateError::NotFound => Errors::new(&[("id", "venue does not exist")]),
+                VenueUpdateError::Forbidden => {
+                    Errors::new(&[("id", "venue was added by another user")])
+                }
+                VenueUpdateError::Other => Errors::new(&[("database", "failed to update venue")]),
+            })
+    })
+    .await
+}
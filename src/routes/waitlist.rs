@@ -0,0 +1,79 @@
+use crate::auth::Auth;
+use crate::database::orders::MAX_TICKETS_PER_ORDER;
+use crate::database::waitlist::WaitlistError;
+use crate::database::{self, Db};
+use crate::errors::Errors;
+use rocket::serde::{
+    json::{json, Json, Value},
+    Deserialize,
+};
+
+fn waitlist_error(error: WaitlistError) -> Errors {
+    match error {
+        WaitlistError::EventNotFound => Errors::new(&[("event_id", "does not exist")]),
+        WaitlistError::EventNotOnSale => Errors::new(&[("event_id", "is not on sale")]),
+        WaitlistError::NotSoldOut => {
+            Errors::new(&[("event_id", "tickets are still available, buy them directly")])
+        }
+        WaitlistError::InvalidQuantity => {
+            Errors::new(&[("quantity", "must be between 1 and 10")])
+        }
+        WaitlistError::AlreadyWaiting => Errors::new(&[("event_id", "already on the waitlist")]),
+        WaitlistError::NotWaiting => Errors::new(&[("event_id", "not on the waitlist")]),
+        WaitlistError::Other => Errors::new(&[("database", "failed to process waitlist")]),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct JoinWaitlist {
+    quantity: i32,
+}
+
+#[post("/events/<id>/waitlist", format = "json", data = "<join>")]
+pub async fn join_waitlist(
+    auth: Auth,
+    id: i32,
+    join: Json<JoinWaitlist>,
+    db: Db,
+) -> Result<Value, Errors> {
+    if !(1..=MAX_TICKETS_PER_ORDER).contains(&join.quantity) {
+        return Err(Errors::new(&[("quantity", "must be between 1 and 10")]));
+    }
+
+    db.run(move |conn| {
+        database::waitlist::join(conn, id, auth.id, join.quantity)
+            .map(|entry| json!({ "waitlist": entry }))
+            .map_err(waitlist_error)
+    })
+    .await
+}
+
+#[delete("/events/<id>/waitlist")]
+pub async fn leave_waitlist(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::waitlist::leave(conn, id, auth.id)
+            .map(|entry| json!({ "waitlist": entry }))
+            .map_err(waitlist_error)
+    })
+    .await
+}
+
+#[get("/events/<id>/waitlist/position")]
+pub async fn get_waitlist_position(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::waitlist::position(conn, id, auth.id)
+            .map(|position| json!({ "waitlist": position }))
+            .map_err(waitlist_error)
+    })
+    .await
+}
+
+#[get("/events/<id>/waitlist")]
+pub async fn get_waitlist_summary(auth: Auth, id: i32, db: Db) -> Result<Value, Errors> {
+    db.run(move |conn| {
+        database::waitlist::summary(conn, id, auth.id)
+            .map(|summary| json!({ "waitlist": summary }))
+            .map_err(waitlist_error)
+    })
+    .await
+}
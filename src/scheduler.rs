@@ -0,0 +1,112 @@
+//! Periodic background work, started once Rocket has launched.
+
+use crate::database::{self, Db};
+use diesel::pg::PgConnection;
+use diesel::QueryResult;
+use rocket::fairing::AdHoc;
+use rocket::tokio::time::{interval, Duration};
+use rocket_sync_db_pools::ConnectionPool;
+
+/// How often the trending ranking is rebuilt.
+const RANKING_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// How often the per-user recommendations are rebuilt.
+const RECOMMENDATIONS_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How often pending feed activities are copied into users' feeds.
+const FEED_FAN_OUT_INTERVAL: Duration = Duration::from_secs(30);
+/// How often queued email and push notifications are sent.
+const NOTIFICATION_DELIVERY_INTERVAL: Duration = Duration::from_secs(15);
+/// How often upcoming events are checked for reminders to queue.
+const REMINDER_SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How often returned seats are offered to people on waitlists.
+const WAITLIST_OFFER_INTERVAL: Duration = Duration::from_secs(30);
+/// How often exchange rates are fetched from the provider.
+const RATE_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How often refunds of unmatched provider payments are retried.
+const UNMATCHED_REFUND_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// How often the ledger is checked to balance.
+const LEDGER_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// How often each job worker looks for due jobs.
+const JOB_RUN_INTERVAL: Duration = Duration::from_secs(10);
+/// Job workers per instance. Jobs are leased, so any number can run.
+const JOB_WORKERS: usize = 4;
+
+type Job = fn(&mut PgConnection) -> QueryResult<usize>;
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Background jobs", |rocket| {
+        Box::pin(async move {
+            let pool = match Db::pool(rocket) {
+                Some(pool) => pool.clone(),
+                None => {
+                    eprintln!("scheduler: database pool is not available");
+                    return;
+                }
+            };
+
+            every(pool.clone(), RANKING_REFRESH_INTERVAL, "refresh rankings", database::rankings::refresh);
+            every(pool.clone(), FEED_FAN_OUT_INTERVAL, "fan out feed", database::feed::fan_out);
+            every(
+                pool.clone(),
+                NOTIFICATION_DELIVERY_INTERVAL,
+                "deliver notifications",
+                database::notifications::deliver_pending,
+            );
+            every(
+                pool.clone(),
+                REMINDER_SWEEP_INTERVAL,
+                "schedule reminders",
+                database::reminders::schedule_upcoming,
+            );
+            every(
+                pool.clone(),
+                WAITLIST_OFFER_INTERVAL,
+                "offer waitlist seats",
+                database::waitlist::offer_available,
+            );
+            every(
+                pool.clone(),
+                RATE_REFRESH_INTERVAL,
+                "refresh exchange rates",
+                database::rates::refresh,
+            );
+            every(
+                pool.clone(),
+                UNMATCHED_REFUND_INTERVAL,
+                "refund unmatched payments",
+                database::payments::refund_unmatched,
+            );
+            every(
+                pool.clone(),
+                LEDGER_CHECK_INTERVAL,
+                "check ledger",
+                database::ledger::check_invariants,
+            );
+            for _ in 0..JOB_WORKERS {
+                every(pool.clone(), JOB_RUN_INTERVAL, "run jobs", database::jobs::run_due);
+            }
+            every(
+                pool,
+                RECOMMENDATIONS_REFRESH_INTERVAL,
+                "refresh recommendations",
+                database::recommendations::refresh,
+            );
+        })
+    })
+}
+
+/// Run `job` right away and then once per `period`.
+fn every(pool: ConnectionPool<Db, PgConnection>, period: Duration, name: &'static str, job: Job) {
+    rocket::tokio::spawn(async move {
+        let mut ticker = interval(period);
+        loop {
+            ticker.tick().await;
+            let conn = match pool.get().await {
+                Some(conn) => conn,
+                None => continue,
+            };
+            if let Err(err) = conn.run(job).await {
+                eprintln!("scheduler: {}: {}", name, err);
+            }
+        }
+    });
+}
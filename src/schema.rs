@@ -1,27 +1,507 @@
 // @generated automatically by Diesel CLI.
 
 diesel::table! {
+    categories (id) {
+        id -> Int4,
+        parent_id -> Nullable<Int4>,
+        slug -> Text,
+        name -> Text,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    chain_payments (tx_hash) {
+        tx_hash -> Text,
+        order_id -> Nullable<Int4>,
+        listing_id -> Nullable<Int4>,
+        created_at -> Timestamptz,
+        user_id -> Int4,
+        #[max_length = 42]
+        sender -> Nullable<Varchar>,
+        amount -> Numeric,
+        #[max_length = 8]
+        currency -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        refund_reference -> Nullable<Text>,
+        refunded_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    early_bird_rules (id) {
+        id -> Int4,
+        event_id -> Int4,
+        #[max_length = 16]
+        discount_kind -> Varchar,
+        ends_at -> Nullable<Timestamptz>,
+        max_tickets -> Nullable<Int4>,
+        created_at -> Timestamptz,
+        percent_off -> Nullable<Int4>,
+        amount_off -> Nullable<Numeric>,
+    }
+}
+
+diesel::table! {
+    event_categories (event_id, category_id) {
+        event_id -> Int4,
+        category_id -> Int4,
+    }
+}
+
+diesel::table! {
+    event_rankings (event_id) {
+        event_id -> Int4,
+        score -> Float8,
+        likes_count -> Int8,
+        tickets_sold -> Int8,
+        recent_views -> Int8,
+        computed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    event_series (id) {
+        id -> Int4,
+        userid -> Int4,
+        eventname -> Text,
+        eventdescription -> Text,
+        eventimage -> Text,
+        eventticketprice -> Numeric,
+        venue_id -> Int4,
+        timezone -> Text,
+        rrule -> Text,
+        dtstart -> Timestamp,
+        duration_minutes -> Int4,
+        capacity -> Nullable<Int4>,
+        created_at -> Timestamptz,
+        category_ids -> Array<Int4>,
+        #[max_length = 8]
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    event_tags (event_id, tag_id) {
+        event_id -> Int4,
+        tag_id -> Int4,
+    }
+}
+
+diesel::table! {
+    event_views (id) {
+        id -> Int8,
+        event_id -> Int4,
+        user_id -> Nullable<Int4>,
+        viewed_at -> Timestamptz,
+        viewer_key -> Text,
+        bucket -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::Tsvector;
+
     events (id) {
         id -> Int4,
         userid -> Int4,
         eventname -> Text,
         eventdescription -> Text,
-        eventdate -> Date,
-        eventdatetime -> Timestamp,
-        #[max_length = 255]
-        eventtype -> Varchar,
-        eventcountry -> Text,
-        eventcity -> Text,
-        eventplace -> Text,
         eventimage -> Text,
-        eventticketprice -> Int4,
+        eventticketprice -> Numeric,
+        venue_id -> Int4,
+        search_vector -> Nullable<Tsvector>,
+        #[max_length = 32]
+        status -> Varchar,
+        timezone -> Text,
+        starts_at -> Timestamptz,
+        ends_at -> Timestamptz,
+        series_id -> Nullable<Int4>,
+        recurrence_id -> Nullable<Timestamptz>,
+        capacity -> Nullable<Int4>,
+        tickets_sold -> Int4,
+        transfers_enabled -> Bool,
+        transfer_cutoff_hours -> Int4,
+        resale_enabled -> Bool,
+        resale_max_markup_percent -> Int4,
+        resale_royalty_percent -> Int4,
+        refund_full_until -> Nullable<Timestamptz>,
+        refund_partial_percent -> Int4,
+        refund_cutoff_hours -> Int4,
+        #[max_length = 8]
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    exchange_rates (currency) {
+        #[max_length = 8]
+        currency -> Varchar,
+        per_usd -> Numeric,
+        #[max_length = 32]
+        source -> Varchar,
+        fetched_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    feed_activities (id) {
+        id -> Int8,
+        event_id -> Int4,
+        #[max_length = 32]
+        kind -> Varchar,
+        old_price -> Nullable<Numeric>,
+        new_price -> Nullable<Numeric>,
+        created_at -> Timestamptz,
+        fanned_out_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    feed_items (id) {
+        id -> Int8,
+        user_id -> Int4,
+        activity_id -> Int8,
+        #[max_length = 32]
+        reason -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    follows (id) {
+        id -> Int4,
+        follower_id -> Int4,
+        followed_user_id -> Nullable<Int4>,
+        venue_id -> Nullable<Int4>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    invoice_counters (organizer_id) {
+        organizer_id -> Int4,
+        last_number -> Int4,
+    }
+}
+
+diesel::table! {
+    invoices (id) {
+        id -> Int4,
+        order_id -> Int4,
+        organizer_id -> Int4,
+        number -> Int4,
+        seller_name -> Text,
+        seller_email -> Text,
+        buyer_name -> Text,
+        buyer_email -> Text,
+        tax_rate_bps -> Int4,
+        tax_amount -> Numeric,
+        fee_amount -> Numeric,
+        #[max_length = 8]
+        currency -> Varchar,
+        issued_at -> Timestamptz,
+        event_name -> Text,
+        event_starts_at -> Timestamptz,
+        event_timezone -> Text,
+        venue_name -> Text,
+        venue_address -> Text,
+        venue_city -> Text,
+        venue_country -> Text,
+    }
+}
+
+diesel::table! {
+    ledger_entries (id) {
+        id -> Int4,
+        transaction_id -> Int4,
+        #[max_length = 16]
+        account -> Varchar,
+        organizer_id -> Nullable<Int4>,
+        amount -> Numeric,
+        #[max_length = 8]
+        currency -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    ledger_transactions (id) {
+        id -> Int4,
+        #[max_length = 16]
+        kind -> Varchar,
+        #[max_length = 8]
+        currency -> Varchar,
+        order_id -> Nullable<Int4>,
+        refund_id -> Nullable<Int4>,
+        payout_id -> Nullable<Int4>,
+        created_at -> Timestamptz,
+        listing_id -> Nullable<Int4>,
     }
 }
 
 diesel::table! {
-    likes (user_id) {
+    likes (user_id, event_id) {
         user_id -> Int4,
         event_id -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    notification_deliveries (id) {
+        id -> Int8,
+        user_id -> Int4,
+        #[max_length = 16]
+        channel -> Varchar,
+        #[max_length = 32]
+        kind -> Varchar,
+        title -> Text,
+        body -> Text,
+        #[max_length = 16]
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+        order_id -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    notification_preferences (user_id, kind) {
+        user_id -> Int4,
+        #[max_length = 32]
+        kind -> Varchar,
+        in_app -> Bool,
+        email -> Bool,
+        push -> Bool,
+    }
+}
+
+diesel::table! {
+    notifications (id) {
+        id -> Int8,
+        user_id -> Int4,
+        #[max_length = 32]
+        kind -> Varchar,
+        title -> Text,
+        body -> Text,
+        event_id -> Nullable<Int4>,
+        order_id -> Nullable<Int4>,
+        read_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    orders (id) {
+        id -> Int4,
+        user_id -> Int4,
+        event_id -> Int4,
+        quantity -> Int4,
+        unit_price -> Numeric,
+        total_amount -> Numeric,
+        #[max_length = 32]
+        status -> Varchar,
+        expires_at -> Timestamptz,
+        payment_reference -> Nullable<Text>,
+        created_at -> Timestamptz,
+        paid_at -> Nullable<Timestamptz>,
+        subtotal_amount -> Numeric,
+        discount_amount -> Numeric,
+        promo_code_id -> Nullable<Int4>,
+        early_bird_rule_id -> Nullable<Int4>,
+        #[max_length = 8]
+        currency -> Varchar,
+        #[max_length = 8]
+        pay_currency -> Nullable<Varchar>,
+        pay_amount -> Nullable<Numeric>,
+        #[max_length = 16]
+        payment_method -> Nullable<Varchar>,
+        organizer_id -> Int4,
+    }
+}
+
+diesel::table! {
+    payments (id) {
+        id -> Int4,
+        order_id -> Int4,
+        #[max_length = 16]
+        provider -> Varchar,
+        provider_reference -> Nullable<Text>,
+        #[max_length = 16]
+        method -> Varchar,
+        amount -> Numeric,
+        #[max_length = 8]
+        currency -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        checkout_url -> Nullable<Text>,
+        created_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    payouts (id) {
+        id -> Int4,
+        organizer_id -> Int4,
+        amount -> Numeric,
+        #[max_length = 8]
+        currency -> Varchar,
+        #[max_length = 16]
+        method -> Varchar,
+        destination -> Text,
+        #[max_length = 16]
+        status -> Varchar,
+        reference -> Nullable<Text>,
+        created_at -> Timestamptz,
+        settled_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    promo_codes (id) {
+        id -> Int4,
+        organizer_id -> Int4,
+        event_id -> Nullable<Int4>,
+        #[max_length = 32]
+        code -> Varchar,
+        #[max_length = 16]
+        discount_kind -> Varchar,
+        max_uses -> Nullable<Int4>,
+        max_uses_per_user -> Nullable<Int4>,
+        valid_from -> Nullable<Timestamptz>,
+        valid_until -> Nullable<Timestamptz>,
+        active -> Bool,
+        created_at -> Timestamptz,
+        percent_off -> Nullable<Int4>,
+        amount_off -> Nullable<Numeric>,
+        #[max_length = 8]
+        currency -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    refunds (id) {
+        id -> Int4,
+        ticket_id -> Int4,
+        order_id -> Int4,
+        event_id -> Int4,
+        user_id -> Int4,
+        amount -> Numeric,
+        #[max_length = 16]
+        reason -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        #[max_length = 16]
+        method -> Varchar,
+        payout_reference -> Nullable<Text>,
+        created_at -> Timestamptz,
+        decided_at -> Nullable<Timestamptz>,
+        paid_at -> Nullable<Timestamptz>,
+        #[max_length = 8]
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    scheduled_jobs (id) {
+        id -> Int8,
+        #[max_length = 64]
+        kind -> Varchar,
+        payload -> Jsonb,
+        run_at -> Timestamptz,
+        #[max_length = 16]
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        dedupe_key -> Nullable<Text>,
+        created_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+        max_attempts -> Int4,
+        locked_until -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    tags (id) {
+        id -> Int4,
+        #[max_length = 32]
+        name -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    ticket_listings (id) {
+        id -> Int4,
+        ticket_id -> Int4,
+        event_id -> Int4,
+        seller_id -> Int4,
+        price -> Numeric,
+        #[max_length = 16]
+        status -> Varchar,
+        buyer_id -> Nullable<Int4>,
+        reserved_until -> Nullable<Timestamptz>,
+        payment_reference -> Nullable<Text>,
+        royalty_amount -> Nullable<Numeric>,
+        seller_amount -> Nullable<Numeric>,
+        created_at -> Timestamptz,
+        sold_at -> Nullable<Timestamptz>,
+        #[max_length = 8]
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    ticket_owners (id) {
+        id -> Int4,
+        ticket_id -> Int4,
+        owner_id -> Int4,
+        #[max_length = 16]
+        acquired_via -> Varchar,
+        transfer_id -> Nullable<Int4>,
+        acquired_at -> Timestamptz,
+        released_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    ticket_transfers (id) {
+        id -> Int4,
+        ticket_id -> Int4,
+        from_user_id -> Int4,
+        to_user_id -> Int4,
+        #[max_length = 16]
+        status -> Varchar,
+        created_at -> Timestamptz,
+        responded_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    tickets (id) {
+        id -> Int4,
+        order_id -> Int4,
+        event_id -> Int4,
+        owner_id -> Int4,
+        qr_token -> Text,
+        #[max_length = 32]
+        status -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_recommendations (user_id, event_id) {
+        user_id -> Int4,
+        event_id -> Int4,
+        score -> Float8,
+        computed_at -> Timestamptz,
     }
 }
 
@@ -32,15 +512,142 @@ diesel::table! {
         email -> Text,
         image -> Nullable<Text>,
         hash -> Text,
+        is_admin -> Bool,
+        #[max_length = 42]
+        wallet_address -> Nullable<Varchar>,
+        #[max_length = 8]
+        preferred_currency -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    venues (id) {
+        id -> Int4,
+        name -> Text,
+        address -> Text,
+        city -> Text,
+        country -> Text,
+        latitude -> Nullable<Float8>,
+        longitude -> Nullable<Float8>,
+        capacity -> Nullable<Int4>,
+        timezone -> Text,
+        created_by -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    waitlist_entries (id) {
+        id -> Int4,
+        event_id -> Int4,
+        user_id -> Int4,
+        quantity -> Int4,
+        #[max_length = 16]
+        status -> Varchar,
+        order_id -> Nullable<Int4>,
+        created_at -> Timestamptz,
+        offered_at -> Nullable<Timestamptz>,
     }
 }
 
+diesel::joinable!(chain_payments -> orders (order_id));
+diesel::joinable!(chain_payments -> ticket_listings (listing_id));
+diesel::joinable!(chain_payments -> users (user_id));
+diesel::joinable!(early_bird_rules -> events (event_id));
+diesel::joinable!(event_categories -> categories (category_id));
+diesel::joinable!(event_categories -> events (event_id));
+diesel::joinable!(event_rankings -> events (event_id));
+diesel::joinable!(event_series -> users (userid));
+diesel::joinable!(event_series -> venues (venue_id));
+diesel::joinable!(event_tags -> events (event_id));
+diesel::joinable!(event_tags -> tags (tag_id));
+diesel::joinable!(event_views -> events (event_id));
+diesel::joinable!(event_views -> users (user_id));
+diesel::joinable!(events -> event_series (series_id));
 diesel::joinable!(events -> users (userid));
+diesel::joinable!(events -> venues (venue_id));
+diesel::joinable!(feed_activities -> events (event_id));
+diesel::joinable!(feed_items -> feed_activities (activity_id));
+diesel::joinable!(feed_items -> users (user_id));
+diesel::joinable!(follows -> venues (venue_id));
+diesel::joinable!(invoice_counters -> users (organizer_id));
+diesel::joinable!(invoices -> orders (order_id));
+diesel::joinable!(invoices -> users (organizer_id));
+diesel::joinable!(ledger_entries -> ledger_transactions (transaction_id));
+diesel::joinable!(ledger_entries -> users (organizer_id));
+diesel::joinable!(ledger_transactions -> orders (order_id));
+diesel::joinable!(ledger_transactions -> payouts (payout_id));
+diesel::joinable!(ledger_transactions -> refunds (refund_id));
+diesel::joinable!(ledger_transactions -> ticket_listings (listing_id));
 diesel::joinable!(likes -> events (event_id));
 diesel::joinable!(likes -> users (user_id));
+diesel::joinable!(notification_deliveries -> orders (order_id));
+diesel::joinable!(notification_deliveries -> users (user_id));
+diesel::joinable!(notification_preferences -> users (user_id));
+diesel::joinable!(notifications -> events (event_id));
+diesel::joinable!(notifications -> orders (order_id));
+diesel::joinable!(notifications -> users (user_id));
+diesel::joinable!(orders -> early_bird_rules (early_bird_rule_id));
+diesel::joinable!(orders -> events (event_id));
+diesel::joinable!(orders -> promo_codes (promo_code_id));
+diesel::joinable!(orders -> users (user_id));
+diesel::joinable!(payments -> orders (order_id));
+diesel::joinable!(payouts -> users (organizer_id));
+diesel::joinable!(promo_codes -> events (event_id));
+diesel::joinable!(promo_codes -> users (organizer_id));
+diesel::joinable!(refunds -> events (event_id));
+diesel::joinable!(refunds -> orders (order_id));
+diesel::joinable!(refunds -> tickets (ticket_id));
+diesel::joinable!(refunds -> users (user_id));
+diesel::joinable!(ticket_listings -> events (event_id));
+diesel::joinable!(ticket_listings -> tickets (ticket_id));
+diesel::joinable!(ticket_owners -> ticket_transfers (transfer_id));
+diesel::joinable!(ticket_owners -> tickets (ticket_id));
+diesel::joinable!(ticket_owners -> users (owner_id));
+diesel::joinable!(ticket_transfers -> tickets (ticket_id));
+diesel::joinable!(tickets -> events (event_id));
+diesel::joinable!(tickets -> orders (order_id));
+diesel::joinable!(tickets -> users (owner_id));
+diesel::joinable!(user_recommendations -> events (event_id));
+diesel::joinable!(user_recommendations -> users (user_id));
+diesel::joinable!(waitlist_entries -> events (event_id));
+diesel::joinable!(waitlist_entries -> orders (order_id));
+diesel::joinable!(waitlist_entries -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    categories,
+    chain_payments,
+    early_bird_rules,
+    event_categories,
+    event_rankings,
+    event_series,
+    event_tags,
+    event_views,
     events,
+    exchange_rates,
+    feed_activities,
+    feed_items,
+    follows,
+    invoice_counters,
+    invoices,
+    ledger_entries,
+    ledger_transactions,
     likes,
+    notification_deliveries,
+    notification_preferences,
+    notifications,
+    orders,
+    payments,
+    payouts,
+    promo_codes,
+    refunds,
+    scheduled_jobs,
+    tags,
+    ticket_listings,
+    ticket_owners,
+    ticket_transfers,
+    tickets,
+    user_recommendations,
     users,
+    venues,
+    waitlist_entries,
 );